@@ -0,0 +1,74 @@
+use axum::{
+    Router,
+    http::{self, HeaderName, Method},
+    middleware,
+    routing::{delete, get, post},
+};
+use tower_http::cors::{Any, CorsLayer};
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::{
+    audit::drop_untrusted_user,
+    handlers::*,
+    openapi::ApiDoc,
+    request_id::{REQUEST_ID_HEADER, assign_request_id},
+};
+
+/// Routes of the HTTP API, behind the middleware every request goes through.
+pub fn router() -> Router {
+    let cors = CorsLayer::new()
+        .allow_headers([
+            http::header::CONTENT_TYPE,
+            http::header::AUTHORIZATION,
+            HeaderName::from_static(REQUEST_ID_HEADER),
+        ])
+        .expose_headers([HeaderName::from_static(REQUEST_ID_HEADER)])
+        .allow_methods([Method::POST, Method::GET, Method::DELETE])
+        .allow_origin(Any);
+
+    Router::new()
+        .route("/api/health", get(health_check_handler))
+        .route("/api/health/live", get(health_check_handler))
+        .route("/api/health/ready", get(readiness_handler))
+        .route("/api/admin/doctor", get(doctor_handler))
+        .route("/api/audit", get(get_audit_handler))
+        .route("/api/audit/verify", get(verify_audit_handler))
+        .route("/api/events", get(events_handler))
+        .route("/api/jobs", get(get_jobs_handler))
+        .route("/api/jobs/{name}/run", post(run_job_handler))
+        .route("/api/reports", get(get_report_handler))
+        .route("/api/kiosks", get(get_kiosks_handler))
+        .route("/api/kiosks", post(register_kiosk_handler))
+        .route("/api/kiosks/{id}", delete(delete_kiosk_handler))
+        .route("/api/kiosks/{id}/heartbeat", post(kiosk_heartbeat_handler))
+        .route("/api/kiosks/{id}/voucher", get(get_kiosk_voucher_handler))
+        .route("/api/v1/vouchers", get(list_vouchers_handler))
+        .route("/api/vouchers", get(get_vouchers_handler))
+        .route("/api/vouchers", post(create_voucher_handler))
+        .route("/api/vouchers/details", get(get_voucher_details_handler))
+        .route("/api/vouchers/expired", delete(delete_expired_handler))
+        .route(
+            "/api/vouchers/expired/rolling",
+            delete(delete_expired_rolling_handler),
+        )
+        .route("/api/vouchers/newest", get(get_newest_voucher_handler))
+        .route("/api/vouchers/rolling", get(get_rolling_voucher_handler))
+        .route(
+            "/api/vouchers/rolling/all",
+            get(get_all_rolling_vouchers_handler),
+        )
+        .route(
+            "/api/vouchers/rolling",
+            post(create_rolling_voucher_handler),
+        )
+        .route(
+            "/api/vouchers/rolling/rotate",
+            post(rotate_rolling_voucher_handler),
+        )
+        .route("/api/vouchers/selected", delete(delete_selected_handler))
+        .merge(SwaggerUi::new("/api/docs").url("/api/openapi.json", ApiDoc::openapi()))
+        .layer(middleware::from_fn(drop_untrusted_user))
+        .layer(middleware::from_fn(assign_request_id))
+        .layer(cors)
+}
@@ -0,0 +1,724 @@
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Seek, SeekFrom, Write},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Request},
+    http::{HeaderMap, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, error, info, warn};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{environment::ENVIRONMENT, kiosks::KIOSK_REGISTRY, listener::Peer, models::Voucher};
+
+pub static AUDIT_LOG: OnceLock<AuditLog> = OnceLock::new();
+
+const AUDIT_FILE_NAME: &str = "audit-trail.jsonl";
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+/// Headers an authenticating reverse proxy names the user with
+const USER_HEADERS: [&str; 2] = ["x-forwarded-user", "x-remote-user"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Create,
+    Delete,
+    Purge,
+    Rotate,
+    ConfigReload,
+    Login,
+    KioskRegister,
+    KioskRemove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Partial,
+    Failure,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", content = "id", rename_all = "snake_case")]
+pub enum Actor {
+    /// User authenticated by the reverse proxy in front of the backend
+    User(String),
+    /// Registered kiosk that sent its device token
+    Kiosk(String),
+    /// Identity a client claimed without proof the backend can check:
+    /// `kiosk:<id>` for an `X-Kiosk-Id` header without the kiosk's device
+    /// token, `token:<fingerprint>` for any other bearer token
+    Unverified(String),
+    /// Request that carried no identity
+    Anonymous,
+    /// Action taken by the backend itself, e.g. a scheduled task
+    System,
+    /// Admin command run by this operating system user
+    Cli(String),
+}
+
+impl Actor {
+    /// The user headers are only left on requests from a trusted proxy, see
+    /// [`drop_untrusted_user`]. A kiosk id is only taken as is along with the
+    /// kiosk's device token.
+    pub async fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|h| h.to_str().ok())
+                .map(str::trim)
+                .filter(|h| !h.is_empty())
+        };
+
+        if let Some(user) = USER_HEADERS.into_iter().find_map(header) {
+            return Self::User(user.to_string());
+        }
+        let token = header("authorization")
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .map(str::trim);
+        if let Some(kiosk) = header("x-kiosk-id") {
+            let verified = match (KIOSK_REGISTRY.get(), token) {
+                (Some(registry), Some(token)) => registry.has_token(kiosk, token).await,
+                _ => false,
+            };
+            return if verified {
+                Self::Kiosk(kiosk.to_string())
+            } else {
+                Self::Unverified(format!("kiosk:{kiosk}"))
+            };
+        }
+        if let Some(token) = token {
+            // Never persist the token itself
+            let digest = hex(&Sha256::digest(token.as_bytes()));
+            return Self::Unverified(format!("token:{}", &digest[..12]));
+        }
+        Self::Anonymous
+    }
+
+    /// `kind:id`, or the kind alone for actors without an id.
+    pub fn label(&self) -> String {
+        match self.id() {
+            Some(id) => format!("{}:{}", self.kind(), id),
+            None => self.kind().to_string(),
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::User(_) => "user",
+            Self::Unverified(_) => "unverified",
+            Self::Kiosk(_) => "kiosk",
+            Self::Anonymous => "anonymous",
+            Self::System => "system",
+            Self::Cli(_) => "cli",
+        }
+    }
+
+    fn id(&self) -> Option<&str> {
+        match self {
+            Self::User(id) | Self::Kiosk(id) | Self::Unverified(id) | Self::Cli(id) => Some(id),
+            Self::Anonymous | Self::System => None,
+        }
+    }
+}
+
+fn is_trusted_proxy(peer: &Peer) -> bool {
+    ENVIRONMENT
+        .get()
+        .is_some_and(|environment| environment.trusted_proxies.contains(peer))
+}
+
+/// Middleware removing the user headers of requests that did not come from
+/// one of the configured trusted proxies, as any client could otherwise pick
+/// the actor recorded in the trail.
+pub async fn drop_untrusted_user(mut request: Request, next: Next) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<Peer>>()
+        .map(|ConnectInfo(peer)| *peer);
+    if !peer.as_ref().is_some_and(is_trusted_proxy) {
+        for name in USER_HEADERS {
+            if request.headers_mut().remove(name).is_some() {
+                debug!(
+                    "Ignoring the {} header from {:?}, which is not a trusted proxy",
+                    name, peer
+                );
+            }
+        }
+    }
+    next.run(request).await
+}
+
+/// Address a request came from, as recorded in the trail.
+///
+/// It is the peer of the connection, unless the peer is a trusted proxy: then
+/// it is the right-most `X-Forwarded-For` hop that is not a trusted proxy, or
+/// `X-Real-IP`. Hops further left were added by the client and could be
+/// forged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceIp(pub String);
+
+impl SourceIp {
+    pub fn new(peer: Option<Peer>, headers: &HeaderMap) -> Self {
+        let Some(peer) = peer else {
+            return Self("unknown".to_string());
+        };
+        if !is_trusted_proxy(&peer) {
+            return Self(peer.to_string());
+        }
+
+        let hops: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(','))
+            .map(str::trim)
+            .filter(|hop| !hop.is_empty())
+            .collect();
+        let untrusted = hops.iter().rev().find(|hop| {
+            !hop.parse::<IpAddr>()
+                .is_ok_and(|ip| is_trusted_proxy(&Peer::Ip(ip)))
+        });
+        // Only trusted proxies along the way, the first one is the client
+        let forwarded = untrusted.or(hops.first()).copied().or_else(|| {
+            headers
+                .get("x-real-ip")
+                .and_then(|h| h.to_str().ok())
+                .map(str::trim)
+                .filter(|h| !h.is_empty())
+        });
+        Self(forwarded.map_or_else(|| peer.to_string(), str::to_string))
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for SourceIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<Peer>>()
+            .map(|ConnectInfo(peer)| *peer);
+        Ok(Self::new(peer, &parts.headers))
+    }
+}
+
+/// Audit event waiting to be appended to the trail.
+#[derive(Debug, Clone)]
+pub struct AuditRecord {
+    pub action: AuditAction,
+    pub actor: Actor,
+    pub source_ip: Option<String>,
+    pub voucher_ids: Vec<String>,
+    pub voucher_codes: Vec<String>,
+    pub unused_voucher_ids: Option<Vec<String>>,
+    pub parameters: serde_json::Value,
+    pub outcome: AuditOutcome,
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    pub fn new(action: AuditAction, actor: Actor) -> Self {
+        Self {
+            action,
+            actor,
+            source_ip: None,
+            voucher_ids: Vec::new(),
+            voucher_codes: Vec::new(),
+            unused_voucher_ids: None,
+            parameters: serde_json::Value::Null,
+            outcome: AuditOutcome::Success,
+            error: None,
+        }
+    }
+
+    pub fn source_ip(mut self, ip: &str) -> Self {
+        self.source_ip = Some(ip.to_string()).filter(|ip| ip != "unknown");
+        self
+    }
+
+    pub fn vouchers<'v>(mut self, vouchers: impl IntoIterator<Item = &'v Voucher>) -> Self {
+        for voucher in vouchers {
+            self.voucher_ids.push(voucher.id.clone());
+            self.voucher_codes.push(voucher.code.clone());
+        }
+        self
+    }
+
+    /// Vouchers being deleted. Those no guest had used are noted as well, so
+    /// that reports can tell the vouchers that lapsed unused.
+    pub fn removed<'v>(mut self, vouchers: impl IntoIterator<Item = &'v Voucher>) -> Self {
+        let unused = self.unused_voucher_ids.get_or_insert_with(Vec::new);
+        for voucher in vouchers {
+            if voucher.authorized_guest_count == 0 && voucher.activated_at.is_none() {
+                unused.push(voucher.id.clone());
+            }
+            self.voucher_ids.push(voucher.id.clone());
+            self.voucher_codes.push(voucher.code.clone());
+        }
+        self
+    }
+
+    pub fn voucher_ids(mut self, ids: &[String]) -> Self {
+        self.voucher_ids.extend(ids.iter().cloned());
+        self
+    }
+
+    pub fn parameters(mut self, parameters: serde_json::Value) -> Self {
+        self.parameters = parameters;
+        self
+    }
+
+    pub fn outcome(mut self, outcome: AuditOutcome) -> Self {
+        self.outcome = outcome;
+        self
+    }
+
+    pub fn failed(mut self, error: impl ToString) -> Self {
+        self.outcome = AuditOutcome::Failure;
+        self.error = Some(error.to_string());
+        self
+    }
+
+    /// Appends the record to the global audit trail, if one is configured.
+    pub fn record(self) {
+        if let Some(audit_log) = AUDIT_LOG.get() {
+            audit_log.append(self);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub timestamp: String,
+    pub action: AuditAction,
+    pub actor: Actor,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_ip: Option<String>,
+    pub voucher_ids: Vec<String>,
+    pub voucher_codes: Vec<String>,
+    /// Deleted vouchers no guest had used, only recorded by deletions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unused_voucher_ids: Option<Vec<String>>,
+    pub parameters: serde_json::Value,
+    pub outcome: AuditOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+impl AuditEntry {
+    /// Hash of the entry's content chained to the previous entry's hash.
+    fn compute_hash(&self) -> String {
+        let mut unhashed = self.clone();
+        unhashed.hash = String::new();
+        let content = serde_json::to_string(&unhashed).expect("Audit entry is serializable");
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.prev_hash.as_bytes());
+        hasher.update(content.as_bytes());
+        hex(&hasher.finalize())
+    }
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    pub action: Option<AuditAction>,
+    pub actor_type: Option<String>,
+    pub actor: Option<String>,
+    pub source_ip: Option<String>,
+    /// Matches either a voucher id or a voucher code
+    pub voucher: Option<String>,
+    pub outcome: Option<AuditOutcome>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditPage {
+    pub data: Vec<AuditEntry>,
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditVerification {
+    pub valid: bool,
+    /// Well-formed entries
+    pub entries: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_invalid_seq: Option<u64>,
+    /// Numbers of the lines that are not an entry, e.g. after a partial write
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub malformed_lines: Vec<u64>,
+    /// Why the trail could not be read to the end
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug)]
+struct AuditState {
+    file: File,
+    /// Sequence number and hash of the last entry, the next one is chained to
+    last: Option<(u64, String)>,
+    /// Length of the file read so far, the admin commands append to the
+    /// trail while the server runs
+    read_len: u64,
+    /// Lines in the first `read_len` bytes
+    read_lines: u64,
+}
+
+/// Append-only, hash-chained record of every state-changing action.
+#[derive(Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+    state: Mutex<AuditState>,
+}
+
+impl AuditLog {
+    pub fn try_new(data_dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(data_dir).map_err(|e| {
+            format!(
+                "Failed to create data directory {}: {e}",
+                data_dir.display()
+            )
+        })?;
+        let path = data_dir.join(AUDIT_FILE_NAME);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| format!("Failed to open audit trail {}: {e}", path.display()))?;
+
+        let mut state = AuditState {
+            file,
+            last: None,
+            read_len: 0,
+            read_lines: 0,
+        };
+        state
+            .file
+            .lock_shared()
+            .map_err(|e| format!("Failed to lock audit trail: {e}"))?;
+        let read = state.read_new_entries();
+        let _ = state.file.unlock();
+        read?;
+
+        let audit_log = Self {
+            path,
+            state: Mutex::new(state),
+        };
+
+        let verification = audit_log.verify();
+        if verification.valid {
+            info!(
+                "Loaded audit trail with {} entries from {}",
+                verification.entries,
+                audit_log.path.display()
+            );
+        }
+        if let Some(seq) = verification.first_invalid_seq {
+            error!(
+                "Audit trail hash chain is broken at entry {}, it may have been tampered with",
+                seq
+            );
+        }
+        if !verification.malformed_lines.is_empty() {
+            error!(
+                "Audit trail has malformed lines {:?}, which are skipped",
+                verification.malformed_lines
+            );
+        }
+
+        Ok(audit_log)
+    }
+
+    pub fn append(&self, record: AuditRecord) {
+        let mut state = self.state.lock().expect("Audit trail lock poisoned");
+
+        // Another process may have appended entries, which the new one must
+        // be chained to
+        if let Err(e) = state.file.lock() {
+            error!("Failed to lock audit trail: {}", e);
+        }
+        if let Err(e) = state.read_new_entries() {
+            error!("{}", e);
+        }
+
+        let (seq, prev_hash) = match &state.last {
+            Some((last_seq, last_hash)) => (last_seq + 1, last_hash.clone()),
+            None => (1, GENESIS_HASH.to_string()),
+        };
+        let mut entry = AuditEntry {
+            seq,
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            action: record.action,
+            actor: record.actor,
+            source_ip: record.source_ip,
+            voucher_ids: record.voucher_ids,
+            voucher_codes: record.voucher_codes,
+            unused_voucher_ids: record.unused_voucher_ids,
+            parameters: record.parameters,
+            outcome: record.outcome,
+            error: record.error,
+            prev_hash,
+            hash: String::new(),
+        };
+        entry.hash = entry.compute_hash();
+
+        let mut line = serde_json::to_string(&entry).expect("Audit entry is serializable");
+        // Ends what is left of an interrupted write, which becomes a
+        // malformed line rather than taking the new entry with it
+        if state
+            .file
+            .metadata()
+            .is_ok_and(|metadata| metadata.len() > state.read_len)
+        {
+            line.insert(0, '\n');
+        }
+        if let Err(e) = writeln!(state.file, "{line}").and_then(|_| state.file.sync_data()) {
+            error!("Failed to persist audit entry {}: {}", seq, e);
+        }
+        // The next entry is chained to this one only once it is in the file
+        if let Err(e) = state.read_new_entries() {
+            error!("{}", e);
+        }
+        let _ = state.file.unlock();
+    }
+
+    /// One page of the matching entries, newest first. Only the entries up
+    /// to the end of the page are held while the trail is read.
+    pub fn query(&self, query: &AuditQuery) -> AuditPage {
+        let offset = query.offset.unwrap_or(0);
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+
+        let mut total = 0;
+        let mut newest = VecDeque::new();
+        let read = self.scan(|_, entry| {
+            if let Some(entry) = entry
+                && Self::matches(&entry, query)
+            {
+                total += 1;
+                if newest.len() == offset.saturating_add(limit) {
+                    newest.pop_front();
+                }
+                newest.push_back(entry);
+            }
+        });
+        if let Err(e) = read {
+            error!("{}", e);
+        }
+
+        AuditPage {
+            total,
+            data: newest.into_iter().rev().skip(offset).take(limit).collect(),
+            offset,
+            limit,
+        }
+    }
+
+    fn matches(entry: &AuditEntry, query: &AuditQuery) -> bool {
+        if query.action.is_some_and(|action| action != entry.action)
+            || query
+                .outcome
+                .is_some_and(|outcome| outcome != entry.outcome)
+        {
+            return false;
+        }
+        if let Some(actor_type) = &query.actor_type
+            && !entry.actor.kind().eq_ignore_ascii_case(actor_type)
+        {
+            return false;
+        }
+        if let Some(actor) = &query.actor
+            && !entry
+                .actor
+                .id()
+                .is_some_and(|id| id.contains(actor.as_str()))
+        {
+            return false;
+        }
+        if let Some(ip) = &query.source_ip
+            && entry.source_ip.as_deref() != Some(ip.as_str())
+        {
+            return false;
+        }
+        if let Some(voucher) = &query.voucher
+            && !entry.voucher_ids.contains(voucher)
+            && !entry.voucher_codes.contains(voucher)
+        {
+            return false;
+        }
+        if query.since.is_some() || query.until.is_some() {
+            let Ok(timestamp) = DateTime::parse_from_rfc3339(&entry.timestamp) else {
+                warn!("Audit entry {} has an invalid timestamp", entry.seq);
+                return false;
+            };
+            if query.since.is_some_and(|since| timestamp < since)
+                || query.until.is_some_and(|until| timestamp > until)
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Every entry, oldest first.
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        let mut entries = Vec::new();
+        if let Err(e) = self.scan(|_, entry| entries.extend(entry)) {
+            error!("{}", e);
+        }
+        entries
+    }
+
+    /// Entries recorded at or after `since`, oldest first. Only those are
+    /// kept in memory while the trail is read.
+    pub fn entries_since(&self, since: DateTime<Utc>) -> Vec<AuditEntry> {
+        let mut entries = Vec::new();
+        let read = self.scan(|_, entry| {
+            if let Some(entry) = entry
+                && DateTime::parse_from_rfc3339(&entry.timestamp).is_ok_and(|at| at >= since)
+            {
+                entries.push(entry);
+            }
+        });
+        if let Err(e) = read {
+            error!("{}", e);
+        }
+        entries
+    }
+
+    /// Recomputes the hash chain and reports the first entry that does not
+    /// match, along with the lines that are not entries.
+    pub fn verify(&self) -> AuditVerification {
+        let mut prev_hash = GENESIS_HASH.to_string();
+        let mut verification = AuditVerification {
+            valid: false,
+            entries: 0,
+            first_invalid_seq: None,
+            malformed_lines: Vec::new(),
+            error: None,
+        };
+        let read = self.scan(|line, entry| {
+            let Some(entry) = entry else {
+                verification.malformed_lines.push(line);
+                return;
+            };
+            verification.entries += 1;
+            if verification.first_invalid_seq.is_none()
+                && (entry.prev_hash != prev_hash || entry.compute_hash() != entry.hash)
+            {
+                verification.first_invalid_seq = Some(entry.seq);
+            }
+            prev_hash = entry.hash;
+        });
+        verification.error = read.err();
+        verification.valid = verification.first_invalid_seq.is_none()
+            && verification.malformed_lines.is_empty()
+            && verification.error.is_none();
+        verification
+    }
+
+    /// Reads the trail from the start, passing each line number along with
+    /// the entry, `None` for a malformed line. The trail is read through its
+    /// own handle so that appends only wait for the file lock.
+    fn scan(&self, visit: impl FnMut(u64, Option<AuditEntry>)) -> Result<(), String> {
+        let file = File::open(&self.path)
+            .map_err(|e| format!("Failed to open audit trail {}: {e}", self.path.display()))?;
+        file.lock_shared()
+            .map_err(|e| format!("Failed to lock audit trail: {e}"))?;
+        let read = read_lines(&file, 1, visit);
+        let _ = file.unlock();
+        read.map(|_| ())
+            .map_err(|e| format!("Failed to read audit trail: {e}"))
+    }
+}
+
+impl AuditState {
+    /// Follows the entries appended to the file since it was last read.
+    fn read_new_entries(&mut self) -> Result<(), String> {
+        let mut file = self
+            .file
+            .try_clone()
+            .map_err(|e| format!("Failed to read audit trail: {e}"))?;
+        file.seek(SeekFrom::Start(self.read_len))
+            .map_err(|e| format!("Failed to read audit trail: {e}"))?;
+        let mut last = self.last.take();
+        let read = read_lines(&file, self.read_lines + 1, |line, entry| match entry {
+            Some(entry) => last = Some((entry.seq, entry.hash)),
+            None => warn!("Skipping malformed audit trail line {}", line),
+        });
+        self.last = last;
+        let (len, lines) = read.map_err(|e| format!("Failed to read audit trail: {e}"))?;
+        self.read_len += len;
+        self.read_lines += lines;
+        Ok(())
+    }
+}
+
+/// Reads the complete lines of `file` from its current position, which is
+/// line `first_line` of the trail, and returns the bytes and lines read. A
+/// line being written is left for the next read.
+fn read_lines(
+    file: &File,
+    first_line: u64,
+    mut visit: impl FnMut(u64, Option<AuditEntry>),
+) -> io::Result<(u64, u64)> {
+    let mut reader = BufReader::new(file);
+    let (mut len, mut lines) = (0, 0);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = reader.read_line(&mut line)?;
+        if read == 0 || !line.ends_with('\n') {
+            break;
+        }
+        len += read as u64;
+        lines += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let number = first_line + lines - 1;
+        match serde_json::from_str::<AuditEntry>(&line) {
+            Ok(entry) => visit(number, Some(entry)),
+            Err(e) => {
+                debug!("Audit trail line {} is malformed: {}", number, e);
+                visit(number, None);
+            }
+        }
+    }
+    Ok((len, lines))
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,213 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+/// Backend of the UniFi Voucher Manager. Without a command, runs the server.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(flatten)]
+    pub config: ConfigArgs,
+    /// Format of the command output
+    #[arg(long, short = 'o', value_enum, default_value_t = OutputFormat::Table, global = true, help_heading = "Output")]
+    pub output: OutputFormat,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the HTTP server (the default)
+    Serve,
+    /// Manage the vouchers of the controller
+    #[command(subcommand)]
+    Vouchers(VoucherCommand),
+    /// Inspect and rotate the rolling voucher pools
+    #[command(subcommand)]
+    Rolling(RollingCommand),
+    /// Show the voucher tiers
+    #[command(subcommand)]
+    Tiers(TierCommand),
+    /// Validate the configuration
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Check the connection to the UniFi controller
+    #[command(subcommand)]
+    Controller(ControllerCommand),
+    /// Diagnose the configuration and the connection to the controller,
+    /// printing a report safe to attach to a support ticket
+    Doctor,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum VoucherCommand {
+    /// List the vouchers, newest first
+    List {
+        /// Only list expired vouchers
+        #[arg(long)]
+        expired: bool,
+        /// Only list vouchers whose name contains this text
+        #[arg(long, value_name = "TEXT")]
+        name: Option<String>,
+    },
+    /// Create vouchers, from a tier or with explicit limits
+    Create {
+        /// Number of vouchers to create
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        count: u32,
+        /// Name (note) of the vouchers
+        #[arg(long)]
+        name: String,
+        /// Tier whose duration and limits are used, see `tiers list`
+        #[arg(long, value_name = "ID", conflicts_with_all = ["minutes", "download_kbps", "upload_kbps", "data_limit_mb"])]
+        tier: Option<String>,
+        /// Validity once activated, in minutes
+        #[arg(long, required_unless_present = "tier")]
+        minutes: Option<u64>,
+        /// Number of guests that can use each voucher, unlimited when unset
+        #[arg(long)]
+        guests: Option<u64>,
+        /// Download speed limit, unlimited when unset
+        #[arg(long, value_name = "KBPS")]
+        download_kbps: Option<u64>,
+        /// Upload speed limit, unlimited when unset
+        #[arg(long, value_name = "KBPS")]
+        upload_kbps: Option<u64>,
+        /// Data usage limit, unlimited when unset
+        #[arg(long, value_name = "MB")]
+        data_limit_mb: Option<u64>,
+    },
+    /// Delete vouchers by id
+    Delete {
+        #[arg(required = true, value_name = "ID")]
+        ids: Vec<String>,
+    },
+    /// Delete the expired vouchers
+    Purge {
+        /// Only delete expired rolling vouchers
+        #[arg(long)]
+        rolling: bool,
+        /// Only delete the expired vouchers of this rolling pool
+        #[arg(long, value_name = "NAME", requires = "rolling")]
+        pool: Option<String>,
+        /// List the vouchers that would be deleted without deleting them
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum RollingCommand {
+    /// Show the unused vouchers and current code of each pool
+    Status {
+        /// Only show this pool
+        #[arg(long, value_name = "NAME")]
+        pool: Option<String>,
+    },
+    /// Top up a pool to its minimum of unused vouchers
+    Rotate {
+        /// Pool to rotate, the default pool when unset
+        #[arg(long, value_name = "NAME")]
+        pool: Option<String>,
+        /// Delete the current unused vouchers first, e.g. after a code leaked
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TierCommand {
+    /// List the tiers of the voucher configuration
+    List,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Load every configuration file and show the effective settings
+    Check,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ControllerCommand {
+    /// Log in to the controller and measure its latency
+    Test,
+    /// Show the fingerprints of the certificate the controller presents, to pin it
+    Fingerprint,
+}
+
+/// Flags overriding the configuration file and environment variables.
+#[derive(Debug, Clone, Default, Args)]
+#[command(next_help_heading = "Configuration")]
+pub struct ConfigArgs {
+    /// TOML configuration file, `/app/config/backend.toml` is used when it exists
+    #[arg(
+        long,
+        short = 'c',
+        env = "BACKEND_CONFIG",
+        value_name = "PATH",
+        global = true
+    )]
+    pub config: Option<PathBuf>,
+    /// Treat an invalid timezone and unknown configuration keys as errors
+    #[arg(long, global = true)]
+    pub strict: bool,
+    /// URL of the UniFi controller
+    #[arg(long, value_name = "URL", global = true)]
+    pub controller_url: Option<String>,
+    /// UniFi site to manage
+    #[arg(long, value_name = "ID", global = true)]
+    pub site_id: Option<String>,
+    /// Address the HTTP server binds to
+    #[arg(long, value_name = "HOST", global = true)]
+    pub bind_host: Option<String>,
+    /// Port the HTTP server binds to
+    #[arg(long, value_name = "PORT", global = true)]
+    pub bind_port: Option<String>,
+    /// Directory of the state kept by the backend
+    #[arg(long, value_name = "PATH", global = true)]
+    pub data_dir: Option<String>,
+    /// Directory of the log files, empty to log to the console only
+    #[arg(long, value_name = "PATH", global = true)]
+    pub log_dir: Option<String>,
+    /// Format of the logs
+    #[arg(long, value_name = "text|json", global = true)]
+    pub log_format: Option<String>,
+    /// Timezone used to display dates, e.g. Europe/Paris
+    #[arg(long, value_name = "TZ", global = true)]
+    pub timezone: Option<String>,
+}
+
+impl ConfigArgs {
+    /// Values set on the command line as `(configuration key, flag, value)`.
+    pub fn overrides(&self) -> Vec<(&'static str, &'static str, String)> {
+        let flags = [
+            (
+                "unifi.controller_url",
+                "--controller-url",
+                &self.controller_url,
+            ),
+            ("unifi.site_id", "--site-id", &self.site_id),
+            ("server.bind_host", "--bind-host", &self.bind_host),
+            ("server.bind_port", "--bind-port", &self.bind_port),
+            ("server.data_dir", "--data-dir", &self.data_dir),
+            ("log.dir", "--log-dir", &self.log_dir),
+            ("log.format", "--log-format", &self.log_format),
+            ("timezone", "--timezone", &self.timezone),
+        ];
+        let mut overrides: Vec<_> = flags
+            .into_iter()
+            .filter_map(|(key, flag, value)| value.clone().map(|v| (key, flag, v)))
+            .collect();
+        if self.strict {
+            overrides.push(("strict", "--strict", "true".to_string()));
+        }
+        overrides
+    }
+}
@@ -0,0 +1,776 @@
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::Serialize;
+use tokio::net::lookup_host;
+use tracing::warn;
+
+use crate::{
+    audit::{AUDIT_LOG, Actor, AuditAction, AuditLog, AuditRecord},
+    cli::{
+        Command, ConfigCommand, ControllerCommand, OutputFormat, RollingCommand, TierCommand,
+        VoucherCommand,
+    },
+    doctor::{CheckStatus, describe_certificate, diagnose},
+    environment::{ENVIRONMENT, Environment},
+    handlers::purge_vouchers,
+    models::{CreateVoucherRequest, Voucher, VoucherStatus},
+    output::Output,
+    scheduler::Scheduler,
+    tls,
+    unifi_api::{UNIFI_API, UnifiAPI, client},
+    voucher_config::{ConfigSource, RollingVoucherConfig, VOUCHER_CONFIG, VoucherConfig},
+};
+
+const FINGERPRINT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const VOUCHER_HEADERS: [&str; 8] = [
+    "ID", "CODE", "NAME", "CREATED", "EXPIRES", "MINUTES", "GUESTS", "STATUS",
+];
+
+/// Runs an admin command against the controller, without the HTTP server,
+/// and prints its output.
+pub async fn run(command: Command, format: OutputFormat) -> Result<(), String> {
+    let output = execute(command).await?;
+    output.print(format)?;
+    match output.failure() {
+        Some(failure) => Err(failure.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Runs an admin command and returns its output, without printing it.
+pub async fn execute(command: Command) -> Result<Output, String> {
+    let environment = ENVIRONMENT.get().expect("Environment not set");
+    Ok(match command {
+        Command::Serve => unreachable!("The server is not an admin command"),
+        Command::Vouchers(command) => vouchers(environment, command).await?,
+        Command::Rolling(RollingCommand::Status { pool }) => {
+            rolling_status(environment, pool).await?
+        }
+        Command::Rolling(RollingCommand::Rotate { pool, force }) => {
+            open_audit_log(environment);
+            rolling_rotate(pool, force).await?
+        }
+        Command::Tiers(TierCommand::List) => tiers()?,
+        Command::Config(ConfigCommand::Check) => config_check(environment).await?,
+        Command::Controller(ControllerCommand::Test) => controller_test(environment).await?,
+        Command::Controller(ControllerCommand::Fingerprint) => {
+            controller_fingerprint(environment).await?
+        }
+        Command::Doctor => doctor(environment).await,
+    })
+}
+
+async fn vouchers(environment: &Environment, command: VoucherCommand) -> Result<Output, String> {
+    match command {
+        VoucherCommand::List { expired, name } => {
+            let client = connect().await?;
+            let mut vouchers = fetch_vouchers(client).await?;
+            vouchers.retain(|v| {
+                (!expired || v.expired) && name.as_ref().is_none_or(|name| v.name.contains(name))
+            });
+            vouchers.sort_by_key(|voucher| std::cmp::Reverse(voucher.created_at_epoch));
+
+            let mut output = Output::new(&vouchers, &VOUCHER_HEADERS);
+            voucher_rows(&mut output, &vouchers);
+            Ok(output.summary(format!("{} vouchers", vouchers.len())))
+        }
+        VoucherCommand::Create {
+            count,
+            name,
+            tier,
+            minutes,
+            guests,
+            download_kbps,
+            upload_kbps,
+            data_limit_mb,
+        } => {
+            let request = match tier {
+                Some(id) => {
+                    let tier = voucher_config()?
+                        .tier(&id)
+                        .ok_or_else(|| format!("Unknown tier '{id}', see `tiers list`"))?;
+                    CreateVoucherRequest {
+                        count,
+                        name,
+                        authorized_guest_limit: guests,
+                        time_limit_minutes: (tier.duration_hours * 60.0).round() as u64,
+                        data_usage_limit_mbytes: tier.data_limit_mb,
+                        rx_rate_limit_kbps: tier.download_mbps.map(|mbps| mbps * 1000),
+                        tx_rate_limit_kbps: tier.upload_mbps.map(|mbps| mbps * 1000),
+                    }
+                }
+                None => CreateVoucherRequest {
+                    count,
+                    name,
+                    authorized_guest_limit: guests,
+                    time_limit_minutes: minutes.expect("--minutes is required without --tier"),
+                    data_usage_limit_mbytes: data_limit_mb,
+                    rx_rate_limit_kbps: download_kbps,
+                    tx_rate_limit_kbps: upload_kbps,
+                },
+            };
+
+            open_audit_log(environment);
+            let client = connect().await?;
+            let audit = AuditRecord::new(AuditAction::Create, operator())
+                .parameters(serde_json::to_value(&request).unwrap_or_default());
+            let vouchers = match client.create_voucher(request).await {
+                Ok(response) => {
+                    audit.vouchers(&response.vouchers).record();
+                    response.vouchers
+                }
+                Err(e) => {
+                    audit.failed(&e).record();
+                    return Err(format!("Failed to create vouchers: {e}"));
+                }
+            };
+
+            let mut output = Output::new(&vouchers, &VOUCHER_HEADERS);
+            voucher_rows(&mut output, &vouchers);
+            let output =
+                output.summary(format!("Created {} of {} vouchers", vouchers.len(), count));
+            if vouchers.len() < count as usize {
+                return Ok(output.failed(format!(
+                    "Only {} of {} vouchers were created",
+                    vouchers.len(),
+                    count
+                )));
+            }
+            Ok(output)
+        }
+        VoucherCommand::Delete { ids } => {
+            open_audit_log(environment);
+            let client = connect().await?;
+            let vouchers = fetch_vouchers(client).await?;
+            let unknown: Vec<&str> = ids
+                .iter()
+                .filter(|id| !vouchers.iter().any(|v| &v.id == *id))
+                .map(String::as_str)
+                .collect();
+            if !unknown.is_empty() {
+                return Err(format!("Unknown voucher ids: {}", unknown.join(", ")));
+            }
+
+            let selected: Vec<Voucher> = vouchers
+                .into_iter()
+                .filter(|v| ids.contains(&v.id))
+                .collect();
+            delete(selected, AuditRecord::new(AuditAction::Delete, operator())).await
+        }
+        VoucherCommand::Purge {
+            rolling,
+            pool,
+            dry_run,
+        } => {
+            let pool = match pool {
+                Some(name) => Some(rolling_pool(Some(&name))?),
+                None => None,
+            };
+            // Rolling vouchers are recognized by the prefixes of their pools
+            if rolling {
+                voucher_config()?;
+            }
+            if !dry_run {
+                open_audit_log(environment);
+            }
+            let client = connect().await?;
+            let (scope, selected) = if rolling {
+                (
+                    "expired_rolling",
+                    client.get_expired_rolling_vouchers(pool).await,
+                )
+            } else {
+                ("expired", client.get_expired_vouchers().await)
+            };
+            let selected = selected.map_err(|e| format!("Failed to fetch vouchers: {e}"))?;
+
+            if dry_run {
+                let mut output = Output::new(Deletion::new(true, &selected, 0), &VOUCHER_HEADERS);
+                voucher_rows(&mut output, &selected);
+                return Ok(output.summary(format!("Would delete {} vouchers", selected.len())));
+            }
+            let audit =
+                AuditRecord::new(AuditAction::Purge, operator()).parameters(serde_json::json!({
+                    "scope": scope,
+                    "pool": pool.map(|pool| pool.name.as_str()),
+                    "trigger": "cli",
+                }));
+            delete(selected, audit).await
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Deletion<'v> {
+    dry_run: bool,
+    requested: usize,
+    deleted: usize,
+    vouchers: &'v [Voucher],
+}
+
+impl<'v> Deletion<'v> {
+    fn new(dry_run: bool, vouchers: &'v [Voucher], deleted: usize) -> Self {
+        Self {
+            dry_run,
+            requested: vouchers.len(),
+            deleted,
+            vouchers,
+        }
+    }
+}
+
+async fn delete(vouchers: Vec<Voucher>, audit: AuditRecord) -> Result<Output, String> {
+    let response = purge_vouchers(Ok(vouchers.clone()), audit)
+        .await
+        .map_err(|e| format!("Failed to delete vouchers: {e}"))?;
+
+    let deleted = response.data.len();
+    let mut output = Output::new(Deletion::new(false, &vouchers, deleted), &VOUCHER_HEADERS);
+    voucher_rows(&mut output, &vouchers);
+    let output = output.summary(format!(
+        "Deleted {} of {} vouchers",
+        deleted,
+        vouchers.len()
+    ));
+    if deleted < vouchers.len() {
+        return Ok(output.failed(format!(
+            "Only {} of {} vouchers were deleted",
+            deleted,
+            vouchers.len()
+        )));
+    }
+    Ok(output)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PoolStatus<'p> {
+    name: &'p str,
+    prefix: String,
+    enabled: bool,
+    minimum: u32,
+    unused: usize,
+    /// Newest unused voucher, the one handed out to guests
+    current: Option<&'p Voucher>,
+    rotated_at: Option<String>,
+}
+
+async fn rolling_status(environment: &Environment, pool: Option<String>) -> Result<Output, String> {
+    let config = voucher_config()?;
+    let pools: Vec<&RollingVoucherConfig> = match pool {
+        Some(name) => vec![rolling_pool(Some(&name))?],
+        None => config.pools.iter().collect(),
+    };
+    let client = connect().await?;
+    let vouchers = fetch_vouchers(client).await?;
+
+    let now = Utc::now();
+    let statuses: Vec<PoolStatus> = pools
+        .into_iter()
+        .map(|pool| {
+            let unused: Vec<&Voucher> = vouchers
+                .iter()
+                .filter(|v| client.is_unused_rolling_voucher(pool, v))
+                .collect();
+            PoolStatus {
+                name: &pool.name,
+                prefix: pool.prefix(),
+                enabled: pool.enabled,
+                minimum: pool.min_rolling_vouchers,
+                unused: unused.len(),
+                current: unused
+                    .iter()
+                    .max_by_key(|voucher| voucher.created_at_epoch)
+                    .copied(),
+                rotated_at: pool.rotation_start(now, environment.timezone).map(|start| {
+                    start
+                        .with_timezone(&environment.timezone)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string()
+                }),
+            }
+        })
+        .collect();
+
+    let mut output = Output::new(
+        &statuses,
+        &[
+            "POOL",
+            "PREFIX",
+            "ENABLED",
+            "UNUSED",
+            "MINIMUM",
+            "CURRENT CODE",
+            "ROTATED AT",
+        ],
+    );
+    for status in &statuses {
+        output.row([
+            status.name.to_string(),
+            status.prefix.clone(),
+            status.enabled.to_string(),
+            status.unused.to_string(),
+            status.minimum.to_string(),
+            status.current.map(|v| v.code.clone()).unwrap_or_default(),
+            status.rotated_at.clone().unwrap_or_default(),
+        ]);
+    }
+    Ok(output)
+}
+
+async fn rolling_rotate(pool: Option<String>, force: bool) -> Result<Output, String> {
+    let pool = rolling_pool(pool.as_deref())?;
+    if !pool.enabled {
+        return Err(format!("Rolling pool '{}' is disabled", pool.name));
+    }
+    let client = connect().await?;
+
+    let mut retired = Vec::new();
+    if force {
+        retired = client
+            .get_all_unused_rolling_vouchers(pool)
+            .await
+            .map_err(|e| format!("Failed to fetch vouchers: {e}"))?;
+        let audit =
+            AuditRecord::new(AuditAction::Rotate, operator()).parameters(serde_json::json!({
+                "trigger": "cli",
+                "pool": pool.name,
+                "force": true,
+            }));
+        purge_vouchers(Ok(retired.clone()), audit)
+            .await
+            .map_err(|e| format!("Failed to delete the unused rolling vouchers: {e}"))?;
+    }
+
+    let audit = AuditRecord::new(AuditAction::Rotate, operator())
+        .parameters(serde_json::json!({ "trigger": "cli", "pool": pool.name }));
+    let created = match client.top_up_rolling_vouchers(pool).await {
+        Ok(created) => {
+            if !created.is_empty() {
+                audit.vouchers(&created).record();
+            }
+            created
+        }
+        Err(e) => {
+            audit.failed(&e).record();
+            return Err(format!(
+                "Failed to top up rolling pool '{}': {e}",
+                pool.name
+            ));
+        }
+    };
+
+    let mut output = Output::new(
+        serde_json::json!({ "pool": pool.name, "retired": retired, "created": created }),
+        &["ACTION", "ID", "CODE", "NAME"],
+    );
+    for (action, voucher) in retired
+        .iter()
+        .map(|v| ("retired", v))
+        .chain(created.iter().map(|v| ("created", v)))
+    {
+        output.row([action, &voucher.id, &voucher.code, &voucher.name]);
+    }
+    let output = output.summary(match created.len() {
+        0 => format!(
+            "Rolling pool '{}' already has its minimum of unused vouchers",
+            pool.name
+        ),
+        n => format!("Created {} rolling vouchers in pool '{}'", n, pool.name),
+    });
+    if force && created.is_empty() {
+        return Ok(output.failed(format!(
+            "No rolling voucher was created in pool '{}' after retiring its vouchers",
+            pool.name
+        )));
+    }
+    Ok(output)
+}
+
+fn tiers() -> Result<Output, String> {
+    let config = voucher_config()?;
+    let mut output = Output::new(
+        &config.tiers,
+        &[
+            "ID",
+            "NAME",
+            "HOURS",
+            "DOWNLOAD MBPS",
+            "UPLOAD MBPS",
+            "DATA LIMIT MB",
+            "DESCRIPTION",
+        ],
+    );
+    let limit =
+        |value: Option<u64>| value.map_or_else(|| "unlimited".to_string(), |v| v.to_string());
+    for tier in &config.tiers {
+        output.row([
+            tier.id.clone(),
+            tier.name.clone(),
+            tier.duration_hours.to_string(),
+            limit(tier.download_mbps),
+            limit(tier.upload_mbps),
+            limit(tier.data_limit_mb),
+            tier.description.clone(),
+        ]);
+    }
+    Ok(output)
+}
+
+/// Loads every configuration file the server reads at startup. The backend
+/// configuration itself was already validated before the command runs.
+async fn config_check(environment: &Environment) -> Result<Output, String> {
+    let mut settings: Vec<(String, String)> = vec![
+        (
+            "unifi.controller_url".into(),
+            environment.unifi_controller_url.clone(),
+        ),
+        ("unifi.site_id".into(), environment.unifi_site_id.clone()),
+        ("unifi.username".into(), environment.unifi_username.clone()),
+        ("unifi.password".into(), "<redacted>".into()),
+        (
+            "unifi.totp_secret".into(),
+            match environment.unifi_totp {
+                Some(_) => "<redacted>".into(),
+                None => "not set".into(),
+            },
+        ),
+        (
+            "unifi.has_valid_cert".into(),
+            environment.unifi_has_valid_cert.to_string(),
+        ),
+        (
+            "unifi.ca_bundle".into(),
+            environment.unifi_ca_bundle.as_ref().map_or_else(
+                || "not set".to_string(),
+                |bundle| {
+                    format!(
+                        "{} ({} certificates)",
+                        bundle.path.display(),
+                        bundle.certificates.len()
+                    )
+                },
+            ),
+        ),
+        (
+            "unifi.pinned_fingerprints".into(),
+            environment.unifi_certificate_pins.len().to_string(),
+        ),
+        (
+            "server.bind".into(),
+            match (environment.backend_tcp_enabled, &environment.backend_tls) {
+                (false, _) => "disabled".to_string(),
+                (true, Some(_)) => format!(
+                    "https://{}:{}",
+                    environment.backend_bind_host, environment.backend_bind_port
+                ),
+                (true, None) => format!(
+                    "http://{}:{}",
+                    environment.backend_bind_host, environment.backend_bind_port
+                ),
+            },
+        ),
+        (
+            "server.tls_cert".into(),
+            environment.backend_tls.as_ref().map_or_else(
+                || "not set".to_string(),
+                |tls| tls.cert.display().to_string(),
+            ),
+        ),
+        (
+            "server.unix_socket".into(),
+            environment.backend_unix_socket.as_ref().map_or_else(
+                || "not set".to_string(),
+                |path| {
+                    format!(
+                        "{} (mode {:o})",
+                        path.display(),
+                        environment.backend_unix_socket_mode
+                    )
+                },
+            ),
+        ),
+        (
+            "server.trusted_proxies".into(),
+            if environment.trusted_proxies.is_empty() {
+                "none".to_string()
+            } else {
+                let proxies: Vec<String> = environment
+                    .trusted_proxies
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                proxies.join(", ")
+            },
+        ),
+        (
+            "server.data_dir".into(),
+            environment.data_dir.display().to_string(),
+        ),
+        (
+            "log.dir".into(),
+            environment.log_dir.as_ref().map_or_else(
+                || "console only".to_string(),
+                |dir| dir.display().to_string(),
+            ),
+        ),
+        ("timezone".into(), environment.timezone.to_string()),
+    ];
+    let mut problems = Vec::new();
+
+    match VoucherConfig::try_new() {
+        Ok(config) => {
+            match &config.source {
+                ConfigSource::File { path } => {
+                    settings.push(("vouchers.file".into(), path.clone()))
+                }
+                ConfigSource::Defaults { reason } => {
+                    settings.push(("vouchers.file".into(), "built-in defaults".into()));
+                    problems.push(format!("Voucher configuration: {reason}"));
+                }
+            }
+            settings.push(("vouchers.tiers".into(), config.tiers.len().to_string()));
+            for pool in &config.pools {
+                settings.push((
+                    format!("vouchers.pools.{}", pool.name),
+                    format!(
+                        "{}, {} minimum, prefix {}",
+                        if pool.enabled { "enabled" } else { "disabled" },
+                        pool.min_rolling_vouchers,
+                        pool.prefix()
+                    ),
+                ));
+            }
+        }
+        Err(e) => problems.push(format!("Voucher configuration: {e}")),
+    }
+
+    match Scheduler::try_new(environment.timezone) {
+        Ok(scheduler) => {
+            for job in scheduler.statuses().await {
+                settings.push((
+                    format!("jobs.{}", job.name),
+                    format!(
+                        "{} ({})",
+                        job.schedule,
+                        if job.enabled { "enabled" } else { "disabled" }
+                    ),
+                ));
+            }
+        }
+        Err(e) => problems.push(format!("Scheduler configuration: {e}")),
+    }
+
+    let mut output = Output::settings(serde_json::json!({
+        "valid": problems.is_empty(),
+        "problems": problems,
+        "settings": settings
+            .iter()
+            .map(|(key, value)| (key.clone(), serde_json::json!(value)))
+            .collect::<serde_json::Map<_, _>>(),
+    }));
+    for (key, value) in &settings {
+        output.row([key, value]);
+    }
+    if problems.is_empty() {
+        return Ok(output.summary("Configuration is valid".to_string()));
+    }
+    Ok(output.failed(problems.join("\n")))
+}
+
+async fn controller_test(environment: &Environment) -> Result<Output, String> {
+    let started = Instant::now();
+    let client = connect().await?;
+    let login = started.elapsed();
+    let latency = client
+        .probe()
+        .await
+        .map_err(|e| format!("Failed to reach the controller: {e}"))?;
+    let vouchers = fetch_vouchers(client).await?;
+
+    let mut output = Output::settings(serde_json::json!({
+        "controller": environment.unifi_controller_url,
+        "site": environment.unifi_site_id,
+        "loginMs": login.as_millis() as u64,
+        "latencyMs": latency.as_millis() as u64,
+        "session": client.session_info(),
+        "vouchers": vouchers.len(),
+    }));
+    output.row(["controller", &environment.unifi_controller_url]);
+    output.row(["site", &environment.unifi_site_id]);
+    output.row(["login", &format!("ok in {} ms", login.as_millis())]);
+    output.row(["latency", &format!("{} ms", latency.as_millis())]);
+    output.row(["vouchers", &vouchers.len().to_string()]);
+    Ok(output)
+}
+
+/// Connects to the controller without logging in and shows the certificate
+/// chain it presents, for trust on first use.
+async fn controller_fingerprint(environment: &Environment) -> Result<Output, String> {
+    let url = Url::parse(&environment.unifi_controller_url)
+        .map_err(|e| format!("Invalid controller URL: {e}"))?;
+    if url.scheme() != "https" {
+        return Err("The controller URL is not https, there is no certificate to pin".to_string());
+    }
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Err("The controller URL has no host".to_string());
+    };
+    // IPv6 addresses are bracketed in URLs
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let address = lookup_host((host, port))
+        .await
+        .map_err(|e| format!("Failed to resolve {host}: {e}"))?
+        .next()
+        .ok_or_else(|| format!("{host} resolves to no address"))?;
+    let (chain, verification) =
+        tls::inspect(environment, host, address, FINGERPRINT_TIMEOUT).await?;
+
+    let certificates: Vec<serde_json::Value> =
+        chain.iter().map(|der| describe_certificate(der)).collect();
+    let mut output = Output::new(
+        serde_json::json!({
+            "trusted": verification.is_ok(),
+            "verificationError": verification.as_ref().err().map(|e| e.to_string()),
+            "chain": certificates,
+        }),
+        &["CERTIFICATE", "SUBJECT", "SHA-256", "SPKI SHA-256"],
+    );
+    for (index, certificate) in certificates.iter().enumerate() {
+        let position = if index == 0 {
+            "leaf".to_string()
+        } else {
+            format!("chain {index}")
+        };
+        output.row([
+            position.as_str(),
+            certificate["subject"].as_str().unwrap_or("unparsable"),
+            certificate["sha256"].as_str().unwrap_or_default(),
+            certificate["spkiSha256"].as_str().unwrap_or_default(),
+        ]);
+    }
+    let leaf = chain.first().map(tls::fingerprints);
+    let summary = match (verification, leaf.and_then(|leaf| leaf.public_key)) {
+        (Ok(()), _) => "The certificate is trusted with the current configuration".to_string(),
+        (Err(e), Some(spki)) => format!(
+            "The certificate is not trusted ({e}). After checking the fingerprint on the controller, pin it with UNIFI_SPKI_FINGERPRINTS={spki}"
+        ),
+        (Err(e), None) => format!("The certificate is not trusted ({e})"),
+    };
+    Ok(output.summary(summary))
+}
+
+async fn doctor(environment: &Environment) -> Output {
+    let report = diagnose(environment).await;
+    let mut output = Output::new(&report, &["CHECK", "STATUS", "MESSAGE"]);
+    for check in &report.checks {
+        output.row([check.name, check.status.as_str(), &check.message]);
+    }
+    let counts = [
+        CheckStatus::Pass,
+        CheckStatus::Warn,
+        CheckStatus::Fail,
+        CheckStatus::Skip,
+    ]
+    .map(|status| {
+        let count = report
+            .checks
+            .iter()
+            .filter(|check| check.status == status)
+            .count();
+        format!("{count} {}", status.as_str())
+    });
+    let summary = counts.join(", ");
+    if report.status == CheckStatus::Fail {
+        return output.failed(format!("Doctor found problems: {summary}"));
+    }
+    output.summary(summary)
+}
+
+fn voucher_rows(output: &mut Output, vouchers: &[Voucher]) {
+    for voucher in vouchers {
+        let status = match voucher.status {
+            VoucherStatus::Unused => "unused",
+            VoucherStatus::Active => "in use",
+            VoucherStatus::Expired => "expired",
+        };
+        let guests = match voucher.authorized_guest_limit.filter(|limit| *limit > 0) {
+            Some(limit) => format!("{}/{}", voucher.authorized_guest_count, limit),
+            None => voucher.authorized_guest_count.to_string(),
+        };
+        output.row([
+            voucher.id.clone(),
+            voucher.code.clone(),
+            voucher.name.clone(),
+            display_date(&voucher.created_at),
+            voucher
+                .expires_at
+                .as_deref()
+                .map(display_date)
+                .unwrap_or_default(),
+            voucher.time_limit_minutes.to_string(),
+            guests,
+            status.to_string(),
+        ]);
+    }
+}
+
+/// Table rendering of an RFC 3339 date, already in the configured timezone.
+fn display_date(value: &str) -> String {
+    DateTime::parse_from_rfc3339(value)
+        .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|_| value.to_string())
+}
+
+/// The operating system user running the command, as recorded in the audit
+/// trail.
+fn operator() -> Actor {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    Actor::Cli(user)
+}
+
+/// Opens the audit trail for commands that change vouchers. Appends lock the
+/// file, so entries stay chained while the server writes to it as well.
+fn open_audit_log(environment: &Environment) {
+    match AuditLog::try_new(&environment.data_dir) {
+        Ok(audit_log) => AUDIT_LOG.set(audit_log).expect("Failed to set audit trail"),
+        Err(e) => warn!("Audit trail disabled, failed to open it: {e}"),
+    }
+}
+
+fn voucher_config() -> Result<&'static VoucherConfig, String> {
+    if let Some(config) = VOUCHER_CONFIG.get() {
+        return Ok(config);
+    }
+    let config = VoucherConfig::try_new()
+        .map_err(|e| format!("Failed to load voucher configuration: {e}"))?;
+    Ok(VOUCHER_CONFIG.get_or_init(|| config))
+}
+
+fn rolling_pool(name: Option<&str>) -> Result<&'static RollingVoucherConfig, String> {
+    voucher_config()?
+        .pool(name)
+        .ok_or_else(|| format!("Unknown rolling pool '{}'", name.unwrap_or_default()))
+}
+
+/// Logs in to the controller once, without the retries of the server.
+async fn connect() -> Result<&'static UnifiAPI<'static>, String> {
+    let api = UnifiAPI::try_new()
+        .await
+        .map_err(|e| format!("Failed to connect to the UniFi controller: {e}"))?;
+    UNIFI_API.set(api).expect("Failed to set UnifiAPI");
+    client().map_err(|e| e.to_string())
+}
+
+async fn fetch_vouchers(client: &UnifiAPI<'_>) -> Result<Vec<Voucher>, String> {
+    Ok(client
+        .get_all_vouchers()
+        .await
+        .map_err(|e| format!("Failed to fetch vouchers: {e}"))?
+        .data)
+}
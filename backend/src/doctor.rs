@@ -0,0 +1,795 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::{StatusCode, Url, header::DATE};
+use serde::{Deserialize, Serialize, de::IgnoredAny};
+use serde_json::json;
+use tokio::net::{TcpStream, lookup_host};
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use utoipa::ToSchema;
+use x509_parser::{extensions::GeneralName, parse_x509_certificate};
+
+use crate::{
+    environment::Environment,
+    error::ApiError,
+    tls,
+    unifi_api::UnifiAPI,
+    voucher_config::{ConfigSource, VoucherConfig},
+};
+
+const PRINT_CONFIG_PATH: &str = "/app/frontend/public/print-config.json";
+const PRINT_SECTIONS: [&str; 7] = [
+    "logo",
+    "header",
+    "code",
+    "qr",
+    "details",
+    "additionalInfo",
+    "footer",
+];
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Certificates expiring sooner than this are reported
+const CERTIFICATE_WARNING_DAYS: i64 = 14;
+const CLOCK_SKEW_WARNING: i64 = 30;
+const CLOCK_SKEW_FAILURE: i64 = 300;
+const REDACTED: &str = "<redacted>";
+
+/// Verdict of a check, ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum CheckStatus {
+    Pass,
+    /// Not run because a check it depends on failed
+    Skip,
+    Warn,
+    Fail,
+}
+
+impl CheckStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::Skip => "skip",
+            Self::Warn => "warn",
+            Self::Fail => "fail",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub message: String,
+    #[serde(skip_serializing_if = "serde_json::Value::is_null")]
+    pub details: serde_json::Value,
+    pub duration_ms: u64,
+}
+
+/// Result of every check, safe to attach to a ticket: credentials are
+/// replaced by `<redacted>` wherever they appear.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DoctorReport {
+    /// Worst status of all checks
+    pub status: CheckStatus,
+    pub generated_at: DateTime<Utc>,
+    pub version: &'static str,
+    pub configuration: BTreeMap<&'static str, String>,
+    pub checks: Vec<Check>,
+}
+
+/// Diagnoses the configuration and the connection to the controller, one
+/// layer at a time so that the first failing check points at the cause.
+pub async fn diagnose(environment: &Environment) -> DoctorReport {
+    let mut doctor = Doctor {
+        environment,
+        checks: Vec::new(),
+    };
+
+    doctor
+        .run("voucherTiers", |_| async { voucher_tiers() })
+        .await;
+    doctor
+        .run("printConfig", |_| async { print_config() })
+        .await;
+
+    let target = doctor
+        .run("controllerUrl", |_| async { controller_url(environment) })
+        .await;
+    let addresses = match target {
+        Some(target) => doctor
+            .run("dns", |_| dns(target.clone()))
+            .await
+            .map(|a| (target, a)),
+        None => doctor.skip("dns", "controllerUrl"),
+    };
+    let connected = match &addresses {
+        Some((_, addresses)) => doctor.run("tcp", |_| tcp(addresses.clone())).await,
+        None => doctor.skip("tcp", "dns"),
+    };
+    match (&addresses, connected) {
+        (Some((target, _)), Some(address)) if target.tls => {
+            let target = target.clone();
+            doctor.run("tls", |env| tls(env, target, address)).await;
+        }
+        (Some(_), Some(_)) => {
+            doctor.checks.push(Check {
+                name: "tls",
+                status: CheckStatus::Skip,
+                message: "The controller is reached over plain HTTP".to_string(),
+                details: serde_json::Value::Null,
+                duration_ms: 0,
+            });
+        }
+        _ => {
+            doctor.skip::<()>("tls", "tcp");
+        }
+    }
+
+    // The controller must be reachable for the remaining checks
+    if doctor.failed("tcp") {
+        for name in ["clock", "login", "site", "permissions"] {
+            doctor.skip::<()>(name, "tcp");
+        }
+        return doctor.report();
+    }
+
+    doctor.run("clock", clock).await;
+    let client = doctor.run("login", login).await;
+    let Some(client) = client else {
+        doctor.skip::<()>("site", "login");
+        doctor.skip::<()>("permissions", "login");
+        return doctor.report();
+    };
+    let role = doctor.run("site", |env| site(env, client.clone())).await;
+    doctor
+        .run("permissions", |_| {
+            permissions(client.clone(), role.flatten())
+        })
+        .await;
+    // The session was only opened for the checks
+    client.logout().await;
+    doctor.report()
+}
+
+/// Outcome of a single check, with the value later checks depend on.
+struct Outcome<T> {
+    status: CheckStatus,
+    message: String,
+    details: serde_json::Value,
+    value: Option<T>,
+}
+
+impl<T> Outcome<T> {
+    fn pass(message: impl Into<String>, details: serde_json::Value, value: T) -> Self {
+        Self {
+            status: CheckStatus::Pass,
+            message: message.into(),
+            details,
+            value: Some(value),
+        }
+    }
+
+    fn warn(message: impl Into<String>, details: serde_json::Value, value: T) -> Self {
+        Self {
+            status: CheckStatus::Warn,
+            message: message.into(),
+            details,
+            value: Some(value),
+        }
+    }
+
+    fn fail(message: impl Into<String>, details: serde_json::Value) -> Self {
+        Self {
+            status: CheckStatus::Fail,
+            message: message.into(),
+            details,
+            value: None,
+        }
+    }
+}
+
+struct Doctor<'e> {
+    environment: &'e Environment,
+    checks: Vec<Check>,
+}
+
+impl<'e> Doctor<'e> {
+    async fn run<T, F, Fut>(&mut self, name: &'static str, check: F) -> Option<T>
+    where
+        F: FnOnce(&'e Environment) -> Fut,
+        Fut: Future<Output = Outcome<T>>,
+    {
+        let started = Instant::now();
+        let outcome = check(self.environment).await;
+        self.checks.push(Check {
+            name,
+            status: outcome.status,
+            message: outcome.message,
+            details: outcome.details,
+            duration_ms: started.elapsed().as_millis() as u64,
+        });
+        outcome.value
+    }
+
+    fn skip<T>(&mut self, name: &'static str, cause: &str) -> Option<T> {
+        self.checks.push(Check {
+            name,
+            status: CheckStatus::Skip,
+            message: format!("Skipped because the {cause} check failed"),
+            details: serde_json::Value::Null,
+            duration_ms: 0,
+        });
+        None
+    }
+
+    fn failed(&self, name: &str) -> bool {
+        self.checks
+            .iter()
+            .any(|check| check.name == name && check.status != CheckStatus::Pass)
+    }
+
+    fn report(self) -> DoctorReport {
+        let environment = self.environment;
+        let configuration = BTreeMap::from([
+            (
+                "unifi.controller_url",
+                environment.unifi_controller_url.clone(),
+            ),
+            ("unifi.site_id", environment.unifi_site_id.clone()),
+            ("unifi.username", REDACTED.to_string()),
+            ("unifi.password", REDACTED.to_string()),
+            (
+                "unifi.has_valid_cert",
+                environment.unifi_has_valid_cert.to_string(),
+            ),
+            (
+                "unifi.read_timeout_secs",
+                environment.unifi_read_timeout.as_secs().to_string(),
+            ),
+            (
+                "unifi.write_timeout_secs",
+                environment.unifi_write_timeout.as_secs().to_string(),
+            ),
+            ("timezone", environment.timezone.to_string()),
+        ]);
+        let mut report = DoctorReport {
+            status: self
+                .checks
+                .iter()
+                .map(|check| check.status)
+                .max()
+                .unwrap_or(CheckStatus::Pass),
+            generated_at: Utc::now(),
+            version: env!("CARGO_PKG_VERSION"),
+            configuration,
+            checks: self.checks,
+        };
+        redact(&mut report, environment);
+        report
+    }
+}
+
+/// Removes the credentials from every message, in case the controller or a
+/// library echoed them back.
+fn redact(report: &mut DoctorReport, environment: &Environment) {
+    let secrets: Vec<&str> = [
+        environment.unifi_password.as_str(),
+        environment.unifi_username.as_str(),
+    ]
+    .into_iter()
+    .filter(|secret| secret.len() >= 3)
+    .collect();
+
+    fn scrub(value: &mut serde_json::Value, secrets: &[&str]) {
+        match value {
+            serde_json::Value::String(s) => {
+                for secret in secrets {
+                    if s.contains(secret) {
+                        *s = s.replace(secret, REDACTED);
+                    }
+                }
+            }
+            serde_json::Value::Array(values) => values.iter_mut().for_each(|v| scrub(v, secrets)),
+            serde_json::Value::Object(map) => map.values_mut().for_each(|v| scrub(v, secrets)),
+            _ => {}
+        }
+    }
+
+    for check in &mut report.checks {
+        for secret in &secrets {
+            check.message = check.message.replace(secret, REDACTED);
+        }
+        scrub(&mut check.details, &secrets);
+    }
+    for value in report.configuration.values_mut() {
+        for secret in &secrets {
+            *value = value.replace(secret, REDACTED);
+        }
+    }
+}
+
+fn voucher_tiers() -> Outcome<()> {
+    let config = match VoucherConfig::try_new() {
+        Ok(config) => config,
+        Err(e) => return Outcome::fail(format!("Invalid rolling pools: {e}"), json!({})),
+    };
+    if let ConfigSource::Defaults { reason } = &config.source {
+        return Outcome::fail(reason.clone(), json!({ "source": config.source }));
+    }
+
+    let mut problems = Vec::new();
+    let mut ids = HashSet::new();
+    for tier in &config.tiers {
+        if !ids.insert(tier.id.as_str()) {
+            problems.push(format!("tier '{}' is defined twice", tier.id));
+        }
+        if tier.duration_hours <= 0.0 {
+            problems.push(format!(
+                "tier '{}' has a duration of {}h",
+                tier.id, tier.duration_hours
+            ));
+        }
+    }
+    let details = json!({
+        "source": config.source,
+        "tiers": config.tiers.iter().map(|tier| &tier.id).collect::<Vec<_>>(),
+        "rollingPools": config.pools.iter().map(|pool| &pool.name).collect::<Vec<_>>(),
+    });
+    if !problems.is_empty() {
+        return Outcome::fail(problems.join(", "), details);
+    }
+    if config.tiers.is_empty() {
+        return Outcome::warn(
+            "No tiers are defined, Quick Create has nothing to offer",
+            details,
+            (),
+        );
+    }
+    Outcome::pass(
+        format!(
+            "{} tiers and {} rolling pools",
+            config.tiers.len(),
+            config.pools.len()
+        ),
+        details,
+        (),
+    )
+}
+
+/// Schema of `print-config.json`, as read by the print page of the frontend.
+/// Only the settings the backend can check are read, the other sections
+/// must merely be present.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PrintConfig {
+    #[serde(rename = "logo")]
+    _logo: IgnoredAny,
+    #[serde(rename = "header")]
+    _header: IgnoredAny,
+    #[serde(rename = "footer")]
+    _footer: IgnoredAny,
+    qr_code: PrintQrCode,
+    layout: PrintLayout,
+    #[serde(rename = "additionalInfo")]
+    _additional_info: IgnoredAny,
+}
+
+#[derive(Debug, Deserialize)]
+struct PrintQrCode {
+    size: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct PrintLayout {
+    order: Vec<String>,
+}
+
+fn print_config() -> Outcome<()> {
+    let details = json!({ "path": PRINT_CONFIG_PATH });
+    let content = match fs::read_to_string(PRINT_CONFIG_PATH) {
+        Ok(content) => content,
+        Err(e) => {
+            return Outcome::warn(
+                format!(
+                    "Failed to read {PRINT_CONFIG_PATH}, printed vouchers use the default layout: {e}"
+                ),
+                details,
+                (),
+            );
+        }
+    };
+    let config: PrintConfig = match serde_json::from_str(&content) {
+        Ok(config) => config,
+        Err(e) => return Outcome::fail(format!("Invalid print configuration: {e}"), details),
+    };
+
+    let mut problems = Vec::new();
+    let unknown: Vec<&str> = config
+        .layout
+        .order
+        .iter()
+        .map(String::as_str)
+        .filter(|section| !PRINT_SECTIONS.contains(section))
+        .collect();
+    if !unknown.is_empty() {
+        problems.push(format!(
+            "unknown sections in layout.order: {} (expected {})",
+            unknown.join(", "),
+            PRINT_SECTIONS.join(", ")
+        ));
+    }
+    if config.qr_code.size == 0 {
+        problems.push("qrCode.size must be greater than 0".to_string());
+    }
+    if problems.is_empty() {
+        Outcome::pass("Valid print configuration", details, ())
+    } else {
+        Outcome::fail(problems.join(", "), details)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Target {
+    host: String,
+    port: u16,
+    tls: bool,
+}
+
+fn controller_url(environment: &Environment) -> Outcome<Target> {
+    let url = match Url::parse(&environment.unifi_controller_url) {
+        Ok(url) => url,
+        Err(e) => return Outcome::fail(format!("Invalid controller URL: {e}"), json!({})),
+    };
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Outcome::fail("The controller URL has no host", json!({}));
+    };
+    let target = Target {
+        // IPv6 addresses are bracketed in URLs
+        host: host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string(),
+        port,
+        tls: url.scheme() == "https",
+    };
+    let details = json!({ "host": target.host, "port": port, "scheme": url.scheme() });
+    if !url.username().is_empty() || url.password().is_some() {
+        return Outcome::warn(
+            "The controller URL contains credentials, which are ignored",
+            details,
+            target,
+        );
+    }
+    Outcome::pass(format!("{}:{}", target.host, port), details, target)
+}
+
+async fn dns(target: Target) -> Outcome<Vec<SocketAddr>> {
+    let lookup = tokio::time::timeout(
+        NETWORK_TIMEOUT,
+        lookup_host((target.host.as_str(), target.port)),
+    );
+    match lookup.await {
+        Ok(Ok(addresses)) => {
+            let addresses: Vec<SocketAddr> = addresses.collect();
+            if addresses.is_empty() {
+                return Outcome::fail(format!("{} has no address", target.host), json!({}));
+            }
+            let ips: Vec<String> = addresses.iter().map(|a| a.ip().to_string()).collect();
+            Outcome::pass(
+                format!("{} resolves to {}", target.host, ips.join(", ")),
+                json!({ "addresses": ips }),
+                addresses,
+            )
+        }
+        Ok(Err(e)) => Outcome::fail(format!("Failed to resolve {}: {e}", target.host), json!({})),
+        Err(_) => Outcome::fail(
+            format!(
+                "Resolving {} timed out after {}s",
+                target.host,
+                NETWORK_TIMEOUT.as_secs()
+            ),
+            json!({}),
+        ),
+    }
+}
+
+async fn tcp(addresses: Vec<SocketAddr>) -> Outcome<SocketAddr> {
+    let mut errors = Vec::new();
+    for address in addresses {
+        let started = Instant::now();
+        match tokio::time::timeout(NETWORK_TIMEOUT, TcpStream::connect(address)).await {
+            Ok(Ok(_)) => {
+                let latency = started.elapsed().as_millis() as u64;
+                return Outcome::pass(
+                    format!("Connected to {address} in {latency} ms"),
+                    json!({ "address": address.to_string(), "latencyMs": latency, "failed": errors }),
+                    address,
+                );
+            }
+            Ok(Err(e)) => errors.push(format!("{address}: {e}")),
+            Err(_) => errors.push(format!(
+                "{address}: timed out after {}s",
+                NETWORK_TIMEOUT.as_secs()
+            )),
+        }
+    }
+    Outcome::fail(
+        format!(
+            "Could not connect to the controller ({})",
+            errors.join(", ")
+        ),
+        json!({ "failed": errors }),
+    )
+}
+
+async fn tls(environment: &Environment, target: Target, address: SocketAddr) -> Outcome<()> {
+    let (chain, verification) =
+        match tls::inspect(environment, &target.host, address, NETWORK_TIMEOUT).await {
+            Ok(presented) => presented,
+            Err(e) => return Outcome::fail(e, json!({})),
+        };
+
+    let certificates: Vec<serde_json::Value> =
+        chain.iter().map(|der| describe_certificate(der)).collect();
+    let days_left = certificates
+        .first()
+        .and_then(|leaf| leaf["notAfter"].as_str())
+        .and_then(|not_after| DateTime::parse_from_rfc3339(not_after).ok())
+        .map(|not_after| (not_after.with_timezone(&Utc) - Utc::now()).num_days());
+    let details = json!({
+        "trusted": verification.is_ok(),
+        "verificationError": verification.as_ref().err().map(|e| e.to_string()),
+        "daysLeft": days_left,
+        "chain": certificates,
+    });
+
+    if tls::has_custom_trust(environment) {
+        return match verification {
+            Ok(()) if days_left.is_some_and(|days| days < CERTIFICATE_WARNING_DAYS) => {
+                Outcome::warn(
+                    format!(
+                        "The certificate matches the configured CA bundle or pins, and expires in {} days",
+                        days_left.unwrap_or_default()
+                    ),
+                    details,
+                    (),
+                )
+            }
+            Ok(()) => Outcome::pass(
+                "The certificate matches the configured CA bundle or pins",
+                details,
+                (),
+            ),
+            Err(e) => Outcome::fail(
+                format!("The certificate is rejected by the configured CA bundle or pins ({e})"),
+                details,
+            ),
+        };
+    }
+
+    match (verification, environment.unifi_has_valid_cert) {
+        (Ok(()), _) if days_left.is_some_and(|days| days < CERTIFICATE_WARNING_DAYS) => {
+            Outcome::warn(
+                format!(
+                    "The certificate expires in {} days",
+                    days_left.unwrap_or_default()
+                ),
+                details,
+                (),
+            )
+        }
+        (Ok(()), true) => Outcome::pass("The certificate is trusted", details, ()),
+        (Ok(()), false) => Outcome::warn(
+            "The certificate is trusted, UNIFI_HAS_VALID_CERT can be set to true to verify it",
+            details,
+            (),
+        ),
+        (Err(e), true) => Outcome::fail(
+            format!(
+                "The certificate is not trusted ({e}). Set UNIFI_HAS_VALID_CERT=false if the controller uses a self-signed certificate"
+            ),
+            details,
+        ),
+        (Err(_), false) if days_left.is_some_and(|days| days < CERTIFICATE_WARNING_DAYS) => {
+            Outcome::warn(
+                format!(
+                    "The certificate is not trusted, which UNIFI_HAS_VALID_CERT=false allows, and expires in {} days",
+                    days_left.unwrap_or_default()
+                ),
+                details,
+                (),
+            )
+        }
+        (Err(e), false) => Outcome::warn(
+            format!(
+                "The certificate is not trusted ({e}), which UNIFI_HAS_VALID_CERT=false allows without any verification. Pin it with UNIFI_SPKI_FINGERPRINTS, see `backend controller fingerprint`"
+            ),
+            details,
+            (),
+        ),
+    }
+}
+
+pub(crate) fn describe_certificate(der: &CertificateDer<'_>) -> serde_json::Value {
+    let fingerprints = tls::fingerprints(der);
+    let Ok((_, cert)) = parse_x509_certificate(der.as_ref()) else {
+        return json!({ "sha256": fingerprints.certificate, "error": "Unparsable certificate" });
+    };
+    let not_after = DateTime::from_timestamp(cert.validity().not_after.timestamp(), 0);
+    let not_before = DateTime::from_timestamp(cert.validity().not_before.timestamp(), 0);
+    let names: Vec<String> = cert
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|san| {
+            san.value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(dns) => Some(dns.to_string()),
+                    GeneralName::IPAddress(ip) => match ip.len() {
+                        4 => <[u8; 4]>::try_from(*ip)
+                            .ok()
+                            .map(|ip| std::net::IpAddr::from(ip).to_string()),
+                        16 => <[u8; 16]>::try_from(*ip)
+                            .ok()
+                            .map(|ip| std::net::IpAddr::from(ip).to_string()),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    json!({
+        "subject": cert.subject().to_string(),
+        "issuer": cert.issuer().to_string(),
+        "subjectAltNames": names,
+        "notBefore": not_before,
+        "notAfter": not_after,
+        "sha256": fingerprints.certificate,
+        "spkiSha256": fingerprints.public_key,
+    })
+}
+
+/// Compares the `Date` header of the controller with the local clock, as
+/// voucher expiry and rotation times rely on both agreeing.
+async fn clock(environment: &Environment) -> Outcome<()> {
+    let client = match tls::client_builder(environment).and_then(|builder| {
+        builder
+            .timeout(NETWORK_TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())
+    }) {
+        Ok(client) => client,
+        Err(e) => return Outcome::fail(format!("Failed to build HTTP client: {e}"), json!({})),
+    };
+
+    let started = Utc::now();
+    let response = match client
+        .get(format!("{}/status", environment.unifi_controller_url))
+        .send()
+        .await
+    {
+        Ok(response) => response,
+        Err(e) => return Outcome::fail(format!("The controller did not answer: {e}"), json!({})),
+    };
+    let midpoint = started + (Utc::now() - started) / 2;
+    let Some(date) = response
+        .headers()
+        .get(DATE)
+        .and_then(|date| date.to_str().ok())
+        .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+    else {
+        return Outcome::warn(
+            "The controller sent no Date header to compare clocks",
+            json!({}),
+            (),
+        );
+    };
+
+    let skew = (midpoint - date.with_timezone(&Utc)).num_seconds();
+    let details = json!({ "controllerTime": date, "localTime": midpoint, "skewSeconds": skew });
+    match skew.abs() {
+        s if s >= CLOCK_SKEW_FAILURE => Outcome::fail(
+            format!(
+                "The local clock is {skew}s off the controller, voucher expiry and rotation times will be wrong"
+            ),
+            details,
+        ),
+        s if s >= CLOCK_SKEW_WARNING => Outcome::warn(
+            format!("The local clock is {skew}s off the controller"),
+            details,
+            (),
+        ),
+        _ => Outcome::pass(format!("Clocks agree within {}s", skew.abs()), details, ()),
+    }
+}
+
+async fn login(environment: &Environment) -> Outcome<Arc<UnifiAPI<'_>>> {
+    match UnifiAPI::try_from_environment(environment).await {
+        Ok(client) => Outcome::pass(
+            "Logged in",
+            json!({ "session": client.session_info() }),
+            Arc::new(client),
+        ),
+        Err(e) => Outcome::fail(format!("Failed to log in: {e}"), json!({})),
+    }
+}
+
+/// Checks that the configured site exists and returns the account's role on it.
+async fn site(environment: &Environment, client: Arc<UnifiAPI<'_>>) -> Outcome<Option<String>> {
+    let sites = match client.get_sites().await {
+        Ok(sites) => sites,
+        Err(e) => return Outcome::fail(format!("Failed to list the sites: {e}"), json!({})),
+    };
+    let names: Vec<&str> = sites.iter().map(|site| site.name.as_str()).collect();
+    let available = if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(", ")
+    };
+    let details = json!({ "sites": sites });
+    match sites
+        .iter()
+        .find(|site| site.name == environment.unifi_site_id)
+    {
+        Some(site) => Outcome::pass(
+            format!("Site '{}' ({}) exists", site.name, site.description),
+            details,
+            site.role.clone(),
+        ),
+        None => Outcome::fail(
+            format!(
+                "Site '{}' does not exist or is not accessible, available sites: {}",
+                environment.unifi_site_id, available
+            ),
+            details,
+        ),
+    }
+}
+
+async fn permissions(client: Arc<UnifiAPI<'_>>, role: Option<String>) -> Outcome<()> {
+    let details = json!({ "role": role });
+    match client.get_all_vouchers().await {
+        Ok(_) => {}
+        Err(ApiError::ControllerRejected { status, message })
+            if status == StatusCode::FORBIDDEN.as_u16()
+                || status == StatusCode::UNAUTHORIZED.as_u16() =>
+        {
+            return Outcome::fail(
+                format!("The account cannot read vouchers: {message}"),
+                details,
+            );
+        }
+        Err(e) => return Outcome::fail(format!("Failed to read vouchers: {e}"), details),
+    }
+    match role.as_deref() {
+        Some("admin") => Outcome::pass("The account can read and manage vouchers", details, ()),
+        Some("readonly") => Outcome::fail(
+            "The account is read-only on this site and cannot create or delete vouchers",
+            details,
+        ),
+        Some(role) => Outcome::warn(
+            format!(
+                "The account can read vouchers, but its '{role}' role may not allow creating them"
+            ),
+            details,
+            (),
+        ),
+        None => Outcome::warn(
+            "The account can read vouchers, its role could not be determined",
+            details,
+            (),
+        ),
+    }
+}
@@ -1,92 +1,392 @@
-use std::{env, sync::OnceLock};
+use std::{
+    collections::HashMap,
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::OnceLock,
+    time::Duration,
+};
 
 use chrono_tz::Tz;
-use tracing::{error, info};
+use toml::{
+    Spanned,
+    de::{DeTable, DeValue},
+};
+use tracing::{info, warn};
 
+use crate::{
+    cli::ConfigArgs,
+    listener::{Peer, ServerTls},
+    logging::{LogFormat, LogRotation, SyslogConfig},
+    tls::{CaBundle, CertificatePin},
+    totp::Totp,
+};
+
+const DEFAULT_CONFIG_FILE: &str = "/app/config/backend.toml";
 const DEFAULT_BACKEND_BIND_HOST: &str = "127.0.0.1";
 const DEFAULT_BACKEND_BIND_PORT: u16 = 8080;
+const DEFAULT_UNIX_SOCKET_MODE: u32 = 0o660;
 const DEFAULT_UNIFI_SITE_ID: &str = "default";
+const DEFAULT_LOG_DIR: &str = "/app/logs";
+const DEFAULT_DATA_DIR: &str = "/app/data";
+const DEFAULT_LOG_MAX_FILES: usize = 30;
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 20;
+const DEFAULT_UNIFI_READ_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_UNIFI_WRITE_TIMEOUT_SECS: u64 = 20;
+const DEFAULT_UNIFI_RETRY_ATTEMPTS: u32 = 3;
+const DEFAULT_UNIFI_RETRY_BASE_DELAY_MS: u64 = 250;
+const DEFAULT_UNIFI_RETRY_MAX_DELAY_MS: u64 = 4000;
+const DEFAULT_UNIFI_CIRCUIT_FAILURE_THRESHOLD: u32 = 5;
+const DEFAULT_UNIFI_CIRCUIT_COOLDOWN_SECS: u64 = 30;
 
 pub static ENVIRONMENT: OnceLock<Environment> = OnceLock::new();
 
+/// A configuration key, named `section.key` in the TOML file.
+struct Key {
+    name: &'static str,
+    env: &'static str,
+    /// Can also be read from a file, named by `<ENV>_FILE` or `<key>_file`
+    secret: bool,
+}
+
+impl Key {
+    const fn new(name: &'static str, env: &'static str) -> Self {
+        Self {
+            name,
+            env,
+            secret: false,
+        }
+    }
+
+    const fn secret(name: &'static str, env: &'static str) -> Self {
+        Self {
+            name,
+            env,
+            secret: true,
+        }
+    }
+}
+
+/// Tables of the configuration file, every other key is at the top level.
+const SECTIONS: [&str; 3] = ["unifi", "server", "log"];
+
+const KEYS: &[Key] = &[
+    Key::new("strict", "BACKEND_STRICT_CONFIG"),
+    Key::new("timezone", "TIMEZONE"),
+    Key::new("unifi.controller_url", "UNIFI_CONTROLLER_URL"),
+    Key::new("unifi.site_id", "UNIFI_SITE_ID"),
+    Key::secret("unifi.username", "UNIFI_USERNAME"),
+    Key::secret("unifi.password", "UNIFI_PASSWORD"),
+    Key::secret("unifi.totp_secret", "UNIFI_TOTP_SECRET"),
+    Key::new("unifi.has_valid_cert", "UNIFI_HAS_VALID_CERT"),
+    Key::new("unifi.ca_bundle", "UNIFI_CA_BUNDLE"),
+    Key::new("unifi.cert_fingerprints", "UNIFI_CERT_FINGERPRINTS"),
+    Key::new("unifi.spki_fingerprints", "UNIFI_SPKI_FINGERPRINTS"),
+    Key::new("unifi.read_timeout_secs", "UNIFI_READ_TIMEOUT_SECS"),
+    Key::new("unifi.write_timeout_secs", "UNIFI_WRITE_TIMEOUT_SECS"),
+    Key::new("unifi.retry_attempts", "UNIFI_RETRY_ATTEMPTS"),
+    Key::new("unifi.retry_base_delay_ms", "UNIFI_RETRY_BASE_DELAY_MS"),
+    Key::new("unifi.retry_max_delay_ms", "UNIFI_RETRY_MAX_DELAY_MS"),
+    Key::new(
+        "unifi.circuit_failure_threshold",
+        "UNIFI_CIRCUIT_FAILURE_THRESHOLD",
+    ),
+    Key::new("unifi.circuit_cooldown_secs", "UNIFI_CIRCUIT_COOLDOWN_SECS"),
+    Key::new("server.bind_host", "BACKEND_BIND_HOST"),
+    Key::new("server.bind_port", "BACKEND_BIND_PORT"),
+    Key::new("server.tcp_enabled", "BACKEND_TCP_ENABLED"),
+    Key::new("server.tls_cert", "BACKEND_TLS_CERT"),
+    Key::new("server.tls_key", "BACKEND_TLS_KEY"),
+    Key::new("server.unix_socket", "BACKEND_UNIX_SOCKET"),
+    Key::new("server.unix_socket_mode", "BACKEND_UNIX_SOCKET_MODE"),
+    Key::new("server.trusted_proxies", "BACKEND_TRUSTED_PROXIES"),
+    Key::new("server.data_dir", "BACKEND_DATA_DIR"),
+    Key::new(
+        "server.shutdown_timeout_secs",
+        "BACKEND_SHUTDOWN_TIMEOUT_SECS",
+    ),
+    Key::new("log.dir", "BACKEND_LOG_DIR"),
+    Key::new("log.format", "BACKEND_LOG_FORMAT"),
+    Key::new("log.rotation", "BACKEND_LOG_ROTATION"),
+    Key::new("log.max_files", "BACKEND_LOG_MAX_FILES"),
+    Key::new("log.syslog_url", "BACKEND_SYSLOG_URL"),
+];
+
+fn key(name: &str) -> Option<&'static Key> {
+    KEYS.iter().find(|key| key.name == name)
+}
+
 #[derive(Debug, Clone)]
 pub struct Environment {
     pub unifi_controller_url: String,
     pub unifi_site_id: String,
     pub unifi_username: String,
     pub unifi_password: String,
+    /// Seed of the admin account's authenticator app, when it requires MFA
+    pub unifi_totp: Option<Totp>,
     pub backend_bind_host: String,
     pub backend_bind_port: u16,
+    /// Whether to listen on the bind host and port, which can be turned off
+    /// when the Unix socket is used instead
+    pub backend_tcp_enabled: bool,
+    /// Certificate and key to serve HTTPS with instead of plain HTTP
+    pub backend_tls: Option<ServerTls>,
+    /// Unix domain socket to listen on as well
+    pub backend_unix_socket: Option<PathBuf>,
+    /// Permission bits of the Unix socket
+    pub backend_unix_socket_mode: u32,
+    /// Peers whose `X-Forwarded-User`/`X-Remote-User` headers name the user
+    /// in the audit trail
+    pub trusted_proxies: Vec<Peer>,
     pub unifi_has_valid_cert: bool,
+    /// CA certificates to verify the controller against instead of the
+    /// public roots
+    pub unifi_ca_bundle: Option<CaBundle>,
+    /// Fingerprints one of which the controller certificate must match
+    pub unifi_certificate_pins: Vec<CertificatePin>,
     pub timezone: Tz,
+    /// Directory for the rolling log files, `None` to log to the console only
+    pub log_dir: Option<PathBuf>,
+    pub log_format: LogFormat,
+    pub log_rotation: LogRotation,
+    /// Number of rotated files to keep per log, 0 keeps all of them
+    pub log_max_files: usize,
+    pub syslog: Option<SyslogConfig>,
+    /// Directory for state persisted by the backend, such as the audit trail
+    pub data_dir: PathBuf,
+    /// How long a shutdown waits for in-flight requests and jobs
+    pub shutdown_timeout: Duration,
+    /// Timeout of a single controller read, including login
+    pub unifi_read_timeout: Duration,
+    /// Timeout of a single controller write (voucher creation or deletion)
+    pub unifi_write_timeout: Duration,
+    /// Attempts made for an idempotent controller read, 1 disables retries
+    pub unifi_retry_attempts: u32,
+    pub unifi_retry_base_delay: Duration,
+    pub unifi_retry_max_delay: Duration,
+    /// Consecutive controller failures after which requests fail fast
+    pub unifi_circuit_failure_threshold: u32,
+    /// How long requests fail fast before the controller is probed again
+    pub unifi_circuit_cooldown: Duration,
 }
 
 impl Environment {
-    pub fn try_new() -> Result<Self, String> {
+    /// Builds the configuration from, by increasing precedence, the defaults,
+    /// the TOML file, the environment and the command line. Every problem is
+    /// reported at once, one per line.
+    pub fn try_new(args: &ConfigArgs) -> Result<Self, String> {
         #[cfg(feature = "dotenv")]
         dotenvy::dotenv().map_err(|e| format!("Failed to load .env file: {e}"))?;
 
-        let unifi_controller_url: String =
-            env::var("UNIFI_CONTROLLER_URL").map_err(|e| format!("UNIFI_CONTROLLER_URL: {e}"))?;
+        let mut layers = Layers::default();
+        match &args.config {
+            Some(path) => layers.load_file(path),
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                layers.load_file(Path::new(DEFAULT_CONFIG_FILE))
+            }
+            None => {}
+        }
+        layers.load_env();
+        layers.load_flags(args);
+
+        let strict = layers.parse("strict", false, Self::parse_bool);
 
-        if !unifi_controller_url.starts_with("http://")
+        let unifi_controller_url = layers.required("unifi.controller_url");
+        if !unifi_controller_url.is_empty()
+            && !unifi_controller_url.starts_with("http://")
             && !unifi_controller_url.starts_with("https://")
         {
-            return Err("UNIFI_CONTROLLER_URL must start with http:// or https://".to_string());
+            layers.invalid(
+                "unifi.controller_url",
+                "must start with http:// or https://",
+            );
         }
+        let unifi_controller_url = unifi_controller_url.trim_end_matches('/').to_string();
+        let unifi_username = layers.required("unifi.username");
+        let unifi_password = layers.required("unifi.password");
+        let unifi_totp = layers.parse("unifi.totp_secret", None, |s| {
+            if s.trim().is_empty() {
+                Ok(None)
+            } else {
+                Totp::from_base32(s).map(Some)
+            }
+        });
+        let unifi_site_id = layers.parse("unifi.site_id", DEFAULT_UNIFI_SITE_ID.to_owned(), |s| {
+            Ok(s.to_string())
+        });
+        let unifi_has_valid_cert = layers.parse("unifi.has_valid_cert", true, Self::parse_bool);
+        let unifi_ca_bundle = layers.parse("unifi.ca_bundle", None, |s| {
+            if s.trim().is_empty() {
+                Ok(None)
+            } else {
+                CaBundle::load(Path::new(s.trim())).map(Some)
+            }
+        });
+        let mut unifi_certificate_pins = layers.parse("unifi.cert_fingerprints", Vec::new(), |s| {
+            CertificatePin::parse_list(s, CertificatePin::Certificate)
+        });
+        unifi_certificate_pins.extend(layers.parse("unifi.spki_fingerprints", Vec::new(), |s| {
+            CertificatePin::parse_list(s, CertificatePin::PublicKey)
+        }));
 
-        let unifi_username: String =
-            env::var("UNIFI_USERNAME").map_err(|e| format!("UNIFI_USERNAME: {e}"))?;
-        let unifi_password: String =
-            env::var("UNIFI_PASSWORD").map_err(|e| format!("UNIFI_PASSWORD: {e}"))?;
-        let unifi_site_id: String =
-            env::var("UNIFI_SITE_ID").unwrap_or(DEFAULT_UNIFI_SITE_ID.to_owned());
-
-        let backend_bind_host: String =
-            env::var("BACKEND_BIND_HOST").unwrap_or(DEFAULT_BACKEND_BIND_HOST.to_owned());
-        let backend_bind_port: u16 = match env::var("BACKEND_BIND_PORT") {
-            Ok(port_str) => port_str
-                .parse()
-                .map_err(|e| format!("Invalid BACKEND_BIND_PORT: {e}"))?,
-            Err(_) => DEFAULT_BACKEND_BIND_PORT,
-        };
-
-        let unifi_has_valid_cert: bool = match env::var("UNIFI_HAS_VALID_CERT") {
-            Ok(val) => {
-                Self::parse_bool(&val).map_err(|e| format!("Invalid UNIFI_HAS_VALID_CERT: {e}"))?
+        let backend_bind_host = layers.parse(
+            "server.bind_host",
+            DEFAULT_BACKEND_BIND_HOST.to_owned(),
+            |s| Ok(s.to_string()),
+        );
+        let backend_bind_port = layers.number("server.bind_port", DEFAULT_BACKEND_BIND_PORT);
+        let backend_tcp_enabled = layers.parse("server.tcp_enabled", true, Self::parse_bool);
+        let optional_path = |s: &str| Ok((!s.trim().is_empty()).then(|| PathBuf::from(s.trim())));
+        let backend_tls = match (
+            layers.parse("server.tls_cert", None, optional_path),
+            layers.parse("server.tls_key", None, optional_path),
+        ) {
+            (Some(cert), Some(key)) => match ServerTls::load(cert, key) {
+                Ok(tls) => Some(tls),
+                Err(e) => {
+                    layers.invalid("server.tls_cert", &e);
+                    None
+                }
+            },
+            (Some(_), None) => {
+                layers.invalid("server.tls_key", "required when server.tls_cert is set");
+                None
+            }
+            (None, Some(_)) => {
+                layers.invalid("server.tls_cert", "required when server.tls_key is set");
+                None
             }
-            Err(_) => true,
+            (None, None) => None,
         };
+        let backend_unix_socket = layers.parse("server.unix_socket", None, optional_path);
+        let backend_unix_socket_mode = layers.parse(
+            "server.unix_socket_mode",
+            DEFAULT_UNIX_SOCKET_MODE,
+            Self::parse_mode,
+        );
+        let trusted_proxies = layers.parse("server.trusted_proxies", Vec::new(), Peer::parse_list);
+        if !backend_tcp_enabled && backend_unix_socket.is_none() {
+            layers.invalid(
+                "server.tcp_enabled",
+                "the server needs a listener, set server.unix_socket to turn off TCP",
+            );
+        }
+        let data_dir = layers.parse("server.data_dir", PathBuf::from(DEFAULT_DATA_DIR), |s| {
+            Ok(PathBuf::from(s))
+        });
+        let shutdown_timeout = Duration::from_secs(layers.number(
+            "server.shutdown_timeout_secs",
+            DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+        ));
 
-        let timezone: Tz = match env::var("TIMEZONE") {
-            Ok(s) => match s.parse() {
+        // An unknown timezone used to silently fall back to UTC, strict mode
+        // makes it an error
+        let timezone = match layers.settings.get("timezone").cloned() {
+            Some(setting) => match setting.value.trim().parse::<Tz>() {
                 Ok(tz) => {
-                    info!("Using timezone: {}", s);
+                    info!("Using timezone: {}", tz);
                     tz
                 }
                 Err(_) => {
-                    error!("Using UTC, could not parse timezone: {}", s);
+                    layers.soft_problem(
+                        Some(setting.origin),
+                        "timezone",
+                        format!("unknown timezone '{}'", setting.value),
+                    );
                     Tz::UTC
                 }
             },
-            Err(_) => {
-                info!("TIMEZONE environment variable not set, defaulting to UTC");
+            None => {
+                info!("No timezone configured, defaulting to UTC");
                 Tz::UTC
             }
         };
 
+        let log_dir = layers.parse("log.dir", Some(PathBuf::from(DEFAULT_LOG_DIR)), |s| {
+            Ok((!s.trim().is_empty()).then(|| PathBuf::from(s)))
+        });
+        let log_format = layers.parse("log.format", LogFormat::Text, str::parse);
+        let log_rotation = layers.parse("log.rotation", LogRotation::Daily, str::parse);
+        let log_max_files = layers.number("log.max_files", DEFAULT_LOG_MAX_FILES);
+        let syslog = layers.parse("log.syslog_url", None, |s| {
+            if s.trim().is_empty() {
+                Ok(None)
+            } else {
+                s.parse().map(Some)
+            }
+        });
+
+        let unifi_read_timeout = Duration::from_secs(
+            layers.number("unifi.read_timeout_secs", DEFAULT_UNIFI_READ_TIMEOUT_SECS),
+        );
+        let unifi_write_timeout = Duration::from_secs(
+            layers.number("unifi.write_timeout_secs", DEFAULT_UNIFI_WRITE_TIMEOUT_SECS),
+        );
+        let unifi_retry_attempts =
+            layers.number("unifi.retry_attempts", DEFAULT_UNIFI_RETRY_ATTEMPTS);
+        let unifi_retry_base_delay = Duration::from_millis(layers.number(
+            "unifi.retry_base_delay_ms",
+            DEFAULT_UNIFI_RETRY_BASE_DELAY_MS,
+        ));
+        let unifi_retry_max_delay = Duration::from_millis(
+            layers.number("unifi.retry_max_delay_ms", DEFAULT_UNIFI_RETRY_MAX_DELAY_MS),
+        );
+        let unifi_circuit_failure_threshold = layers.number(
+            "unifi.circuit_failure_threshold",
+            DEFAULT_UNIFI_CIRCUIT_FAILURE_THRESHOLD,
+        );
+        let unifi_circuit_cooldown = Duration::from_secs(layers.number(
+            "unifi.circuit_cooldown_secs",
+            DEFAULT_UNIFI_CIRCUIT_COOLDOWN_SECS,
+        ));
+
+        layers.finish(strict)?;
+
         Ok(Self {
             unifi_controller_url,
             unifi_site_id,
             unifi_username,
             unifi_password,
+            unifi_totp,
             backend_bind_host,
             backend_bind_port,
+            backend_tcp_enabled,
+            backend_tls,
+            backend_unix_socket,
+            backend_unix_socket_mode,
+            trusted_proxies,
             unifi_has_valid_cert,
+            unifi_ca_bundle,
+            unifi_certificate_pins,
             timezone,
+            log_dir,
+            log_format,
+            log_rotation,
+            log_max_files,
+            syslog,
+            data_dir,
+            shutdown_timeout,
+            unifi_read_timeout,
+            unifi_write_timeout,
+            unifi_retry_attempts,
+            unifi_retry_base_delay,
+            unifi_retry_max_delay,
+            unifi_circuit_failure_threshold,
+            unifi_circuit_cooldown,
         })
     }
 
+    /// Parses permission bits written in octal, as for chmod.
+    fn parse_mode(s: &str) -> Result<u32, String> {
+        let digits = s.trim().trim_start_matches("0o");
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if mode <= 0o777 => Ok(mode),
+            _ => Err(format!(
+                "expected permission bits in octal such as 660, found '{s}'"
+            )),
+        }
+    }
+
     fn parse_bool(s: &str) -> Result<bool, String> {
         match s.trim().to_lowercase().as_str() {
             "true" | "1" | "yes" => Ok(true),
@@ -95,3 +395,300 @@ impl Environment {
         }
     }
 }
+
+/// Where a configuration value came from.
+#[derive(Debug, Clone)]
+enum Origin {
+    File {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+    },
+    Env(String),
+    Flag(&'static str),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File { path, line, column } => {
+                write!(f, "{}:{}:{}", path.display(), line, column)
+            }
+            Self::Env(name) => write!(f, "environment variable {name}"),
+            Self::Flag(flag) => write!(f, "flag {flag}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Setting {
+    value: String,
+    origin: Origin,
+}
+
+#[derive(Debug)]
+struct Problem {
+    origin: Option<Origin>,
+    key: Option<String>,
+    message: String,
+    /// Only an error in strict mode, a warning otherwise
+    soft: bool,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(origin) = &self.origin {
+            write!(f, "{origin}: ")?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, "{key}: ")?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Raw values of every layer, later layers overriding earlier ones, and the
+/// problems found along the way.
+#[derive(Debug, Default)]
+struct Layers {
+    settings: HashMap<&'static str, Setting>,
+    problems: Vec<Problem>,
+}
+
+impl Layers {
+    fn load_file(&mut self, path: &Path) {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                self.problems.push(Problem {
+                    origin: None,
+                    key: None,
+                    message: format!("Failed to read configuration file {}: {e}", path.display()),
+                    soft: false,
+                });
+                return;
+            }
+        };
+        info!("Loading configuration file {}", path.display());
+
+        let location = |offset: usize| {
+            let before = &source[..offset.min(source.len())];
+            Origin::File {
+                path: path.to_path_buf(),
+                line: before.matches('\n').count() + 1,
+                column: before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1,
+            }
+        };
+
+        // Syntax errors are recovered from so that the rest of the file is
+        // still checked
+        let (document, errors) = DeTable::parse_recoverable(&source);
+        for error in errors {
+            self.problems.push(Problem {
+                origin: Some(location(error.span().map_or(0, |span| span.start))),
+                key: None,
+                message: error.message().trim().to_string(),
+                soft: false,
+            });
+        }
+
+        for (name, value) in document.get_ref() {
+            let name = name.get_ref().as_ref();
+            match value.get_ref() {
+                DeValue::Table(section) if SECTIONS.contains(&name) => {
+                    for (key, value) in section {
+                        let full_name = format!("{name}.{}", key.get_ref());
+                        self.file_value(&full_name, value, location(value.span().start));
+                    }
+                }
+                _ => self.file_value(name, value, location(value.span().start)),
+            }
+        }
+    }
+
+    fn file_value(&mut self, name: &str, value: &Spanned<DeValue>, origin: Origin) {
+        // Unknown keys are only a warning whatever their type, tables included
+        let (key, secret_file) = if let Some(key) = key(name) {
+            (key, false)
+        } else if let Some(key) = name
+            .strip_suffix("_file")
+            .and_then(key)
+            .filter(|k| k.secret)
+        {
+            (key, true)
+        } else {
+            let kind = match value.get_ref() {
+                DeValue::Table(_) => "unknown table",
+                _ => "unknown key",
+            };
+            return self.soft_problem(Some(origin), name, kind.to_string());
+        };
+
+        let scalar = match value.get_ref() {
+            DeValue::String(s) => s.to_string(),
+            DeValue::Integer(i) => match i64::from_str_radix(i.as_str(), i.radix()) {
+                Ok(i) => i.to_string(),
+                Err(e) => return self.problem(Some(origin), name, e.to_string()),
+            },
+            DeValue::Float(f) => f.as_str().to_string(),
+            DeValue::Boolean(b) => b.to_string(),
+            other => {
+                return self.problem(
+                    Some(origin),
+                    name,
+                    format!(
+                        "expected a string, number or boolean, found {}",
+                        other.type_str()
+                    ),
+                );
+            }
+        };
+
+        if secret_file {
+            match read_secret(&scalar) {
+                Ok(secret) => self.set(key.name, secret, origin),
+                Err(e) => self.problem(Some(origin), name, e),
+            }
+        } else {
+            self.set(key.name, scalar, origin);
+        }
+    }
+
+    fn load_env(&mut self) {
+        for key in KEYS {
+            let value = env::var(key.env).ok();
+            let file_var = format!("{}_FILE", key.env);
+            let file = key.secret.then(|| env::var(&file_var).ok()).flatten();
+
+            match (value, file) {
+                (Some(_), Some(_)) => self.problem(
+                    Some(Origin::Env(file_var.clone())),
+                    key.name,
+                    format!("set either {} or {}, not both", key.env, file_var),
+                ),
+                (Some(value), None) => self.set(key.name, value, Origin::Env(key.env.to_string())),
+                (None, Some(path)) => match read_secret(&path) {
+                    Ok(secret) => self.set(key.name, secret, Origin::Env(file_var)),
+                    Err(e) => self.problem(Some(Origin::Env(file_var)), key.name, e),
+                },
+                (None, None) => {}
+            }
+        }
+    }
+
+    fn load_flags(&mut self, args: &ConfigArgs) {
+        for (name, flag, value) in args.overrides() {
+            let key = key(name).expect("Every flag maps to a configuration key");
+            self.set(key.name, value, Origin::Flag(flag));
+        }
+    }
+
+    fn set(&mut self, name: &'static str, value: String, origin: Origin) {
+        self.settings.insert(name, Setting { value, origin });
+    }
+
+    fn problem(&mut self, origin: Option<Origin>, key: &str, message: String) {
+        self.problems.push(Problem {
+            origin,
+            key: Some(key.to_string()),
+            message,
+            soft: false,
+        });
+    }
+
+    fn soft_problem(&mut self, origin: Option<Origin>, key: &str, message: String) {
+        self.problems.push(Problem {
+            origin,
+            key: Some(key.to_string()),
+            message,
+            soft: true,
+        });
+    }
+
+    /// Reports the value of `name` as invalid.
+    fn invalid(&mut self, name: &str, message: &str) {
+        let origin = self.settings.get(name).map(|s| s.origin.clone());
+        self.problem(origin, name, message.to_string());
+    }
+
+    fn parse<T>(
+        &mut self,
+        name: &'static str,
+        default: T,
+        parse: impl FnOnce(&str) -> Result<T, String>,
+    ) -> T {
+        let Some(setting) = self.settings.get(name) else {
+            return default;
+        };
+        match parse(&setting.value) {
+            Ok(value) => value,
+            Err(e) => {
+                let origin = setting.origin.clone();
+                self.problem(Some(origin), name, e);
+                default
+            }
+        }
+    }
+
+    fn number<T>(&mut self, name: &'static str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.parse(name, default, |s| {
+            s.trim()
+                .parse()
+                .map_err(|e| format!("expected a number, found '{s}': {e}"))
+        })
+    }
+
+    fn required(&mut self, name: &'static str) -> String {
+        match self.settings.get(name) {
+            Some(setting) if !setting.value.trim().is_empty() => setting.value.clone(),
+            _ => {
+                let key = key(name).expect("Required keys are registered");
+                let alternatives = if key.secret {
+                    format!("{}, {} or {}_FILE", key.name, key.env, key.env)
+                } else {
+                    format!("{} or {}", key.name, key.env)
+                };
+                self.problem(None, name, format!("required, set it with {alternatives}"));
+                String::new()
+            }
+        }
+    }
+
+    /// Fails with every error found, logging soft problems as warnings unless
+    /// `strict` turns them into errors.
+    fn finish(mut self, strict: bool) -> Result<(), String> {
+        // Problems of the file first, in the order they appear in it
+        self.problems.sort_by_key(|p| match &p.origin {
+            Some(Origin::File { line, column, .. }) => (0, *line, *column),
+            Some(Origin::Env(_)) => (1, 0, 0),
+            Some(Origin::Flag(_)) => (2, 0, 0),
+            None => (3, 0, 0),
+        });
+        let (errors, warnings): (Vec<_>, Vec<_>) =
+            self.problems.into_iter().partition(|p| !p.soft || strict);
+        for warning in &warnings {
+            warn!("Configuration: {}, ignoring it", warning);
+        }
+        if errors.is_empty() {
+            return Ok(());
+        }
+        let lines: Vec<String> = errors.iter().map(|e| format!("  - {e}")).collect();
+        Err(format!(
+            "Invalid configuration ({} problem{}):\n{}",
+            errors.len(),
+            if errors.len() == 1 { "" } else { "s" },
+            lines.join("\n")
+        ))
+    }
+}
+
+/// Reads a secret mounted as a file, such as a Docker secret.
+fn read_secret(path: &str) -> Result<String, String> {
+    fs::read_to_string(path)
+        .map(|s| s.trim_end_matches(['\r', '\n']).to_string())
+        .map_err(|e| format!("failed to read secret file {path}: {e}"))
+}
@@ -0,0 +1,227 @@
+use std::fmt;
+
+use axum::{
+    Json,
+    extract::{
+        FromRequest, FromRequestParts, Request,
+        rejection::{JsonRejection, QueryRejection},
+    },
+    http::{HeaderValue, StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use utoipa::ToSchema;
+
+use crate::request_id;
+
+const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+const PROBLEM_TYPE_PREFIX: &str = "urn:unifi-voucher-manager:problem:";
+
+/// Failure of an API operation, rendered as an RFC 7807 problem document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiError {
+    /// The controller refused the configured credentials
+    ControllerAuthFailed(String),
+    /// The controller could not be reached or did not answer in time
+    ControllerUnreachable(String),
+    /// The controller answered with an error, `status` is its HTTP status
+    ControllerRejected {
+        status: u16,
+        message: String,
+    },
+    /// The controller asked us to slow down
+    RateLimited {
+        retry_after: Option<u64>,
+        message: String,
+    },
+    /// The controller created fewer vouchers than requested
+    PartialCreation(String),
+    NotFound(String),
+    Validation(String),
+    /// The client did not present valid credentials
+    Unauthorized(String),
+    Forbidden(String),
+    Conflict(String),
+    /// A backend component this request needs is disabled or not ready
+    Unavailable(String),
+    Internal(String),
+}
+
+impl ApiError {
+    /// Stable identifier clients can match on.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ControllerAuthFailed(_) => "controller_auth_failed",
+            Self::ControllerUnreachable(_) => "controller_unreachable",
+            Self::ControllerRejected { .. } => "controller_rejected",
+            Self::RateLimited { .. } => "rate_limited",
+            Self::PartialCreation(_) => "partial_creation",
+            Self::NotFound(_) => "not_found",
+            Self::Validation(_) => "validation_failed",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
+            Self::Conflict(_) => "conflict",
+            Self::Unavailable(_) => "unavailable",
+            Self::Internal(_) => "internal_error",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::ControllerAuthFailed(_) => "UniFi controller login failed",
+            Self::ControllerUnreachable(_) => "UniFi controller unreachable",
+            Self::ControllerRejected { .. } => "UniFi controller rejected the request",
+            Self::RateLimited { .. } => "Too many requests",
+            Self::PartialCreation(_) => "Voucher creation incomplete",
+            Self::NotFound(_) => "Not found",
+            Self::Validation(_) => "Invalid request",
+            Self::Unauthorized(_) => "Unauthorized",
+            Self::Forbidden(_) => "Forbidden",
+            Self::Conflict(_) => "Conflict",
+            Self::Unavailable(_) => "Service unavailable",
+            Self::Internal(_) => "Internal error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::ControllerAuthFailed(_) => StatusCode::BAD_GATEWAY,
+            Self::ControllerUnreachable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            // A 4xx means the controller did not like what we asked for,
+            // anything else is the controller's own failure
+            Self::ControllerRejected { status, .. } if (400..500).contains(status) => {
+                StatusCode::BAD_REQUEST
+            }
+            Self::ControllerRejected { .. } => StatusCode::BAD_GATEWAY,
+            Self::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            Self::PartialCreation(_) => StatusCode::BAD_GATEWAY,
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn detail(&self) -> &str {
+        match self {
+            Self::ControllerAuthFailed(message)
+            | Self::ControllerUnreachable(message)
+            | Self::ControllerRejected { message, .. }
+            | Self::RateLimited { message, .. }
+            | Self::PartialCreation(message)
+            | Self::NotFound(message)
+            | Self::Validation(message)
+            | Self::Unauthorized(message)
+            | Self::Forbidden(message)
+            | Self::Conflict(message)
+            | Self::Unavailable(message)
+            | Self::Internal(message) => message,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ControllerRejected { status, message } => {
+                write!(f, "{} ({}): {}", self.title(), status, message)
+            }
+            _ => write!(f, "{}: {}", self.title(), self.detail()),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// RFC 7807 problem details body.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Problem {
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: &'static str,
+    pub status: u16,
+    pub detail: String,
+    pub code: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let problem = Problem {
+            problem_type: format!("{PROBLEM_TYPE_PREFIX}{}", self.code()),
+            title: self.title(),
+            status: status.as_u16(),
+            detail: self.detail().to_string(),
+            code: self.code(),
+            request_id: request_id::current(),
+        };
+
+        let mut response = (status, Json(problem)).into_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(PROBLEM_CONTENT_TYPE),
+        );
+        if let Self::RateLimited {
+            retry_after: Some(seconds),
+            ..
+        } = self
+        {
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::Validation(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::Validation(rejection.body_text())
+    }
+}
+
+/// `Json` extractor that reports malformed bodies as problem details.
+#[derive(Debug)]
+pub struct ApiJson<T>(pub T);
+
+impl<S, T> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(request, state).await?;
+        Ok(Self(value))
+    }
+}
+
+/// `Query` extractor that reports malformed query strings as problem details.
+#[derive(Debug)]
+pub struct ApiQuery<T>(pub T);
+
+impl<S, T> FromRequestParts<S> for ApiQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(value) =
+            axum::extract::Query::<T>::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::LazyLock;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+use crate::models::Voucher;
+
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+static EVENTS: LazyLock<broadcast::Sender<Event>> =
+    LazyLock::new(|| broadcast::channel(EVENT_CHANNEL_CAPACITY).0);
+
+/// Notifications pushed to clients of `GET /api/events`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Event {
+    RollingVoucherRedeemed {
+        pool: String,
+        voucher_id: String,
+        code: String,
+    },
+    RollingVoucherExpired {
+        pool: String,
+        voucher_id: String,
+        code: String,
+    },
+    RollingVoucherRetired {
+        pool: String,
+        voucher_id: String,
+        code: String,
+    },
+    RollingPoolToppedUp {
+        pool: String,
+        vouchers: Vec<Voucher>,
+        unused: usize,
+    },
+}
+
+pub fn publish(event: Event) {
+    // Sending only fails when nobody is listening, which is fine
+    let _ = EVENTS.send(event);
+}
+
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    EVENTS.subscribe()
+}
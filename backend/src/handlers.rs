@@ -1,15 +1,57 @@
+use std::convert::Infallible;
+
 use axum::{
-    extract::Query,
-    http::{HeaderMap, StatusCode},
-    response::Json,
+    extract::Path,
+    http::{HeaderMap, StatusCode, header},
+    response::{
+        IntoResponse, Json, Response,
+        sse::{Event as SseEvent, KeepAlive, Sse},
+    },
+};
+use chrono::Utc;
+use tokio_stream::{
+    Stream, StreamExt,
+    wrappers::{BroadcastStream, WatchStream},
 };
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
-use crate::{models::*, unifi_api::UNIFI_API};
+use crate::{
+    audit::{
+        AUDIT_LOG, Actor, AuditAction, AuditLog, AuditOutcome, AuditPage, AuditQuery, AuditRecord,
+        AuditVerification, SourceIp,
+    },
+    doctor::{self, DoctorReport},
+    environment::ENVIRONMENT,
+    error::{ApiError, ApiJson, ApiQuery, Problem},
+    events,
+    health::{self, HealthStatus, Readiness},
+    kiosks::{
+        self, KIOSK_REGISTRY, KioskRegistry, KioskStatus, RegisterKioskRequest, RegisteredKiosk,
+    },
+    logging::AUDIT_TARGET,
+    models::*,
+    openapi::ControllerErrors,
+    reports::{Report, ReportFormat, ReportQuery},
+    resilience::CircuitState,
+    scheduler::{JobKind, JobRun, JobStatus, SCHEDULER},
+    shutdown,
+    unifi_api::{UNIFI_API, client},
+    voucher_config::{RollingVoucherConfig, VOUCHER_CONFIG},
+    voucher_query::{VoucherListQuery, VoucherPage},
+};
 
-pub async fn get_vouchers_handler() -> Result<Json<GetVouchersResponse>, StatusCode> {
+#[utoipa::path(
+    get,
+    path = "/api/vouchers",
+    tag = "vouchers",
+    responses(
+        (status = 200, description = "Every voucher of the site", body = GetVouchersResponse),
+        ControllerErrors,
+    )
+)]
+pub async fn get_vouchers_handler() -> Result<Json<GetVouchersResponse>, ApiError> {
     debug!("Received request to get vouchers");
-    let client = UNIFI_API.get().expect("UnifiAPI not initialized");
+    let client = client()?;
     match client.get_all_vouchers().await {
         Ok(response) => Ok(Json(response)),
         Err(e) => {
@@ -19,26 +61,84 @@ pub async fn get_vouchers_handler() -> Result<Json<GetVouchersResponse>, StatusC
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/vouchers",
+    tag = "vouchers",
+    params(VoucherListQuery),
+    responses(
+        (status = 200, description = "One page of the matching vouchers", body = VoucherPage),
+        (status = 422, description = "Malformed query, unknown tier or invalid cursor", body = Problem, content_type = "application/problem+json"),
+        ControllerErrors,
+    )
+)]
+pub async fn list_vouchers_handler(
+    ApiQuery(query): ApiQuery<VoucherListQuery>,
+) -> Result<Json<VoucherPage>, ApiError> {
+    debug!("Received request to list vouchers: {:?}", query);
+    let client = client()?;
+    let vouchers = client.get_all_vouchers().await.map_err(|e| {
+        error!("Failed to get vouchers: {}", e);
+        e
+    })?;
+    let voucher_config = VOUCHER_CONFIG
+        .get()
+        .expect("Voucher config not initialized");
+    query.page(vouchers.data, &voucher_config.tiers).map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/vouchers/rolling",
+    tag = "rolling",
+    params(
+        ("index" = Option<usize>, Query, description = "Position among the unused vouchers of the pool, oldest first, skipping those reserved by kiosks. Defaults to 0, lets several displays show different codes"),
+        ("pool" = Option<String>, Query, description = "Rolling pool name, the default pool when absent"),
+    ),
+    responses(
+        (status = 200, description = "Unused rolling voucher at `index`", body = Voucher),
+        (status = 404, description = "Unknown pool, or no unused voucher at `index`", body = Problem, content_type = "application/problem+json"),
+        ControllerErrors,
+    )
+)]
 pub async fn get_rolling_voucher_handler(
-    Query(params): Query<std::collections::HashMap<String, String>>,
-) -> Result<Json<Voucher>, StatusCode> {
+    ApiQuery(params): ApiQuery<std::collections::HashMap<String, String>>,
+) -> Result<Json<Voucher>, ApiError> {
     debug!("Received request to get rolling voucher");
-    let client = UNIFI_API.get().expect("UnifiAPI not initialized");
-    
+    let client = client()?;
+
     // Check if an index was provided for multi-kiosk support
-    let index = params.get("index")
+    let index = params
+        .get("index")
         .and_then(|s| s.parse::<usize>().ok())
         .unwrap_or(0);
-    
-    debug!("Getting rolling voucher at index {}", index);
-    match client.get_rolling_voucher_by_index(index).await {
+
+    let pool = rolling_pool(params.get("pool").map(String::as_str))?;
+
+    // Vouchers reserved by registered kiosks are never handed out by index
+    let reserved = kiosks::reserved_voucher_ids().await;
+
+    debug!(
+        "Getting rolling voucher at index {} of pool '{}'",
+        index, pool.name
+    );
+    match client
+        .get_rolling_voucher_by_index(pool, index, &reserved)
+        .await
+    {
         Ok(Some(voucher)) => {
-            info!("Returning rolling voucher at index {}: id={}, code={}", index, voucher.id, voucher.code);
+            info!(
+                "Returning rolling voucher at index {}: id={}, code={}",
+                index, voucher.id, voucher.code
+            );
             Ok(Json(voucher))
         }
         Ok(None) => {
             info!("No rolling voucher found at index {}", index);
-            Err(StatusCode::NOT_FOUND)
+            Err(ApiError::NotFound(format!(
+                "Pool '{}' has no unused rolling voucher at index {}",
+                pool.name, index
+            )))
         }
         Err(e) => {
             error!("Failed to get rolling voucher at index {}: {}", index, e);
@@ -47,9 +147,19 @@ pub async fn get_rolling_voucher_handler(
     }
 }
 
-pub async fn get_newest_voucher_handler() -> Result<Json<Voucher>, StatusCode> {
+#[utoipa::path(
+    get,
+    path = "/api/vouchers/newest",
+    tag = "vouchers",
+    responses(
+        (status = 200, description = "Most recently created voucher", body = Voucher),
+        (status = 404, description = "There are no vouchers", body = Problem, content_type = "application/problem+json"),
+        ControllerErrors,
+    )
+)]
+pub async fn get_newest_voucher_handler() -> Result<Json<Voucher>, ApiError> {
     debug!("Received request to get newest voucher");
-    let client = UNIFI_API.get().expect("UnifiAPI not initialized");
+    let client = client()?;
     match client.get_newest_voucher().await {
         Ok(voucher) => Ok(Json(voucher)),
         Err(e) => {
@@ -59,11 +169,23 @@ pub async fn get_newest_voucher_handler() -> Result<Json<Voucher>, StatusCode> {
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/vouchers/details",
+    tag = "vouchers",
+    params(DetailsRequest),
+    responses(
+        (status = 200, description = "The voucher", body = Voucher),
+        (status = 404, description = "Unknown voucher", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Missing `id`", body = Problem, content_type = "application/problem+json"),
+        ControllerErrors,
+    )
+)]
 pub async fn get_voucher_details_handler(
-    Query(params): Query<DetailsRequest>,
-) -> Result<Json<Voucher>, StatusCode> {
+    ApiQuery(params): ApiQuery<DetailsRequest>,
+) -> Result<Json<Voucher>, ApiError> {
     debug!("Received request to get voucher details");
-    let client = UNIFI_API.get().expect("UnifiAPI not initialized");
+    let client = client()?;
     match client.get_voucher_details(params.id).await {
         Ok(voucher) => Ok(Json(voucher)),
         Err(e) => {
@@ -73,94 +195,165 @@ pub async fn get_voucher_details_handler(
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/vouchers",
+    tag = "vouchers",
+    request_body = CreateVoucherRequest,
+    responses(
+        (status = 200, description = "Created vouchers", body = CreateVoucherResponse),
+        (status = 422, description = "Malformed request body", body = Problem, content_type = "application/problem+json"),
+        ControllerErrors,
+    )
+)]
 pub async fn create_voucher_handler(
     headers: HeaderMap,
-    Json(request): Json<CreateVoucherRequest>,
-) -> Result<Json<CreateVoucherResponse>, StatusCode> {
+    SourceIp(source_ip): SourceIp,
+    ApiJson(request): ApiJson<CreateVoucherRequest>,
+) -> Result<Json<CreateVoucherResponse>, ApiError> {
     debug!("Received request to create voucher");
-    
+
     // Extract hostname and IP for logging
     let hostname = headers
         .get("host")
         .and_then(|h| h.to_str().ok())
         .unwrap_or("unknown");
-    let client_ip = headers
-        .get("x-forwarded-for")
-        .and_then(|h| h.to_str().ok())
-        .or_else(|| headers.get("x-real-ip").and_then(|h| h.to_str().ok()))
-        .unwrap_or("unknown");
-    
-    info!("Creating voucher - hostname: {}, client_ip: {}, count: {}, duration: {}min", 
-        hostname, client_ip, request.count, request.time_limit_minutes);
-    
-    let client = UNIFI_API.get().expect("UnifiAPI not initialized");
+    info!(
+        "Creating voucher - hostname: {}, client_ip: {}, count: {}, duration: {}min",
+        hostname, source_ip, request.count, request.time_limit_minutes
+    );
+
+    let audit = AuditRecord::new(AuditAction::Create, Actor::from_headers(&headers).await)
+        .source_ip(&source_ip)
+        .parameters(serde_json::to_value(&request).unwrap_or_default());
+
+    let client = client()?;
     match client.create_voucher(request.clone()).await {
         Ok(response) => {
-            info!("Voucher creation successful - hostname: {}, vouchers_created: {}", 
-                hostname, response.vouchers.len());
-            if let Some(first_voucher) = response.vouchers.first() {
-                info!("Voucher created - hostname: {}, id: {}, code: {}", 
-                    hostname, first_voucher.id, first_voucher.code);
+            audit.vouchers(&response.vouchers).record();
+            info!(
+                "Voucher creation successful - hostname: {}, vouchers_created: {}",
+                hostname,
+                response.vouchers.len()
+            );
+            for voucher in &response.vouchers {
+                info!(target: AUDIT_TARGET, event = "voucher_issued", hostname, client_ip = %source_ip,
+                    voucher_id = %voucher.id, code = %voucher.code,
+                    time_limit_minutes = voucher.time_limit_minutes, "Voucher issued");
             }
             Ok(Json(response))
         }
         Err(e) => {
-            error!("Failed to create voucher - hostname: {}, error: {}", hostname, e);
+            error!(
+                "Failed to create voucher - hostname: {}, error: {}",
+                hostname, e
+            );
+            audit.failed(&e).record();
             Err(e)
         }
     }
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/vouchers/rolling",
+    tag = "rolling",
+    params(
+        PoolRequest,
+        ("x-forwarded-for" = String, Header, description = "Address of the guest, set by the reverse proxy. Each address gets one voucher per pool"),
+    ),
+    responses(
+        (status = 200, description = "Voucher issued to the guest", body = Voucher),
+        (status = 403, description = "A voucher was already issued to this address", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown pool", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Missing `x-forwarded-for` header", body = Problem, content_type = "application/problem+json"),
+        ControllerErrors,
+    )
+)]
 pub async fn create_rolling_voucher_handler(
     headers: HeaderMap,
-) -> Result<Json<Voucher>, StatusCode> {
+    SourceIp(source_ip): SourceIp,
+    ApiQuery(params): ApiQuery<PoolRequest>,
+) -> Result<Json<Voucher>, ApiError> {
     debug!("Received request to create rolling voucher");
 
-    let client = UNIFI_API.get().expect("UnifiAPI not initialized");
-    
+    let pool = rolling_pool(params.pool.as_deref())?;
+    let client = client()?;
+
     // Extract hostname for logging
     let hostname = headers
         .get("host")
         .and_then(|h| h.to_str().ok())
         .unwrap_or("unknown");
 
-    if let Some(forwarded) = headers.get("x-forwarded-for") {
-        if let Ok(ip) = forwarded.to_str() {
-            debug!("Client IP from x-forwarded-for: {}", ip);
-            
-            info!("Creating rolling voucher - hostname: {}, client_ip: {}", hostname, ip);
+    if let Some(forwarded) = headers.get("x-forwarded-for")
+        && let Ok(ip) = forwarded.to_str()
+    {
+        debug!("Client IP from x-forwarded-for: {}", ip);
 
-            // Check if user already rotated the rolling voucher
-            if client.check_rolling_voucher_ip(ip).await? {
-                info!("Rolling voucher already rotated - hostname: {}, ip: {}", hostname, ip);
-                return Err(StatusCode::FORBIDDEN);
-            }
+        info!(
+            "Creating rolling voucher - hostname: {}, client_ip: {}, pool: {}",
+            hostname, ip, pool.name
+        );
+        let audit = AuditRecord::new(AuditAction::Create, Actor::from_headers(&headers).await)
+            .source_ip(&source_ip)
+            .parameters(serde_json::json!({ "rolling": true, "pool": pool.name }));
 
-            // Voucher rotation allowed, create a new rolling voucher
-            match client.create_rolling_voucher(ip).await {
-                Ok(response) => {
-                    info!("Rolling voucher created - hostname: {}, ip: {}, voucher_id: {}, code: {}", 
-                        hostname, ip, response.id, response.code);
-                    return Ok(Json(response));
-                }
-                Err(e) => {
-                    error!("Failed to create rolling voucher - hostname: {}, ip: {}, error: {}", 
-                        hostname, ip, e);
-                    return Err(e);
-                }
+        // Check if user already rotated the rolling voucher
+        if client.check_rolling_voucher_ip(pool, ip).await? {
+            info!(
+                "Rolling voucher already rotated - hostname: {}, ip: {}",
+                hostname, ip
+            );
+            return Err(ApiError::Forbidden(
+                "A rolling voucher was already issued to this device".to_string(),
+            ));
+        }
+
+        // Voucher rotation allowed, create a new rolling voucher
+        match client.create_rolling_voucher(pool, ip).await {
+            Ok(response) => {
+                info!(target: AUDIT_TARGET, event = "rolling_voucher_issued", hostname, client_ip = ip, pool = %pool.name,
+                    voucher_id = %response.id, code = %response.code, "Rolling voucher issued");
+                audit.vouchers([&response]).record();
+                return Ok(Json(response));
+            }
+            Err(e) => {
+                error!(
+                    "Failed to create rolling voucher - hostname: {}, ip: {}, error: {}",
+                    hostname, ip, e
+                );
+                audit.failed(&e).record();
+                return Err(e);
             }
         }
     }
 
     error!("Invalid x-forwarded-for header - hostname: {}", hostname);
-    Err(StatusCode::BAD_REQUEST)
+    Err(ApiError::Validation(
+        "The client address is missing from the x-forwarded-for header".to_string(),
+    ))
 }
 
-pub async fn get_all_rolling_vouchers_handler() -> Result<Json<Vec<Voucher>>, StatusCode> {
+#[utoipa::path(
+    get,
+    path = "/api/vouchers/rolling/all",
+    tag = "rolling",
+    params(PoolRequest),
+    responses(
+        (status = 200, description = "Unused vouchers of the pool, oldest first", body = Vec<Voucher>),
+        (status = 404, description = "Unknown pool", body = Problem, content_type = "application/problem+json"),
+        ControllerErrors,
+    )
+)]
+pub async fn get_all_rolling_vouchers_handler(
+    ApiQuery(params): ApiQuery<PoolRequest>,
+) -> Result<Json<Vec<Voucher>>, ApiError> {
     debug!("Received request to get all unused rolling vouchers");
-    let client = UNIFI_API.get().expect("UnifiAPI not initialized");
-    
-    match client.get_all_unused_rolling_vouchers().await {
+    let pool = rolling_pool(params.pool.as_deref())?;
+    let client = client()?;
+
+    match client.get_all_unused_rolling_vouchers(pool).await {
         Ok(vouchers) => {
             debug!("Found {} unused rolling vouchers", vouchers.len());
             Ok(Json(vouchers))
@@ -172,56 +365,121 @@ pub async fn get_all_rolling_vouchers_handler() -> Result<Json<Vec<Voucher>>, St
     }
 }
 
-pub async fn rotate_rolling_voucher_handler() -> Result<Json<serde_json::Value>, StatusCode> {
+#[utoipa::path(
+    post,
+    path = "/api/vouchers/rolling/rotate",
+    tag = "rolling",
+    params(PoolRequest),
+    responses(
+        (status = 200, description = "Whether the pool had to be topped up", body = RotateResponse),
+        (status = 404, description = "Unknown pool", body = Problem, content_type = "application/problem+json"),
+        ControllerErrors,
+    )
+)]
+pub async fn rotate_rolling_voucher_handler(
+    headers: HeaderMap,
+    SourceIp(source_ip): SourceIp,
+    ApiQuery(params): ApiQuery<PoolRequest>,
+) -> Result<Json<RotateResponse>, ApiError> {
     debug!("Received request to check and rotate rolling voucher if needed");
+    let pool = rolling_pool(params.pool.as_deref())?;
+    let audit = AuditRecord::new(AuditAction::Rotate, Actor::from_headers(&headers).await)
+        .source_ip(&source_ip)
+        .parameters(serde_json::json!({ "pool": pool.name }));
 
-    let client = UNIFI_API.get().expect("UnifiAPI not initialized");
-    
-    match client.create_new_rolling_voucher_if_needed().await {
-        Ok(Some(voucher)) => {
-            info!("New rolling voucher created: id={}, code={}", voucher.id, voucher.code);
-            Ok(Json(serde_json::json!({
-                "status": "created",
-                "voucher": voucher
-            })))
-        }
-        Ok(None) => {
+    let client = client()?;
+    match client.top_up_rolling_vouchers(pool).await {
+        Ok(created) if created.is_empty() => {
             debug!("No new rolling voucher needed, minimum count already met");
-            Ok(Json(serde_json::json!({
-                "status": "no_action_needed",
-                "message": "Minimum rolling vouchers already exist"
-            })))
+            Ok(Json(RotateResponse::NoActionNeeded {
+                message: "Minimum rolling vouchers already exist".to_string(),
+            }))
+        }
+        Ok(created) => {
+            info!(
+                "Created {} rolling vouchers in pool '{}'",
+                created.len(),
+                pool.name
+            );
+            audit.vouchers(&created).record();
+            Ok(Json(RotateResponse::Created { vouchers: created }))
         }
         Err(e) => {
             error!("Failed to check/create rolling voucher: {}", e);
+            audit.failed(&e).record();
             Err(e)
         }
     }
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/vouchers/selected",
+    tag = "vouchers",
+    params(DeleteRequest),
+    responses(
+        (status = 200, description = "Deleted vouchers, fewer than requested when some failed", body = DeleteResponse),
+        (status = 422, description = "Missing `ids`", body = Problem, content_type = "application/problem+json"),
+        ControllerErrors,
+    )
+)]
 pub async fn delete_selected_handler(
-    Query(params): Query<DeleteRequest>,
-) -> Result<Json<DeleteResponse>, StatusCode> {
-    info!("Received request to delete selected vouchers: ids={}", params.ids);
-    let client = UNIFI_API.get().expect("UnifiAPI not initialized");
+    headers: HeaderMap,
+    SourceIp(source_ip): SourceIp,
+    ApiQuery(params): ApiQuery<DeleteRequest>,
+) -> Result<Json<DeleteResponse>, ApiError> {
+    info!(
+        "Received request to delete selected vouchers: ids={}",
+        params.ids
+    );
+    let client = client()?;
     let ids: Vec<String> = params.ids.split(',').map(|s| s.to_string()).collect();
     info!("Parsed {} voucher IDs to delete", ids.len());
-    match client.delete_vouchers_by_ids(ids).await {
+
+    // Look up the codes so the audit trail records what was actually removed
+    let mut audit = AuditRecord::new(AuditAction::Delete, Actor::from_headers(&headers).await)
+        .source_ip(&source_ip);
+    match client.get_all_vouchers().await {
+        Ok(all) => audit = audit.removed(all.data.iter().filter(|v| ids.contains(&v.id))),
+        Err(_) => audit = audit.voucher_ids(&ids),
+    }
+
+    match client.delete_vouchers_by_ids(ids.clone()).await {
         Ok(response) => {
-            info!("Successfully deleted vouchers, response data length: {}", response.data.len());
+            info!(
+                "Successfully deleted vouchers, response data length: {}",
+                response.data.len()
+            );
+            audit.outcome(delete_outcome(&response, ids.len())).record();
             Ok(Json(response))
         }
         Err(e) => {
             error!("Failed to delete selected vouchers: {}", e);
+            audit.failed(&e).record();
             Err(e)
         }
     }
 }
 
-pub async fn delete_expired_handler() -> Result<Json<DeleteResponse>, StatusCode> {
+#[utoipa::path(
+    delete,
+    path = "/api/vouchers/expired",
+    tag = "vouchers",
+    responses(
+        (status = 200, description = "Deleted expired vouchers", body = DeleteResponse),
+        ControllerErrors,
+    )
+)]
+pub async fn delete_expired_handler(
+    headers: HeaderMap,
+    SourceIp(source_ip): SourceIp,
+) -> Result<Json<DeleteResponse>, ApiError> {
     debug!("Received request to delete expired vouchers");
-    let client = UNIFI_API.get().expect("UnifiAPI not initialized");
-    match client.delete_expired_vouchers().await {
+    let client = client()?;
+    let audit = AuditRecord::new(AuditAction::Purge, Actor::from_headers(&headers).await)
+        .source_ip(&source_ip)
+        .parameters(serde_json::json!({ "scope": "expired" }));
+    match purge_vouchers(client.get_expired_vouchers().await, audit).await {
         Ok(response) => Ok(Json(response)),
         Err(e) => {
             error!("Failed to delete expired vouchers: {}", e);
@@ -230,10 +488,38 @@ pub async fn delete_expired_handler() -> Result<Json<DeleteResponse>, StatusCode
     }
 }
 
-pub async fn delete_expired_rolling_handler() -> Result<Json<DeleteResponse>, StatusCode> {
+#[utoipa::path(
+    delete,
+    path = "/api/vouchers/expired/rolling",
+    tag = "rolling",
+    params(
+        ("pool" = Option<String>, Query, description = "Rolling pool name, every pool when absent"),
+    ),
+    responses(
+        (status = 200, description = "Deleted expired rolling vouchers", body = DeleteResponse),
+        (status = 404, description = "Unknown pool", body = Problem, content_type = "application/problem+json"),
+        ControllerErrors,
+    )
+)]
+pub async fn delete_expired_rolling_handler(
+    headers: HeaderMap,
+    SourceIp(source_ip): SourceIp,
+    ApiQuery(params): ApiQuery<PoolRequest>,
+) -> Result<Json<DeleteResponse>, ApiError> {
     debug!("Received request to delete expired rolling voucher");
-    let client = UNIFI_API.get().expect("UnifiAPI not initialized");
-    match client.delete_expired_rolling_vouchers().await {
+    // Without a pool, the expired vouchers of every pool are purged
+    let pool = match params.pool.as_deref() {
+        Some(name) => Some(rolling_pool(Some(name))?),
+        None => None,
+    };
+    let client = client()?;
+    let audit = AuditRecord::new(AuditAction::Purge, Actor::from_headers(&headers).await)
+        .source_ip(&source_ip)
+        .parameters(serde_json::json!({
+            "scope": "expired_rolling",
+            "pool": pool.map(|pool| pool.name.as_str()),
+        }));
+    match purge_vouchers(client.get_expired_rolling_vouchers(pool).await, audit).await {
         Ok(response) => Ok(Json(response)),
         Err(e) => {
             error!("Failed to delete expired rolling voucher: {}", e);
@@ -242,10 +528,392 @@ pub async fn delete_expired_rolling_handler() -> Result<Json<DeleteResponse>, St
     }
 }
 
-pub async fn health_check_handler() -> Result<Json<HealthCheckResponse>, StatusCode> {
+/// Deletes the vouchers selected for a purge and records the outcome.
+pub async fn purge_vouchers(
+    selected: Result<Vec<Voucher>, ApiError>,
+    audit: AuditRecord,
+) -> Result<DeleteResponse, ApiError> {
+    let client = client()?;
+    let vouchers = match selected {
+        Ok(vouchers) => vouchers,
+        Err(e) => {
+            audit.failed(&e).record();
+            return Err(e);
+        }
+    };
+
+    let audit = audit.removed(&vouchers);
+    let ids: Vec<String> = vouchers.into_iter().map(|v| v.id).collect();
+    match client.delete_vouchers_by_ids(ids.clone()).await {
+        Ok(response) => {
+            audit.outcome(delete_outcome(&response, ids.len())).record();
+            Ok(response)
+        }
+        Err(e) => {
+            audit.failed(&e).record();
+            Err(e)
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/audit",
+    tag = "audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "Matching entries, newest first", body = AuditPage),
+        (status = 422, description = "Malformed query", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "The audit trail is disabled", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_audit_handler(
+    ApiQuery(query): ApiQuery<AuditQuery>,
+) -> Result<Json<AuditPage>, ApiError> {
+    debug!("Received request to query the audit trail");
+    Ok(Json(audit_log()?.query(&query)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/audit/verify",
+    tag = "audit",
+    responses(
+        (status = 200, description = "Result of checking the hash chain", body = AuditVerification),
+        (status = 503, description = "The audit trail is disabled", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn verify_audit_handler() -> Result<Json<AuditVerification>, ApiError> {
+    debug!("Received request to verify the audit trail");
+    Ok(Json(audit_log()?.verify()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/reports",
+    tag = "reports",
+    params(ReportQuery),
+    responses(
+        (status = 200, description = "Figures of the vouchers created during the range, grouped by tier, pool, operator and day", content(
+            (Report = "application/json"),
+            (String = "text/csv", example = "group,key,issued,activated,...\r\ntotal,,12,9,..."),
+        )),
+        (status = 422, description = "Malformed query, `from` after `to` or a range over 366 days", body = Problem, content_type = "application/problem+json"),
+        ControllerErrors,
+    )
+)]
+pub async fn get_report_handler(
+    ApiQuery(query): ApiQuery<ReportQuery>,
+) -> Result<Response, ApiError> {
+    debug!("Received request for a report: {:?}", query);
+    let environment = ENVIRONMENT.get().expect("Environment not set");
+    let now = Utc::now();
+    let (from, to) = query.range(now.with_timezone(&environment.timezone).date_naive())?;
+
+    let report = Report::generate(from, to, now).await?;
+    Ok(match query.format.unwrap_or_default() {
+        ReportFormat::Json => Json(report).into_response(),
+        ReportFormat::Csv => (
+            [
+                (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"voucher-report-{from}-{to}.csv\""),
+                ),
+            ],
+            report.to_csv(),
+        )
+            .into_response(),
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/jobs",
+    tag = "jobs",
+    responses(
+        (status = 200, description = "Schedule and last run of every job", body = Vec<JobStatus>),
+    )
+)]
+pub async fn get_jobs_handler() -> Result<Json<Vec<JobStatus>>, ApiError> {
+    debug!("Received request to list scheduled jobs");
+    let scheduler = SCHEDULER.get().expect("Scheduler not initialized");
+    Ok(Json(scheduler.statuses().await))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/jobs/{name}/run",
+    tag = "jobs",
+    params(
+        ("name" = String, Path, description = "Job name, e.g. `purge_expired`"),
+    ),
+    responses(
+        (status = 200, description = "Outcome of the run", body = JobRun),
+        (status = 404, description = "Unknown job", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "The job is already running", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn run_job_handler(
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<Json<JobRun>, ApiError> {
+    info!("Received request to run job {}", name);
+    let kind: JobKind = name.parse().map_err(|e: String| {
+        error!("{}", e);
+        ApiError::NotFound(e)
+    })?;
+
+    let scheduler = SCHEDULER.get().expect("Scheduler not initialized");
+    match scheduler
+        .trigger(kind, Actor::from_headers(&headers).await)
+        .await
+    {
+        Ok(run) => Ok(Json(run)),
+        Err(e) => {
+            error!("Could not run job {}: {}", name, e);
+            Err(ApiError::Conflict(format!("Could not run job {name}: {e}")))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/doctor",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Redacted diagnosis of the configuration and controller connectivity", body = DoctorReport),
+    )
+)]
+pub async fn doctor_handler() -> Json<DoctorReport> {
+    info!("Received request to run the doctor");
+    let environment = ENVIRONMENT.get().expect("Environment not set");
+    Json(doctor::diagnose(environment).await)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/events",
+    tag = "events",
+    responses(
+        (status = 200, description = "Stream of events, one JSON document per `data` line", body = events::Event, content_type = "text/event-stream"),
+    )
+)]
+pub async fn events_handler() -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
+    debug!("Client subscribed to events");
+    // `None` marks the shutdown, which must close the stream for the server
+    // to finish draining its connections
+    let shutdown = WatchStream::new(shutdown::subscribe())
+        .filter(|&shutdown| shutdown)
+        .map(|_| None);
+    let stream = BroadcastStream::new(events::subscribe())
+        .map(Some)
+        .merge(shutdown)
+        .take_while(Option::is_some)
+        .filter_map(|event| match event? {
+            Ok(event) => SseEvent::default().json_data(event).ok().map(Ok),
+            Err(e) => {
+                warn!("Events subscriber fell behind: {}", e);
+                None
+            }
+        });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/kiosks",
+    tag = "kiosks",
+    responses(
+        (status = 200, description = "Registered kiosks", body = Vec<KioskStatus>),
+        (status = 503, description = "The kiosk registry is disabled", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn get_kiosks_handler() -> Result<Json<Vec<KioskStatus>>, ApiError> {
+    Ok(Json(kiosk_registry()?.list().await))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/kiosks",
+    tag = "kiosks",
+    request_body = RegisterKioskRequest,
+    responses(
+        (status = 200, description = "Registered kiosk with its device token", body = RegisteredKiosk),
+        (status = 404, description = "Unknown pool", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "Malformed request body", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "The kiosk registry is disabled", body = Problem, content_type = "application/problem+json"),
+        ControllerErrors,
+    )
+)]
+pub async fn register_kiosk_handler(
+    headers: HeaderMap,
+    ApiJson(request): ApiJson<RegisterKioskRequest>,
+) -> Result<Json<RegisteredKiosk>, ApiError> {
+    debug!("Received request to register kiosk {}", request.id);
+    let registry = kiosk_registry()?;
+    registry
+        .register(request, Actor::from_headers(&headers).await)
+        .await
+        .map(Json)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/kiosks/{id}",
+    tag = "kiosks",
+    params(
+        ("id" = String, Path, description = "Kiosk id"),
+    ),
+    responses(
+        (status = 200, description = "The removed kiosk", body = KioskStatus),
+        (status = 404, description = "Unknown kiosk", body = Problem, content_type = "application/problem+json"),
+        (status = 503, description = "The kiosk registry is disabled", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn delete_kiosk_handler(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<KioskStatus>, ApiError> {
+    debug!("Received request to remove kiosk {}", id);
+    let registry = kiosk_registry()?;
+    registry
+        .remove(&id, Actor::from_headers(&headers).await)
+        .await
+        .map(Json)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/kiosks/{id}/heartbeat",
+    tag = "kiosks",
+    params(
+        ("id" = String, Path, description = "Kiosk id"),
+    ),
+    security(("kioskToken" = [])),
+    responses(
+        (status = 200, description = "The kiosk, now online", body = KioskStatus),
+        (status = 401, description = "Missing or invalid device token", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown kiosk", body = Problem, content_type = "application/problem+json"),
+    )
+)]
+pub async fn kiosk_heartbeat_handler(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<KioskStatus>, ApiError> {
+    let registry = kiosk_registry()?;
+    registry.authenticate(&id, &headers).await?;
+    registry.heartbeat(&id).await.map(Json)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/kiosks/{id}/voucher",
+    tag = "kiosks",
+    params(
+        ("id" = String, Path, description = "Kiosk id"),
+    ),
+    security(("kioskToken" = [])),
+    responses(
+        (status = 200, description = "Voucher reserved for the kiosk", body = Voucher),
+        (status = 401, description = "Missing or invalid device token", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "Unknown kiosk", body = Problem, content_type = "application/problem+json"),
+        ControllerErrors,
+    )
+)]
+pub async fn get_kiosk_voucher_handler(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<Voucher>, ApiError> {
+    debug!("Received request for the voucher of kiosk {}", id);
+    let registry = kiosk_registry()?;
+    registry.authenticate(&id, &headers).await?;
+    match registry.reserved_voucher(&id).await {
+        Ok(voucher) => Ok(Json(voucher)),
+        Err(e) => {
+            error!("Failed to get the voucher of kiosk {}: {}", id, e);
+            Err(e)
+        }
+    }
+}
+
+/// Looks up a rolling pool by name, the default pool when none is given.
+fn rolling_pool(name: Option<&str>) -> Result<&'static RollingVoucherConfig, ApiError> {
+    let voucher_config = VOUCHER_CONFIG
+        .get()
+        .expect("Voucher config not initialized");
+    voucher_config.pool(name).ok_or_else(|| {
+        warn!(
+            "Unknown rolling pool requested: {}",
+            name.unwrap_or_default()
+        );
+        ApiError::NotFound(format!(
+            "Unknown rolling pool '{}'",
+            name.unwrap_or_default()
+        ))
+    })
+}
+
+fn kiosk_registry() -> Result<&'static KioskRegistry, ApiError> {
+    KIOSK_REGISTRY.get().ok_or_else(|| {
+        warn!("Kiosk registry is not available");
+        ApiError::Unavailable("The kiosk registry is disabled".to_string())
+    })
+}
+
+fn audit_log() -> Result<&'static AuditLog, ApiError> {
+    AUDIT_LOG.get().ok_or_else(|| {
+        error!("Audit trail requested but it is not available");
+        ApiError::Unavailable("The audit trail is disabled".to_string())
+    })
+}
+
+fn delete_outcome(response: &DeleteResponse, requested: usize) -> AuditOutcome {
+    match response.data.len() {
+        deleted if deleted == requested => AuditOutcome::Success,
+        0 => AuditOutcome::Failure,
+        _ => AuditOutcome::Partial,
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/health/live",
+    tag = "health",
+    responses(
+        (status = 200, description = "The process is running, also served at `/api/health`", body = HealthCheckResponse),
+    )
+)]
+pub async fn health_check_handler() -> Result<Json<HealthCheckResponse>, ApiError> {
     debug!("Received health check request");
+    let controller = UNIFI_API.get().map(|api| api.circuit_status());
+    // Degraded while the circuit breaker keeps requests away from the controller
+    let degraded = controller
+        .as_ref()
+        .is_some_and(|c| c.state != CircuitState::Closed);
     let response = HealthCheckResponse {
-        status: "ok".to_string(),
+        status: if degraded { "degraded" } else { "ok" }.to_string(),
+        controller,
     };
     Ok(Json(response))
 }
+
+/// Readiness probe, 503 when a component needed to serve vouchers is down.
+#[utoipa::path(
+    get,
+    path = "/api/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Voucher requests can be served", body = Readiness),
+        (status = 503, description = "A component is down", body = Readiness),
+    )
+)]
+pub async fn readiness_handler() -> (StatusCode, Json<Readiness>) {
+    debug!("Received readiness check request");
+    let readiness = health::readiness().await;
+    let status = match readiness.status {
+        HealthStatus::Down => StatusCode::SERVICE_UNAVAILABLE,
+        HealthStatus::Ok | HealthStatus::Degraded => StatusCode::OK,
+    };
+    (status, Json(readiness))
+}
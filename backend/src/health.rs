@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use utoipa::ToSchema;
+
+use crate::{
+    pool_maintainer,
+    resilience::CircuitState,
+    scheduler::{JobOutcome, SCHEDULER},
+    unifi_api::UNIFI_API,
+    voucher_config::{ConfigSource, VOUCHER_CONFIG},
+};
+
+/// Verdict of a component, ordered from best to worst.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum HealthStatus {
+    Ok,
+    /// Working, but something needs attention
+    Degraded,
+    /// Vouchers cannot be served
+    Down,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Component {
+    pub status: HealthStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub details: serde_json::Value,
+}
+
+impl Component {
+    fn ok(details: serde_json::Value) -> Self {
+        Self {
+            status: HealthStatus::Ok,
+            message: None,
+            details,
+        }
+    }
+
+    fn with(status: HealthStatus, message: impl Into<String>, details: serde_json::Value) -> Self {
+        Self {
+            status,
+            message: Some(message.into()),
+            details,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Readiness {
+    /// Worst status of all components
+    pub status: HealthStatus,
+    pub checked_at: DateTime<Utc>,
+    pub components: BTreeMap<&'static str, Component>,
+}
+
+/// Checks every component the voucher routes depend on.
+pub async fn readiness() -> Readiness {
+    let components = BTreeMap::from([
+        ("controller", controller().await),
+        ("session", session()),
+        ("sync", sync()),
+        ("config", config()),
+        ("rollingPools", rolling_pools()),
+        ("scheduler", scheduler().await),
+    ]);
+    Readiness {
+        status: components
+            .values()
+            .map(|c| c.status)
+            .max()
+            .unwrap_or(HealthStatus::Ok),
+        checked_at: Utc::now(),
+        components,
+    }
+}
+
+async fn controller() -> Component {
+    let Some(client) = UNIFI_API.get() else {
+        return Component::with(
+            HealthStatus::Down,
+            "Not connected to the UniFi controller yet",
+            json!({}),
+        );
+    };
+    let circuit = client.circuit_status();
+    match client.probe().await {
+        Ok(latency) if circuit.state == CircuitState::Closed => Component::ok(json!({
+            "latencyMs": latency.as_millis() as u64,
+            "circuit": circuit,
+        })),
+        Ok(latency) => Component::with(
+            HealthStatus::Degraded,
+            "The controller answers again but recent requests failed",
+            json!({ "latencyMs": latency.as_millis() as u64, "circuit": circuit }),
+        ),
+        Err(e) => Component::with(
+            HealthStatus::Down,
+            e.to_string(),
+            json!({ "circuit": circuit }),
+        ),
+    }
+}
+
+fn session() -> Component {
+    let Some(client) = UNIFI_API.get() else {
+        return Component::with(HealthStatus::Down, "No controller client", json!({}));
+    };
+    match client.session_info() {
+        Some(session) => match &session.last_refresh_error {
+            Some(error) => Component::with(
+                HealthStatus::Degraded,
+                format!(
+                    "Renewing the session failed, it expires at {}: {error}",
+                    session.expires_at
+                ),
+                json!(session),
+            ),
+            None => Component::ok(json!(session)),
+        },
+        None => Component::with(
+            HealthStatus::Degraded,
+            "No active session, the next request will log in again",
+            json!({}),
+        ),
+    }
+}
+
+fn sync() -> Component {
+    match UNIFI_API.get().and_then(|client| client.last_sync()) {
+        Some(sync) => Component::ok(json!({ "lastSuccess": sync })),
+        None => Component::with(
+            HealthStatus::Degraded,
+            "No controller request has succeeded yet",
+            json!({}),
+        ),
+    }
+}
+
+fn config() -> Component {
+    let Some(config) = VOUCHER_CONFIG.get() else {
+        return Component::with(
+            HealthStatus::Down,
+            "Voucher configuration not loaded",
+            json!({}),
+        );
+    };
+    let pools: Vec<&str> = config.pools.iter().map(|p| p.name.as_str()).collect();
+    let details = json!({ "source": config.source, "pools": pools });
+    match &config.source {
+        ConfigSource::File { .. } => Component::ok(details),
+        ConfigSource::Defaults { reason } => Component::with(
+            HealthStatus::Degraded,
+            format!("Using the built-in defaults: {reason}"),
+            details,
+        ),
+    }
+}
+
+fn rolling_pools() -> Component {
+    let Some(config) = VOUCHER_CONFIG.get() else {
+        return Component::with(
+            HealthStatus::Down,
+            "Voucher configuration not loaded",
+            json!({}),
+        );
+    };
+
+    let mut status = HealthStatus::Ok;
+    let mut problems = Vec::new();
+    let mut pools = serde_json::Map::new();
+    for pool in config.enabled_pools() {
+        let observation = pool_maintainer::observation(&pool.name);
+        match &observation {
+            None => {
+                status = status.max(HealthStatus::Degraded);
+                problems.push(format!("'{}' has not been checked yet", pool.name));
+            }
+            Some(seen) if seen.unused < pool.min_rolling_vouchers as usize => {
+                status = status.max(HealthStatus::Degraded);
+                problems.push(format!(
+                    "'{}' has {} unused vouchers, below its minimum of {}",
+                    pool.name, seen.unused, pool.min_rolling_vouchers
+                ));
+            }
+            Some(_) => {}
+        }
+        pools.insert(
+            pool.name.clone(),
+            json!({ "minimum": pool.min_rolling_vouchers, "observed": observation }),
+        );
+    }
+
+    Component {
+        status,
+        message: (!problems.is_empty()).then(|| problems.join(", ")),
+        details: json!(pools),
+    }
+}
+
+async fn scheduler() -> Component {
+    let Some(scheduler) = SCHEDULER.get() else {
+        return Component::with(HealthStatus::Degraded, "Scheduler not started", json!({}));
+    };
+    let jobs = scheduler.statuses().await;
+    let failed: Vec<&str> = jobs
+        .iter()
+        .filter(|job| {
+            job.enabled
+                && job
+                    .last_run
+                    .as_ref()
+                    .is_some_and(|run| run.outcome == JobOutcome::Failure)
+        })
+        .map(|job| job.name)
+        .collect();
+
+    if failed.is_empty() {
+        Component::ok(json!(jobs))
+    } else {
+        Component::with(
+            HealthStatus::Degraded,
+            format!("Last run failed: {}", failed.join(", ")),
+            json!(jobs),
+        )
+    }
+}
@@ -0,0 +1,449 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use axum::http::HeaderMap;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+use crate::{
+    audit::{Actor, AuditAction, AuditRecord, hex},
+    error::ApiError,
+    locks::KeyedMutex,
+    models::Voucher,
+    unifi_api::client,
+    voucher_config::{RollingVoucherConfig, VOUCHER_CONFIG},
+};
+
+pub static KIOSK_REGISTRY: OnceLock<KioskRegistry> = OnceLock::new();
+
+const KIOSKS_FILE_NAME: &str = "kiosks.json";
+// A kiosk counts as offline once it missed a few heartbeats
+const ONLINE_WINDOW: Duration = Duration::minutes(2);
+
+/// Voucher held for a kiosk until it is redeemed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Reservation {
+    voucher_id: String,
+    code: String,
+    /// Guest count when the voucher was last seen, to count new redemptions
+    guest_count: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Kiosk {
+    id: String,
+    name: String,
+    pool: String,
+    /// SHA-256 of the device token, the token itself is never stored
+    token_hash: String,
+    registered_at: DateTime<Utc>,
+    last_seen: Option<DateTime<Utc>>,
+    reservation: Option<Reservation>,
+    redemptions: u64,
+}
+
+impl Kiosk {
+    fn status(&self, now: DateTime<Utc>) -> KioskStatus {
+        KioskStatus {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            pool: self.pool.clone(),
+            registered_at: self
+                .registered_at
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+            last_seen: self
+                .last_seen
+                .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            online: self.last_seen.is_some_and(|t| now - t <= ONLINE_WINDOW),
+            voucher_id: self.reservation.as_ref().map(|r| r.voucher_id.clone()),
+            current_code: self.reservation.as_ref().map(|r| r.code.clone()),
+            redemptions: self.redemptions,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct KioskStatus {
+    pub id: String,
+    pub name: String,
+    pub pool: String,
+    pub registered_at: String,
+    pub last_seen: Option<String>,
+    pub online: bool,
+    pub voucher_id: Option<String>,
+    pub current_code: Option<String>,
+    pub redemptions: u64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterKioskRequest {
+    pub id: String,
+    pub name: Option<String>,
+    /// Rolling pool the kiosk hands out vouchers from, the default pool if unset
+    pub pool: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisteredKiosk {
+    #[serde(flatten)]
+    pub kiosk: KioskStatus,
+    /// Device token, only returned once at registration
+    pub token: String,
+}
+
+/// Registered kiosks and the rolling voucher reserved for each of them.
+#[derive(Debug)]
+pub struct KioskRegistry {
+    path: PathBuf,
+    // Never held across controller calls, so that a slow controller does not
+    // hold up the other kiosks
+    kiosks: Mutex<Vec<Kiosk>>,
+    /// Serialises the reservations of each kiosk
+    reserving: KeyedMutex,
+}
+
+impl KioskRegistry {
+    pub fn try_new(data_dir: &Path) -> Result<Self, String> {
+        fs::create_dir_all(data_dir).map_err(|e| {
+            format!(
+                "Failed to create data directory {}: {e}",
+                data_dir.display()
+            )
+        })?;
+        let path = data_dir.join(KIOSKS_FILE_NAME);
+
+        let kiosks: Vec<Kiosk> = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("Failed to parse kiosk registry {}: {e}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(format!(
+                    "Failed to read kiosk registry {}: {e}",
+                    path.display()
+                ));
+            }
+        };
+        info!(
+            "Loaded {} registered kiosks from {}",
+            kiosks.len(),
+            path.display()
+        );
+
+        Ok(Self {
+            path,
+            kiosks: Mutex::new(kiosks),
+            reserving: KeyedMutex::default(),
+        })
+    }
+
+    pub async fn list(&self) -> Vec<KioskStatus> {
+        let now = Utc::now();
+        self.kiosks
+            .lock()
+            .await
+            .iter()
+            .map(|k| k.status(now))
+            .collect()
+    }
+
+    pub async fn register(
+        &self,
+        request: RegisterKioskRequest,
+        actor: Actor,
+    ) -> Result<RegisteredKiosk, ApiError> {
+        let id = request.id.trim().to_string();
+        if id.is_empty()
+            || !id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            warn!("Rejected kiosk registration with invalid id '{}'", id);
+            return Err(ApiError::Validation(
+                "Kiosk ids may only contain letters, digits, '-' and '_'".to_string(),
+            ));
+        }
+        let pool = rolling_pool(request.pool.as_deref())?;
+
+        let mut kiosks = self.kiosks.lock().await;
+        if kiosks.iter().any(|k| k.id == id) {
+            warn!("Kiosk '{}' is already registered", id);
+            return Err(ApiError::Conflict(format!(
+                "Kiosk '{id}' is already registered"
+            )));
+        }
+
+        let mut token = [0u8; 32];
+        rand::rng().fill_bytes(&mut token);
+        let token = hex(&token);
+
+        let kiosk = Kiosk {
+            name: request
+                .name
+                .map(|n| n.trim().to_string())
+                .filter(|n| !n.is_empty())
+                .unwrap_or_else(|| id.clone()),
+            id,
+            pool: pool.name.clone(),
+            token_hash: token_hash(&token),
+            registered_at: Utc::now(),
+            last_seen: None,
+            reservation: None,
+            redemptions: 0,
+        };
+        info!(
+            "Registered kiosk '{}' ({}) on pool '{}'",
+            kiosk.id, kiosk.name, kiosk.pool
+        );
+        AuditRecord::new(AuditAction::KioskRegister, actor)
+            .parameters(
+                serde_json::json!({ "kiosk": kiosk.id, "name": kiosk.name, "pool": kiosk.pool }),
+            )
+            .record();
+
+        let status = kiosk.status(Utc::now());
+        kiosks.push(kiosk);
+        self.save(&kiosks);
+        Ok(RegisteredKiosk {
+            kiosk: status,
+            token,
+        })
+    }
+
+    /// Removes a kiosk. Its reserved voucher goes back to the pool.
+    pub async fn remove(&self, id: &str, actor: Actor) -> Result<KioskStatus, ApiError> {
+        let mut kiosks = self.kiosks.lock().await;
+        let Some(index) = kiosks.iter().position(|k| k.id == id) else {
+            return Err(unknown_kiosk(id));
+        };
+        let kiosk = kiosks.remove(index);
+        info!("Removed kiosk '{}'", kiosk.id);
+        AuditRecord::new(AuditAction::KioskRemove, actor)
+            .parameters(serde_json::json!({ "kiosk": kiosk.id }))
+            .record();
+        self.save(&kiosks);
+        Ok(kiosk.status(Utc::now()))
+    }
+
+    /// Checks the device token sent as `Authorization: Bearer <token>`.
+    pub async fn authenticate(&self, id: &str, headers: &HeaderMap) -> Result<(), ApiError> {
+        let token = headers
+            .get("authorization")
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .map(str::trim)
+            .ok_or_else(|| ApiError::Unauthorized("Missing kiosk device token".to_string()))?;
+
+        let kiosks = self.kiosks.lock().await;
+        match kiosks.iter().find(|k| k.id == id) {
+            Some(kiosk) if kiosk.token_hash == token_hash(token) => Ok(()),
+            Some(_) => {
+                warn!("Kiosk '{}' sent an invalid device token", id);
+                Err(ApiError::Unauthorized(
+                    "Invalid kiosk device token".to_string(),
+                ))
+            }
+            None => Err(unknown_kiosk(id)),
+        }
+    }
+
+    /// Whether `token` is the device token of the kiosk `id`.
+    pub async fn has_token(&self, id: &str, token: &str) -> bool {
+        let kiosks = self.kiosks.lock().await;
+        kiosks
+            .iter()
+            .any(|k| k.id == id && k.token_hash == token_hash(token))
+    }
+
+    pub async fn heartbeat(&self, id: &str) -> Result<KioskStatus, ApiError> {
+        let mut kiosks = self.kiosks.lock().await;
+        let now = Utc::now();
+        let kiosk = kiosks
+            .iter_mut()
+            .find(|k| k.id == id)
+            .ok_or_else(|| unknown_kiosk(id))?;
+        kiosk.last_seen = Some(now);
+        let status = kiosk.status(now);
+        self.save(&kiosks);
+        Ok(status)
+    }
+
+    /// Returns the voucher reserved for a kiosk, reserving a new one once the
+    /// previous one was redeemed, expired or removed.
+    pub async fn reserved_voucher(&self, id: &str) -> Result<Voucher, ApiError> {
+        let client = client()?;
+        let _reserving = self.reserving.lock(id).await;
+
+        let (pool, reservation) = {
+            let kiosks = self.kiosks.lock().await;
+            let kiosk = kiosks
+                .iter()
+                .find(|k| k.id == id)
+                .ok_or_else(|| unknown_kiosk(id))?;
+            let pool = rolling_pool(Some(&kiosk.pool)).map_err(|_| {
+                error!(
+                    "Kiosk '{}' uses the unknown rolling pool '{}'",
+                    id, kiosk.pool
+                );
+                ApiError::Conflict(format!(
+                    "Kiosk '{}' uses the rolling pool '{}', which is no longer configured",
+                    id, kiosk.pool
+                ))
+            })?;
+            (pool, kiosk.reservation.clone())
+        };
+
+        let vouchers = client.get_all_vouchers().await?.data;
+
+        // The kiosk may have been removed meanwhile
+        let mut kiosks = self.kiosks.lock().await;
+        let index = kiosks
+            .iter()
+            .position(|k| k.id == id)
+            .ok_or_else(|| unknown_kiosk(id))?;
+        kiosks[index].last_seen = Some(Utc::now());
+
+        if let Some(reservation) = reservation {
+            let current = vouchers.iter().find(|v| v.id == reservation.voucher_id);
+            if let Some(current) = current {
+                let kiosk = &mut kiosks[index];
+                kiosk.redemptions += current
+                    .authorized_guest_count
+                    .saturating_sub(reservation.guest_count);
+                if client.is_unused_rolling_voucher(pool, current) {
+                    if let Some(r) = kiosk.reservation.as_mut() {
+                        r.guest_count = current.authorized_guest_count;
+                    }
+                    let voucher = current.clone();
+                    self.save(&kiosks);
+                    return Ok(voucher);
+                }
+            }
+            info!(
+                "Releasing voucher {} of kiosk '{}'",
+                reservation.voucher_id, id
+            );
+            kiosks[index].reservation = None;
+        }
+
+        // Oldest unused voucher of the pool that no other kiosk holds
+        let reserved: HashSet<&str> = kiosks
+            .iter()
+            .filter_map(|k| k.reservation.as_ref().map(|r| r.voucher_id.as_str()))
+            .collect();
+        let mut candidates: Vec<&Voucher> = vouchers
+            .iter()
+            .filter(|v| {
+                client.is_unused_rolling_voucher(pool, v) && !reserved.contains(v.id.as_str())
+            })
+            .collect();
+        candidates.sort_by_key(|voucher| voucher.created_at_epoch);
+
+        let voucher = match candidates.first() {
+            Some(voucher) => (*voucher).clone(),
+            None => {
+                self.save(&kiosks);
+                drop(kiosks);
+                let audit = AuditRecord::new(AuditAction::Create, Actor::Kiosk(id.to_string()))
+                    .parameters(
+                        serde_json::json!({ "rolling": true, "pool": pool.name, "kiosk": id }),
+                    );
+                let voucher = match client.create_kiosk_voucher(pool, id).await {
+                    Ok(voucher) => {
+                        audit.vouchers([&voucher]).record();
+                        voucher
+                    }
+                    Err(e) => {
+                        error!("Failed to create a voucher for kiosk '{}': {}", id, e);
+                        audit.failed(&e).record();
+                        return Err(e);
+                    }
+                };
+                kiosks = self.kiosks.lock().await;
+                voucher
+            }
+        };
+
+        // Left in the pool if the kiosk was removed while it was created
+        let kiosk = kiosks
+            .iter_mut()
+            .find(|k| k.id == id)
+            .ok_or_else(|| unknown_kiosk(id))?;
+        info!("Reserved voucher {} for kiosk '{}'", voucher.id, id);
+        kiosk.reservation = Some(Reservation {
+            voucher_id: voucher.id.clone(),
+            code: voucher.code.clone(),
+            guest_count: voucher.authorized_guest_count,
+        });
+        self.save(&kiosks);
+        Ok(voucher)
+    }
+
+    /// Ids of the vouchers currently reserved by a kiosk.
+    pub async fn reserved_voucher_ids(&self) -> HashSet<String> {
+        self.kiosks
+            .lock()
+            .await
+            .iter()
+            .filter_map(|k| k.reservation.as_ref().map(|r| r.voucher_id.clone()))
+            .collect()
+    }
+
+    fn save(&self, kiosks: &[Kiosk]) {
+        let content = serde_json::to_string_pretty(kiosks).expect("Kiosks are serializable");
+        // Write to a temporary file first so a crash never leaves a truncated registry
+        let tmp_path = self.path.with_extension("json.tmp");
+        if let Err(e) =
+            fs::write(&tmp_path, content).and_then(|_| fs::rename(&tmp_path, &self.path))
+        {
+            error!(
+                "Failed to persist kiosk registry {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Ids of the vouchers reserved by registered kiosks, none without a
+/// registry.
+pub async fn reserved_voucher_ids() -> HashSet<String> {
+    match KIOSK_REGISTRY.get() {
+        Some(registry) => registry.reserved_voucher_ids().await,
+        None => HashSet::new(),
+    }
+}
+
+fn rolling_pool(name: Option<&str>) -> Result<&'static RollingVoucherConfig, ApiError> {
+    VOUCHER_CONFIG
+        .get()
+        .expect("Voucher config not initialized")
+        .pool(name)
+        .ok_or_else(|| {
+            ApiError::NotFound(format!(
+                "Unknown rolling pool '{}'",
+                name.unwrap_or_default()
+            ))
+        })
+}
+
+fn unknown_kiosk(id: &str) -> ApiError {
+    ApiError::NotFound(format!("Kiosk '{id}' is not registered"))
+}
+
+fn token_hash(token: &str) -> String {
+    hex(&Sha256::digest(token.as_bytes()))
+}
@@ -1,6 +1,30 @@
+pub mod app;
+pub mod audit;
+pub mod cli;
+pub mod commands;
+pub mod doctor;
 pub mod environment;
+pub mod error;
+pub mod events;
 pub mod handlers;
+pub mod health;
+pub mod kiosks;
+pub mod listener;
+pub mod locks;
+pub mod logging;
 pub mod models;
+pub mod openapi;
+pub mod output;
+pub mod pool_maintainer;
+pub mod reports;
+pub mod request_id;
+pub mod resilience;
+pub mod scheduler;
+pub mod session;
+pub mod shutdown;
 pub mod tasks;
+pub mod tls;
+pub mod totp;
 pub mod unifi_api;
 pub mod voucher_config;
+pub mod voucher_query;
@@ -0,0 +1,382 @@
+use std::{
+    fmt, fs,
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use axum::{
+    Router,
+    extract::connect_info::Connected,
+    serve::{IncomingStream, Listener},
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    task::JoinHandle,
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    },
+    server::TlsStream,
+};
+use tracing::{debug, info, warn};
+
+use crate::{environment::Environment, shutdown};
+
+/// How often the certificate and key files are checked for changes
+pub const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// Connections that do not complete the handshake in time are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Handshaken connections waiting for the server to pick them up
+const ACCEPT_BACKLOG: usize = 64;
+
+/// Where a connection came from, available to handlers and middleware as
+/// `ConnectInfo<Peer>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Peer {
+    Ip(IpAddr),
+    /// Any process allowed to connect to the Unix socket
+    UnixSocket,
+}
+
+impl Peer {
+    /// Comma separated IP addresses, `unix` standing for the Unix socket.
+    pub fn parse_list(list: &str) -> Result<Vec<Self>, String> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|peer| !peer.is_empty())
+            .map(|peer| match peer {
+                "unix" => Ok(Self::UnixSocket),
+                address => address
+                    .parse()
+                    .map(Self::Ip)
+                    .map_err(|_| format!("'{address}' is neither an IP address nor 'unix'")),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(address) => address.fmt(f),
+            Self::UnixSocket => f.write_str("unix"),
+        }
+    }
+}
+
+impl Connected<IncomingStream<'_, TcpListener>> for Peer {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        Self::Ip(stream.remote_addr().ip())
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for Peer {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        Self::Ip(stream.remote_addr().ip())
+    }
+}
+
+#[cfg(unix)]
+impl Connected<IncomingStream<'_, tokio::net::UnixListener>> for Peer {
+    fn connect_info(_stream: IncomingStream<'_, tokio::net::UnixListener>) -> Self {
+        Self::UnixSocket
+    }
+}
+
+/// Certificate and private key the backend serves HTTPS with.
+#[derive(Debug, Clone)]
+pub struct ServerTls {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl ServerTls {
+    /// Checks that the PEM files at `cert` and `key` form a usable pair.
+    pub fn load(cert: PathBuf, key: PathBuf) -> Result<Self, String> {
+        load_server_config(&cert, &key)?;
+        Ok(Self { cert, key })
+    }
+}
+
+/// Reads a PEM certificate chain and private key into a server configuration.
+pub fn load_server_config(cert: &Path, key: &Path) -> Result<Arc<ServerConfig>, String> {
+    let certificates = CertificateDer::pem_file_iter(cert)
+        .map_err(|e| format!("failed to read {}: {e}", cert.display()))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid PEM in {}: {e}", cert.display()))?;
+    if certificates.is_empty() {
+        return Err(format!("{} contains no certificate", cert.display()));
+    }
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| format!("failed to read the private key in {}: {e}", key.display()))?;
+    let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("failed to set up TLS: {e}"))?
+        .with_no_client_auth()
+        .with_single_cert(certificates, key)
+        .map_err(|e| format!("the certificate and key do not form a valid pair: {e}"))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// HTTPS listener. Handshakes run in the background so that a slow client
+/// does not hold up the others, and the certificate is reloaded when its
+/// files change, without dropping open connections.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    accepted: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    /// Listens on `address`, checking every `reload_interval` whether the
+    /// certificate changed.
+    pub async fn bind(
+        address: &str,
+        tls: &ServerTls,
+        reload_interval: Duration,
+    ) -> Result<Self, String> {
+        let config = load_server_config(&tls.cert, &tls.key)
+            .map_err(|e| format!("Failed to load the TLS certificate: {e}"))?;
+        let acceptor = Arc::new(RwLock::new(TlsAcceptor::from(config)));
+        let listener = TcpListener::bind(address)
+            .await
+            .map_err(|e| format!("Could not bind listener on {address}: {e}"))?;
+        let local_addr = listener
+            .local_addr()
+            .map_err(|e| format!("Could not read the address of the listener: {e}"))?;
+
+        tokio::spawn(reload_on_change(
+            tls.clone(),
+            reload_interval,
+            acceptor.clone(),
+        ));
+        let (sender, accepted) = mpsc::channel(ACCEPT_BACKLOG);
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = tokio::select! {
+                    result = listener.accept() => match result {
+                        Ok(connection) => connection,
+                        Err(e) => {
+                            // Such as too many open files, which clears up
+                            warn!("Failed to accept a connection: {}", e);
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                            continue;
+                        }
+                    },
+                    _ = shutdown::requested() => return,
+                };
+                let acceptor = acceptor.read().expect("TLS acceptor lock poisoned").clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send((stream, peer)).await;
+                        }
+                        Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", peer, e),
+                        Err(_) => debug!("TLS handshake with {} timed out", peer),
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            local_addr,
+            accepted,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.accepted.recv().await {
+            Some(connection) => connection,
+            // Only once the shutdown started, when the server stops accepting
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Swaps in the certificate whenever the certificate or key file changes,
+/// e.g. after a renewal. A pair that fails to load is reported and the
+/// previous one kept, as the two files may not be updated at once.
+async fn reload_on_change(tls: ServerTls, interval: Duration, acceptor: Arc<RwLock<TlsAcceptor>>) {
+    let mut loaded = (modified(&tls.cert), modified(&tls.key));
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(interval) => {}
+            _ = shutdown::requested() => return,
+        }
+        let current = (modified(&tls.cert), modified(&tls.key));
+        if current == loaded {
+            continue;
+        }
+        // Tried once per change, the next write to either file retries
+        loaded = current;
+        match load_server_config(&tls.cert, &tls.key) {
+            Ok(config) => {
+                *acceptor.write().expect("TLS acceptor lock poisoned") = TlsAcceptor::from(config);
+                info!("Reloaded the TLS certificate from {}", tls.cert.display());
+            }
+            Err(e) => warn!(
+                "Failed to reload the TLS certificate, keeping the previous one: {}",
+                e
+            ),
+        }
+    }
+}
+
+/// Binds a Unix domain socket at `path` with the permission bits `mode`,
+/// replacing a socket a previous run left behind but not one another server
+/// still listens on.
+///
+/// The socket is bound in a directory only the owner can enter and moved to
+/// `path` once its mode is set, so it is never reachable with the looser bits
+/// of the umask.
+#[cfg(unix)]
+pub fn bind_unix(path: &Path, mode: u32) -> Result<tokio::net::UnixListener, String> {
+    use std::os::unix::{
+        fs::{DirBuilderExt, FileTypeExt, PermissionsExt},
+        net::UnixStream,
+    };
+
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(format!("{} exists and is not a socket", path.display()));
+        }
+        match UnixStream::connect(path) {
+            Ok(_) => return Err(format!("{} is in use by another server", path.display())),
+            // Nothing listens on it any more, the rename below replaces it
+            Err(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {}
+            Err(e) => {
+                return Err(format!(
+                    "Could not tell whether {} is in use: {e}",
+                    path.display()
+                ));
+            }
+        }
+    }
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("{} does not name a socket file", path.display()))?;
+    let parent = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    // Next to the socket, as the rename cannot cross file systems
+    let private = parent.join(format!(
+        ".{}.{}",
+        file_name.to_string_lossy(),
+        std::process::id()
+    ));
+    // Left behind by a crashed run that had the same process id, as in a
+    // container
+    let _ = fs::remove_dir_all(&private);
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&private)
+        .map_err(|e| format!("Failed to create {}: {e}", private.display()))?;
+    let staged = private.join("socket");
+    let result = tokio::net::UnixListener::bind(&staged)
+        .map_err(|e| format!("Could not bind the Unix socket {}: {e}", path.display()))
+        .and_then(|listener| {
+            fs::set_permissions(&staged, fs::Permissions::from_mode(mode))
+                .map_err(|e| format!("Failed to set the permissions of {}: {e}", path.display()))?;
+            fs::rename(&staged, path)
+                .map_err(|e| format!("Failed to move the socket to {}: {e}", path.display()))?;
+            Ok(listener)
+        });
+    let _ = fs::remove_dir_all(&private);
+    result
+}
+
+/// Binds the configured listeners and serves `app` on each of them until the
+/// shutdown starts. Every listener is bound before any is served, so that a
+/// bad address or socket path fails the startup.
+pub async fn start(
+    environment: &Environment,
+    app: Router,
+) -> Result<Vec<JoinHandle<Result<(), String>>>, String> {
+    let mut servers = Vec::new();
+    if environment.backend_tcp_enabled {
+        let address = format!(
+            "{}:{}",
+            environment.backend_bind_host, environment.backend_bind_port
+        );
+        match &environment.backend_tls {
+            Some(tls) => {
+                let listener = TlsListener::bind(&address, tls, RELOAD_CHECK_INTERVAL).await?;
+                info!("Server running on https://{}", address);
+                servers.push(spawn_server(listener, app.clone(), None));
+            }
+            None => {
+                let listener = TcpListener::bind(&address)
+                    .await
+                    .map_err(|e| format!("Could not bind listener on {address}: {e}"))?;
+                info!("Server running on http://{}", address);
+                servers.push(spawn_server(listener, app.clone(), None));
+            }
+        }
+    }
+    #[cfg(unix)]
+    if let Some(path) = &environment.backend_unix_socket {
+        let listener = bind_unix(path, environment.backend_unix_socket_mode)?;
+        info!(
+            "Server running on unix:{} (mode {:o})",
+            path.display(),
+            environment.backend_unix_socket_mode
+        );
+        servers.push(spawn_server(listener, app, Some(path.clone())));
+    }
+    #[cfg(not(unix))]
+    if environment.backend_unix_socket.is_some() {
+        return Err("Unix sockets are not supported on this platform".to_string());
+    }
+    Ok(servers)
+}
+
+/// Serves `app` until the shutdown starts and in-flight requests finished,
+/// then removes the socket file at `cleanup`, if any.
+fn spawn_server<L>(
+    listener: L,
+    app: Router,
+    cleanup: Option<PathBuf>,
+) -> JoinHandle<Result<(), String>>
+where
+    L: Listener,
+    L::Addr: std::fmt::Debug,
+    Peer: for<'a> Connected<IncomingStream<'a, L>>,
+{
+    tokio::spawn(async move {
+        let result = axum::serve(listener, app.into_make_service_with_connect_info::<Peer>())
+            .with_graceful_shutdown(shutdown::requested())
+            .await
+            .map_err(|e| format!("Server failed: {e}"));
+        if let Some(path) = cleanup
+            && let Err(e) = fs::remove_file(&path)
+        {
+            warn!("Failed to remove the socket {}: {}", path.display(), e);
+        }
+        result
+    })
+}
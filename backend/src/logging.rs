@@ -0,0 +1,433 @@
+use std::{
+    io::{self, Write},
+    net::{TcpStream, ToSocketAddrs, UdpSocket},
+    path::Path,
+    str::FromStr,
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread,
+    time::Duration,
+};
+
+use chrono::{SecondsFormat, Utc};
+use tracing::{Level, Metadata};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry,
+    filter::{LevelFilter, Targets},
+    fmt::{self, MakeWriter},
+    prelude::*,
+};
+
+use crate::environment::Environment;
+
+/// Target used for events that must end up in the dedicated audit log.
+pub const AUDIT_TARGET: &str = "audit";
+
+const LOG_FILE_NAME: &str = "vouchers.log";
+const AUDIT_LOG_FILE_NAME: &str = "audit.log";
+const SYSLOG_APP_NAME: &str = "unifi-voucher-manager";
+// user-level messages
+const SYSLOG_FACILITY: u8 = 1;
+const SYSLOG_QUEUE_SIZE: usize = 1024;
+const SYSLOG_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("Log format must be text or json, found: {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    Daily,
+    Never,
+}
+
+impl LogRotation {
+    fn as_rotation(self) -> Rotation {
+        match self {
+            Self::Minutely => Rotation::MINUTELY,
+            Self::Hourly => Rotation::HOURLY,
+            Self::Daily => Rotation::DAILY,
+            Self::Never => Rotation::NEVER,
+        }
+    }
+}
+
+impl FromStr for LogRotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "minutely" => Ok(Self::Minutely),
+            "hourly" => Ok(Self::Hourly),
+            "daily" => Ok(Self::Daily),
+            "never" => Ok(Self::Never),
+            _ => Err(format!(
+                "Log rotation must be minutely, hourly, daily or never, found: {s}"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyslogProtocol {
+    Udp,
+    Tcp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyslogConfig {
+    pub protocol: SyslogProtocol,
+    /// `host:port` of the syslog server
+    pub address: String,
+}
+
+impl FromStr for SyslogConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (protocol, address) = match s.trim().split_once("://") {
+            Some(("udp", address)) => (SyslogProtocol::Udp, address),
+            Some(("tcp", address)) => (SyslogProtocol::Tcp, address),
+            _ => {
+                return Err(format!(
+                    "Syslog URL must be udp://host:port or tcp://host:port, found: {s}"
+                ));
+            }
+        };
+
+        if address
+            .rsplit_once(':')
+            .is_none_or(|(_, port)| port.parse::<u16>().is_err())
+        {
+            return Err(format!("Syslog URL must include a valid port, found: {s}"));
+        }
+
+        Ok(Self {
+            protocol,
+            address: address.to_string(),
+        })
+    }
+}
+
+/// Installs the global tracing subscriber described by the environment.
+///
+/// The returned guards flush the file writers when dropped and must be kept
+/// alive for as long as logging is needed.
+pub fn init(environment: &Environment) -> Result<Vec<WorkerGuard>, String> {
+    let mut layers: Vec<BoxedLayer> = Vec::new();
+    let mut guards = Vec::new();
+
+    layers.push(
+        format_layer(environment.log_format, std::io::stdout, true)
+            .with_filter(env_filter(LevelFilter::INFO))
+            .boxed(),
+    );
+
+    if let Some(log_dir) = environment.log_dir.as_deref()
+        && ensure_log_dir(log_dir)
+    {
+        let (writer, guard) =
+            tracing_appender::non_blocking(file_appender(environment, log_dir, LOG_FILE_NAME)?);
+        guards.push(guard);
+        layers.push(
+            format_layer(environment.log_format, writer, false)
+                .with_filter(env_filter(LevelFilter::INFO))
+                .boxed(),
+        );
+
+        // The audit log ignores BACKEND_LOG_LEVEL so issuance events are
+        // always recorded.
+        let (writer, guard) = tracing_appender::non_blocking(file_appender(
+            environment,
+            log_dir,
+            AUDIT_LOG_FILE_NAME,
+        )?);
+        guards.push(guard);
+        layers.push(
+            format_layer(environment.log_format, writer, false)
+                .with_filter(Targets::new().with_target(AUDIT_TARGET, LevelFilter::INFO))
+                .boxed(),
+        );
+    }
+
+    if let Some(syslog) = &environment.syslog {
+        layers.push(
+            fmt::layer()
+                .with_writer(SyslogWriter::spawn(syslog.clone()))
+                .with_ansi(false)
+                .without_time()
+                .with_level(false)
+                .with_filter(env_filter(LevelFilter::INFO))
+                .boxed(),
+        );
+    }
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .try_init()
+        .map_err(|e| format!("Failed to install tracing subscriber: {e}"))?;
+
+    Ok(guards)
+}
+
+/// Logs warnings and errors to stderr only, for the admin commands whose
+/// output goes to stdout.
+pub fn init_cli(environment: &Environment) -> Result<(), String> {
+    tracing_subscriber::registry()
+        .with(
+            format_layer(environment.log_format, io::stderr, true)
+                .with_filter(env_filter(LevelFilter::WARN)),
+        )
+        .try_init()
+        .map_err(|e| format!("Failed to install tracing subscriber: {e}"))
+}
+
+fn env_filter(default: LevelFilter) -> EnvFilter {
+    EnvFilter::builder()
+        .with_env_var("BACKEND_LOG_LEVEL")
+        .with_default_directive(default.into())
+        .from_env_lossy()
+}
+
+fn format_layer<W>(format: LogFormat, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+fn ensure_log_dir(log_dir: &Path) -> bool {
+    if log_dir.exists() {
+        return true;
+    }
+
+    match std::fs::create_dir_all(log_dir) {
+        Ok(()) => true,
+        Err(e) => {
+            eprintln!(
+                "Failed to create logs directory {}: {}",
+                log_dir.display(),
+                e
+            );
+            eprintln!("Logging to console only");
+            false
+        }
+    }
+}
+
+fn file_appender(
+    environment: &Environment,
+    log_dir: &Path,
+    file_name: &str,
+) -> Result<RollingFileAppender, String> {
+    let mut builder = RollingFileAppender::builder()
+        .rotation(environment.log_rotation.as_rotation())
+        .filename_prefix(file_name);
+    if environment.log_max_files > 0 {
+        builder = builder.max_log_files(environment.log_max_files);
+    }
+    builder
+        .build(log_dir)
+        .map_err(|e| format!("Failed to create log file {file_name}: {e}"))
+}
+
+/// Deletes rotated log files that have not been written to for `days` days.
+pub fn remove_logs_older_than(log_dir: &Path, days: u64) -> io::Result<usize> {
+    let max_age = Duration::from_secs(days * 24 * 60 * 60);
+    let mut removed = 0;
+
+    for entry in std::fs::read_dir(log_dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if !name.starts_with(LOG_FILE_NAME) && !name.starts_with(AUDIT_LOG_FILE_NAME) {
+            continue;
+        }
+        // Without rotation the file being written has no date suffix and
+        // would only get older
+        if name == LOG_FILE_NAME || name == AUDIT_LOG_FILE_NAME {
+            continue;
+        }
+
+        let modified = entry.metadata()?.modified()?;
+        if modified.elapsed().is_ok_and(|age| age > max_age) {
+            std::fs::remove_file(entry.path())?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Formats events as RFC 5424 messages and hands them to a background thread
+/// so that a slow or unreachable syslog server never blocks the caller.
+struct SyslogWriter {
+    sender: SyncSender<Vec<u8>>,
+    hostname: String,
+}
+
+impl SyslogWriter {
+    fn spawn(config: SyslogConfig) -> Self {
+        let (sender, receiver) = mpsc::sync_channel(SYSLOG_QUEUE_SIZE);
+        thread::Builder::new()
+            .name("syslog".to_string())
+            .spawn(move || run_syslog_sender(config, receiver))
+            .expect("Failed to spawn syslog thread");
+
+        let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname")
+            .ok()
+            .or_else(|| std::env::var("HOSTNAME").ok())
+            .map(|h| h.trim().to_string())
+            .filter(|h| !h.is_empty())
+            .unwrap_or_else(|| "-".to_string());
+
+        Self { sender, hostname }
+    }
+}
+
+impl<'a> MakeWriter<'a> for SyslogWriter {
+    type Writer = SyslogMessage<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        SyslogMessage::new(self, Level::INFO, "-")
+    }
+
+    fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+        let msg_id = if meta.target() == AUDIT_TARGET {
+            AUDIT_TARGET
+        } else {
+            "-"
+        };
+        SyslogMessage::new(self, *meta.level(), msg_id)
+    }
+}
+
+struct SyslogMessage<'a> {
+    writer: &'a SyslogWriter,
+    header: String,
+    body: Vec<u8>,
+}
+
+impl<'a> SyslogMessage<'a> {
+    fn new(writer: &'a SyslogWriter, level: Level, msg_id: &str) -> Self {
+        let severity = match level {
+            Level::ERROR => 3,
+            Level::WARN => 4,
+            Level::INFO => 6,
+            Level::DEBUG | Level::TRACE => 7,
+        };
+        let header = format!(
+            "<{}>1 {} {} {} {} {} - ",
+            SYSLOG_FACILITY * 8 + severity,
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            writer.hostname,
+            SYSLOG_APP_NAME,
+            std::process::id(),
+            msg_id,
+        );
+        Self {
+            writer,
+            header,
+            body: Vec::new(),
+        }
+    }
+}
+
+impl Write for SyslogMessage<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.body.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for SyslogMessage<'_> {
+    fn drop(&mut self) {
+        let body = self.body.trim_ascii_end();
+        if body.is_empty() {
+            return;
+        }
+        let mut message = std::mem::take(&mut self.header).into_bytes();
+        message.extend_from_slice(body);
+        // Drop the message rather than block if the server cannot keep up
+        let _ = self.writer.sender.try_send(message);
+    }
+}
+
+fn run_syslog_sender(config: SyslogConfig, receiver: Receiver<Vec<u8>>) {
+    match config.protocol {
+        SyslogProtocol::Udp => {
+            let socket = match UdpSocket::bind("0.0.0.0:0")
+                .and_then(|socket| socket.connect(&config.address).map(|_| socket))
+            {
+                Ok(socket) => socket,
+                Err(e) => {
+                    eprintln!("Failed to open syslog socket to {}: {}", config.address, e);
+                    return;
+                }
+            };
+            for message in receiver {
+                let _ = socket.send(&message);
+            }
+        }
+        SyslogProtocol::Tcp => {
+            let mut stream: Option<TcpStream> = None;
+            for message in receiver {
+                // Octet-counting framing (RFC 6587)
+                let mut frame = format!("{} ", message.len()).into_bytes();
+                frame.extend_from_slice(&message);
+
+                // Reconnect once if the server dropped the connection
+                for _ in 0..2 {
+                    if stream.is_none() {
+                        stream = connect_tcp(&config.address);
+                    }
+                    match stream.as_mut().map(|s| s.write_all(&frame)) {
+                        Some(Ok(())) => break,
+                        Some(Err(_)) => stream = None,
+                        None => break,
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn connect_tcp(address: &str) -> Option<TcpStream> {
+    let addr = address.to_socket_addrs().ok()?.next()?;
+    match TcpStream::connect_timeout(&addr, SYSLOG_CONNECT_TIMEOUT) {
+        Ok(stream) => Some(stream),
+        Err(e) => {
+            eprintln!("Failed to connect to syslog server {address}: {e}");
+            None
+        }
+    }
+}
@@ -1,79 +1,49 @@
-use axum::{
-    Router,
-    http::{self, Method},
-    routing::{delete, get, post},
-};
-use tower_http::cors::{Any, CorsLayer};
-use tracing::{error, info, level_filters::LevelFilter, warn};
-use tracing_subscriber::{EnvFilter, fmt, prelude::*};
-use std::path::Path;
+use std::process::ExitCode;
+
+use clap::Parser;
+use tracing::{error, level_filters::LevelFilter};
+use tracing_subscriber::fmt;
 
 use backend::{
+    app,
+    audit::{AUDIT_LOG, Actor, AuditAction, AuditLog, AuditRecord},
+    cli::{Cli, Command, OutputFormat},
+    commands,
     environment::{ENVIRONMENT, Environment},
-    handlers::*,
-    tasks::run_daily_purge,
-    unifi_api::{UNIFI_API, UnifiAPI},
+    kiosks::{KIOSK_REGISTRY, KioskRegistry},
+    listener, logging,
+    pool_maintainer::run_pool_maintainer,
+    scheduler::{SCHEDULER, Scheduler},
+    shutdown,
+    unifi_api::{CONNECT_RETRY_DELAY, UNIFI_API, connect_controller},
     voucher_config::{VOUCHER_CONFIG, VoucherConfig},
 };
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     // =================================
-    // Initialize tracing
+    // Load configuration
     // =================================
-    
-    // Create logs directory if it doesn't exist
-    let log_dir = Path::new("/app/logs");
-    if !log_dir.exists() {
-        if let Err(e) = std::fs::create_dir_all(log_dir) {
-            eprintln!("Failed to create logs directory: {}", e);
-            eprintln!("Logging to console only");
-        }
-    }
-    
-    // Set up file appender for voucher logs (if directory exists)
-    let file_layer_option = if log_dir.exists() {
-        let file_appender = tracing_appender::rolling::daily("/app/logs", "vouchers.log");
-        let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
-        
-        // Leak the guard to keep it alive for the entire program duration
-        // This ensures log files are properly flushed
-        std::mem::forget(guard);
-        
-        Some(fmt::layer()
-            .with_writer(non_blocking)
-            .with_ansi(false))
-    } else {
-        None
+    // The global subscriber depends on the environment, so messages emitted
+    // while loading it go to the console only. Admin commands keep stdout for
+    // their output and only report warnings.
+    let cli = Cli::parse();
+    let command = cli
+        .command
+        .filter(|command| !matches!(command, Command::Serve));
+    let level = match command {
+        Some(_) => LevelFilter::WARN,
+        None => LevelFilter::INFO,
     };
-    
-    // Set up console output
-    let console_layer = fmt::layer()
-        .with_writer(std::io::stdout);
-    
-    // Combine layers
-    let registry = tracing_subscriber::registry()
-        .with(
-            EnvFilter::builder()
-                .with_env_var("BACKEND_LOG_LEVEL")
-                .with_default_directive(LevelFilter::INFO.into())
-                .from_env_lossy(),
-        )
-        .with(console_layer);
-    
-    if let Some(file_layer) = file_layer_option {
-        registry.with(file_layer).init();
-    } else {
-        registry.init();
-    }
-
-    // =================================
-    // Setup environment variables manager
-    // =================================
-    let env = match Environment::try_new() {
+    let console = fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(level)
+        .finish();
+    let env = match tracing::subscriber::with_default(console, || Environment::try_new(&cli.config))
+    {
         Ok(env) => env,
         Err(e) => {
-            error!("Failed to load environment variables: {e}");
+            eprintln!("Failed to load configuration: {e}");
             std::process::exit(1);
         }
     };
@@ -82,87 +52,115 @@ async fn main() {
         .expect("Failed to set environment variables");
     let environment = ENVIRONMENT.get().expect("Environment not set");
 
+    if let Some(command) = command {
+        return run_command(environment, command, cli.output).await;
+    }
+
     // =================================
-    // Load voucher configuration
+    // Initialize tracing
     // =================================
-    let voucher_config = match VoucherConfig::try_new() {
-        Ok(cfg) => cfg,
+    let log_guards = match logging::init(environment) {
+        Ok(guards) => guards,
         Err(e) => {
-            error!("Failed to load voucher configuration: {e}");
+            eprintln!("Failed to initialize logging: {e}");
             std::process::exit(1);
         }
     };
+
+    let result = run(environment).await;
+    if let Err(e) = &result {
+        error!("{e}");
+    }
+    // Flush the buffered log lines, which exiting the process would drop
+    drop(log_guards);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(_) => ExitCode::FAILURE,
+    }
+}
+
+/// Runs an admin command instead of the server, logging to stderr only.
+async fn run_command(
+    environment: &Environment,
+    command: Command,
+    format: OutputFormat,
+) -> ExitCode {
+    if let Err(e) = logging::init_cli(environment) {
+        eprintln!("Failed to initialize logging: {e}");
+        return ExitCode::FAILURE;
+    }
+    let result = commands::run(command, format).await;
+    if let Some(api) = UNIFI_API.get() {
+        api.logout().await;
+    }
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(environment: &'static Environment) -> Result<(), String> {
+    // =================================
+    // Open audit trail
+    // =================================
+    match AuditLog::try_new(&environment.data_dir) {
+        Ok(audit_log) => AUDIT_LOG.set(audit_log).expect("Failed to set audit trail"),
+        Err(e) => error!("Audit trail disabled, failed to open it: {e}"),
+    }
+
+    // =================================
+    // Load voucher configuration
+    // =================================
+    let voucher_config = VoucherConfig::try_new()
+        .map_err(|e| format!("Failed to load voucher configuration: {e}"))?;
+    AuditRecord::new(AuditAction::ConfigReload, Actor::System)
+        .parameters(serde_json::json!({ "rollingPools": voucher_config.pools }))
+        .record();
     VOUCHER_CONFIG
         .set(voucher_config)
         .expect("Failed to set voucher configuration");
 
     // =================================
-    // Setup UniFi Controller API connection
+    // Load kiosk registry
     // =================================
-    loop {
-        match UnifiAPI::try_new().await {
-            Ok(api) => {
-                UNIFI_API.set(api).expect("Failed to set UnifiAPI");
-                info!("Successfully connected to Unifi controller");
-                break;
-            }
-            Err(e) => {
-                error!("Failed to initialize UnifiAPI wrapper: {}", e);
-                warn!("Retrying connection in 5 seconds...");
-                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-            }
-        }
+    match KioskRegistry::try_new(&environment.data_dir) {
+        Ok(registry) => KIOSK_REGISTRY
+            .set(registry)
+            .expect("Failed to set kiosk registry"),
+        Err(e) => error!("Kiosk registry disabled, failed to load it: {e}"),
     }
 
+    // =================================
+    // Load job schedules
+    // =================================
+    let scheduler = Scheduler::try_new(environment.timezone)
+        .map_err(|e| format!("Failed to load scheduler configuration: {e}"))?;
+    SCHEDULER.set(scheduler).expect("Failed to set scheduler");
+
     // =================================
     // Start scheduled tasks
     // =================================
-    tokio::spawn(run_daily_purge(environment.timezone));
+    // Jobs needing the controller fail, and are reported as such, until the
+    // background connection below succeeds
+    Scheduler::start();
+    tokio::spawn(async move {
+        if connect_controller(environment, CONNECT_RETRY_DELAY).await {
+            if let Some(api) = UNIFI_API.get() {
+                tokio::spawn(api.keep_session_fresh());
+            }
+            run_pool_maintainer().await;
+        }
+    });
 
     // =================================
     // Setup Axum server
     // =================================
-    let cors = CorsLayer::new()
-        .allow_headers([http::header::CONTENT_TYPE])
-        .allow_methods([Method::POST, Method::GET, Method::DELETE])
-        .allow_origin(Any);
-
-    let app = Router::new()
-        .route("/api/health", get(health_check_handler))
-        .route("/api/vouchers", get(get_vouchers_handler))
-        .route("/api/vouchers", post(create_voucher_handler))
-        .route("/api/vouchers/details", get(get_voucher_details_handler))
-        .route("/api/vouchers/expired", delete(delete_expired_handler))
-        .route(
-            "/api/vouchers/expired/rolling",
-            delete(delete_expired_rolling_handler),
-        )
-        .route("/api/vouchers/newest", get(get_newest_voucher_handler))
-        .route("/api/vouchers/rolling", get(get_rolling_voucher_handler))
-        .route("/api/vouchers/rolling/all", get(get_all_rolling_vouchers_handler))
-        .route(
-            "/api/vouchers/rolling",
-            post(create_rolling_voucher_handler),
-        )
-        .route(
-            "/api/vouchers/rolling/rotate",
-            post(rotate_rolling_voucher_handler),
-        )
-        .route("/api/vouchers/selected", delete(delete_selected_handler))
-        .layer(cors);
-
-    let bind_address = format!(
-        "{}:{}",
-        environment.backend_bind_host, environment.backend_bind_port
-    );
-
-    let listener = tokio::net::TcpListener::bind(&bind_address)
-        .await
-        .expect("Could not bind listener");
+    let servers = listener::start(environment, app::router()).await?;
 
-    info!("Server running on http://{}", bind_address);
+    tokio::spawn(shutdown::listen_for_signals());
 
-    axum::serve(listener, app)
-        .await
-        .expect("Axum server should never error");
+    shutdown::drain(servers, environment.shutdown_timeout).await
 }
@@ -1,20 +1,29 @@
 #![allow(dead_code)]
 
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::resilience::CircuitStatus;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Voucher {
     #[serde(rename = "id", alias = "_id")]
     pub id: String,
+    /// Creation time, RFC 3339 with the offset of the backend's timezone
     #[serde(
         rename = "createdAt",
         alias = "create_time",
         deserialize_with = "deserialize_timestamp"
     )]
     pub created_at: String,
+    /// Creation time in seconds since the Unix epoch
+    #[serde(rename = "createdAtEpoch", skip_deserializing)]
+    pub created_at_epoch: Option<i64>,
+    /// Note given at creation, rolling vouchers start with their pool prefix
     #[serde(rename = "name", alias = "note", default)]
     pub name: String,
     pub code: String,
+    /// Guests that can use the code, unlimited when absent or 0
     #[serde(
         rename = "authorizedGuestLimit",
         alias = "quota",
@@ -25,6 +34,7 @@ pub struct Voucher {
     pub authorized_guest_limit: Option<u64>,
     #[serde(rename = "authorizedGuestCount", alias = "used", default)]
     pub authorized_guest_count: u64,
+    /// First use, RFC 3339
     #[serde(
         rename = "activatedAt",
         alias = "start_time",
@@ -33,6 +43,13 @@ pub struct Voucher {
         skip_serializing_if = "Option::is_none"
     )]
     pub activated_at: Option<String>,
+    #[serde(
+        rename = "activatedAtEpoch",
+        skip_deserializing,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub activated_at_epoch: Option<i64>,
+    /// End of the validity, RFC 3339, known once the voucher is activated
     #[serde(
         rename = "expiresAt",
         alias = "end_time",
@@ -41,8 +58,28 @@ pub struct Voucher {
         skip_serializing_if = "Option::is_none"
     )]
     pub expires_at: Option<String>,
+    #[serde(
+        rename = "expiresAtEpoch",
+        skip_deserializing,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expires_at_epoch: Option<i64>,
     #[serde(default)]
     pub expired: bool,
+    #[serde(skip_deserializing)]
+    pub status: VoucherStatus,
+    /// Minutes of validity left, the full duration until first use
+    #[serde(rename = "remainingMinutes", skip_deserializing)]
+    pub remaining_minutes: u64,
+    /// Data left, only known before first use since the controller does not
+    /// report usage. Absent when unlimited or unknown
+    #[serde(
+        rename = "remainingDataMBytes",
+        skip_deserializing,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub remaining_data_mbytes: Option<u64>,
+    /// Validity once first used
     #[serde(rename = "timeLimitMinutes", alias = "duration", default)]
     pub time_limit_minutes: u64,
     #[serde(
@@ -53,6 +90,7 @@ pub struct Voucher {
         skip_serializing_if = "Option::is_none"
     )]
     pub data_usage_limit_mbytes: Option<u64>,
+    /// Upload limit of the guest
     #[serde(
         rename = "txRateLimitKbps",
         alias = "qos_rate_max_up",
@@ -61,6 +99,7 @@ pub struct Voucher {
         skip_serializing_if = "Option::is_none"
     )]
     pub tx_rate_limit_kbps: Option<u64>,
+    /// Download limit of the guest
     #[serde(
         rename = "rxRateLimitKbps",
         alias = "qos_rate_max_down",
@@ -69,7 +108,29 @@ pub struct Voucher {
         skip_serializing_if = "Option::is_none"
     )]
     pub rx_rate_limit_kbps: Option<u64>,
-    
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VoucherStatus {
+    /// Not used by any guest yet
+    #[default]
+    Unused,
+    /// Used by at least one guest and not expired
+    Active,
+    Expired,
+}
+
+impl VoucherStatus {
+    pub fn of(voucher: &Voucher) -> Self {
+        if voucher.expired {
+            Self::Expired
+        } else if voucher.authorized_guest_count > 0 || voucher.activated_at.is_some() {
+            Self::Active
+        } else {
+            Self::Unused
+        }
+    }
 }
 
 // Custom deserializer for timestamps that can be either integers or strings
@@ -227,11 +288,7 @@ where
             E: de::Error,
         {
             // Convert boolean to Option: true -> Some(0), false -> None
-            if value {
-                Ok(Some(0))
-            } else {
-                Ok(None)
-            }
+            if value { Ok(Some(0)) } else { Ok(None) }
         }
 
         fn visit_i64<E>(self, value: i64) -> Result<Self::Value, E>
@@ -256,18 +313,26 @@ where
     deserializer.deserialize_option(OptionalU64Visitor)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct CreateVoucherRequest {
+    /// Number of vouchers to create
+    #[schema(minimum = 1)]
     pub count: u32,
+    /// Note stored with the vouchers
     pub name: String,
+    /// Guests per voucher, unlimited when absent or 0
     #[serde(rename = "authorizedGuestLimit")]
     pub authorized_guest_limit: Option<u64>,
+    /// Validity once first used
     #[serde(rename = "timeLimitMinutes")]
+    #[schema(minimum = 1)]
     pub time_limit_minutes: u64,
     #[serde(rename = "dataUsageLimitMBytes")]
     pub data_usage_limit_mbytes: Option<u64>,
+    /// Download limit of the guest
     #[serde(rename = "rxRateLimitKbps")]
     pub rx_rate_limit_kbps: Option<u64>,
+    /// Upload limit of the guest
     #[serde(rename = "txRateLimitKbps")]
     pub tx_rate_limit_kbps: Option<u64>,
 }
@@ -283,42 +348,90 @@ pub struct CreateVoucherData {
     pub create_time: i64,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CreateVoucherResponse {
     pub vouchers: Vec<Voucher>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct GetVouchersResponse {
     pub data: Vec<Voucher>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Guest authorization as returned by `stat/guest`, one per device and
+/// voucher use.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Guest {
+    /// Voucher the guest was authorized with, absent for other methods
+    #[serde(default)]
+    pub voucher_id: Option<String>,
+    #[serde(default)]
+    pub tx_bytes: u64,
+    #[serde(default)]
+    pub rx_bytes: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetGuestsResponse {
+    pub data: Vec<Guest>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct DeleteResponse {
+    /// One empty object per deleted voucher
     pub data: Vec<serde_json::Value>,
     pub meta: DeleteMeta,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct DeleteMeta {
     pub rc: String,
 }
 
-#[derive(Debug, Serialize)]
+/// Outcome of `POST /api/vouchers/rolling/rotate`.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum RotateResponse {
+    /// The pool was below its minimum, every voucher created to top it up is
+    /// returned
+    Created {
+        vouchers: Vec<Voucher>,
+    },
+    NoActionNeeded {
+        message: String,
+    },
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct HealthCheckResponse {
     pub status: String,
+    /// Circuit breaker state, absent until the controller client exists
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub controller: Option<CircuitStatus>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DeleteRequest {
+    /// Comma-separated voucher ids
+    #[param(example = "65a1f0c2e4b0a1b2c3d4e5f6,65a1f0c2e4b0a1b2c3d4e5f7")]
     pub ids: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct DetailsRequest {
+    /// Voucher id
     pub id: String,
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PoolRequest {
+    /// Rolling pool name, the default pool when absent
+    pub pool: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Site {
     pub id: String,
@@ -337,6 +450,23 @@ pub struct GetSitesResponse {
     pub data: Vec<Site>,
 }
 
+/// Site the logged in account can access, from `/api/self/sites`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ControllerSite {
+    /// Identifier used in the API paths, e.g. `default`
+    pub name: String,
+    #[serde(rename = "desc", default)]
+    pub description: String,
+    /// Role of the account on the site, e.g. `admin` or `readonly`
+    #[serde(default)]
+    pub role: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GetControllerSitesResponse {
+    pub data: Vec<ControllerSite>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ErrorResponse {
     #[serde(rename = "statusCode")]
@@ -0,0 +1,103 @@
+use utoipa::{
+    IntoResponses, Modify, OpenApi,
+    openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+};
+
+use crate::{error::Problem, handlers};
+
+/// OpenAPI document of the backend, served at `/api/openapi.json` and
+/// browsable at `/api/docs`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "UniFi Voucher Manager API",
+        description = "Backend of the UniFi Voucher Manager. Errors are RFC 7807 problem documents \
+            (`application/problem+json`) whose `code` identifies the failure.",
+        license(name = "MIT"),
+    ),
+    paths(
+        handlers::list_vouchers_handler,
+        handlers::get_vouchers_handler,
+        handlers::create_voucher_handler,
+        handlers::get_voucher_details_handler,
+        handlers::get_newest_voucher_handler,
+        handlers::delete_selected_handler,
+        handlers::delete_expired_handler,
+        handlers::delete_expired_rolling_handler,
+        handlers::get_rolling_voucher_handler,
+        handlers::get_all_rolling_vouchers_handler,
+        handlers::create_rolling_voucher_handler,
+        handlers::rotate_rolling_voucher_handler,
+        handlers::get_kiosks_handler,
+        handlers::register_kiosk_handler,
+        handlers::delete_kiosk_handler,
+        handlers::kiosk_heartbeat_handler,
+        handlers::get_kiosk_voucher_handler,
+        handlers::events_handler,
+        handlers::get_audit_handler,
+        handlers::verify_audit_handler,
+        handlers::get_report_handler,
+        handlers::get_jobs_handler,
+        handlers::run_job_handler,
+        handlers::doctor_handler,
+        handlers::health_check_handler,
+        handlers::readiness_handler,
+    ),
+    modifiers(&KioskTokenAuth),
+    tags(
+        (name = "vouchers", description = "Vouchers of the configured site"),
+        (name = "rolling", description = "Rolling voucher pools handed out to guests and kiosks"),
+        (name = "kiosks", description = "Registered kiosk displays"),
+        (name = "events", description = "Server-sent notifications"),
+        (name = "audit", description = "Tamper-evident audit trail"),
+        (name = "reports", description = "Usage and activation figures"),
+        (name = "jobs", description = "Scheduled maintenance jobs"),
+        (name = "admin", description = "Diagnostics, to be restricted by the reverse proxy"),
+        (name = "health", description = "Liveness and readiness probes"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Security scheme of the kiosk routes, which expect the device token
+/// returned at registration.
+struct KioskTokenAuth;
+
+impl Modify for KioskTokenAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "kioskToken",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .description(Some("Device token returned when the kiosk was registered"))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// Failures of any route that calls the controller.
+#[allow(dead_code)]
+#[derive(IntoResponses)]
+pub enum ControllerErrors {
+    /// The controller rejected the request (`controller_rejected`)
+    #[response(status = 400, content_type = "application/problem+json")]
+    Rejected(Problem),
+    /// The controller is rate limiting requests (`rate_limited`)
+    #[response(
+        status = 429,
+        content_type = "application/problem+json",
+        headers(("Retry-After" = u64, description = "Seconds to wait, when the controller sent it"))
+    )]
+    RateLimited(Problem),
+    /// The controller refused the configured credentials, failed, or created
+    /// fewer vouchers than requested (`controller_auth_failed`,
+    /// `controller_rejected`, `partial_creation`)
+    #[response(status = 502, content_type = "application/problem+json")]
+    BadGateway(Problem),
+    /// The controller cannot be reached or the circuit breaker is open
+    /// (`controller_unreachable`)
+    #[response(status = 503, content_type = "application/problem+json")]
+    Unreachable(Problem),
+}
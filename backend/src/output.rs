@@ -0,0 +1,128 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::cli::OutputFormat;
+
+/// Result of an admin command. The JSON form keeps the structure of the
+/// API responses, the table and CSV forms list one row per item.
+pub struct Output {
+    json: serde_json::Value,
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+    /// Line printed under the table, omitted from JSON and CSV
+    summary: Option<String>,
+    /// Why the command only partly succeeded, making it exit with an error
+    failure: Option<String>,
+}
+
+impl Output {
+    pub fn new(json: impl Serialize, headers: &[&'static str]) -> Self {
+        Self {
+            json: serde_json::to_value(json).expect("Command output is serializable"),
+            headers: headers.to_vec(),
+            rows: Vec::new(),
+            summary: None,
+            failure: None,
+        }
+    }
+
+    /// Two column table of settings, e.g. for a check.
+    pub fn settings(json: impl Serialize) -> Self {
+        Self::new(json, &["SETTING", "VALUE"])
+    }
+
+    pub fn row<I, S>(&mut self, cells: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        let row: Vec<String> = cells.into_iter().map(|c| c.to_string()).collect();
+        debug_assert_eq!(
+            row.len(),
+            self.headers.len(),
+            "Row does not match the headers"
+        );
+        self.rows.push(row);
+    }
+
+    pub fn summary(mut self, summary: String) -> Self {
+        self.summary = Some(summary);
+        self
+    }
+
+    pub fn failed(mut self, failure: String) -> Self {
+        self.failure = Some(failure);
+        self
+    }
+
+    pub fn failure(&self) -> Option<&str> {
+        self.failure.as_deref()
+    }
+
+    pub fn print(&self, format: OutputFormat) -> Result<(), String> {
+        io::stdout()
+            .lock()
+            .write_all(self.render(format).as_bytes())
+            .map_err(|e| format!("Failed to write output: {e}"))
+    }
+
+    pub fn render(&self, format: OutputFormat) -> String {
+        match format {
+            OutputFormat::Table => self.table(),
+            OutputFormat::Json => {
+                let mut json = serde_json::to_string_pretty(&self.json)
+                    .expect("Command output is serializable");
+                json.push('\n');
+                json
+            }
+            OutputFormat::Csv => self.csv(),
+        }
+    }
+
+    fn table(&self) -> String {
+        let mut widths: Vec<usize> = self.headers.iter().map(|h| h.chars().count()).collect();
+        for row in &self.rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+
+        let line = |cells: &mut dyn Iterator<Item = &str>| {
+            let padded: Vec<String> = cells
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect();
+            format!("{}\n", padded.join("  ").trim_end())
+        };
+        let mut table = line(&mut self.headers.iter().copied());
+        for row in &self.rows {
+            table.push_str(&line(&mut row.iter().map(String::as_str)));
+        }
+        if let Some(summary) = &self.summary {
+            table.push_str(&format!("\n{summary}\n"));
+        }
+        table
+    }
+
+    fn csv(&self) -> String {
+        let line = |cells: &mut dyn Iterator<Item = &str>| {
+            let escaped: Vec<String> = cells.map(csv_field).collect();
+            format!("{}\r\n", escaped.join(","))
+        };
+        let mut csv = line(&mut self.headers.iter().copied());
+        for row in &self.rows {
+            csv.push_str(&line(&mut row.iter().map(String::as_str)));
+        }
+        csv
+    }
+}
+
+/// Quotes a field as described by RFC 4180 when it needs to be.
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    collections::HashMap,
+    sync::{OnceLock, RwLock},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    audit::{Actor, AuditAction, AuditRecord},
+    events::{self, Event},
+    kiosks,
+    models::Voucher,
+    shutdown,
+    unifi_api::{UnifiAPI, client},
+    voucher_config::{RollingVoucherConfig, VOUCHER_CONFIG},
+};
+
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// Latest size of each rolling pool seen by its maintainer, by pool name
+static OBSERVATIONS: OnceLock<RwLock<HashMap<String, PoolObservation>>> = OnceLock::new();
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PoolObservation {
+    /// Unused vouchers no kiosk has reserved
+    pub unused: usize,
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Last observed size of a rolling pool, `None` before its first check.
+pub fn observation(pool: &str) -> Option<PoolObservation> {
+    OBSERVATIONS.get()?.read().ok()?.get(pool).cloned()
+}
+
+fn observe(pool: &str, unused: usize) {
+    let observations = OBSERVATIONS.get_or_init(Default::default);
+    if let Ok(mut observations) = observations.write() {
+        observations.insert(
+            pool.to_string(),
+            PoolObservation {
+                unused,
+                checked_at: Utc::now(),
+            },
+        );
+    }
+}
+
+/// Watches the unused rolling vouchers and refills each pool as soon as one is
+/// redeemed or expires, instead of waiting for a kiosk to request a rotation.
+pub async fn run_pool_maintainer() {
+    let voucher_config = VOUCHER_CONFIG
+        .get()
+        .expect("Voucher config not initialized");
+    let mut pools = voucher_config.enabled_pools().peekable();
+    if pools.peek().is_none() {
+        info!("Rolling vouchers are disabled, not starting the pool maintainer");
+        return;
+    }
+
+    for pool in pools {
+        tokio::spawn(maintain_pool(pool));
+    }
+}
+
+async fn maintain_pool(pool: &'static RollingVoucherConfig) {
+    let interval = Duration::from_secs(pool.check_interval_seconds.max(1));
+    info!(
+        "Starting maintainer of rolling pool '{}', checking every {}s",
+        pool.name,
+        interval.as_secs()
+    );
+
+    // Unused rolling vouchers seen on the previous check, by id
+    let mut watched: HashMap<String, Voucher> = HashMap::new();
+    let mut failures: u32 = 0;
+
+    while !shutdown::is_requested() {
+        let operation = shutdown::operation();
+        let result = check_pool(pool, &mut watched).await;
+        drop(operation);
+        match result {
+            Ok(()) => failures = 0,
+            Err(e) => {
+                failures = failures.saturating_add(1);
+                warn!(
+                    "Check of rolling pool '{}' failed ({} in a row): {}",
+                    pool.name, failures, e
+                );
+            }
+        }
+
+        let delay = match failures {
+            0 => interval,
+            n => interval
+                .saturating_mul(2u32.saturating_pow(n.min(16)))
+                .min(MAX_BACKOFF),
+        };
+        tokio::select! {
+            _ = sleep(delay) => {}
+            _ = shutdown::requested() => {}
+        }
+    }
+    debug!("Stopped maintainer of rolling pool '{}'", pool.name);
+}
+
+async fn check_pool(
+    pool: &RollingVoucherConfig,
+    watched: &mut HashMap<String, Voucher>,
+) -> Result<(), String> {
+    let client = client().map_err(|e| e.to_string())?;
+    let min_vouchers = pool.min_rolling_vouchers as usize;
+
+    retire_rotated(client, pool).await?;
+
+    let vouchers = client
+        .get_all_vouchers()
+        .await
+        .map_err(|e| format!("Failed to fetch vouchers: {e}"))?
+        .data;
+    let by_id: HashMap<&str, &Voucher> = vouchers.iter().map(|v| (v.id.as_str(), v)).collect();
+
+    // Report what happened to the vouchers that were unused last time
+    for (id, previous) in watched.iter() {
+        match by_id.get(id.as_str()) {
+            Some(current) if current.authorized_guest_count > previous.authorized_guest_count => {
+                info!("Rolling voucher {} was redeemed", id);
+                events::publish(Event::RollingVoucherRedeemed {
+                    pool: pool.name.clone(),
+                    voucher_id: id.clone(),
+                    code: current.code.clone(),
+                });
+            }
+            Some(current) if current.expired => {
+                info!("Rolling voucher {} expired unused", id);
+                events::publish(Event::RollingVoucherExpired {
+                    pool: pool.name.clone(),
+                    voucher_id: id.clone(),
+                    code: current.code.clone(),
+                });
+            }
+            Some(_) => {}
+            None => debug!("Rolling voucher {} ({}) was removed", id, previous.code),
+        }
+    }
+
+    *watched = vouchers
+        .into_iter()
+        .filter(|v| client.is_unused_rolling_voucher(pool, v))
+        .map(|v| (v.id.clone(), v))
+        .collect();
+    // Vouchers reserved by registered kiosks are not available to others
+    let reserved = kiosks::reserved_voucher_ids().await;
+    let available = watched.keys().filter(|id| !reserved.contains(*id)).count();
+    observe(&pool.name, available);
+
+    if available >= min_vouchers {
+        return Ok(());
+    }
+
+    info!(
+        "Rolling pool '{}' has {} available vouchers (min: {}), topping it up",
+        pool.name, available, min_vouchers
+    );
+    let audit = AuditRecord::new(AuditAction::Rotate, Actor::System)
+        .parameters(serde_json::json!({ "trigger": "pool_maintainer", "pool": pool.name }));
+    match client.top_up_rolling_vouchers(pool).await {
+        Ok(created) if created.is_empty() => {
+            audit.failed("no voucher could be created").record();
+            Err("No rolling voucher could be created".to_string())
+        }
+        Ok(created) => {
+            audit.vouchers(&created).record();
+            for voucher in &created {
+                watched.insert(voucher.id.clone(), voucher.clone());
+            }
+            let available = available + created.len();
+            observe(&pool.name, available);
+            events::publish(Event::RollingPoolToppedUp {
+                pool: pool.name.clone(),
+                vouchers: created,
+                unused: available,
+            });
+            Ok(())
+        }
+        Err(e) => {
+            error!("Failed to top up rolling pool '{}': {}", pool.name, e);
+            audit.failed(&e).record();
+            Err(format!("Failed to top up the rolling pool: {e}"))
+        }
+    }
+}
+
+async fn retire_rotated(client: &UnifiAPI<'_>, pool: &RollingVoucherConfig) -> Result<(), String> {
+    let audit = AuditRecord::new(AuditAction::Rotate, Actor::System)
+        .parameters(serde_json::json!({ "trigger": "rotation_schedule", "pool": pool.name }));
+    match client.retire_rotated_rolling_vouchers(pool).await {
+        Ok(retired) if retired.is_empty() => Ok(()),
+        Ok(retired) => {
+            audit.removed(&retired).record();
+            for voucher in retired {
+                events::publish(Event::RollingVoucherRetired {
+                    pool: pool.name.clone(),
+                    voucher_id: voucher.id,
+                    code: voucher.code,
+                });
+            }
+            Ok(())
+        }
+        Err(e) => {
+            audit.failed(&e).record();
+            Err(format!("Failed to retire rotated rolling vouchers: {e}"))
+        }
+    }
+}
@@ -0,0 +1,618 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Days, NaiveDate, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    audit::{AUDIT_LOG, AuditAction, AuditEntry, AuditOutcome},
+    environment::ENVIRONMENT,
+    error::ApiError,
+    models::{CreateVoucherRequest, Guest, Voucher},
+    output::csv_field,
+    unifi_api::client,
+    voucher_config::{RollingVoucherConfig, VOUCHER_CONFIG, VoucherTier},
+};
+
+/// Days covered by default, ending today.
+const DEFAULT_REPORT_DAYS: u64 = 7;
+pub(crate) const MAX_REPORT_DAYS: u64 = 366;
+/// Subdirectory of the data directory the reports job saves to
+const REPORTS_DIR: &str = "reports";
+/// Unit of the controller's data limits
+const BYTES_PER_MBYTE: f64 = 1024.0 * 1024.0;
+
+const CSV_HEADERS: [&str; 13] = [
+    "group",
+    "key",
+    "issued",
+    "activated",
+    "unused",
+    "expired_unused",
+    "unknown",
+    "activation_rate",
+    "expired_unused_rate",
+    "avg_minutes_to_activation",
+    "time_utilisation",
+    "data_used_mbytes",
+    "data_utilisation",
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    /// One row per group, the totals first
+    Csv,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ReportQuery {
+    /// First day, in the backend's timezone. Defaults to six days before `to`
+    pub from: Option<NaiveDate>,
+    /// Last day, inclusive. Defaults to today
+    pub to: Option<NaiveDate>,
+    /// Defaults to `json`
+    pub format: Option<ReportFormat>,
+}
+
+impl ReportQuery {
+    /// First and last day of the report, `today` being in the backend's
+    /// timezone.
+    pub fn range(&self, today: NaiveDate) -> Result<(NaiveDate, NaiveDate), ApiError> {
+        let to = self.to.unwrap_or(today);
+        let from = match self.from {
+            Some(from) => from,
+            None => to
+                .checked_sub_days(Days::new(DEFAULT_REPORT_DAYS - 1))
+                .ok_or_else(|| ApiError::Validation("`to` is out of range".to_string()))?,
+        };
+        if from > to {
+            return Err(ApiError::Validation("`from` is after `to`".to_string()));
+        }
+        if (to - from).num_days() as u64 >= MAX_REPORT_DAYS {
+            return Err(ApiError::Validation(format!(
+                "A report covers at most {MAX_REPORT_DAYS} days"
+            )));
+        }
+        Ok((from, to))
+    }
+}
+
+/// Figures of a set of vouchers issued during the report's range.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportMetrics {
+    pub issued: u64,
+    /// Used by at least one guest
+    pub activated: u64,
+    /// Not used yet and still valid
+    pub unused: u64,
+    /// Expired, retired or deleted without any guest using them
+    pub expired_unused: u64,
+    /// Deleted without the backend knowing whether they had been used
+    pub unknown: u64,
+    /// Share of the vouchers whose outcome is known that were activated
+    pub activation_rate: Option<f64>,
+    /// Share of the vouchers whose outcome is known that lapsed unused
+    pub expired_unused_rate: Option<f64>,
+    /// Mean time from creation to first use
+    pub avg_minutes_to_activation: Option<f64>,
+    /// Share of the validity of the activated vouchers that has elapsed
+    pub time_utilisation: Option<f64>,
+    /// Traffic of the guests the controller still reports
+    pub data_used_mbytes: f64,
+    /// Share of the data limit used, over activated vouchers with a limit
+    pub data_utilisation: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportGroup {
+    /// Tier id, pool name, operator (`kind:id`) or day. Null for vouchers
+    /// of no tier or pool, or created outside the backend
+    pub key: Option<String>,
+    #[serde(flatten)]
+    pub metrics: ReportMetrics,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    /// Timezone the days are counted in
+    pub timezone: String,
+    pub generated_at: String,
+    pub totals: ReportMetrics,
+    /// Rolling vouchers belong to their pool rather than a tier
+    pub by_tier: Vec<ReportGroup>,
+    pub by_pool: Vec<ReportGroup>,
+    /// Who created the vouchers, as recorded in the audit trail
+    pub by_operator: Vec<ReportGroup>,
+    /// Every day of the range, including those without vouchers
+    pub by_day: Vec<ReportGroup>,
+}
+
+/// Controller data and local history a report is computed from.
+pub struct ReportSources<'a> {
+    /// Vouchers still on the controller
+    pub vouchers: &'a [Voucher],
+    /// Guests authorized since the start of the range
+    pub guests: &'a [Guest],
+    /// The audit trail since the start of the range, oldest entry first
+    pub history: &'a [AuditEntry],
+    pub tiers: &'a [VoucherTier],
+    pub pools: &'a [RollingVoucherConfig],
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Activated,
+    Unused,
+    ExpiredUnused,
+    Unknown,
+}
+
+/// A voucher issued during the range.
+#[derive(Debug)]
+struct Issued {
+    day: NaiveDate,
+    tier: Option<String>,
+    pool: Option<String>,
+    operator: Option<String>,
+    outcome: Outcome,
+    seconds_to_activation: Option<i64>,
+    /// Elapsed and total validity in seconds, once activated
+    validity: Option<(i64, i64)>,
+    /// `None` when the controller no longer reports the traffic of the guests
+    data_used_bytes: Option<u64>,
+    data_limit_bytes: Option<u64>,
+}
+
+/// Creation of a voucher as recorded in the audit trail.
+struct Creation<'a> {
+    entry: &'a AuditEntry,
+    at: DateTime<Utc>,
+}
+
+impl Creation<'_> {
+    fn pool(&self) -> Option<String> {
+        self.entry.parameters["pool"].as_str().map(str::to_string)
+    }
+
+    /// Settings the voucher was created with, unless it is a rolling one.
+    fn request(&self) -> Option<CreateVoucherRequest> {
+        serde_json::from_value(self.entry.parameters.clone()).ok()
+    }
+}
+
+/// Sums of a group, turned into [`ReportMetrics`] once complete.
+#[derive(Debug, Default)]
+struct Tally {
+    issued: u64,
+    activated: u64,
+    unused: u64,
+    expired_unused: u64,
+    unknown: u64,
+    seconds_to_activation: i64,
+    activations_timed: u64,
+    validity_elapsed: i64,
+    validity_total: i64,
+    data_used: u64,
+    limited_data_used: u64,
+    data_limit: u64,
+}
+
+impl Tally {
+    fn add(&mut self, voucher: &Issued) {
+        self.issued += 1;
+        match voucher.outcome {
+            Outcome::Activated => self.activated += 1,
+            Outcome::Unused => self.unused += 1,
+            Outcome::ExpiredUnused => self.expired_unused += 1,
+            Outcome::Unknown => self.unknown += 1,
+        }
+        if let Some(seconds) = voucher.seconds_to_activation {
+            self.seconds_to_activation += seconds;
+            self.activations_timed += 1;
+        }
+        if let Some((elapsed, total)) = voucher.validity {
+            self.validity_elapsed += elapsed;
+            self.validity_total += total;
+        }
+        self.data_used += voucher.data_used_bytes.unwrap_or(0);
+        // Vouchers of unknown traffic would understate the utilisation
+        if voucher.outcome == Outcome::Activated
+            && let Some(used) = voucher.data_used_bytes
+            && let Some(limit) = voucher.data_limit_bytes
+        {
+            self.limited_data_used += used.min(limit);
+            self.data_limit += limit;
+        }
+    }
+
+    fn metrics(&self) -> ReportMetrics {
+        let known = self.activated + self.unused + self.expired_unused;
+        let share =
+            |part: u64, whole: u64| (whole > 0).then(|| round(part as f64 / whole as f64, 4));
+        ReportMetrics {
+            issued: self.issued,
+            activated: self.activated,
+            unused: self.unused,
+            expired_unused: self.expired_unused,
+            unknown: self.unknown,
+            activation_rate: share(self.activated, known),
+            expired_unused_rate: share(self.expired_unused, known),
+            avg_minutes_to_activation: (self.activations_timed > 0).then(|| {
+                round(
+                    self.seconds_to_activation as f64 / self.activations_timed as f64 / 60.0,
+                    1,
+                )
+            }),
+            time_utilisation: share(self.validity_elapsed as u64, self.validity_total as u64),
+            data_used_mbytes: round(self.data_used as f64 / BYTES_PER_MBYTE, 2),
+            data_utilisation: share(self.limited_data_used, self.data_limit),
+        }
+    }
+}
+
+fn round(value: f64, decimals: i32) -> f64 {
+    let factor = 10f64.powi(decimals);
+    (value * factor).round() / factor
+}
+
+fn groups(tallies: BTreeMap<Option<String>, Tally>) -> Vec<ReportGroup> {
+    let mut groups: Vec<ReportGroup> = tallies
+        .into_iter()
+        .map(|(key, tally)| ReportGroup {
+            key,
+            metrics: tally.metrics(),
+        })
+        .collect();
+    // Largest first, vouchers of no tier, pool or operator last
+    groups.sort_by(|a, b| {
+        a.key
+            .is_none()
+            .cmp(&b.key.is_none())
+            .then(b.metrics.issued.cmp(&a.metrics.issued))
+            .then(a.key.cmp(&b.key))
+    });
+    groups
+}
+
+impl Report {
+    /// Fetches the vouchers and guests from the controller and computes the
+    /// report of the range with the audit trail.
+    pub async fn generate(
+        from: NaiveDate,
+        to: NaiveDate,
+        now: DateTime<Utc>,
+    ) -> Result<Self, ApiError> {
+        let environment = ENVIRONMENT.get().expect("Environment not set");
+        let client = client()?;
+        let vouchers = client.get_all_vouchers().await.map_err(|e| {
+            error!("Failed to get vouchers for a report: {}", e);
+            e
+        })?;
+        // A day early, whatever the offset of the timezone
+        let start = from
+            .checked_sub_days(Days::new(1))
+            .and_then(|day| day.and_hms_opt(0, 0, 0))
+            .map_or(now, |start| start.and_utc());
+        let guests = client
+            .get_guests((now - start).to_std().unwrap_or_default())
+            .await
+            .map_err(|e| {
+                error!("Failed to get guests for a report: {}", e);
+                e
+            })?;
+        // Without the audit trail, only the vouchers still on the controller
+        // count. Earlier entries concern vouchers created before the range
+        let history = AUDIT_LOG
+            .get()
+            .map(|audit| audit.entries_since(start))
+            .unwrap_or_default();
+        let voucher_config = VOUCHER_CONFIG
+            .get()
+            .expect("Voucher config not initialized");
+
+        let sources = ReportSources {
+            vouchers: &vouchers.data,
+            guests: &guests,
+            history: &history,
+            tiers: &voucher_config.tiers,
+            pools: &voucher_config.pools,
+        };
+        Ok(Self::compute(from, to, &sources, environment.timezone, now))
+    }
+
+    /// Writes the report as JSON to the `reports` directory of `data_dir`,
+    /// replacing an earlier report of the same range.
+    pub fn save(&self, data_dir: &Path) -> Result<PathBuf, String> {
+        let dir = data_dir.join(REPORTS_DIR);
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+        let path = dir.join(format!("voucher-report-{}-{}.json", self.from, self.to));
+        let json = serde_json::to_string_pretty(self).expect("Report is serializable");
+        fs::write(&path, json).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Computes the figures of the vouchers created from `from` to `to`, the
+    /// days being counted in `timezone`.
+    ///
+    /// The controller only lists the vouchers it still has. Those deleted
+    /// since are taken from the audit trail, along with whether they had been
+    /// used when the backend deleted them.
+    pub fn compute(
+        from: NaiveDate,
+        to: NaiveDate,
+        sources: &ReportSources,
+        timezone: Tz,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let issued = issued_vouchers(from, to, sources, timezone, now);
+
+        let mut totals = Tally::default();
+        let mut by_tier: BTreeMap<Option<String>, Tally> = BTreeMap::new();
+        let mut by_pool: BTreeMap<Option<String>, Tally> = BTreeMap::new();
+        let mut by_operator: BTreeMap<Option<String>, Tally> = BTreeMap::new();
+        let mut by_day: BTreeMap<NaiveDate, Tally> = from
+            .iter_days()
+            .take_while(|day| *day <= to)
+            .map(|day| (day, Tally::default()))
+            .collect();
+        for voucher in &issued {
+            totals.add(voucher);
+            if voucher.pool.is_none() {
+                by_tier
+                    .entry(voucher.tier.clone())
+                    .or_default()
+                    .add(voucher);
+            }
+            by_pool
+                .entry(voucher.pool.clone())
+                .or_default()
+                .add(voucher);
+            by_operator
+                .entry(voucher.operator.clone())
+                .or_default()
+                .add(voucher);
+            by_day.entry(voucher.day).or_default().add(voucher);
+        }
+
+        Self {
+            from,
+            to,
+            timezone: timezone.to_string(),
+            generated_at: now
+                .with_timezone(&timezone)
+                .to_rfc3339_opts(SecondsFormat::Secs, false),
+            totals: totals.metrics(),
+            by_tier: groups(by_tier),
+            by_pool: groups(by_pool),
+            by_operator: groups(by_operator),
+            by_day: by_day
+                .into_iter()
+                .map(|(day, tally)| ReportGroup {
+                    key: Some(day.to_string()),
+                    metrics: tally.metrics(),
+                })
+                .collect(),
+        }
+    }
+
+    /// One row per group, after a `total` row, as described by RFC 4180.
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\r\n", CSV_HEADERS.join(","));
+        let mut row = |group: &str, key: Option<&str>, metrics: &ReportMetrics| {
+            let optional = |value: Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+            let cells = [
+                group.to_string(),
+                key.unwrap_or_default().to_string(),
+                metrics.issued.to_string(),
+                metrics.activated.to_string(),
+                metrics.unused.to_string(),
+                metrics.expired_unused.to_string(),
+                metrics.unknown.to_string(),
+                optional(metrics.activation_rate),
+                optional(metrics.expired_unused_rate),
+                optional(metrics.avg_minutes_to_activation),
+                optional(metrics.time_utilisation),
+                metrics.data_used_mbytes.to_string(),
+                optional(metrics.data_utilisation),
+            ];
+            let escaped: Vec<String> = cells.iter().map(|cell| csv_field(cell)).collect();
+            csv.push_str(&format!("{}\r\n", escaped.join(",")));
+        };
+        row("total", None, &self.totals);
+        for (group, rows) in [
+            ("tier", &self.by_tier),
+            ("pool", &self.by_pool),
+            ("operator", &self.by_operator),
+            ("day", &self.by_day),
+        ] {
+            for entry in rows {
+                row(group, entry.key.as_deref(), &entry.metrics);
+            }
+        }
+        csv
+    }
+}
+
+/// Vouchers created during the range, from the controller's listing and,
+/// for those deleted since, the audit trail.
+fn issued_vouchers(
+    from: NaiveDate,
+    to: NaiveDate,
+    sources: &ReportSources,
+    timezone: Tz,
+    now: DateTime<Utc>,
+) -> Vec<Issued> {
+    let in_range = |at: DateTime<Utc>| {
+        let day = at.with_timezone(&timezone).date_naive();
+        (from..=to).contains(&day).then_some(day)
+    };
+
+    // The first entry naming a voucher records its creation, later ones its
+    // deletion. Deletions note which vouchers were still unused, older
+    // entries did not
+    let mut creations: HashMap<&str, Creation> = HashMap::new();
+    let mut removals: HashMap<&str, Option<bool>> = HashMap::new();
+    for entry in sources.history {
+        if entry.outcome == AuditOutcome::Failure {
+            continue;
+        }
+        let removal = matches!(entry.action, AuditAction::Delete | AuditAction::Purge)
+            || entry.unused_voucher_ids.is_some();
+        for id in &entry.voucher_ids {
+            if removal {
+                let unused = entry
+                    .unused_voucher_ids
+                    .as_ref()
+                    .map(|unused| unused.contains(id));
+                removals.entry(id).or_insert(unused);
+            } else if matches!(entry.action, AuditAction::Create | AuditAction::Rotate)
+                && let Ok(at) = DateTime::parse_from_rfc3339(&entry.timestamp)
+            {
+                creations.entry(id).or_insert(Creation {
+                    entry,
+                    at: at.with_timezone(&Utc),
+                });
+            }
+        }
+    }
+
+    let mut guest_bytes: HashMap<&str, u64> = HashMap::new();
+    for guest in sources.guests {
+        if let Some(id) = &guest.voucher_id {
+            *guest_bytes.entry(id).or_default() += guest.tx_bytes + guest.rx_bytes;
+        }
+    }
+    let data_limit = |mbytes: Option<u64>| {
+        mbytes
+            .filter(|limit| *limit > 0)
+            .map(|limit| (limit as f64 * BYTES_PER_MBYTE) as u64)
+    };
+    let tier_of = |minutes: u64, data: Option<u64>, down: Option<u64>, up: Option<u64>| {
+        sources
+            .tiers
+            .iter()
+            .find(|tier| tier.matches(minutes, data, down, up))
+            .map(|tier| tier.id.clone())
+    };
+
+    let mut issued = Vec::new();
+    let mut listed = HashSet::new();
+    for voucher in sources.vouchers {
+        listed.insert(voucher.id.as_str());
+        let Some(day) = voucher
+            .created_at_epoch
+            .and_then(|epoch| DateTime::from_timestamp(epoch, 0))
+            .and_then(in_range)
+        else {
+            continue;
+        };
+        let pool = sources
+            .pools
+            .iter()
+            .find(|pool| pool.contains(voucher))
+            .map(|pool| pool.name.clone());
+        let tier = match pool {
+            Some(_) => None,
+            None => tier_of(
+                voucher.time_limit_minutes,
+                voucher.data_usage_limit_mbytes,
+                voucher.rx_rate_limit_kbps,
+                voucher.tx_rate_limit_kbps,
+            ),
+        };
+        let outcome = if voucher.authorized_guest_count > 0 || voucher.activated_at.is_some() {
+            Outcome::Activated
+        } else if voucher.expired {
+            Outcome::ExpiredUnused
+        } else {
+            Outcome::Unused
+        };
+        let validity = voucher.activated_at_epoch.map(|activated| {
+            let total = match voucher.time_limit_minutes {
+                0 => voucher
+                    .expires_at_epoch
+                    .map_or(0, |expires| expires - activated),
+                minutes => minutes as i64 * 60,
+            };
+            ((now.timestamp() - activated).clamp(0, total), total)
+        });
+        issued.push(Issued {
+            day,
+            tier,
+            pool,
+            operator: creations
+                .get(voucher.id.as_str())
+                .map(|creation| creation.entry.actor.label()),
+            outcome,
+            seconds_to_activation: voucher
+                .activated_at_epoch
+                .zip(voucher.created_at_epoch)
+                .map(|(activated, created)| (activated - created).max(0)),
+            validity,
+            data_used_bytes: Some(guest_bytes.get(voucher.id.as_str()).copied().unwrap_or(0)),
+            data_limit_bytes: data_limit(voucher.data_usage_limit_mbytes),
+        });
+    }
+
+    for (id, creation) in &creations {
+        if listed.contains(id) {
+            continue;
+        }
+        let Some(day) = in_range(creation.at) else {
+            continue;
+        };
+        let pool = creation.pool();
+        let request = creation.request();
+        let tier = match (&pool, &request) {
+            (None, Some(request)) => tier_of(
+                request.time_limit_minutes,
+                request.data_usage_limit_mbytes,
+                request.rx_rate_limit_kbps,
+                request.tx_rate_limit_kbps,
+            ),
+            _ => None,
+        };
+        let data_limit_bytes = match &request {
+            Some(request) => data_limit(request.data_usage_limit_mbytes),
+            None => sources
+                .pools
+                .iter()
+                .find(|configured| pool.as_deref() == Some(configured.name.as_str()))
+                .and_then(|configured| data_limit(configured.data_limit_mb())),
+        };
+        let outcome = match removals.get(id) {
+            Some(Some(true)) => Outcome::ExpiredUnused,
+            Some(Some(false)) => Outcome::Activated,
+            Some(None) | None => Outcome::Unknown,
+        };
+        issued.push(Issued {
+            day,
+            tier,
+            pool,
+            operator: Some(creation.entry.actor.label()),
+            outcome,
+            seconds_to_activation: None,
+            validity: None,
+            // The guests of a deleted voucher are usually no longer listed
+            data_used_bytes: guest_bytes.get(id).copied(),
+            data_limit_bytes,
+        });
+    }
+    issued
+}
@@ -0,0 +1,240 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use serde::Serialize;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::{environment::Environment, error::ApiError};
+
+/// How failed idempotent controller reads are retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, 1 disables retries
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_environment(environment: &Environment) -> Self {
+        Self {
+            max_attempts: environment.unifi_retry_attempts.max(1),
+            base_delay: environment.unifi_retry_base_delay,
+            max_delay: environment.unifi_retry_max_delay,
+        }
+    }
+
+    /// Whether a failed attempt is worth repeating: the controller was
+    /// unreachable, overloaded or restarting.
+    pub fn is_retryable(error: &ApiError) -> bool {
+        matches!(
+            error,
+            ApiError::ControllerUnreachable(_)
+                | ApiError::RateLimited { .. }
+                | ApiError::ControllerRejected { status: 500.., .. }
+        )
+    }
+
+    /// Delay before the retry following `attempt` (starting at 1), using
+    /// "full jitter" exponential backoff. A `Retry-After` sent by the
+    /// controller takes precedence, capped at `max_delay`.
+    pub fn delay(&self, attempt: u32, error: &ApiError) -> Duration {
+        if let ApiError::RateLimited {
+            retry_after: Some(seconds),
+            ..
+        } = error
+        {
+            return Duration::from_secs(*seconds).min(self.max_delay);
+        }
+        self.backoff(attempt)
+    }
+
+    /// Exponential backoff with full jitter following `attempt`, for waits
+    /// that are not caused by an error.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        let millis = exponential.as_millis() as u64;
+        Duration::from_millis(rand::rng().random_range(0..=millis))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum CircuitState {
+    /// Requests flow normally
+    Closed,
+    /// The controller is considered down, requests fail immediately
+    Open,
+    /// The cooldown elapsed, a single probe request is let through
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitStatus {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub opened_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug)]
+struct Circuit {
+    state: CircuitState,
+    consecutive_failures: u32,
+    opened_at: Option<(Instant, DateTime<Utc>)>,
+    last_error: Option<String>,
+    probe_in_flight: bool,
+}
+
+/// Stops calling the controller after repeated failures so that kiosk polls
+/// and guest requests fail fast instead of each waiting for a timeout.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    circuit: Mutex<Circuit>,
+}
+
+impl CircuitBreaker {
+    pub fn from_environment(environment: &Environment) -> Self {
+        Self {
+            failure_threshold: environment.unifi_circuit_failure_threshold.max(1),
+            cooldown: environment.unifi_circuit_cooldown,
+            circuit: Mutex::new(Circuit {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                last_error: None,
+                probe_in_flight: false,
+            }),
+        }
+    }
+
+    /// Whether an error means the controller itself is in trouble, as
+    /// opposed to it rejecting a particular request.
+    pub fn is_failure(error: &ApiError) -> bool {
+        matches!(
+            error,
+            ApiError::ControllerUnreachable(_) | ApiError::ControllerRejected { status: 500.., .. }
+        )
+    }
+
+    /// Checks whether a request may be sent, failing fast while the circuit
+    /// is open. The outcome is reported through the returned permit.
+    pub fn acquire(&self) -> Result<CircuitPermit<'_>, ApiError> {
+        let mut circuit = self.circuit.lock().expect("Circuit breaker lock poisoned");
+        let permit = |probe| CircuitPermit {
+            breaker: self,
+            probe,
+        };
+        match circuit.state {
+            CircuitState::Closed => Ok(permit(false)),
+            CircuitState::Open => {
+                let (opened, _) = circuit.opened_at.expect("Open circuit without timestamp");
+                let elapsed = opened.elapsed();
+                if elapsed < self.cooldown {
+                    let remaining = (self.cooldown - elapsed).as_secs().max(1);
+                    return Err(ApiError::ControllerUnreachable(format!(
+                        "The UniFi controller is unavailable after repeated failures, retrying in {remaining}s"
+                    )));
+                }
+                info!("Circuit breaker cooldown elapsed, probing the UniFi controller");
+                circuit.state = CircuitState::HalfOpen;
+                circuit.probe_in_flight = true;
+                Ok(permit(true))
+            }
+            CircuitState::HalfOpen if circuit.probe_in_flight => {
+                Err(ApiError::ControllerUnreachable(
+                    "The UniFi controller is unavailable, a reconnection attempt is in progress"
+                        .to_string(),
+                ))
+            }
+            CircuitState::HalfOpen => {
+                circuit.probe_in_flight = true;
+                Ok(permit(true))
+            }
+        }
+    }
+
+    fn record(&self, result: Result<(), &ApiError>) {
+        let mut circuit = self.circuit.lock().expect("Circuit breaker lock poisoned");
+        circuit.probe_in_flight = false;
+        match result {
+            Err(error) if Self::is_failure(error) => {
+                circuit.consecutive_failures += 1;
+                circuit.last_error = Some(error.to_string());
+                let should_open = circuit.state == CircuitState::HalfOpen
+                    || circuit.consecutive_failures >= self.failure_threshold;
+                if should_open {
+                    if circuit.state == CircuitState::Closed {
+                        warn!(
+                            "UniFi controller failed {} times in a row, failing fast for {:?}: {}",
+                            circuit.consecutive_failures, self.cooldown, error
+                        );
+                    }
+                    circuit.state = CircuitState::Open;
+                    circuit.opened_at = Some((Instant::now(), Utc::now()));
+                }
+            }
+            _ => {
+                if circuit.state != CircuitState::Closed {
+                    info!("UniFi controller is reachable again, closing circuit breaker");
+                }
+                circuit.state = CircuitState::Closed;
+                circuit.consecutive_failures = 0;
+                circuit.opened_at = None;
+                circuit.last_error = None;
+            }
+        }
+    }
+
+    pub fn status(&self) -> CircuitStatus {
+        let circuit = self.circuit.lock().expect("Circuit breaker lock poisoned");
+        CircuitStatus {
+            state: circuit.state,
+            consecutive_failures: circuit.consecutive_failures,
+            opened_at: circuit.opened_at.map(|(_, at)| at),
+            last_error: circuit.last_error.clone(),
+        }
+    }
+}
+
+/// Request let through by [`CircuitBreaker::acquire`]. Dropping it without
+/// recording an outcome, as happens when the request is cancelled, lets the
+/// next request probe the controller instead.
+#[derive(Debug)]
+#[must_use = "the outcome of the request must be recorded"]
+pub struct CircuitPermit<'b> {
+    breaker: &'b CircuitBreaker,
+    probe: bool,
+}
+
+impl CircuitPermit<'_> {
+    pub fn record(mut self, result: Result<(), &ApiError>) {
+        // Recording clears the probe itself
+        self.probe = false;
+        self.breaker.record(result);
+    }
+}
+
+impl Drop for CircuitPermit<'_> {
+    fn drop(&mut self) {
+        if self.probe {
+            let mut circuit = self
+                .breaker
+                .circuit
+                .lock()
+                .expect("Circuit breaker lock poisoned");
+            circuit.probe_in_flight = false;
+        }
+    }
+}
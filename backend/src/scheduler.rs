@@ -0,0 +1,369 @@
+use std::{collections::HashMap, fs, str::FromStr, sync::OnceLock, time::Duration};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use chrono_tz::Tz;
+use croner::Cron;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, error, info, warn};
+use utoipa::ToSchema;
+
+use crate::{audit::Actor, reports::MAX_REPORT_DAYS, shutdown, tasks};
+
+pub static SCHEDULER: OnceLock<Scheduler> = OnceLock::new();
+
+const CONFIG_FILE_PATH: &str = "/app/config/scheduler.json";
+const DEFAULT_RETENTION_DAYS: u64 = 90;
+const DEFAULT_REPORT_DAYS: u64 = 7;
+// Upper bound on a single sleep so that wall clock changes are picked up
+const MAX_SLEEP: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    PurgeExpired,
+    PurgeRolling,
+    PoolTopUp,
+    Reports,
+    RetentionCleanup,
+}
+
+impl JobKind {
+    pub const ALL: [JobKind; 5] = [
+        JobKind::PurgeExpired,
+        JobKind::PurgeRolling,
+        JobKind::PoolTopUp,
+        JobKind::Reports,
+        JobKind::RetentionCleanup,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            JobKind::PurgeExpired => "purge_expired",
+            JobKind::PurgeRolling => "purge_rolling",
+            JobKind::PoolTopUp => "pool_top_up",
+            JobKind::Reports => "reports",
+            JobKind::RetentionCleanup => "retention_cleanup",
+        }
+    }
+
+    fn default_config(self) -> JobConfig {
+        let (enabled, schedule) = match self {
+            JobKind::PurgeExpired => (false, "30 3 * * *"),
+            // Matches the historical midnight purge of rolling vouchers
+            JobKind::PurgeRolling => (true, "0 0 * * *"),
+            JobKind::PoolTopUp => (false, "*/5 * * * *"),
+            JobKind::Reports => (false, "0 6 * * 1"),
+            JobKind::RetentionCleanup => (false, "15 4 * * *"),
+        };
+        JobConfig {
+            enabled,
+            schedule: schedule.to_string(),
+            retention_days: None,
+            report_days: None,
+        }
+    }
+}
+
+impl FromStr for JobKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        JobKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| format!("Unknown job: {s}"))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Cron expression evaluated in the configured timezone
+    pub schedule: String,
+    /// Age after which files are removed by the retention cleanup job
+    pub retention_days: Option<u64>,
+    /// Days covered by the reports job, ending the day before it runs
+    pub report_days: Option<u64>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl JobConfig {
+    pub fn retention_days(&self) -> u64 {
+        self.retention_days.unwrap_or(DEFAULT_RETENTION_DAYS)
+    }
+
+    pub fn report_days(&self) -> u64 {
+        self.report_days.unwrap_or(DEFAULT_REPORT_DAYS)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SchedulerConfigFile {
+    #[serde(default)]
+    pub jobs: HashMap<JobKind, JobConfig>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobOutcome {
+    Success,
+    Failure,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JobRun {
+    pub started_at: String,
+    pub finished_at: String,
+    pub trigger: String,
+    pub outcome: JobOutcome,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct JobStatus {
+    pub name: &'static str,
+    pub enabled: bool,
+    pub schedule: String,
+    pub running: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_run: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_run: Option<JobRun>,
+}
+
+#[derive(Debug, Default)]
+struct JobState {
+    next_run: Option<DateTime<Tz>>,
+    last_run: Option<JobRun>,
+}
+
+#[derive(Debug)]
+struct Job {
+    kind: JobKind,
+    config: JobConfig,
+    cron: Option<Cron>,
+    state: RwLock<JobState>,
+    /// Held while the job runs so that a job never overlaps with itself
+    running: Mutex<()>,
+}
+
+#[derive(Debug)]
+pub struct Scheduler {
+    timezone: Tz,
+    jobs: Vec<Job>,
+}
+
+impl Scheduler {
+    pub fn try_new(timezone: Tz) -> Result<Self, String> {
+        let file_config = match fs::read_to_string(CONFIG_FILE_PATH) {
+            Ok(content) => serde_json::from_str::<SchedulerConfigFile>(&content)
+                .map_err(|e| format!("Failed to parse scheduler config file: {e}"))?,
+            Err(e) => {
+                warn!(
+                    "Could not read scheduler config file ({}), using default schedules",
+                    e
+                );
+                SchedulerConfigFile::default()
+            }
+        };
+        Self::from_config(timezone, file_config)
+    }
+
+    /// Jobs missing from `file_config` keep their default schedule.
+    pub fn from_config(timezone: Tz, mut file_config: SchedulerConfigFile) -> Result<Self, String> {
+        let mut errors = Vec::new();
+        let jobs = JobKind::ALL
+            .into_iter()
+            .map(|kind| {
+                let config = file_config
+                    .jobs
+                    .remove(&kind)
+                    .unwrap_or_else(|| kind.default_config());
+                let cron = match Cron::from_str(&config.schedule) {
+                    Ok(cron) => Some(cron),
+                    Err(e) => {
+                        errors.push(format!(
+                            "{}: invalid schedule '{}': {e}",
+                            kind.name(),
+                            config.schedule
+                        ));
+                        None
+                    }
+                };
+                if config
+                    .report_days
+                    .is_some_and(|days| !(1..=MAX_REPORT_DAYS).contains(&days))
+                {
+                    errors.push(format!(
+                        "{}: reportDays must be from 1 to {MAX_REPORT_DAYS}",
+                        kind.name()
+                    ));
+                }
+                Job {
+                    kind,
+                    config,
+                    cron,
+                    state: RwLock::new(JobState::default()),
+                    running: Mutex::new(()),
+                }
+            })
+            .collect();
+
+        if !errors.is_empty() {
+            return Err(errors.join("; "));
+        }
+
+        Ok(Self { timezone, jobs })
+    }
+
+    /// Spawns one task per enabled job on the global scheduler.
+    pub fn start() {
+        let scheduler = SCHEDULER.get().expect("Scheduler not initialized");
+        for job in &scheduler.jobs {
+            if job.config.enabled {
+                info!(
+                    "Scheduling job {} with '{}' ({})",
+                    job.kind.name(),
+                    job.config.schedule,
+                    scheduler.timezone
+                );
+                tokio::spawn(scheduler.run_job_loop(job));
+            } else {
+                info!("Job {} is disabled", job.kind.name());
+            }
+        }
+    }
+
+    async fn run_job_loop(&'static self, job: &'static Job) {
+        loop {
+            let next = match self.next_occurrence(job, Utc::now()) {
+                Ok(next) => next,
+                Err(e) => {
+                    error!(
+                        "Job {} has no upcoming run, stopping it: {}",
+                        job.kind.name(),
+                        e
+                    );
+                    job.state.write().await.next_run = None;
+                    return;
+                }
+            };
+            job.state.write().await.next_run = Some(next);
+
+            // Sleep in bounded steps so a suspended host or clock change
+            // cannot make the job miss its slot by hours
+            loop {
+                let remaining = (next.with_timezone(&Utc) - Utc::now())
+                    .to_std()
+                    .unwrap_or(Duration::ZERO);
+                if remaining.is_zero() {
+                    break;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(remaining.min(MAX_SLEEP)) => {}
+                    _ = shutdown::requested() => {
+                        debug!("Stopping job {}", job.kind.name());
+                        return;
+                    }
+                }
+            }
+
+            if let Err(e) = self.execute(job, "schedule", Actor::System).await {
+                warn!("Skipped scheduled run of {}: {}", job.kind.name(), e);
+            }
+        }
+    }
+
+    async fn execute(&self, job: &Job, trigger: &str, actor: Actor) -> Result<JobRun, String> {
+        let Ok(_running) = job.running.try_lock() else {
+            return Err("job is already running".to_string());
+        };
+
+        // A job interrupted halfway could leave a pool partly refilled, so
+        // the shutdown waits for it
+        let _operation = shutdown::operation();
+        let started_at = Utc::now();
+        info!("Running job {} (trigger: {})", job.kind.name(), trigger);
+        let result = tasks::run(job.kind, &job.config, trigger, actor).await;
+        let finished_at = Utc::now();
+
+        let (outcome, message) = match result {
+            Ok(message) => {
+                info!("Job {} finished: {}", job.kind.name(), message);
+                (JobOutcome::Success, message)
+            }
+            Err(message) => {
+                error!("Job {} failed: {}", job.kind.name(), message);
+                (JobOutcome::Failure, message)
+            }
+        };
+
+        let run = JobRun {
+            started_at: self.format_time(started_at),
+            finished_at: self.format_time(finished_at),
+            trigger: trigger.to_string(),
+            outcome,
+            message,
+        };
+        job.state.write().await.last_run = Some(run.clone());
+        Ok(run)
+    }
+
+    /// Runs a job immediately, whether or not it is enabled.
+    pub async fn trigger(&self, kind: JobKind, actor: Actor) -> Result<JobRun, String> {
+        let job = self.job(kind);
+        self.execute(job, "manual", actor).await
+    }
+
+    pub async fn statuses(&self) -> Vec<JobStatus> {
+        let mut statuses = Vec::with_capacity(self.jobs.len());
+        for job in &self.jobs {
+            let state = job.state.read().await;
+            statuses.push(JobStatus {
+                name: job.kind.name(),
+                enabled: job.config.enabled,
+                schedule: job.config.schedule.clone(),
+                running: job.running.try_lock().is_err(),
+                next_run: state
+                    .next_run
+                    .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                last_run: state.last_run.clone(),
+            });
+        }
+        statuses
+    }
+
+    /// First run of a job strictly after `after`, in the scheduler's
+    /// timezone. A time skipped by a DST change runs at the end of the gap,
+    /// a repeated one only once.
+    pub fn next_run(&self, kind: JobKind, after: DateTime<Utc>) -> Result<DateTime<Tz>, String> {
+        self.next_occurrence(self.job(kind), after)
+    }
+
+    fn next_occurrence(&self, job: &Job, after: DateTime<Utc>) -> Result<DateTime<Tz>, String> {
+        let cron = job.cron.as_ref().expect("Every job has a valid schedule");
+        cron.find_next_occurrence(&after.with_timezone(&self.timezone), false)
+            .map_err(|e| e.to_string())
+    }
+
+    fn job(&self, kind: JobKind) -> &Job {
+        self.jobs
+            .iter()
+            .find(|job| job.kind == kind)
+            .expect("Every job kind is registered")
+    }
+
+    fn format_time(&self, time: DateTime<Utc>) -> String {
+        time.with_timezone(&self.timezone)
+            .to_rfc3339_opts(SecondsFormat::Secs, true)
+    }
+}
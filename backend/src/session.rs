@@ -0,0 +1,331 @@
+use std::{
+    future::Future,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use reqwest::{
+    Url,
+    cookie::{CookieStore, Jar},
+    header::{HeaderMap, HeaderValue, SET_COOKIE},
+};
+use serde::Serialize;
+use tokio::sync::{Mutex, Notify};
+use tracing::{info, warn};
+
+use crate::error::ApiError;
+
+/// Lifetime assumed when the controller does not say how long its session
+/// cookie lasts.
+pub const DEFAULT_LIFETIME: Duration = Duration::from_secs(30 * 60);
+
+/// Share of the lifetime after which the session is renewed ahead of expiry.
+const REFRESH_AFTER: f64 = 0.8;
+
+/// Share of the remaining lifetime to wait for after a failed renewal, so
+/// that a controller rejecting the credentials is not asked over and over.
+const RETRY_AFTER: f64 = 0.25;
+
+/// Shortest wait between two failed renewals.
+const MIN_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Cookie jar that can be emptied, so that a rejected session's cookies are
+/// not sent along with the next login.
+#[derive(Debug, Default)]
+pub struct SessionCookies {
+    jar: RwLock<Arc<Jar>>,
+}
+
+impl SessionCookies {
+    fn clear(&self) {
+        if let Ok(mut jar) = self.jar.write() {
+            *jar = Arc::new(Jar::default());
+        }
+    }
+
+    fn current(&self) -> Arc<Jar> {
+        self.jar.read().map(|jar| jar.clone()).unwrap_or_default()
+    }
+}
+
+impl CookieStore for SessionCookies {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        self.current().set_cookies(cookie_headers, url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        self.current().cookies(url)
+    }
+}
+
+/// How the session lifetime was determined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LifetimeSource {
+    /// `Max-Age` or `Expires` of the session cookie
+    Cookie,
+    /// The controller did not say, [`DEFAULT_LIFETIME`] is assumed
+    Assumed,
+}
+
+/// Age and expiry of the controller session.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionInfo {
+    pub established_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// When the session is renewed in the background
+    pub refresh_at: DateTime<Utc>,
+    pub age_seconds: i64,
+    pub lifetime_seconds: u64,
+    pub lifetime_source: LifetimeSource,
+    /// Logins since startup
+    pub logins: u64,
+    /// Why the last background renewal failed, cleared by the next login
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_refresh_error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Established {
+    generation: u64,
+    established_at: DateTime<Utc>,
+    started: Instant,
+    lifetime: Duration,
+    source: LifetimeSource,
+    /// When to try again after a failed renewal
+    retry_at: Option<Instant>,
+}
+
+impl Established {
+    fn expires(&self) -> Instant {
+        self.started + self.lifetime
+    }
+
+    fn refresh_due(&self) -> Instant {
+        let due = self.started + self.lifetime.mul_f64(REFRESH_AFTER);
+        self.retry_at.map_or(due, |retry_at| retry_at.max(due))
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    current: Option<Established>,
+    logins: u64,
+    last_refresh_error: Option<String>,
+}
+
+/// Session with the controller. Logins are serialised: callers finding the
+/// session expired wait for a single login instead of each starting their own.
+///
+/// Every login starts a new generation. Requests remember the generation they
+/// were sent with, so a rejection only discards the session it was made for
+/// and not one another caller already renewed.
+#[derive(Debug, Default)]
+pub struct ControllerSession {
+    cookies: Arc<SessionCookies>,
+    state: RwLock<State>,
+    login_lock: Mutex<()>,
+    changed: Notify,
+}
+
+impl ControllerSession {
+    /// Cookie store to build the controller's HTTP client with.
+    pub fn cookies(&self) -> Arc<SessionCookies> {
+        self.cookies.clone()
+    }
+
+    /// Generation of the current session, unless it expired.
+    fn valid(&self) -> Option<u64> {
+        let state = self.state.read().ok()?;
+        let current = state.current.as_ref()?;
+        (Instant::now() < current.expires()).then_some(current.generation)
+    }
+
+    /// Returns the generation of a valid session, running `login` first when
+    /// there is none. Concurrent callers share a single login.
+    pub async fn ensure<F, Fut>(&self, login: F) -> Result<u64, ApiError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Option<Duration>, ApiError>>,
+    {
+        if let Some(generation) = self.valid() {
+            return Ok(generation);
+        }
+        let _guard = self.login_lock.lock().await;
+        // Another caller may have logged in while this one waited
+        if let Some(generation) = self.valid() {
+            return Ok(generation);
+        }
+        info!("Session expired or not authenticated, logging in");
+        self.cookies.clear();
+        let lifetime = login().await?;
+        Ok(self.establish(lifetime))
+    }
+
+    /// Logs in again ahead of expiry, unless the session `generation` was
+    /// already replaced. The current session stays usable meanwhile, and a
+    /// failed renewal is retried after a share of its remaining lifetime.
+    pub async fn renew<F, Fut>(&self, generation: u64, login: F) -> Result<u64, ApiError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Option<Duration>, ApiError>>,
+    {
+        let _guard = self.login_lock.lock().await;
+        if let Some(current) = self.valid()
+            && current != generation
+        {
+            return Ok(current);
+        }
+        match login().await {
+            Ok(lifetime) => Ok(self.establish(lifetime)),
+            Err(error) => {
+                if let Ok(mut state) = self.state.write() {
+                    state.last_refresh_error = Some(error.to_string());
+                    if let Some(current) = state
+                        .current
+                        .as_mut()
+                        .filter(|current| current.generation == generation)
+                    {
+                        let remaining = current.expires().saturating_duration_since(Instant::now());
+                        let delay = remaining.mul_f64(RETRY_AFTER).max(MIN_RETRY_DELAY);
+                        current.retry_at = Some(Instant::now() + delay);
+                    }
+                }
+                Err(error)
+            }
+        }
+    }
+
+    fn establish(&self, lifetime: Option<Duration>) -> u64 {
+        let (lifetime, source) = match lifetime {
+            Some(lifetime) => (lifetime, LifetimeSource::Cookie),
+            None => (DEFAULT_LIFETIME, LifetimeSource::Assumed),
+        };
+        let generation = {
+            let mut state = self.state.write().expect("Session state poisoned");
+            state.logins += 1;
+            state.last_refresh_error = None;
+            let generation = state.logins;
+            state.current = Some(Established {
+                generation,
+                established_at: Utc::now(),
+                started: Instant::now(),
+                lifetime,
+                source,
+                retry_at: None,
+            });
+            generation
+        };
+        info!(
+            "Controller session established, expires in {}s ({:?} lifetime)",
+            lifetime.as_secs(),
+            source
+        );
+        self.changed.notify_waiters();
+        generation
+    }
+
+    /// Discards the session `generation` after the controller rejected it,
+    /// along with its cookies. A no-op when it was already replaced.
+    pub fn invalidate(&self, generation: u64) {
+        let Ok(mut state) = self.state.write() else {
+            return;
+        };
+        if state
+            .current
+            .as_ref()
+            .is_some_and(|current| current.generation == generation)
+        {
+            warn!("Controller rejected the session, discarding its cookies");
+            state.current = None;
+            self.cookies.clear();
+        }
+    }
+
+    /// Forgets the session after logging out.
+    pub fn end(&self) {
+        if let Ok(mut state) = self.state.write() {
+            state.current = None;
+        }
+        self.cookies.clear();
+        self.changed.notify_waiters();
+    }
+
+    pub fn is_established(&self) -> bool {
+        self.valid().is_some()
+    }
+
+    /// Waits until the current session is due for renewal and returns its
+    /// generation. Waits for a login while there is no session or it expires
+    /// before the next renewal, as requests then log in by themselves.
+    pub async fn refresh_due(&self) -> u64 {
+        loop {
+            let changed = self.changed.notified();
+            let due = self.state.read().ok().and_then(|state| {
+                state
+                    .current
+                    .as_ref()
+                    .filter(|current| current.refresh_due() < current.expires())
+                    .map(|current| (current.generation, current.refresh_due()))
+            });
+            match due {
+                Some((generation, at)) => tokio::select! {
+                    _ = tokio::time::sleep_until(at.into()) => return generation,
+                    _ = changed => {}
+                },
+                None => changed.await,
+            }
+        }
+    }
+
+    /// Current session, `None` when it expired or was never established.
+    pub fn info(&self) -> Option<SessionInfo> {
+        let state = self.state.read().ok()?;
+        let current = state.current.as_ref()?;
+        let now_instant = Instant::now();
+        let remaining = current.expires().checked_duration_since(now_instant)?;
+        let until_refresh = current.refresh_due().saturating_duration_since(now_instant);
+        let now = Utc::now();
+        Some(SessionInfo {
+            established_at: current.established_at,
+            expires_at: now + chrono::Duration::from_std(remaining).ok()?,
+            refresh_at: now + chrono::Duration::from_std(until_refresh).ok()?,
+            age_seconds: (now - current.established_at).num_seconds(),
+            lifetime_seconds: current.lifetime.as_secs(),
+            lifetime_source: current.source,
+            logins: state.logins,
+            last_refresh_error: state.last_refresh_error.clone(),
+        })
+    }
+}
+
+/// Lifetime of the session cookies set by a login response, from `Max-Age`
+/// or else `Expires`. The shortest wins when several cookies carry one.
+pub fn cookie_lifetime(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|cookie| {
+            let attributes: Vec<(String, &str)> = cookie
+                .split(';')
+                .skip(1)
+                .filter_map(|attribute| attribute.split_once('='))
+                .map(|(name, value)| (name.trim().to_lowercase(), value.trim()))
+                .collect();
+            let max_age = attributes
+                .iter()
+                .find(|(name, _)| name == "max-age")
+                .and_then(|(_, value)| value.parse::<u64>().ok())
+                .map(Duration::from_secs);
+            max_age.or_else(|| {
+                let expires = attributes.iter().find(|(name, _)| name == "expires")?.1;
+                let expires = DateTime::parse_from_rfc2822(expires).ok()?;
+                (expires.with_timezone(&Utc) - Utc::now()).to_std().ok()
+            })
+        })
+        .filter(|lifetime| !lifetime.is_zero())
+        .min()
+}
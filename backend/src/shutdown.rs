@@ -0,0 +1,148 @@
+use std::{
+    sync::{
+        LazyLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::{
+    sync::{Notify, watch},
+    task::JoinHandle,
+};
+use tracing::{info, warn};
+
+use crate::unifi_api::UNIFI_API;
+
+static SHUTDOWN: LazyLock<watch::Sender<bool>> = LazyLock::new(|| watch::channel(false).0);
+
+/// Background operations that must not be cut short, see [`operation`]
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+static DRAINED: Notify = Notify::const_new();
+
+/// Waits for SIGTERM or SIGINT and starts the shutdown. A second signal
+/// exits immediately.
+pub async fn listen_for_signals() {
+    let name = wait_for_signal().await;
+    info!("Received {}, shutting down", name);
+    trigger();
+
+    let name = wait_for_signal().await;
+    warn!("Received {} again, exiting without waiting", name);
+    std::process::exit(130);
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() -> &'static str {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = tokio::signal::ctrl_c() => "SIGINT",
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    "SIGINT"
+}
+
+pub fn trigger() {
+    SHUTDOWN.send_replace(true);
+}
+
+pub fn is_requested() -> bool {
+    *SHUTDOWN.borrow()
+}
+
+/// Resolves once the shutdown started.
+pub async fn requested() {
+    let mut receiver = SHUTDOWN.subscribe();
+    // Only fails if the sender is gone, which it never is
+    let _ = receiver.wait_for(|&shutdown| shutdown).await;
+}
+
+/// Receiver for streams that must end when the shutdown starts.
+pub fn subscribe() -> watch::Receiver<bool> {
+    SHUTDOWN.subscribe()
+}
+
+/// Marks a background operation the shutdown waits for until the returned
+/// guard is dropped.
+pub fn operation() -> OperationGuard {
+    IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+    OperationGuard(())
+}
+
+#[must_use = "the operation ends when the guard is dropped"]
+pub struct OperationGuard(());
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        if IN_FLIGHT.fetch_sub(1, Ordering::SeqCst) == 1 {
+            DRAINED.notify_waiters();
+        }
+    }
+}
+
+/// Number of background operations still running.
+pub fn in_flight() -> usize {
+    IN_FLIGHT.load(Ordering::SeqCst)
+}
+
+/// Waits until no background operation is running.
+pub async fn drained() {
+    loop {
+        let notified = DRAINED.notified();
+        if in_flight() == 0 {
+            return;
+        }
+        notified.await;
+    }
+}
+
+/// Waits for the shutdown to complete: once it starts, the servers stop
+/// accepting connections and wait for in-flight requests, then scheduled jobs
+/// and pool checks still running are waited for, all within `timeout`.
+pub async fn drain(
+    servers: Vec<JoinHandle<Result<(), String>>>,
+    timeout: Duration,
+) -> Result<(), String> {
+    let drain = async {
+        for server in servers {
+            server
+                .await
+                .map_err(|e| format!("Server task failed: {e}"))??;
+        }
+        if in_flight() > 0 {
+            info!(
+                "Waiting for {} background operations to finish",
+                in_flight()
+            );
+        }
+        drained().await;
+        if let Some(api) = UNIFI_API.get() {
+            api.logout().await;
+        }
+        Ok::<(), String>(())
+    };
+    let deadline = async {
+        requested().await;
+        tokio::time::sleep(timeout).await;
+    };
+
+    tokio::select! {
+        result = drain => {
+            result?;
+            info!("Shutdown complete");
+        }
+        _ = deadline => warn!(
+            "Shutdown timed out after {}s, abandoning {} background operations and open requests",
+            timeout.as_secs(),
+            in_flight()
+        ),
+    }
+    Ok(())
+}
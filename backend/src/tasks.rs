@@ -1,41 +1,141 @@
-use chrono::Utc;
-use chrono_tz::Tz;
-use tokio::time::sleep;
-use tracing::{error, info};
-
-use crate::handlers::delete_expired_rolling_handler;
-
-pub async fn run_daily_purge(timezone: Tz) {
-    loop {
-        let now = Utc::now().with_timezone(&timezone);
-        let next_midnight = now
-            .date_naive()
-            .succ_opt()
-            .expect("Next day is not representable")
-            .and_hms_opt(0, 0, 0)
-            .expect("Could not get next midnight")
-            .and_local_timezone(timezone)
-            .latest()
-            .expect("Could not convert next midnight time to local timezone");
-
-        let delta = next_midnight - now;
-        let duration = delta
-            .to_std()
-            .expect("Duration to next midnight is less than 0");
-
-        info!(
-            "Next purge of expired rolling vouchers at midnight ({}), in {} hours and {} minutes...",
-            timezone,
-            delta.num_hours(),
-            delta.num_minutes() % 60
-        );
-
-        sleep(duration).await;
-
-        info!("Purging expired rolling vouchers...");
-        match delete_expired_rolling_handler().await {
-            Ok(response) => info!("Deleted {} rolling vouchers (status: {})", response.data.len(), response.meta.rc),
-            Err(code) => error!("Failed to delete rolling vouchers: {}", code),
-        };
+use chrono::{Days, Utc};
+use tracing::info;
+
+use crate::{
+    audit::{Actor, AuditAction, AuditRecord},
+    environment::ENVIRONMENT,
+    handlers::purge_vouchers,
+    logging,
+    reports::Report,
+    scheduler::{JobConfig, JobKind},
+    unifi_api::client,
+    voucher_config::VOUCHER_CONFIG,
+};
+
+/// Runs one scheduled job and returns a short summary of what it did.
+pub async fn run(
+    kind: JobKind,
+    config: &JobConfig,
+    trigger: &str,
+    actor: Actor,
+) -> Result<String, String> {
+    match kind {
+        JobKind::PurgeExpired => purge(false, trigger, actor).await,
+        JobKind::PurgeRolling => purge(true, trigger, actor).await,
+        JobKind::PoolTopUp => top_up_rolling_pool(trigger, actor).await,
+        JobKind::Reports => report(config.report_days()).await,
+        JobKind::RetentionCleanup => retention_cleanup(config.retention_days()),
+    }
+}
+
+async fn purge(rolling_only: bool, trigger: &str, actor: Actor) -> Result<String, String> {
+    let client = client().map_err(|e| e.to_string())?;
+    let scope = if rolling_only {
+        "expired_rolling"
+    } else {
+        "expired"
+    };
+
+    info!("Purging {} vouchers...", scope.replace('_', " "));
+    let selected = if rolling_only {
+        client.get_expired_rolling_vouchers(None).await
+    } else {
+        client.get_expired_vouchers().await
+    };
+    let audit = AuditRecord::new(AuditAction::Purge, actor)
+        .parameters(serde_json::json!({ "scope": scope, "trigger": trigger }));
+
+    let response = purge_vouchers(selected, audit)
+        .await
+        .map_err(|e| format!("Failed to delete vouchers: {e}"))?;
+    Ok(format!(
+        "Deleted {} vouchers (status: {})",
+        response.data.len(),
+        response.meta.rc
+    ))
+}
+
+async fn top_up_rolling_pool(trigger: &str, actor: Actor) -> Result<String, String> {
+    let voucher_config = VOUCHER_CONFIG
+        .get()
+        .expect("Voucher config not initialized");
+    let client = client().map_err(|e| e.to_string())?;
+
+    let mut summaries = Vec::new();
+    let mut failures = Vec::new();
+    for pool in voucher_config.enabled_pools() {
+        let audit = AuditRecord::new(AuditAction::Rotate, actor.clone())
+            .parameters(serde_json::json!({ "trigger": trigger, "pool": pool.name }));
+        match client.top_up_rolling_vouchers(pool).await {
+            Ok(created) if created.is_empty() => {
+                summaries.push(format!("{}: already at its minimum size", pool.name))
+            }
+            Ok(created) => {
+                audit.vouchers(&created).record();
+                let ids: Vec<&str> = created.iter().map(|v| v.id.as_str()).collect();
+                summaries.push(format!(
+                    "{}: created {} rolling vouchers ({})",
+                    pool.name,
+                    created.len(),
+                    ids.join(", ")
+                ));
+            }
+            Err(e) => {
+                audit.failed(&e).record();
+                failures.push(format!(
+                    "Failed to top up rolling pool '{}': {e}",
+                    pool.name
+                ));
+            }
+        }
     }
+
+    if !failures.is_empty() {
+        return Err(failures.join("; "));
+    }
+    if summaries.is_empty() {
+        return Ok("Rolling vouchers are disabled, nothing to do".to_string());
+    }
+    Ok(summaries.join("; "))
+}
+
+/// Computes the report of the `days` before today and saves it to the data
+/// directory.
+async fn report(days: u64) -> Result<String, String> {
+    let environment = ENVIRONMENT.get().expect("Environment not set");
+    let now = Utc::now();
+    let today = now.with_timezone(&environment.timezone).date_naive();
+    let (from, to) = today
+        .checked_sub_days(Days::new(days))
+        .zip(today.checked_sub_days(Days::new(1)))
+        .ok_or_else(|| format!("Cannot report on the {days} days before {today}"))?;
+
+    let report = Report::generate(from, to, now)
+        .await
+        .map_err(|e| format!("Failed to compute the report: {e}"))?;
+    let path = report.save(&environment.data_dir)?;
+    let totals = &report.totals;
+    Ok(format!(
+        "Report of {} to {}: {} vouchers issued, {} activated, {} expired unused (saved to {})",
+        from,
+        to,
+        totals.issued,
+        totals.activated,
+        totals.expired_unused,
+        path.display()
+    ))
+}
+
+fn retention_cleanup(retention_days: u64) -> Result<String, String> {
+    let environment = ENVIRONMENT.get().expect("Environment not set");
+    let Some(log_dir) = environment.log_dir.as_deref() else {
+        return Ok("File logging is disabled, nothing to do".to_string());
+    };
+
+    let removed = logging::remove_logs_older_than(log_dir, retention_days)
+        .map_err(|e| format!("Failed to clean up {}: {e}", log_dir.display()))?;
+    Ok(format!(
+        "Removed {} log files older than {} days",
+        removed, retention_days
+    ))
 }
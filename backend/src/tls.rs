@@ -0,0 +1,348 @@
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use reqwest::ClientBuilder;
+use sha2::{Digest, Sha256};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    TlsConnector,
+    rustls::{
+        self, ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+        client::{
+            WebPkiServerVerifier,
+            danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        },
+        crypto::{self, CryptoProvider, ring},
+        pki_types::{CertificateDer, ServerName, UnixTime, pem::PemObject},
+    },
+};
+use x509_parser::parse_x509_certificate;
+
+use crate::{audit::hex, environment::Environment};
+
+/// SHA-256 fingerprint the certificate of the controller must match.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CertificatePin {
+    /// Of the whole certificate, which changes whenever it is renewed
+    Certificate([u8; 32]),
+    /// Of its public key (SubjectPublicKeyInfo), which survives renewals
+    /// keeping the same key
+    PublicKey([u8; 32]),
+}
+
+impl CertificatePin {
+    /// Parses comma-separated fingerprints of one kind.
+    pub fn parse_list(value: &str, kind: fn([u8; 32]) -> Self) -> Result<Vec<Self>, String> {
+        value
+            .split(',')
+            .filter(|fingerprint| !fingerprint.trim().is_empty())
+            .map(|fingerprint| parse_fingerprint(fingerprint).map(kind))
+            .collect()
+    }
+
+    fn matches(&self, certificate: &CertificateDer<'_>) -> bool {
+        match self {
+            Self::Certificate(pin) => Sha256::digest(certificate.as_ref()).as_slice() == pin,
+            Self::PublicKey(pin) => public_key_sha256(certificate).as_ref() == Some(pin),
+        }
+    }
+}
+
+/// Parses a SHA-256 fingerprint in hex, with or without the colons
+/// `openssl x509 -fingerprint` prints.
+fn parse_fingerprint(value: &str) -> Result<[u8; 32], String> {
+    let digits: String = value.chars().filter(|c| !matches!(c, ':' | ' ')).collect();
+    if digits.len() != 64 || !digits.is_ascii() {
+        return Err(format!(
+            "'{}' is not a SHA-256 fingerprint, expected 64 hex digits",
+            value.trim()
+        ));
+    }
+    let mut fingerprint = [0; 32];
+    for (i, byte) in fingerprint.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&digits[i * 2..i * 2 + 2], 16)
+            .map_err(|_| format!("'{}' is not a hex fingerprint", value.trim()))?;
+    }
+    Ok(fingerprint)
+}
+
+/// SHA-256 of the SubjectPublicKeyInfo of a certificate.
+fn public_key_sha256(certificate: &CertificateDer<'_>) -> Option<[u8; 32]> {
+    let (_, parsed) = parse_x509_certificate(certificate.as_ref()).ok()?;
+    Some(Sha256::digest(parsed.public_key().raw).into())
+}
+
+/// Hex SHA-256 fingerprints of a certificate, in the formats pins accept.
+#[derive(Debug, Clone)]
+pub struct Fingerprints {
+    pub certificate: String,
+    /// `None` when the certificate cannot be parsed
+    pub public_key: Option<String>,
+}
+
+pub fn fingerprints(certificate: &CertificateDer<'_>) -> Fingerprints {
+    Fingerprints {
+        certificate: hex(&Sha256::digest(certificate.as_ref())),
+        public_key: public_key_sha256(certificate).map(|digest| hex(&digest)),
+    }
+}
+
+/// CA certificates the controller certificate is verified against, instead
+/// of the public roots.
+#[derive(Debug, Clone)]
+pub struct CaBundle {
+    pub path: PathBuf,
+    pub certificates: Vec<CertificateDer<'static>>,
+}
+
+impl CaBundle {
+    /// Reads the PEM certificates of `path`.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let pem = fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        let certificates = CertificateDer::pem_slice_iter(&pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("invalid PEM in {}: {e}", path.display()))?;
+        if certificates.is_empty() {
+            return Err(format!("{} contains no certificate", path.display()));
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            certificates,
+        })
+    }
+}
+
+/// Verifies the controller certificate against the configured CA bundle
+/// and pins. With pins alone, as for a self-signed certificate, matching a
+/// pin is all that is required.
+#[derive(Debug)]
+struct ControllerVerifier {
+    /// `None` when no CA bundle is configured
+    chain: Option<Arc<WebPkiServerVerifier>>,
+    pins: Vec<CertificatePin>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for ControllerVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(chain) = &self.chain {
+            chain.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        }
+        if !self.pins.is_empty() && !self.pins.iter().any(|pin| pin.matches(end_entity)) {
+            return Err(rustls::Error::General(format!(
+                "the certificate (SHA-256 {}) matches none of the pinned fingerprints",
+                fingerprints(end_entity).certificate
+            )));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Whether a CA bundle or pins replace the default verification.
+pub fn has_custom_trust(environment: &Environment) -> bool {
+    environment.unifi_ca_bundle.is_some() || !environment.unifi_certificate_pins.is_empty()
+}
+
+fn custom_verifier(environment: &Environment) -> Result<Option<Arc<ControllerVerifier>>, String> {
+    if !has_custom_trust(environment) {
+        return Ok(None);
+    }
+    let provider = Arc::new(ring::default_provider());
+    let chain = match &environment.unifi_ca_bundle {
+        Some(bundle) => {
+            let mut roots = RootCertStore::empty();
+            for certificate in &bundle.certificates {
+                roots.add(certificate.clone()).map_err(|e| {
+                    format!("Invalid CA certificate in {}: {e}", bundle.path.display())
+                })?;
+            }
+            let verifier =
+                WebPkiServerVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                    .build()
+                    .map_err(|e| format!("Failed to set up certificate verification: {e}"))?;
+            Some(verifier)
+        }
+        None => None,
+    };
+    Ok(Some(Arc::new(ControllerVerifier {
+        chain,
+        pins: environment.unifi_certificate_pins.clone(),
+        provider,
+    })))
+}
+
+fn client_config(verifier: Arc<dyn ServerCertVerifier>) -> Result<ClientConfig, String> {
+    Ok(
+        ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("Failed to set up TLS: {e}"))?
+            .dangerous()
+            .with_custom_certificate_verifier(verifier)
+            .with_no_client_auth(),
+    )
+}
+
+/// HTTP client builder verifying the controller certificate as configured:
+/// against the CA bundle and pins when set, else against the public roots
+/// unless `UNIFI_HAS_VALID_CERT` is false.
+pub fn client_builder(environment: &Environment) -> Result<ClientBuilder, String> {
+    let builder = ClientBuilder::new().use_rustls_tls();
+    Ok(match custom_verifier(environment)? {
+        Some(verifier) => builder.use_preconfigured_tls(client_config(verifier)?),
+        None => builder.danger_accept_invalid_certs(!environment.unifi_has_valid_cert),
+    })
+}
+
+/// Chain presented by the controller and the outcome of verifying it
+pub type Presented = (Vec<CertificateDer<'static>>, Result<(), rustls::Error>);
+
+/// Accepts any certificate so that its details can be reported, and records
+/// whether the configured verification succeeded.
+#[derive(Debug)]
+struct RecordingVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    presented: Mutex<Option<Presented>>,
+}
+
+impl ServerCertVerifier for RecordingVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verification = self
+            .inner
+            .verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+            .map(|_| ());
+        let chain = std::iter::once(end_entity)
+            .chain(intermediates)
+            .map(|cert| cert.clone().into_owned())
+            .collect();
+        *self.presented.lock().expect("Verifier lock poisoned") = Some((chain, verification));
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Completes a TLS handshake with the controller at `address` and returns
+/// the chain it presented, whether or not it is trusted. Without a CA bundle
+/// or pins, it is verified against the public roots.
+pub async fn inspect(
+    environment: &Environment,
+    host: &str,
+    address: SocketAddr,
+    timeout: Duration,
+) -> Result<Presented, String> {
+    let inner: Arc<dyn ServerCertVerifier> = match custom_verifier(environment)? {
+        Some(verifier) => verifier,
+        None => {
+            let roots = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+            WebPkiServerVerifier::builder_with_provider(
+                Arc::new(roots),
+                Arc::new(ring::default_provider()),
+            )
+            .build()
+            .map_err(|e| format!("Failed to set up certificate verification: {e}"))?
+        }
+    };
+    let verifier = Arc::new(RecordingVerifier {
+        inner,
+        presented: Mutex::new(None),
+    });
+    let config = client_config(verifier.clone())?;
+    let server_name = ServerName::try_from(host.to_string())
+        .map_err(|_| format!("'{host}' is not a valid TLS server name"))?;
+
+    let handshake = async {
+        let stream = TcpStream::connect(address).await?;
+        TlsConnector::from(Arc::new(config))
+            .connect(server_name, stream)
+            .await
+    };
+    match tokio::time::timeout(timeout, handshake).await {
+        Ok(Ok(_)) => {}
+        Ok(Err(e)) => return Err(format!("TLS handshake failed: {e}")),
+        Err(_) => {
+            return Err(format!(
+                "TLS handshake timed out after {}s",
+                timeout.as_secs()
+            ));
+        }
+    }
+    verifier
+        .presented
+        .lock()
+        .expect("Verifier lock poisoned")
+        .take()
+        .ok_or_else(|| "The controller presented no certificate".to_string())
+}
@@ -0,0 +1,92 @@
+use std::{fmt, time::SystemTime};
+
+use ring::hmac;
+
+/// Seconds each code is valid for, as authenticator apps use.
+const STEP_SECS: u64 = 30;
+const DIGITS: u32 = 6;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Seed of a time-based one-time password (RFC 6238), generating the codes
+/// an authenticator app would show for the same account.
+#[derive(Clone)]
+pub struct Totp {
+    key: hmac::Key,
+}
+
+impl Totp {
+    /// Parses the base32 seed shown when enrolling an authenticator app.
+    /// Spaces, dashes, padding and the letter case are ignored, and an
+    /// `otpauth://` URI is accepted as well.
+    pub fn from_base32(seed: &str) -> Result<Self, String> {
+        let seed = match seed.trim().strip_prefix("otpauth://") {
+            Some(uri) => uri
+                .split_once('?')
+                .and_then(|(_, query)| {
+                    query
+                        .split('&')
+                        .find_map(|pair| pair.strip_prefix("secret="))
+                })
+                .ok_or("the otpauth URI has no secret parameter")?,
+            None => seed,
+        };
+
+        let mut bits: u64 = 0;
+        let mut bit_count = 0;
+        let mut bytes = Vec::new();
+        for c in seed.chars().filter(|c| !matches!(c, ' ' | '-' | '=')) {
+            let value = BASE32_ALPHABET
+                .iter()
+                .position(|&a| a == c.to_ascii_uppercase() as u8)
+                .ok_or_else(|| format!("'{c}' is not a base32 character"))?;
+            bits = (bits << 5) | value as u64;
+            bit_count += 5;
+            if bit_count >= 8 {
+                bit_count -= 8;
+                bytes.push((bits >> bit_count) as u8);
+            }
+        }
+        if bytes.len() < 10 {
+            return Err(
+                "the seed is too short, expected at least 16 base32 characters".to_string(),
+            );
+        }
+        Ok(Self {
+            key: hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, &bytes),
+        })
+    }
+
+    /// Code for the Unix time `at`, in seconds.
+    pub fn code_at(&self, at: u64) -> String {
+        let counter = at / STEP_SECS;
+        let tag = hmac::sign(&self.key, &counter.to_be_bytes());
+        let digest = tag.as_ref();
+        let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+        let truncated = u32::from_be_bytes([
+            digest[offset] & 0x7f,
+            digest[offset + 1],
+            digest[offset + 2],
+            digest[offset + 3],
+        ]);
+        format!(
+            "{:0width$}",
+            truncated % 10u32.pow(DIGITS),
+            width = DIGITS as usize
+        )
+    }
+
+    /// Code valid right now.
+    pub fn current_code(&self) -> String {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        self.code_at(now)
+    }
+}
+
+// The seed is a credential, keep it out of logs
+impl fmt::Debug for Totp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Totp(<redacted>)")
+    }
+}
@@ -1,67 +1,143 @@
-use axum::http::HeaderValue;
-use chrono::DateTime;
-use percent_encoding::{AsciiSet, CONTROLS, utf8_percent_encode};
-use reqwest::{Client, ClientBuilder, StatusCode, cookie::Jar};
-use std::{sync::{Arc, OnceLock, RwLock}, time::Duration};
+use chrono::{DateTime, SecondsFormat, Utc};
+use reqwest::{Client, StatusCode, header::RETRY_AFTER};
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashSet},
+    sync::{Arc, OnceLock, RwLock},
+    time::Duration,
+};
 use tracing::{debug, error, info, warn};
 
 use crate::{
+    audit::{Actor, AuditAction, AuditRecord},
     environment::{ENVIRONMENT, Environment},
+    error::ApiError,
+    kiosks,
+    locks::KeyedMutex,
+    logging::AUDIT_TARGET,
     models::{
-        CreateVoucherApiResponse, CreateVoucherRequest, CreateVoucherResponse, DeleteResponse,
-        ErrorResponse, GetSitesResponse, GetVouchersResponse, Voucher,
+        ControllerSite, CreateVoucherApiResponse, CreateVoucherRequest, CreateVoucherResponse,
+        DeleteResponse, ErrorResponse, GetControllerSitesResponse, GetGuestsResponse,
+        GetVouchersResponse, Guest, Voucher, VoucherStatus,
     },
+    resilience::{CircuitBreaker, CircuitStatus, RetryPolicy},
+    session::{self, ControllerSession, SessionInfo},
+    shutdown, tls,
+    voucher_config::{RollingVoucherConfig, VOUCHER_CONFIG},
 };
 
 const UNIFI_API_ROUTE: &str = "api/s";
-const DATE_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
-const ROLLING_VOUCHER_NAME_PREFIX: &str = "[ROLLING]";
-const FRAGMENT: &AsciiSet = &CONTROLS.add(b'[').add(b']');
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+const CORRELATION_PREFIX: &str = "[ref:";
+/// Login field carrying the two-factor code
+const MFA_TOKEN_FIELD: &str = "ubic_2fa_token";
+/// First wait between connection attempts at startup
+pub const CONNECT_RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_CONNECT_RETRY_DELAY: Duration = Duration::from_secs(60);
 
 pub static UNIFI_API: OnceLock<UnifiAPI> = OnceLock::new();
 
+/// The controller client, or a "controller unavailable" error while the
+/// first connection is still being attempted in the background.
+pub fn client() -> Result<&'static UnifiAPI<'static>, ApiError> {
+    UNIFI_API.get().ok_or_else(|| {
+        ApiError::ControllerUnreachable(
+            "Not connected to the UniFi controller yet, still trying to reach it".to_string(),
+        )
+    })
+}
+
+/// Connects to the UniFi controller, retrying until it answers, or returns
+/// `false` when the shutdown starts first. The wait between attempts starts
+/// at `retry_delay` and doubles each time. Voucher routes report the
+/// controller as unavailable in the meantime. Once connected, the client logs
+/// in again by itself whenever its session is lost, and renews it ahead of
+/// expiry.
+pub async fn connect_controller(environment: &'static Environment, retry_delay: Duration) -> bool {
+    let mut delay = retry_delay;
+    loop {
+        match UnifiAPI::try_from_environment(environment).await {
+            Ok(api) => {
+                UNIFI_API.set(api).expect("Failed to set UnifiAPI");
+                info!("Successfully connected to Unifi controller");
+                return true;
+            }
+            Err(e) => {
+                error!("Failed to initialize UnifiAPI wrapper: {}", e);
+                warn!("Retrying connection in {:?}...", delay);
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = shutdown::requested() => return false,
+                }
+                delay = (delay * 2).min(MAX_CONNECT_RETRY_DELAY);
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 enum RequestType {
     Get,
+    /// POST that only reads, such as a filtered listing, retried like a GET
+    Query,
     Post,
-    Delete,
+}
+
+/// Last request the controller answered successfully.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncInfo {
+    pub at: DateTime<Utc>,
+    pub latency_ms: u64,
 }
 
 #[derive(Debug, Clone)]
 pub struct UnifiAPI<'a> {
     client: Client,
-    cookie_jar: Arc<Jar>,
-    session_expiry: Arc<RwLock<Option<std::time::Instant>>>,
+    session: Arc<ControllerSession>,
+    last_sync: Arc<RwLock<Option<SyncInfo>>>,
     sites_api_url: String,
     voucher_api_url: String,
     environment: &'a Environment,
+    retry_policy: RetryPolicy,
+    circuit_breaker: Arc<CircuitBreaker>,
+    /// Serialises the top-ups of each rolling pool, by pool name
+    top_ups: Arc<KeyedMutex>,
 }
 
 impl<'a> UnifiAPI<'a> {
     pub async fn try_new() -> Result<Self, String> {
-        let environment: &Environment = ENVIRONMENT.get().expect("Environment not set");
+        Self::try_from_environment(ENVIRONMENT.get().expect("Environment not set")).await
+    }
 
-        let cookie_jar = Arc::new(Jar::default());
-        let client = ClientBuilder::new()
+    /// Logs in to the controller described by `environment` rather than the
+    /// global one.
+    pub async fn try_from_environment(environment: &'a Environment) -> Result<Self, String> {
+        let session = Arc::new(ControllerSession::default());
+        let client = tls::client_builder(environment)?
             .timeout(Duration::from_secs(30))
             .connect_timeout(Duration::from_secs(10))
-            .cookie_provider(Arc::clone(&cookie_jar))
-            .danger_accept_invalid_certs(!environment.unifi_has_valid_cert)
-            .use_rustls_tls()
+            .cookie_provider(session.cookies())
             .build()
-            .expect("Failed to build UniFi reqwest client");
+            .map_err(|e| format!("Failed to build the controller HTTP client: {e}"))?;
 
         let mut unifi_api = Self {
             client,
-            cookie_jar,
-            session_expiry: Arc::new(RwLock::new(None)),
+            session,
+            last_sync: Arc::new(RwLock::new(None)),
             sites_api_url: format!("{}/{}", environment.unifi_controller_url, UNIFI_API_ROUTE),
             voucher_api_url: String::new(),
             environment,
+            retry_policy: RetryPolicy::from_environment(environment),
+            circuit_breaker: Arc::new(CircuitBreaker::from_environment(environment)),
+            top_ups: Arc::default(),
         };
 
         // Authenticate immediately
-        unifi_api.login().await?;
+        unifi_api
+            .ensure_authenticated()
+            .await
+            .map_err(|e| e.to_string())?;
 
         let site_id = match environment.unifi_site_id.to_lowercase().as_str() {
             "default" => {
@@ -71,106 +147,220 @@ impl<'a> UnifiAPI<'a> {
             _ => environment.unifi_site_id.clone(),
         };
 
-        unifi_api.voucher_api_url =
-            format!("{}/{}/cmd/hotspot", unifi_api.sites_api_url, site_id);
+        unifi_api.voucher_api_url = format!("{}/{}/cmd/hotspot", unifi_api.sites_api_url, site_id);
 
         Ok(unifi_api)
     }
 
-    async fn login(&self) -> Result<(), String> {
+    /// Logs in and returns the session lifetime the controller announced in
+    /// its cookies, if any. Callers go through [`ControllerSession`], which
+    /// makes sure only one login runs at a time.
+    async fn login(&self) -> Result<Option<Duration>, ApiError> {
         let login_url = format!("{}/api/login", self.environment.unifi_controller_url);
-        
+
         info!("Authenticating with UniFi Controller at: {}", login_url);
-        
-        let login_body = serde_json::json!({
+
+        let mut login_body = serde_json::json!({
             "username": self.environment.unifi_username,
             "password": self.environment.unifi_password,
             "remember": false
         });
-        
-        let response = self.client
+        if let Some(totp) = &self.environment.unifi_totp {
+            login_body[MFA_TOKEN_FIELD] = totp.current_code().into();
+        }
+
+        let audit =
+            AuditRecord::new(AuditAction::Login, Actor::System).parameters(serde_json::json!({
+                "controller": self.environment.unifi_controller_url,
+                "username": self.environment.unifi_username,
+            }));
+
+        let started = std::time::Instant::now();
+        let response = match self
+            .client
             .post(&login_url)
+            .timeout(self.environment.unifi_read_timeout)
             .json(&login_body)
             .send()
             .await
-            .map_err(|e| format!("Login request failed: {}", e))?;
-        
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            error!("UniFi authentication failed: {} - {}", status, error_text);
-            return Err(format!("Authentication failed with status {}: {}", status, error_text));
-        }
-        
-        // Set session expiry to 30 minutes from now
-        if let Ok(mut expiry) = self.session_expiry.write() {
-            *expiry = Some(std::time::Instant::now() + Duration::from_secs(30 * 60));
-        }
-        
-        info!("UniFi authentication successful, session will expire in 30 minutes");
-        Ok(())
-    }
-
-    async fn ensure_authenticated(&self) -> Result<(), String> {
-        // Check if session is still valid
-        let needs_reauth = {
-            if let Ok(expiry) = self.session_expiry.read() {
-                if let Some(exp_time) = *expiry {
-                    std::time::Instant::now() >= exp_time
-                } else {
-                    true
-                }
-            } else {
-                true
+        {
+            Ok(response) => response,
+            Err(e) => {
+                let error = unreachable_error(e);
+                audit.failed(&error).record();
+                return Err(error);
             }
         };
-        
-        if needs_reauth {
-            info!("Session expired or not authenticated, re-authenticating...");
-            self.login().await?;
+
+        if !response.status().is_success() {
+            let error = match controller_error(response).await {
+                ApiError::ControllerRejected {
+                    status: 400 | 401 | 403,
+                    message,
+                } if is_mfa_error(&message) => {
+                    ApiError::ControllerAuthFailed(match self.environment.unifi_totp {
+                        None => format!(
+                            "The controller requires a two-factor code for this account ({message}), \
+                             but no TOTP seed is configured. Set unifi.totp_secret, UNIFI_TOTP_SECRET \
+                             or UNIFI_TOTP_SECRET_FILE to the seed of the account's authenticator app"
+                        ),
+                        Some(_) => format!(
+                            "The controller rejected the two-factor code ({message}), check the \
+                             configured TOTP seed and that the local clock is accurate"
+                        ),
+                    })
+                }
+                ApiError::ControllerRejected {
+                    status: 400 | 401 | 403,
+                    message,
+                } => ApiError::ControllerAuthFailed(format!(
+                    "The controller rejected the configured credentials: {message}"
+                )),
+                other => other,
+            };
+            error!("UniFi authentication failed: {}", error);
+            audit.failed(&error).record();
+            return Err(error);
         }
-        
-        Ok(())
+        audit.record();
+        self.record_sync(started);
+
+        let lifetime = session::cookie_lifetime(response.headers());
+        info!("UniFi authentication successful");
+        Ok(lifetime)
+    }
+
+    /// Generation of a valid session, logging in first when needed.
+    async fn ensure_authenticated(&self) -> Result<u64, ApiError> {
+        self.session.ensure(|| self.login()).await
     }
 
-    fn format_unifi_date(&self, timestamp_string: &str) -> String {
-        // Try parsing as Unix timestamp (in seconds)
-        if let Ok(timestamp) = timestamp_string.parse::<i64>() {
-            if let Some(dt) = DateTime::from_timestamp(timestamp, 0) {
-                let local_time = dt.with_timezone(&self.environment.timezone);
-                return local_time.format(DATE_TIME_FORMAT).to_string();
+    /// Renews the session in the background shortly before it expires, so
+    /// that requests never wait for a login, until the shutdown starts.
+    pub async fn keep_session_fresh(&self) {
+        loop {
+            let generation = tokio::select! {
+                generation = self.session.refresh_due() => generation,
+                _ = shutdown::requested() => return,
+            };
+            info!("Renewing the controller session ahead of expiry");
+            if let Err(e) = self.session.renew(generation, || self.login()).await {
+                // The session stays usable until it expires, and requests
+                // log in again by themselves afterwards
+                warn!("Failed to renew the controller session: {}", e);
             }
         }
-        
-        // Fallback: try parsing as RFC3339 (for backwards compatibility)
-        match DateTime::parse_from_rfc3339(timestamp_string) {
-            Ok(dt) => {
-                let local_time = dt.with_timezone(&self.environment.timezone);
-                local_time.format(DATE_TIME_FORMAT).to_string()
-            }
-            Err(_) => {
-                error!("Failed to parse date: {}", timestamp_string);
-                timestamp_string.to_string()
+    }
+
+    /// Ends the controller session, so that it does not linger until it
+    /// expires. Failures are only logged, the session expires anyway.
+    pub async fn logout(&self) {
+        if !self.session.is_established() {
+            return;
+        }
+        let result = self
+            .client
+            .post(format!(
+                "{}/api/logout",
+                self.environment.unifi_controller_url
+            ))
+            .timeout(PROBE_TIMEOUT.min(self.environment.unifi_write_timeout))
+            .json(&serde_json::json!({}))
+            .send()
+            .await;
+        match result {
+            Ok(response) if response.status().is_success() => {
+                info!("Logged out of the UniFi controller")
             }
+            Ok(response) => warn!("Controller answered the logout with {}", response.status()),
+            Err(e) => warn!(
+                "Failed to log out of the controller: {}",
+                unreachable_error(e)
+            ),
+        }
+        self.session.end();
+    }
+
+    /// State of the circuit breaker guarding controller requests.
+    pub fn circuit_status(&self) -> CircuitStatus {
+        self.circuit_breaker.status()
+    }
+
+    /// Current session, `None` when it expired or was never established.
+    pub fn session_info(&self) -> Option<SessionInfo> {
+        self.session.info()
+    }
+
+    pub fn last_sync(&self) -> Option<SyncInfo> {
+        self.last_sync.read().ok()?.clone()
+    }
+
+    fn record_sync(&self, started: std::time::Instant) {
+        if let Ok(mut last_sync) = self.last_sync.write() {
+            *last_sync = Some(SyncInfo {
+                at: Utc::now(),
+                latency_ms: started.elapsed().as_millis() as u64,
+            });
         }
     }
 
+    /// Measures how long the controller takes to answer an unauthenticated
+    /// request. Any HTTP response counts as reachable, and the circuit
+    /// breaker is bypassed so that an open circuit can still be probed.
+    pub async fn probe(&self) -> Result<Duration, ApiError> {
+        let started = std::time::Instant::now();
+        self.client
+            .get(format!("{}/status", self.environment.unifi_controller_url))
+            .timeout(PROBE_TIMEOUT.min(self.environment.unifi_read_timeout))
+            .send()
+            .await
+            .map_err(unreachable_error)?;
+        Ok(started.elapsed())
+    }
+
+    /// RFC 3339 rendering of a controller timestamp, with the offset of the
+    /// configured timezone.
+    fn format_date(&self, date: DateTime<Utc>) -> String {
+        date.with_timezone(&self.environment.timezone)
+            .to_rfc3339_opts(SecondsFormat::Secs, false)
+    }
+
+    /// Converts the controller's epoch timestamps and fills in the computed
+    /// fields.
     fn process_voucher(&self, voucher: &mut Voucher) {
-        // Convert Unix timestamp strings to formatted dates
-        voucher.created_at = self.format_unifi_date(&voucher.created_at);
-        
-        if let Some(ref activated_at) = voucher.activated_at {
-            voucher.activated_at = Some(self.format_unifi_date(activated_at));
-        }
-        
-        if let Some(ref expires_at) = voucher.expires_at {
-            // Check if expired based on timestamp
-            if let Ok(end_timestamp) = expires_at.parse::<i64>() {
-                let now = chrono::Utc::now().timestamp();
-                voucher.expired = end_timestamp < now;
-            }
-            voucher.expires_at = Some(self.format_unifi_date(expires_at));
+        let now = Utc::now();
+        voucher.name = strip_correlation_marker(&voucher.name).to_string();
+
+        let created_at = parse_unifi_date(&voucher.created_at);
+        voucher.created_at_epoch = created_at.map(|date| date.timestamp());
+        if let Some(created_at) = created_at {
+            voucher.created_at = self.format_date(created_at);
         }
+
+        let activated_at = voucher.activated_at.as_deref().and_then(parse_unifi_date);
+        voucher.activated_at_epoch = activated_at.map(|date| date.timestamp());
+        if let Some(activated_at) = activated_at {
+            voucher.activated_at = Some(self.format_date(activated_at));
+        }
+
+        let expires_at = voucher.expires_at.as_deref().and_then(parse_unifi_date);
+        voucher.expires_at_epoch = expires_at.map(|date| date.timestamp());
+        if let Some(expires_at) = expires_at {
+            voucher.expired = expires_at < now;
+            voucher.expires_at = Some(self.format_date(expires_at));
+        }
+
+        voucher.status = VoucherStatus::of(voucher);
+        voucher.remaining_minutes = match (voucher.status, expires_at) {
+            (VoucherStatus::Expired, _) => 0,
+            // Rounded up, a voucher with seconds left is still usable
+            (_, Some(expires_at)) => ((expires_at - now).num_seconds().max(0) as u64).div_ceil(60),
+            (_, None) => voucher.time_limit_minutes,
+        };
+        voucher.remaining_data_mbytes = match voucher.status {
+            VoucherStatus::Unused => voucher.data_usage_limit_mbytes.filter(|limit| *limit > 0),
+            _ => None,
+        };
     }
 
     fn process_vouchers(&self, mut vouchers: Vec<Voucher>) -> Vec<Voucher> {
@@ -180,70 +370,53 @@ impl<'a> UnifiAPI<'a> {
         vouchers
     }
 
-    async fn make_request_raw<T: serde::ser::Serialize + Sized>(
+    async fn make_request<
+        T: serde::ser::Serialize + Sized,
+        U: serde::de::DeserializeOwned + Sized,
+    >(
         &self,
         request_type: RequestType,
         url: &str,
         body: Option<&T>,
-    ) -> Result<String, StatusCode> {
-        // Ensure we have a valid session
-        self.ensure_authenticated().await.map_err(|e| {
-            error!("Authentication failed: {}", e);
-            StatusCode::UNAUTHORIZED
-        })?;
-
-        // Make request
-        let response_result = match request_type {
-            RequestType::Get => self.client.get(url).send().await,
-            RequestType::Post => {
-                if let Some(b) = body {
-                    self.client.post(url).json(b).send().await
-                } else {
-                    error!("Body is required for POST requests");
-                    return Err(StatusCode::BAD_REQUEST);
-                }
-            }
-            RequestType::Delete => self.client.delete(url).send().await,
+    ) -> Result<U, ApiError> {
+        let max_attempts = match request_type {
+            RequestType::Get | RequestType::Query => self.retry_policy.max_attempts,
+            // Writes are not idempotent: a timed out creation may still have
+            // gone through, so it must not be repeated blindly
+            RequestType::Post => 1,
         };
 
-        // Check if the request was successful
-        let response = match response_result {
-            Ok(resp) => resp,
-            Err(e) => {
-                let status = e.status().unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-                error!(
-                    "Request failed with status {}: {:?}",
-                    status,
-                    e.without_url()
-                );
-                return Err(status);
+        let mut attempt = 1;
+        loop {
+            let permit = self.circuit_breaker.acquire()?;
+            let started = std::time::Instant::now();
+            let result = self
+                .make_authenticated_request(request_type.clone(), url, body)
+                .await;
+            permit.record(result.as_ref().map(|_| ()));
+            if result.is_ok() {
+                self.record_sync(started);
             }
-        };
 
-        // The request was successful, now check the status code
-        let clean_response = match response.error_for_status_ref().is_ok() {
-            true => response,
-            false => {
-                let status = response.status();
-                error!("Request failed with status: {}", status);
-
-                if let Ok(body) = response.json::<ErrorResponse>().await {
-                    error!("Error response message: {}", body.message);
-                } else {
-                    error!("Failed to parse error response body");
+            match result {
+                Err(error) if attempt < max_attempts && RetryPolicy::is_retryable(&error) => {
+                    let delay = self.retry_policy.delay(attempt, &error);
+                    warn!(
+                        "Controller request failed ({}), retrying in {:?} (attempt {}/{})",
+                        error,
+                        delay,
+                        attempt + 1,
+                        max_attempts
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
                 }
-                return Err(status);
+                other => return other,
             }
-        };
-
-        // It's a successful response, return raw text
-        clean_response.text().await.map_err(|e| {
-            error!("Failed to read response body: {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })
+        }
     }
 
-    async fn make_request<
+    async fn make_authenticated_request<
         T: serde::ser::Serialize + Sized,
         U: serde::de::DeserializeOwned + Sized,
     >(
@@ -251,22 +424,29 @@ impl<'a> UnifiAPI<'a> {
         request_type: RequestType,
         url: &str,
         body: Option<&T>,
-    ) -> Result<U, StatusCode> {
-        // Try the request, and if we get a 401, re-authenticate and retry once
-        match self.make_request_internal(request_type.clone(), url, body).await {
-            Err(StatusCode::UNAUTHORIZED) => {
+    ) -> Result<U, ApiError> {
+        // Try the request, and if the session was rejected, re-authenticate and retry once
+        let generation = self.ensure_authenticated().await?;
+        match self
+            .make_request_internal(request_type.clone(), url, body)
+            .await
+        {
+            Err(ApiError::ControllerRejected { status: 401, .. }) => {
                 warn!("Got 401, re-authenticating and retrying...");
-                // Clear session expiry to force re-authentication
-                if let Ok(mut expiry) = self.session_expiry.write() {
-                    *expiry = None;
-                }
-                // Re-authenticate
-                self.ensure_authenticated().await.map_err(|e| {
-                    error!("Re-authentication failed: {}", e);
-                    StatusCode::UNAUTHORIZED
-                })?;
+                // Concurrent requests rejected with the same session share
+                // one login
+                self.session.invalidate(generation);
+                self.ensure_authenticated().await?;
                 // Retry the request
-                self.make_request_internal(request_type, url, body).await
+                match self.make_request_internal(request_type, url, body).await {
+                    Err(ApiError::ControllerRejected {
+                        status: 401,
+                        message,
+                    }) => Err(ApiError::ControllerAuthFailed(format!(
+                        "The controller rejected a freshly created session: {message}"
+                    ))),
+                    other => other,
+                }
             }
             other => other,
         }
@@ -280,61 +460,54 @@ impl<'a> UnifiAPI<'a> {
         request_type: RequestType,
         url: &str,
         body: Option<&T>,
-    ) -> Result<U, StatusCode> {
-        // Ensure we have a valid session
-        self.ensure_authenticated().await.map_err(|e| {
-            error!("Authentication failed: {}", e);
-            StatusCode::UNAUTHORIZED
-        })?;
-
+    ) -> Result<U, ApiError> {
         // Make request
         let response_result = match request_type {
-            RequestType::Get => self.client.get(url).send().await,
-            RequestType::Post => {
+            RequestType::Get => {
+                self.client
+                    .get(url)
+                    .timeout(self.environment.unifi_read_timeout)
+                    .send()
+                    .await
+            }
+            RequestType::Query | RequestType::Post => {
+                let timeout = match request_type {
+                    RequestType::Query => self.environment.unifi_read_timeout,
+                    _ => self.environment.unifi_write_timeout,
+                };
                 if let Some(b) = body {
-                    self.client.post(url).json(b).send().await
+                    self.client.post(url).timeout(timeout).json(b).send().await
                 } else {
                     error!("Body is required for POST requests");
-                    return Err(StatusCode::BAD_REQUEST);
+                    return Err(ApiError::Internal(
+                        "Missing body for a controller POST request".to_string(),
+                    ));
                 }
             }
-            RequestType::Delete => self.client.delete(url).send().await,
         };
 
         // Check if the request was successful
         let response = match response_result {
             Ok(resp) => resp,
             Err(e) => {
-                let status = e.status().unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-                error!(
-                    "Request failed with status {}: {:?}",
-                    status,
-                    e.without_url()
-                );
-                return Err(status);
+                let error = unreachable_error(e);
+                error!("Request failed: {}", error);
+                return Err(error);
             }
         };
 
         // The request was successful, now check the status code
-        let clean_response = match response.error_for_status_ref().is_ok() {
-            true => response,
-            false => {
-                let status = response.status();
-                error!("Request failed with status: {}", status);
-
-                if let Ok(body) = response.json::<ErrorResponse>().await {
-                    error!("Error response message: {}", body.message);
-                } else {
-                    error!("Failed to parse error response body");
-                }
-                return Err(status);
-            }
-        };
+        if !response.status().is_success() {
+            let error = controller_error(response).await;
+            error!("Request failed: {}", error);
+            return Err(error);
+        }
 
         // It's a successful response, now get the response body
-        let response_text = clean_response.text().await.map_err(|e| {
+        let status = response.status();
+        let response_text = response.text().await.map_err(|e| {
             error!("Failed to read response body: {:?}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            unreachable_error(e)
         })?;
 
         // Parse the response body as JSON
@@ -342,23 +515,35 @@ impl<'a> UnifiAPI<'a> {
             serde_json::from_str(&response_text).map_err(|e| {
                 error!("Failed to parse response body as JSON: {:?}", e);
                 debug!("Response body: {}", response_text);
-                StatusCode::INTERNAL_SERVER_ERROR
+                ApiError::Internal(format!("The controller returned invalid JSON: {e}"))
             })?;
 
+        // The classic API reports some failures in the body of a 200 response
+        if response_json["meta"]["rc"] == "error" {
+            let message = response_json["meta"]["msg"]
+                .as_str()
+                .unwrap_or("unknown error")
+                .to_string();
+            error!("Controller reported an error: {}", message);
+            return Err(ApiError::ControllerRejected {
+                status: status.as_u16(),
+                message,
+            });
+        }
+
         // Parse the JSON into the expected structure
         serde_json::from_value::<U>(response_json.clone()).map_err(|e| {
             error!("Failed to parse response JSON structure: {:?}", e);
             error!("Response JSON: {:?}", response_json);
             debug!("Response body: {}", response_text);
-            StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::Internal(format!("Unexpected response from the controller: {e}"))
         })
     }
 
-    pub async fn get_all_vouchers(&self) -> Result<GetVouchersResponse, StatusCode> {
+    pub async fn get_all_vouchers(&self) -> Result<GetVouchersResponse, ApiError> {
         let url = format!(
             "{}/{}/stat/voucher",
-            self.sites_api_url,
-            self.environment.unifi_site_id
+            self.sites_api_url, self.environment.unifi_site_id
         );
         let mut result: GetVouchersResponse = self
             .make_request(RequestType::Get, &url, None::<&()>)
@@ -367,103 +552,164 @@ impl<'a> UnifiAPI<'a> {
         Ok(result)
     }
 
-    async fn get_all_vouchers_raw(&self) -> Result<GetVouchersResponse, StatusCode> {
+    /// Guests authorized during the last `within`, with the traffic of each.
+    pub async fn get_guests(&self, within: Duration) -> Result<Vec<Guest>, ApiError> {
+        let url = format!(
+            "{}/{}/stat/guest",
+            self.sites_api_url, self.environment.unifi_site_id
+        );
+        // The controller counts in whole hours
+        let body = serde_json::json!({ "within": within.as_secs().div_ceil(3600) });
+        let response: GetGuestsResponse = self
+            .make_request(RequestType::Query, &url, Some(&body))
+            .await?;
+        Ok(response.data)
+    }
+
+    /// Sites the account can access, with its role on each.
+    pub async fn get_sites(&self) -> Result<Vec<ControllerSite>, ApiError> {
+        let url = format!("{}/api/self/sites", self.environment.unifi_controller_url);
+        let response: GetControllerSitesResponse = self
+            .make_request(RequestType::Get, &url, None::<&()>)
+            .await?;
+        Ok(response.data)
+    }
+
+    /// Unprocessed vouchers carrying `marker`, created at one of
+    /// `create_times`. The controller filters by creation time, the whole
+    /// listing is searched when the times are unknown.
+    async fn find_marked_vouchers(
+        &self,
+        create_times: &BTreeSet<i64>,
+        marker: &str,
+    ) -> Result<Vec<Voucher>, ApiError> {
         let url = format!(
             "{}/{}/stat/voucher",
-            self.sites_api_url,
-            self.environment.unifi_site_id
+            self.sites_api_url, self.environment.unifi_site_id
         );
-        self.make_request(RequestType::Get, &url, None::<&()>).await
+        let mut vouchers = Vec::new();
+        if create_times.is_empty() {
+            let response: GetVouchersResponse = self
+                .make_request(RequestType::Get, &url, None::<&()>)
+                .await?;
+            vouchers = response.data;
+        }
+        for create_time in create_times {
+            let body = serde_json::json!({ "create_time": create_time });
+            let response: GetVouchersResponse = self
+                .make_request(RequestType::Query, &url, Some(&body))
+                .await?;
+            vouchers.extend(response.data);
+        }
+        vouchers.retain(|voucher| voucher.name.ends_with(marker));
+        Ok(vouchers)
     }
 
-    pub async fn get_rolling_voucher(&self) -> Result<Option<Voucher>, StatusCode> {
+    pub async fn get_rolling_voucher(
+        &self,
+        pool: &RollingVoucherConfig,
+    ) -> Result<Option<Voucher>, ApiError> {
         let response = self.get_all_vouchers().await?;
 
         // Find the most recent unused rolling voucher
         let rolling = response
             .data
             .iter()
-            .filter(|voucher| {
-                voucher.name.starts_with(ROLLING_VOUCHER_NAME_PREFIX) 
-                    && voucher.authorized_guest_count == 0  // Only unused vouchers
-                    && !voucher.expired  // Only non-expired vouchers
-            })
-            .max_by_key(|voucher| {
-                DateTime::parse_from_str(&voucher.created_at, DATE_TIME_FORMAT)
-                    .unwrap_or_else(|_| DateTime::UNIX_EPOCH.fixed_offset())
-            })
+            .filter(|voucher| self.is_unused_rolling_voucher(pool, voucher))
+            .max_by_key(|voucher| voucher.created_at_epoch)
             .cloned();
 
         Ok(rolling)
     }
 
-    pub async fn get_all_unused_rolling_vouchers(&self) -> Result<Vec<Voucher>, StatusCode> {
+    /// Whether a rolling voucher can still be handed out: it has room for
+    /// another guest, has not expired and belongs to the current rotation.
+    pub fn is_unused_rolling_voucher(
+        &self,
+        pool: &RollingVoucherConfig,
+        voucher: &Voucher,
+    ) -> bool {
+        if !pool.contains(voucher)
+            || voucher.expired
+            || voucher.authorized_guest_count >= pool.guest_limit()
+        {
+            return false;
+        }
+
+        match pool.rotation_start(Utc::now(), self.environment.timezone) {
+            Some(start) => voucher
+                .created_at_epoch
+                .is_none_or(|created| created >= start.timestamp()),
+            None => true,
+        }
+    }
+
+    pub async fn get_all_unused_rolling_vouchers(
+        &self,
+        pool: &RollingVoucherConfig,
+    ) -> Result<Vec<Voucher>, ApiError> {
         let response = self.get_all_vouchers().await?;
 
         // Get all unused rolling vouchers, sorted by creation time (oldest first)
         let mut vouchers: Vec<Voucher> = response
             .data
             .into_iter()
-            .filter(|voucher| {
-                voucher.name.starts_with(ROLLING_VOUCHER_NAME_PREFIX)
-                    && voucher.authorized_guest_count == 0
-                    && !voucher.expired
-            })
+            .filter(|voucher| self.is_unused_rolling_voucher(pool, voucher))
             .collect();
 
-        vouchers.sort_by(|a, b| {
-            let time_a = DateTime::parse_from_str(&a.created_at, DATE_TIME_FORMAT)
-                .unwrap_or_else(|_| DateTime::UNIX_EPOCH.fixed_offset());
-            let time_b = DateTime::parse_from_str(&b.created_at, DATE_TIME_FORMAT)
-                .unwrap_or_else(|_| DateTime::UNIX_EPOCH.fixed_offset());
-            time_a.cmp(&time_b)
-        });
+        vouchers.sort_by_key(|voucher| voucher.created_at_epoch);
 
         Ok(vouchers)
     }
 
-    pub async fn get_rolling_voucher_by_index(&self, index: usize) -> Result<Option<Voucher>, StatusCode> {
-        let vouchers = self.get_all_unused_rolling_vouchers().await?;
-        Ok(vouchers.get(index).cloned())
+    /// Unused rolling voucher at `index`, skipping the vouchers in `reserved`.
+    pub async fn get_rolling_voucher_by_index(
+        &self,
+        pool: &RollingVoucherConfig,
+        index: usize,
+        reserved: &HashSet<String>,
+    ) -> Result<Option<Voucher>, ApiError> {
+        let vouchers = self.get_all_unused_rolling_vouchers(pool).await?;
+        Ok(vouchers
+            .into_iter()
+            .filter(|voucher| !reserved.contains(&voucher.id))
+            .nth(index))
     }
 
-    pub async fn get_newest_voucher(&self) -> Result<Voucher, StatusCode> {
+    pub async fn get_newest_voucher(&self) -> Result<Voucher, ApiError> {
         let response = self.get_all_vouchers().await?;
 
         if response.data.is_empty() {
             warn!("No vouchers found when fetching the newest voucher");
-            return Err(StatusCode::NOT_FOUND);
+            return Err(ApiError::NotFound("There are no vouchers".to_string()));
         }
 
         // Find the newest voucher
         let newest = response
             .data
             .iter()
-            .max_by_key(|voucher| {
-                DateTime::parse_from_str(&voucher.created_at, DATE_TIME_FORMAT)
-                    .unwrap_or_else(|_| DateTime::UNIX_EPOCH.fixed_offset())
-            })
+            .max_by_key(|voucher| voucher.created_at_epoch)
             .cloned()
             .expect("At least one voucher should exist");
 
         Ok(newest)
     }
 
-    pub async fn get_voucher_details(&self, id: String) -> Result<Voucher, StatusCode> {
+    pub async fn get_voucher_details(&self, id: String) -> Result<Voucher, ApiError> {
         // Traditional API doesn't have individual voucher endpoint, get all and filter
         let response = self.get_all_vouchers().await?;
-        
+
         response
             .data
             .into_iter()
             .find(|v| v.id == id)
-            .ok_or(StatusCode::NOT_FOUND)
+            .ok_or_else(|| ApiError::NotFound(format!("Voucher {id} does not exist")))
     }
 
     pub async fn create_voucher(
         &self,
         request: CreateVoucherRequest,
-    ) -> Result<CreateVoucherResponse, StatusCode> {
+    ) -> Result<CreateVoucherResponse, ApiError> {
         // Traditional API uses POST with cmd=create-voucher
         let mut body = serde_json::json!({
             "cmd": "create-voucher",
@@ -471,110 +717,149 @@ impl<'a> UnifiAPI<'a> {
             "expire": request.time_limit_minutes,
         });
 
+        // The marker tells these vouchers apart from any other created in the
+        // same second, it is stripped from the name when listing
+        let marker = correlation_marker();
+        body["note"] = if request.name.is_empty() {
+            serde_json::json!(marker)
+        } else {
+            serde_json::json!(format!("{} {marker}", request.name))
+        };
+
         // Add optional fields only if they have meaningful values
-        if !request.name.is_empty() {
-            body["note"] = serde_json::json!(request.name);
-        }
-        
-        if let Some(quota) = request.authorized_guest_limit {
-            if quota > 0 {
-                body["quota"] = serde_json::json!(quota);
-            }
+
+        if let Some(quota) = request.authorized_guest_limit
+            && quota > 0
+        {
+            body["quota"] = serde_json::json!(quota);
         }
-        
-        if let Some(up) = request.tx_rate_limit_kbps {
-            if up > 0 {
-                body["up"] = serde_json::json!(up);
-            }
+
+        if let Some(up) = request.tx_rate_limit_kbps
+            && up > 0
+        {
+            body["up"] = serde_json::json!(up);
         }
-        
-        if let Some(down) = request.rx_rate_limit_kbps {
-            if down > 0 {
-                body["down"] = serde_json::json!(down);
-            }
+
+        if let Some(down) = request.rx_rate_limit_kbps
+            && down > 0
+        {
+            body["down"] = serde_json::json!(down);
         }
-        
-        if let Some(bytes) = request.data_usage_limit_mbytes {
-            if bytes > 0 {
-                body["bytes"] = serde_json::json!(bytes);
-            }
+
+        if let Some(bytes) = request.data_usage_limit_mbytes
+            && bytes > 0
+        {
+            body["bytes"] = serde_json::json!(bytes);
         }
 
         // Make the create voucher request
         let api_response: CreateVoucherApiResponse = self
             .make_request(RequestType::Post, &self.voucher_api_url, Some(&body))
             .await?;
-        
+
         // The UniFi API only returns create_time, not the full voucher details
-        // So we need to fetch all vouchers and find the newly created ones
-        // Get the create timestamps from the API response
-        let create_times: Vec<i64> = api_response.data.iter().map(|d| d.create_time).collect();
-        
-        info!("API returned {} create_times: {:?}", create_times.len(), create_times);
-        
-        // Get raw vouchers without timestamp processing
-        let all_vouchers_raw = self.get_all_vouchers_raw().await?;
-        
-        // Find vouchers matching the create_time from the response
-        let mut newly_created_raw: Vec<Voucher> = all_vouchers_raw
-            .data
-            .into_iter()
-            .filter(|v| {
-                // Parse the created_at field (which is still a timestamp string at this point)
-                if let Ok(created_timestamp) = v.created_at.parse::<i64>() {
-                    create_times.contains(&created_timestamp)
-                } else {
-                    false
-                }
-            })
-            .take(request.count as usize)
-            .collect();
-        
-        // Now process the newly created vouchers to format timestamps
-        newly_created_raw = self.process_vouchers(newly_created_raw);
-        
-        info!("Returning {} newly created vouchers", newly_created_raw.len());
-        
+        let create_times: BTreeSet<i64> = api_response.data.iter().map(|d| d.create_time).collect();
+        debug!(
+            "Controller created vouchers {} at {:?}",
+            marker, create_times
+        );
+
+        // A busy controller may list the new vouchers a little later
+        let expected = request.count as usize;
+        let mut attempt = 1;
+        let created = loop {
+            let found = self.find_marked_vouchers(&create_times, &marker).await?;
+            if found.len() == expected {
+                break found;
+            }
+            if found.len() > expected || attempt >= self.retry_policy.max_attempts {
+                let created = self.process_vouchers(found);
+                let codes: Vec<&str> = created.iter().map(|v| v.code.as_str()).collect();
+                return Err(ApiError::PartialCreation(format!(
+                    "The controller created {} of {} requested vouchers{}",
+                    created.len(),
+                    expected,
+                    if codes.is_empty() {
+                        String::new()
+                    } else {
+                        format!(": {}", codes.join(", "))
+                    }
+                )));
+            }
+            let delay = self.retry_policy.backoff(attempt);
+            debug!(
+                "Found {} of {} created vouchers, looking again in {:?}",
+                found.len(),
+                expected,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        };
+
+        info!("Returning {} newly created vouchers", created.len());
         Ok(CreateVoucherResponse {
-            vouchers: newly_created_raw,
+            vouchers: self.process_vouchers(created),
         })
     }
 
-    pub async fn check_rolling_voucher_ip(&self, ip: &str) -> Result<bool, StatusCode> {
+    pub async fn check_rolling_voucher_ip(
+        &self,
+        pool: &RollingVoucherConfig,
+        ip: &str,
+    ) -> Result<bool, ApiError> {
         let response = self.get_all_vouchers().await?;
 
-        // Find a rolling voucher that contains the given IP address
+        // Find a rolling voucher claimed by the given IP address, the name
+        // ends with `-<ip>` so that 0.0.0.1 does not match 10.0.0.1
+        let suffix = format!("-{ip}");
         let rolling = response
             .data
             .iter()
             .find(|voucher| {
-                !voucher.expired
-                    && voucher.name.starts_with(ROLLING_VOUCHER_NAME_PREFIX)
-                    && voucher.name.ends_with(ip)
+                !voucher.expired && pool.contains(voucher) && voucher.name.ends_with(&suffix)
             })
             .cloned();
 
         Ok(rolling.is_some())
     }
 
-    pub async fn create_rolling_voucher(&self, ip: &str) -> Result<Voucher, StatusCode> {
-        let voucher_config = crate::voucher_config::VOUCHER_CONFIG
-            .get()
-            .expect("Voucher config not initialized");
+    pub async fn create_rolling_voucher(
+        &self,
+        pool: &RollingVoucherConfig,
+        ip: &str,
+    ) -> Result<Voucher, ApiError> {
+        self.create_pool_voucher(pool, ip).await
+    }
+
+    /// Creates a rolling voucher reserved for a registered kiosk.
+    pub async fn create_kiosk_voucher(
+        &self,
+        pool: &RollingVoucherConfig,
+        kiosk_id: &str,
+    ) -> Result<Voucher, ApiError> {
+        self.create_pool_voucher(pool, &format!("kiosk-{kiosk_id}"))
+            .await
+    }
 
+    async fn create_pool_voucher(
+        &self,
+        pool: &RollingVoucherConfig,
+        suffix: &str,
+    ) -> Result<Voucher, ApiError> {
         let request = CreateVoucherRequest {
             count: 1,
             name: format!(
                 "{} {}-{}",
-                ROLLING_VOUCHER_NAME_PREFIX,
+                pool.prefix(),
                 chrono::Local::now().format("%Y%m%d%H%M%S"),
-                ip
+                suffix
             ),
-            time_limit_minutes: voucher_config.duration_minutes(),
-            authorized_guest_limit: None,
-            data_usage_limit_mbytes: voucher_config.data_limit_mb(),
-            tx_rate_limit_kbps: voucher_config.download_kbps(),
-            rx_rate_limit_kbps: voucher_config.upload_kbps(),
+            time_limit_minutes: pool.duration_minutes(),
+            authorized_guest_limit: pool.guest_limit,
+            data_usage_limit_mbytes: pool.data_limit_mb(),
+            tx_rate_limit_kbps: pool.download_kbps(),
+            rx_rate_limit_kbps: pool.upload_kbps(),
         };
 
         let rolling = self
@@ -586,72 +871,129 @@ impl<'a> UnifiAPI<'a> {
 
         match rolling {
             Some(v) => Ok(v),
-            None => Err(StatusCode::INTERNAL_SERVER_ERROR),
+            None => Err(ApiError::Internal(
+                "The controller did not return the created voucher".to_string(),
+            )),
         }
     }
 
-    pub async fn create_new_rolling_voucher_if_needed(&self) -> Result<Option<Voucher>, StatusCode> {
-        let voucher_config = crate::voucher_config::VOUCHER_CONFIG
-            .get()
-            .expect("Voucher config not initialized");
-
-        let min_vouchers = voucher_config.rolling_voucher.min_rolling_vouchers as usize;
-        let unused_vouchers = self.get_all_unused_rolling_vouchers().await?;
-        let current_count = unused_vouchers.len();
+    /// Creates rolling vouchers until the configured minimum of unused ones
+    /// exists and returns the vouchers that were created. Top-ups of a pool
+    /// run one at a time, as two running at once would both see it short and
+    /// create the missing vouchers twice.
+    pub async fn top_up_rolling_vouchers(
+        &self,
+        pool: &RollingVoucherConfig,
+    ) -> Result<Vec<Voucher>, ApiError> {
+        let _top_up = self.top_ups.lock(&pool.name).await;
+        let min_vouchers = pool.min_rolling_vouchers as usize;
+        let unused_vouchers = self.get_all_unused_rolling_vouchers(pool).await?;
+        // Vouchers reserved by registered kiosks are not available to others
+        let reserved = kiosks::reserved_voucher_ids().await;
+        let current_count = unused_vouchers
+            .iter()
+            .filter(|voucher| !reserved.contains(&voucher.id))
+            .count();
 
         if current_count >= min_vouchers {
             // We already have enough unused rolling vouchers
-            debug!("Already have {} unused rolling vouchers (min: {}), no action needed", current_count, min_vouchers);
-            return Ok(None);
+            debug!(
+                "Pool '{}' already has {} available rolling vouchers (min: {}), no action needed",
+                pool.name, current_count, min_vouchers
+            );
+            return Ok(Vec::new());
         }
 
         // Need to create more rolling vouchers
         let vouchers_to_create = min_vouchers - current_count;
-        info!("Creating {} rolling voucher(s) to maintain minimum of {} (current: {})", vouchers_to_create, min_vouchers, current_count);
-
-        // Create vouchers one at a time to ensure unique names
-        let mut created_vouchers = Vec::new();
-        for i in 0..vouchers_to_create {
-            let request = CreateVoucherRequest {
-                count: 1,
-                name: format!(
-                    "{} {}-auto-{}",
-                    ROLLING_VOUCHER_NAME_PREFIX,
-                    chrono::Local::now().format("%Y%m%d%H%M%S"),
-                    i
-                ),
-                time_limit_minutes: voucher_config.duration_minutes(),
-                authorized_guest_limit: None,
-                data_usage_limit_mbytes: voucher_config.data_limit_mb(),
-                tx_rate_limit_kbps: voucher_config.download_kbps(),
-                rx_rate_limit_kbps: voucher_config.upload_kbps(),
-            };
+        info!(
+            "Creating {} rolling voucher(s) in pool '{}' to maintain minimum of {} (current: {})",
+            vouchers_to_create, pool.name, min_vouchers, current_count
+        );
 
-            match self.create_voucher(request).await {
-                Ok(result) => {
-                    if let Some(voucher) = result.vouchers.first() {
-                        info!("Created rolling voucher {}/{}: id={}, code={}", i+1, vouchers_to_create, voucher.id, voucher.code);
-                        created_vouchers.push(voucher.clone());
-                    }
-                }
-                Err(e) => {
-                    error!("Failed to create rolling voucher {}/{}: {}", i+1, vouchers_to_create, e);
-                }
-            }
-            
-            // Small delay between creations to ensure unique timestamps
-            if i < vouchers_to_create - 1 {
-                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-            }
+        let request = CreateVoucherRequest {
+            count: vouchers_to_create as u32,
+            name: format!(
+                "{} {}-auto",
+                pool.prefix(),
+                chrono::Local::now().format("%Y%m%d%H%M%S")
+            ),
+            time_limit_minutes: pool.duration_minutes(),
+            authorized_guest_limit: pool.guest_limit,
+            data_usage_limit_mbytes: pool.data_limit_mb(),
+            tx_rate_limit_kbps: pool.download_kbps(),
+            rx_rate_limit_kbps: pool.upload_kbps(),
+        };
+        let created_vouchers = self
+            .create_voucher(request)
+            .await
+            .map_err(|e| {
+                error!(
+                    "Failed to create {} rolling voucher(s) in pool '{}': {}",
+                    vouchers_to_create, pool.name, e
+                );
+                e
+            })?
+            .vouchers;
+
+        for voucher in &created_vouchers {
+            info!(
+                "Created rolling voucher: id={}, code={}",
+                voucher.id, voucher.code
+            );
+            info!(target: AUDIT_TARGET, event = "rolling_voucher_issued", pool = %pool.name,
+                voucher_id = %voucher.id, code = %voucher.code, "Rolling voucher issued to pool");
         }
-        
-        Ok(created_vouchers.first().cloned())
+
+        Ok(created_vouchers)
+    }
+
+    /// Deletes rolling vouchers left over from a previous rotation period
+    /// once its grace period is over and returns them.
+    pub async fn retire_rotated_rolling_vouchers(
+        &self,
+        pool: &RollingVoucherConfig,
+    ) -> Result<Vec<Voucher>, ApiError> {
+        let now = Utc::now();
+        let Some(start) = pool.rotation_start(now, self.environment.timezone) else {
+            return Ok(Vec::new());
+        };
+        if now < start + pool.rotation_grace() {
+            return Ok(Vec::new());
+        }
+
+        // Fully used vouchers cannot admit anyone else, leave them to the purge
+        let retired: Vec<Voucher> = self
+            .get_all_vouchers()
+            .await?
+            .data
+            .into_iter()
+            .filter(|v| {
+                pool.contains(v)
+                    && !v.expired
+                    && v.authorized_guest_count < pool.guest_limit()
+                    && v.created_at_epoch
+                        .is_some_and(|created| created < start.timestamp())
+            })
+            .collect();
+        if retired.is_empty() {
+            return Ok(retired);
+        }
+
+        info!(
+            "Retiring {} rolling voucher(s) of pool '{}' from the previous rotation",
+            retired.len(),
+            pool.name
+        );
+        let ids: Vec<String> = retired.iter().map(|v| v.id.clone()).collect();
+        self.delete_vouchers_by_ids(ids).await?;
+        Ok(retired)
     }
 
     pub async fn delete_vouchers_by_ids(
         &self,
         ids: Vec<String>,
-    ) -> Result<DeleteResponse, StatusCode> {
+    ) -> Result<DeleteResponse, ApiError> {
         if ids.is_empty() || (ids.len() == 1 && ids[0].is_empty()) {
             return Ok(DeleteResponse {
                 data: vec![],
@@ -662,43 +1004,50 @@ impl<'a> UnifiAPI<'a> {
         }
 
         info!("Sending delete request to UniFi for voucher IDs: {:?}", ids);
-        
+
         // Try deleting vouchers one at a time
         let mut deleted_count = 0;
-        let mut all_successful = true;
-        
+
         for id in &ids {
             let body = serde_json::json!({
                 "cmd": "delete-voucher",
                 "_id": id,
             });
             info!("Delete request body for ID {}: {}", id, body);
-            
-            let result: Result<DeleteResponse, StatusCode> = self.make_request(RequestType::Post, &self.voucher_api_url, Some(&body))
+
+            let result: Result<DeleteResponse, ApiError> = self
+                .make_request(RequestType::Post, &self.voucher_api_url, Some(&body))
                 .await;
-            
+
             match &result {
                 Ok(response) => {
-                    info!("UniFi delete response for {}: data.len={}, meta.rc={}", id, response.data.len(), response.meta.rc);
+                    info!(
+                        "UniFi delete response for {}: data.len={}, meta.rc={}",
+                        id,
+                        response.data.len(),
+                        response.meta.rc
+                    );
                     if response.meta.rc == "ok" {
                         deleted_count += 1;
-                    } else {
-                        all_successful = false;
                     }
                 }
                 Err(e) => {
                     error!("UniFi delete error for {}: {}", id, e);
-                    all_successful = false;
                 }
             }
         }
-        
-        info!("Delete operation completed: {}/{} vouchers deleted", deleted_count, ids.len());
-        
+
+        info!(
+            "Delete operation completed: {}/{} vouchers deleted",
+            deleted_count,
+            ids.len()
+        );
+
         // Create a response with the count of successfully deleted vouchers
         // UniFi API returns empty data array, so we populate it with dummy entries to indicate count
-        let data_array: Vec<serde_json::Value> = (0..deleted_count).map(|_| serde_json::json!({})).collect();
-        
+        let data_array: Vec<serde_json::Value> =
+            (0..deleted_count).map(|_| serde_json::json!({})).collect();
+
         Ok(DeleteResponse {
             data: data_array,
             meta: crate::models::DeleteMeta {
@@ -707,27 +1056,148 @@ impl<'a> UnifiAPI<'a> {
         })
     }
 
-    pub async fn delete_expired_vouchers(&self) -> Result<DeleteResponse, StatusCode> {
+    pub async fn get_expired_vouchers(&self) -> Result<Vec<Voucher>, ApiError> {
+        let response = self.get_all_vouchers().await?;
+        Ok(response.data.into_iter().filter(|v| v.expired).collect())
+    }
+
+    /// Expired vouchers of one rolling pool, or of every pool when `pool` is
+    /// `None`.
+    pub async fn get_expired_rolling_vouchers(
+        &self,
+        pool: Option<&RollingVoucherConfig>,
+    ) -> Result<Vec<Voucher>, ApiError> {
+        let pools: Vec<&RollingVoucherConfig> = match pool {
+            Some(pool) => vec![pool],
+            None => VOUCHER_CONFIG
+                .get()
+                .expect("Voucher config not initialized")
+                .pools
+                .iter()
+                .collect(),
+        };
         let response = self.get_all_vouchers().await?;
-        let expired_ids: Vec<String> = response
+        Ok(response
             .data
             .into_iter()
-            .filter(|v| v.expired)
+            .filter(|v| v.expired && pools.iter().any(|pool| pool.contains(v)))
+            .collect())
+    }
+
+    pub async fn delete_expired_vouchers(&self) -> Result<DeleteResponse, ApiError> {
+        let expired_ids: Vec<String> = self
+            .get_expired_vouchers()
+            .await?
+            .into_iter()
             .map(|v| v.id)
             .collect();
 
         self.delete_vouchers_by_ids(expired_ids).await
     }
 
-    pub async fn delete_expired_rolling_vouchers(&self) -> Result<DeleteResponse, StatusCode> {
-        let response = self.get_all_vouchers().await?;
-        let expired_rolling_ids: Vec<String> = response
-            .data
+    pub async fn delete_expired_rolling_vouchers(
+        &self,
+        pool: Option<&RollingVoucherConfig>,
+    ) -> Result<DeleteResponse, ApiError> {
+        let expired_rolling_ids: Vec<String> = self
+            .get_expired_rolling_vouchers(pool)
+            .await?
             .into_iter()
-            .filter(|v| v.expired && v.name.starts_with(ROLLING_VOUCHER_NAME_PREFIX))
             .map(|v| v.id)
             .collect();
 
         self.delete_vouchers_by_ids(expired_rolling_ids).await
     }
 }
+
+/// Unique tag added to the note of created vouchers, `[ref:` and 12 hex
+/// digits.
+fn correlation_marker() -> String {
+    format!("{CORRELATION_PREFIX}{:012x}]", rand::random::<u64>() >> 16)
+}
+
+/// Note without the marker added at creation.
+fn strip_correlation_marker(note: &str) -> &str {
+    let Some(start) = note.rfind(CORRELATION_PREFIX) else {
+        return note;
+    };
+    let tag = &note[start + CORRELATION_PREFIX.len()..];
+    match tag.strip_suffix(']') {
+        Some(hex) if hex.len() == 12 && hex.chars().all(|c| c.is_ascii_hexdigit()) => {
+            note[..start].trim_end()
+        }
+        _ => note,
+    }
+}
+
+/// Controller timestamps are epoch seconds, RFC 3339 is accepted for
+/// backwards compatibility.
+fn parse_unifi_date(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(timestamp) = value.parse::<i64>() {
+        return DateTime::from_timestamp(timestamp, 0);
+    }
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(date) => Some(date.with_timezone(&Utc)),
+        Err(_) => {
+            error!("Failed to parse date: {}", value);
+            None
+        }
+    }
+}
+
+/// Whether a login rejection is about the two-factor code, such as
+/// `api.err.Ubic2faTokenRequired` or `api.err.Invalid2FAToken`.
+fn is_mfa_error(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("2fa") || message.contains("mfa")
+}
+
+fn unreachable_error(error: reqwest::Error) -> ApiError {
+    let reason = if error.is_timeout() {
+        "the request timed out".to_string()
+    } else if error.is_connect() {
+        "the connection failed".to_string()
+    } else {
+        error.without_url().to_string()
+    };
+    ApiError::ControllerUnreachable(format!("Could not reach the UniFi controller: {reason}"))
+}
+
+/// Turns an unsuccessful controller response into an error carrying the
+/// controller's own message.
+async fn controller_error(response: reqwest::Response) -> ApiError {
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.parse::<u64>().ok());
+    let body = response.text().await.unwrap_or_default();
+    let message = controller_message(&body).unwrap_or_else(|| {
+        status
+            .canonical_reason()
+            .unwrap_or("Unknown error")
+            .to_string()
+    });
+
+    match status {
+        StatusCode::TOO_MANY_REQUESTS => ApiError::RateLimited {
+            retry_after,
+            message: format!("The UniFi controller is rate limiting requests: {message}"),
+        },
+        _ => ApiError::ControllerRejected {
+            status: status.as_u16(),
+            message,
+        },
+    }
+}
+
+/// Error message of the UniFi Network API (`message`) or of the classic
+/// controller API (`meta.msg`).
+fn controller_message(body: &str) -> Option<String> {
+    if let Ok(response) = serde_json::from_str::<ErrorResponse>(body) {
+        return Some(response.message);
+    }
+    let json: serde_json::Value = serde_json::from_str(body).ok()?;
+    json["meta"]["msg"].as_str().map(str::to_string)
+}
@@ -1,38 +1,79 @@
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::sync::OnceLock;
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+use crate::models::Voucher;
 
 pub static VOUCHER_CONFIG: OnceLock<VoucherConfig> = OnceLock::new();
 
 const DEFAULT_ROLLING_DURATION_HOURS: f64 = 24.0;
+const DEFAULT_CHECK_INTERVAL_SECONDS: u64 = 15;
 const CONFIG_FILE_PATH: &str = "/app/frontend/public/voucher-tiers.json";
+pub const DEFAULT_POOL_NAME: &str = "default";
+const DEFAULT_POOL_PREFIX: &str = "[ROLLING]";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RollingVoucherConfig {
+    /// Pool name used by the rolling endpoints, `default` for `rollingVoucher`
+    #[serde(default)]
+    pub name: String,
+    /// Tag at the start of the voucher names of this pool, `[ROLLING:<name>]`
+    /// when unset
+    #[serde(default)]
+    pub prefix: Option<String>,
     pub enabled: bool,
     pub duration_hours: f64,
     pub download_mbps: Option<u64>,
     pub upload_mbps: Option<u64>,
+    #[serde(alias = "dataLimitMB")]
     pub data_limit_mb: Option<u64>,
     #[serde(default = "default_min_rolling_vouchers")]
     pub min_rolling_vouchers: u32,
+    /// How often the pool maintainer looks for redeemed or expired vouchers
+    #[serde(default = "default_check_interval_seconds")]
+    pub check_interval_seconds: u64,
+    /// Number of guests that can use the same code, single use when unset
+    #[serde(default)]
+    pub guest_limit: Option<u64>,
+    /// Replace the codes every N hours, at local times counted from midnight
+    #[serde(default)]
+    pub rotation_interval_hours: Option<f64>,
+    /// Replace the codes at these local times, takes precedence over the interval
+    #[serde(default)]
+    pub rotation_times: Vec<NaiveTime>,
+    /// How long a rotated code keeps working before it is deleted
+    #[serde(default)]
+    pub rotation_grace_minutes: u64,
 }
 
 fn default_min_rolling_vouchers() -> u32 {
     1
 }
 
+fn default_check_interval_seconds() -> u64 {
+    DEFAULT_CHECK_INTERVAL_SECONDS
+}
+
 impl Default for RollingVoucherConfig {
     fn default() -> Self {
         Self {
+            name: DEFAULT_POOL_NAME.to_string(),
+            prefix: Some(DEFAULT_POOL_PREFIX.to_string()),
             enabled: false,
             duration_hours: DEFAULT_ROLLING_DURATION_HOURS,
             download_mbps: None,
             upload_mbps: None,
             data_limit_mb: None,
             min_rolling_vouchers: 1,
+            check_interval_seconds: DEFAULT_CHECK_INTERVAL_SECONDS,
+            guest_limit: None,
+            rotation_interval_hours: None,
+            rotation_times: Vec::new(),
+            rotation_grace_minutes: 0,
         }
     }
 }
@@ -46,19 +87,56 @@ pub struct VoucherTier {
     pub duration_hours: f64,
     pub download_mbps: Option<u64>,
     pub upload_mbps: Option<u64>,
+    #[serde(alias = "dataLimitMB")]
     pub data_limit_mb: Option<u64>,
 }
 
+impl VoucherTier {
+    /// Whether vouchers with this validity and these limits were created
+    /// from the tier. The controller does not record tiers, so the settings
+    /// are compared.
+    pub fn matches(
+        &self,
+        time_limit_minutes: u64,
+        data_limit_mbytes: Option<u64>,
+        download_kbps: Option<u64>,
+        upload_kbps: Option<u64>,
+    ) -> bool {
+        time_limit_minutes == (self.duration_hours * 60.0).round() as u64
+            && data_limit_mbytes == self.data_limit_mb
+            && download_kbps == self.download_mbps.map(|mbps| mbps * 1000)
+            && upload_kbps == self.upload_mbps.map(|mbps| mbps * 1000)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct VoucherConfigFile {
     pub rolling_voucher: Option<RollingVoucherConfig>,
+    #[serde(default)]
+    pub rolling_pools: Vec<RollingVoucherConfig>,
     pub tiers: Vec<VoucherTier>,
 }
 
+/// Where the voucher configuration was loaded from.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ConfigSource {
+    File {
+        path: String,
+    },
+    /// Built-in defaults, `reason` explains why the file was not used
+    Defaults {
+        reason: String,
+    },
+}
+
 #[derive(Debug, Clone)]
 pub struct VoucherConfig {
-    pub rolling_voucher: RollingVoucherConfig,
+    /// Rolling voucher pools, never empty
+    pub pools: Vec<RollingVoucherConfig>,
+    pub tiers: Vec<VoucherTier>,
+    pub source: ConfigSource,
 }
 
 impl VoucherConfig {
@@ -68,9 +146,9 @@ impl VoucherConfig {
             Err(e) => {
                 error!("Failed to read voucher config file: {}", e);
                 info!("Using default rolling voucher configuration");
-                return Ok(Self {
-                    rolling_voucher: RollingVoucherConfig::default(),
-                });
+                return Ok(Self::with_defaults(format!(
+                    "Failed to read {CONFIG_FILE_PATH}: {e}"
+                )));
             }
         };
 
@@ -79,39 +157,217 @@ impl VoucherConfig {
             Err(e) => {
                 error!("Failed to parse voucher config file: {}", e);
                 info!("Using default rolling voucher configuration");
-                return Ok(Self {
-                    rolling_voucher: RollingVoucherConfig::default(),
-                });
+                return Ok(Self::with_defaults(format!(
+                    "Failed to parse {CONFIG_FILE_PATH}: {e}"
+                )));
             }
         };
 
-        let rolling_voucher = config.rolling_voucher.unwrap_or_default();
-        
-        info!(
-            "Loaded rolling voucher config: enabled={}, duration={}h, download={}Mbps, upload={}Mbps, data_limit={}MB",
-            rolling_voucher.enabled,
-            rolling_voucher.duration_hours,
-            rolling_voucher.download_mbps.map(|v| v.to_string()).unwrap_or_else(|| "unlimited".to_string()),
-            rolling_voucher.upload_mbps.map(|v| v.to_string()).unwrap_or_else(|| "unlimited".to_string()),
-            rolling_voucher.data_limit_mb.map(|v| v.to_string()).unwrap_or_else(|| "unlimited".to_string()),
-        );
+        // The single `rollingVoucher` section is the pool named "default"
+        let mut pools = Vec::new();
+        if let Some(mut rolling_voucher) = config.rolling_voucher {
+            rolling_voucher.name = DEFAULT_POOL_NAME.to_string();
+            rolling_voucher
+                .prefix
+                .get_or_insert_with(|| DEFAULT_POOL_PREFIX.to_string());
+            pools.push(rolling_voucher);
+        }
+        pools.extend(config.rolling_pools);
+        if pools.is_empty() {
+            pools.push(RollingVoucherConfig::default());
+        }
+
+        Self::validate_pools(&pools)?;
+
+        for pool in &pools {
+            info!(
+                "Loaded rolling pool '{}' ({}): enabled={}, duration={}h, download={}Mbps, upload={}Mbps, data_limit={}MB, guest_limit={}, min={}",
+                pool.name,
+                pool.prefix(),
+                pool.enabled,
+                pool.duration_hours,
+                pool.download_mbps
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "unlimited".to_string()),
+                pool.upload_mbps
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "unlimited".to_string()),
+                pool.data_limit_mb
+                    .map(|v| v.to_string())
+                    .unwrap_or_else(|| "unlimited".to_string()),
+                pool.guest_limit(),
+                pool.min_rolling_vouchers,
+            );
 
-        Ok(Self { rolling_voucher })
+            if !pool.rotation_times.is_empty() {
+                if pool.rotation_interval_hours.is_some() {
+                    warn!(
+                        "Pool '{}' sets both rotationTimes and rotationIntervalHours, using rotationTimes",
+                        pool.name
+                    );
+                }
+                info!(
+                    "Pool '{}' rotates at {:?} with a {} minute grace period",
+                    pool.name, pool.rotation_times, pool.rotation_grace_minutes
+                );
+            } else if let Some(hours) = pool.rotation_interval_hours {
+                info!(
+                    "Pool '{}' rotates every {}h with a {} minute grace period",
+                    pool.name, hours, pool.rotation_grace_minutes
+                );
+            }
+        }
+
+        Ok(Self {
+            pools,
+            tiers: config.tiers,
+            source: ConfigSource::File {
+                path: CONFIG_FILE_PATH.to_string(),
+            },
+        })
+    }
+
+    fn with_defaults(reason: String) -> Self {
+        Self {
+            source: ConfigSource::Defaults { reason },
+            ..Self::default()
+        }
+    }
+
+    fn validate_pools(pools: &[RollingVoucherConfig]) -> Result<(), String> {
+        for (i, pool) in pools.iter().enumerate() {
+            if pool.name.trim().is_empty() {
+                return Err(format!("Rolling pool #{} has no name", i + 1));
+            }
+            for other in &pools[..i] {
+                if other.name == pool.name {
+                    return Err(format!("Rolling pool '{}' is defined twice", pool.name));
+                }
+                // A voucher must never match the prefix of two pools
+                let (a, b) = (pool.prefix(), other.prefix());
+                if a.starts_with(&b) || b.starts_with(&a) {
+                    return Err(format!(
+                        "Rolling pools '{}' and '{}' have overlapping prefixes '{}' and '{}'",
+                        other.name, pool.name, b, a
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Looks up a pool by name, `None` selecting the default pool.
+    pub fn pool(&self, name: Option<&str>) -> Option<&RollingVoucherConfig> {
+        match name.filter(|n| !n.is_empty()) {
+            Some(name) => self.pools.iter().find(|pool| pool.name == name),
+            None => self
+                .pools
+                .iter()
+                .find(|pool| pool.name == DEFAULT_POOL_NAME)
+                .or_else(|| self.pools.first()),
+        }
+    }
+
+    pub fn tier(&self, id: &str) -> Option<&VoucherTier> {
+        self.tiers.iter().find(|tier| tier.id == id)
+    }
+
+    pub fn enabled_pools(&self) -> impl Iterator<Item = &RollingVoucherConfig> {
+        self.pools.iter().filter(|pool| pool.enabled)
+    }
+}
+
+impl Default for VoucherConfig {
+    fn default() -> Self {
+        Self {
+            pools: vec![RollingVoucherConfig::default()],
+            tiers: Vec::new(),
+            source: ConfigSource::Defaults {
+                reason: "No configuration loaded".to_string(),
+            },
+        }
+    }
+}
+
+impl RollingVoucherConfig {
+    pub fn prefix(&self) -> String {
+        self.prefix
+            .clone()
+            .unwrap_or_else(|| format!("[ROLLING:{}]", self.name))
+    }
+
+    /// Whether a voucher belongs to this pool, judging by its name.
+    pub fn contains(&self, voucher: &Voucher) -> bool {
+        voucher.name.starts_with(&self.prefix())
     }
 
     pub fn duration_minutes(&self) -> u64 {
-        (self.rolling_voucher.duration_hours * 60.0) as u64
+        (self.duration_hours * 60.0) as u64
     }
 
     pub fn download_kbps(&self) -> Option<u64> {
-        self.rolling_voucher.download_mbps.map(|mbps| mbps * 1000)
+        self.download_mbps.map(|mbps| mbps * 1000)
     }
 
     pub fn upload_kbps(&self) -> Option<u64> {
-        self.rolling_voucher.upload_mbps.map(|mbps| mbps * 1000)
+        self.upload_mbps.map(|mbps| mbps * 1000)
     }
 
     pub fn data_limit_mb(&self) -> Option<u64> {
-        self.rolling_voucher.data_limit_mb
+        self.data_limit_mb
+    }
+
+    /// Number of guests a rolling voucher accepts before it counts as used.
+    pub fn guest_limit(&self) -> u64 {
+        self.guest_limit.filter(|l| *l > 0).unwrap_or(1)
+    }
+
+    pub fn rotation_grace(&self) -> Duration {
+        Duration::minutes(self.rotation_grace_minutes as i64)
+    }
+
+    /// Start of the current rotation period, if time-based rotation is enabled.
+    ///
+    /// Rolling vouchers created before this instant belong to a previous
+    /// period and are retired once the grace period is over. An interval is
+    /// laid out as local wall clock times from midnight, so rotations stay on
+    /// the same slots across DST changes and the last period of a day is cut
+    /// short when the interval does not divide it.
+    pub fn rotation_start(&self, now: DateTime<Utc>, timezone: Tz) -> Option<DateTime<Utc>> {
+        let today = now.with_timezone(&timezone).date_naive();
+        let times = if !self.rotation_times.is_empty() {
+            self.rotation_times.clone()
+        } else {
+            let interval_hours = self.rotation_interval_hours.filter(|h| *h > 0.0)?;
+            let interval_seconds = ((interval_hours * 3600.0) as u32).max(60);
+            (0..86_400)
+                .step_by(interval_seconds as usize)
+                .filter_map(|seconds| NaiveTime::from_num_seconds_from_midnight_opt(seconds, 0))
+                .collect()
+        };
+
+        // Latest rotation time already reached, looking back to yesterday
+        [today.pred_opt()?, today]
+            .into_iter()
+            .flat_map(|day| {
+                times
+                    .iter()
+                    .filter_map(move |time| resolve_local(day.and_time(*time), timezone))
+            })
+            .filter(|start| *start <= now)
+            .max()
+    }
+}
+
+/// Converts a local wall clock time to UTC without panicking around DST
+/// changes: ambiguous times resolve to their first occurrence and times that
+/// fall in a gap are moved forward by an hour.
+fn resolve_local(time: NaiveDateTime, timezone: Tz) -> Option<DateTime<Utc>> {
+    match time.and_local_timezone(timezone) {
+        LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => Some(t.with_timezone(&Utc)),
+        LocalResult::None => (time + Duration::hours(1))
+            .and_local_timezone(timezone)
+            .earliest()
+            .map(|t| t.with_timezone(&Utc)),
     }
 }
@@ -0,0 +1,273 @@
+use std::cmp::Ordering;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::ApiError,
+    models::{Voucher, VoucherStatus},
+    voucher_config::VoucherTier,
+};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum VoucherSort {
+    #[default]
+    CreatedAt,
+    /// Vouchers not activated yet sort as expiring last
+    ExpiresAt,
+    /// Case-insensitive
+    Name,
+    Code,
+    /// Unlimited vouchers sort last
+    GuestLimit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct VoucherListQuery {
+    pub status: Option<VoucherStatus>,
+    /// Case-insensitive substring of the name (note)
+    pub name: Option<String>,
+    /// Exact code, dashes and spaces are ignored
+    pub code: Option<String>,
+    /// Tier id, matches vouchers whose duration and limits are the tier's
+    pub tier: Option<String>,
+    /// RFC 3339, inclusive
+    pub created_after: Option<DateTime<Utc>>,
+    /// RFC 3339, inclusive
+    pub created_before: Option<DateTime<Utc>>,
+    /// RFC 3339, inclusive, only matches activated vouchers
+    pub expires_after: Option<DateTime<Utc>>,
+    /// RFC 3339, inclusive, only matches activated vouchers
+    pub expires_before: Option<DateTime<Utc>>,
+    /// Guests allowed per voucher, 0 for unlimited
+    pub guest_limit: Option<u64>,
+    /// Defaults to `createdAt`
+    pub sort: Option<VoucherSort>,
+    /// Defaults to `desc` for `createdAt` and `expiresAt`, `asc` otherwise
+    pub order: Option<SortOrder>,
+    /// `nextCursor` of the previous page
+    pub cursor: Option<String>,
+    /// Page size, 50 by default and at most 500
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VoucherPage {
+    pub data: Vec<Voucher>,
+    /// Vouchers matching the filters, across all pages
+    pub total: usize,
+    pub limit: usize,
+    /// Cursor of the next page, absent on the last one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+/// Value a voucher is sorted by, the voucher id breaking ties.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(untagged)]
+enum SortKey {
+    Number(i64),
+    Text(String),
+}
+
+/// Position after the last voucher of a page. Carries the sort it was made
+/// for, so a cursor cannot be replayed against another order.
+#[derive(Debug, Serialize, Deserialize)]
+struct Cursor {
+    sort: VoucherSort,
+    order: SortOrder,
+    key: SortKey,
+    id: String,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("Cursor serializes");
+        json.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    fn decode(value: &str) -> Option<Self> {
+        if !value.len().is_multiple_of(2) || !value.is_ascii() {
+            return None;
+        }
+        let bytes = (0..value.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&value[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+impl VoucherListQuery {
+    /// Filters, sorts and pages `vouchers`.
+    pub fn page(
+        &self,
+        vouchers: Vec<Voucher>,
+        tiers: &[VoucherTier],
+    ) -> Result<VoucherPage, ApiError> {
+        let tier = match &self.tier {
+            Some(id) => Some(
+                tiers
+                    .iter()
+                    .find(|tier| &tier.id == id)
+                    .ok_or_else(|| ApiError::Validation(format!("Unknown tier '{id}'")))?,
+            ),
+            None => None,
+        };
+        let sort = self.sort.unwrap_or_default();
+        let order = self.order.unwrap_or(match sort {
+            VoucherSort::CreatedAt | VoucherSort::ExpiresAt => SortOrder::Desc,
+            _ => SortOrder::Asc,
+        });
+        let limit = self
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+
+        let mut matching: Vec<(SortKey, Voucher)> = vouchers
+            .into_iter()
+            .filter(|voucher| self.matches(voucher, tier))
+            .map(|voucher| (sort_key(&voucher, sort), voucher))
+            .collect();
+        let compare = |a: (&SortKey, &str), b: (&SortKey, &str)| match order {
+            SortOrder::Asc => a.cmp(&b),
+            SortOrder::Desc => b.cmp(&a),
+        };
+        matching.sort_by(|(a_key, a), (b_key, b)| compare((a_key, &a.id), (b_key, &b.id)));
+
+        let start = match &self.cursor {
+            Some(value) => {
+                let cursor = Cursor::decode(value)
+                    .ok_or_else(|| ApiError::Validation("Invalid cursor".to_string()))?;
+                if cursor.sort != sort || cursor.order != order {
+                    return Err(ApiError::Validation(
+                        "The cursor was issued for another sort order".to_string(),
+                    ));
+                }
+                matching.partition_point(|(key, voucher)| {
+                    compare((key, &voucher.id), (&cursor.key, &cursor.id)) != Ordering::Greater
+                })
+            }
+            None => 0,
+        };
+
+        let total = matching.len();
+        let page: Vec<(SortKey, Voucher)> = matching.into_iter().skip(start).take(limit).collect();
+        let next_cursor = match page.last() {
+            Some((key, voucher)) if start + page.len() < total => Some(
+                Cursor {
+                    sort,
+                    order,
+                    key: key.clone(),
+                    id: voucher.id.clone(),
+                }
+                .encode(),
+            ),
+            _ => None,
+        };
+
+        Ok(VoucherPage {
+            data: page.into_iter().map(|(_, voucher)| voucher).collect(),
+            total,
+            limit,
+            next_cursor,
+        })
+    }
+
+    fn matches(&self, voucher: &Voucher, tier: Option<&VoucherTier>) -> bool {
+        if self.status.is_some_and(|status| status != voucher.status) {
+            return false;
+        }
+        if let Some(name) = &self.name
+            && !voucher.name.to_lowercase().contains(&name.to_lowercase())
+        {
+            return false;
+        }
+        if let Some(code) = &self.code
+            && normalize_code(code) != normalize_code(&voucher.code)
+        {
+            return false;
+        }
+        if tier.is_some_and(|tier| !tier_matches(tier, voucher)) {
+            return false;
+        }
+        if let Some(limit) = self.guest_limit
+            && voucher.authorized_guest_limit.unwrap_or(0) != limit
+        {
+            return false;
+        }
+        in_range(
+            voucher.created_at_epoch,
+            self.created_after,
+            self.created_before,
+        ) && in_range(
+            voucher.expires_at_epoch,
+            self.expires_after,
+            self.expires_before,
+        )
+    }
+}
+
+/// Whether `epoch` lies within the bounds. Without a value, only an
+/// unbounded range matches.
+fn in_range(
+    epoch: Option<i64>,
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+) -> bool {
+    if after.is_none() && before.is_none() {
+        return true;
+    }
+    epoch.is_some_and(|epoch| {
+        after.is_none_or(|after| epoch >= after.timestamp())
+            && before.is_none_or(|before| epoch <= before.timestamp())
+    })
+}
+
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !matches!(c, '-' | ' '))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Whether a voucher was created with the settings of `tier`.
+fn tier_matches(tier: &VoucherTier, voucher: &Voucher) -> bool {
+    tier.matches(
+        voucher.time_limit_minutes,
+        voucher.data_usage_limit_mbytes,
+        voucher.rx_rate_limit_kbps,
+        voucher.tx_rate_limit_kbps,
+    )
+}
+
+fn sort_key(voucher: &Voucher, sort: VoucherSort) -> SortKey {
+    match sort {
+        VoucherSort::CreatedAt => SortKey::Number(voucher.created_at_epoch.unwrap_or(i64::MAX)),
+        VoucherSort::ExpiresAt => SortKey::Number(voucher.expires_at_epoch.unwrap_or(i64::MAX)),
+        VoucherSort::Name => SortKey::Text(voucher.name.to_lowercase()),
+        VoucherSort::Code => SortKey::Text(voucher.code.clone()),
+        VoucherSort::GuestLimit => SortKey::Number(
+            voucher
+                .authorized_guest_limit
+                .filter(|limit| *limit > 0)
+                .map_or(i64::MAX, |limit| limit as i64),
+        ),
+    }
+}
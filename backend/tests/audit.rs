@@ -0,0 +1,248 @@
+//! Hash chain of the audit trail and the actors it records.
+mod common;
+
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use axum::{Router, http::HeaderMap, middleware, routing::get};
+use backend::{
+    audit::{Actor, AuditAction, AuditLog, AuditQuery, AuditRecord, SourceIp, drop_untrusted_user},
+    environment::ENVIRONMENT,
+    listener::{self, Peer},
+};
+use chrono::Utc;
+use common::FakeController;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UnixStream},
+};
+
+/// Empty data directory for one test.
+fn data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("backend-audit-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn trail(dir: &Path) -> PathBuf {
+    dir.join("audit-trail.jsonl")
+}
+
+fn record(voucher: &str) -> AuditRecord {
+    AuditRecord::new(AuditAction::Create, Actor::System).voucher_ids(&[voucher.to_string()])
+}
+
+fn append_raw(dir: &Path, text: &str) {
+    let mut file = OpenOptions::new().append(true).open(trail(dir)).unwrap();
+    file.write_all(text.as_bytes()).unwrap();
+}
+
+fn seqs(audit: &AuditLog) -> Vec<u64> {
+    audit.entries().iter().map(|entry| entry.seq).collect()
+}
+
+#[test]
+fn hash_chain_detects_edited_and_removed_entries() {
+    let dir = data_dir("tamper");
+    let audit = AuditLog::try_new(&dir).unwrap();
+    for voucher in ["a", "b", "c"] {
+        audit.append(record(voucher));
+    }
+    let verification = audit.verify();
+    assert!(verification.valid);
+    assert_eq!(
+        (verification.entries, verification.first_invalid_seq),
+        (3, None)
+    );
+
+    let original = fs::read_to_string(trail(&dir)).unwrap();
+    fs::write(trail(&dir), original.replacen("[\"b\"]", "[\"x\"]", 1)).unwrap();
+    let verification = AuditLog::try_new(&dir).unwrap().verify();
+    assert!(!verification.valid);
+    assert_eq!(verification.first_invalid_seq, Some(2));
+
+    let lines: Vec<&str> = original.lines().collect();
+    fs::write(trail(&dir), format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+    let verification = AuditLog::try_new(&dir).unwrap().verify();
+    assert_eq!(
+        (verification.entries, verification.first_invalid_seq),
+        (2, Some(3))
+    );
+}
+
+#[test]
+fn malformed_lines_are_skipped_and_reported() {
+    let dir = data_dir("malformed");
+    let audit = AuditLog::try_new(&dir).unwrap();
+    audit.append(record("a"));
+    append_raw(&dir, "not an entry\n");
+    audit.append(record("b"));
+    // Cut short, e.g. by a full disk
+    append_raw(&dir, "{\"seq\":3,\"timest");
+    audit.append(record("c"));
+
+    // Still chained to the last well-formed entry
+    assert_eq!(seqs(&audit), [1, 2, 3]);
+    let verification = audit.verify();
+    assert!(!verification.valid);
+    assert_eq!(verification.entries, 3);
+    assert_eq!(verification.first_invalid_seq, None);
+    assert_eq!(verification.malformed_lines, [2, 4]);
+
+    // Reopening the trail does not disable it
+    let reopened = AuditLog::try_new(&dir).unwrap();
+    reopened.append(record("d"));
+    assert_eq!(seqs(&reopened), [1, 2, 3, 4]);
+    assert_eq!(reopened.verify().first_invalid_seq, None);
+}
+
+#[test]
+fn entries_of_other_processes_are_chained_to() {
+    let dir = data_dir("processes");
+    let server = AuditLog::try_new(&dir).unwrap();
+    let command = AuditLog::try_new(&dir).unwrap();
+    server.append(record("a"));
+    command.append(record("b"));
+    server.append(record("c"));
+
+    assert_eq!(seqs(&server), [1, 2, 3]);
+    assert!(command.verify().valid);
+}
+
+#[test]
+fn query_pages_through_the_newest_matching_entries() {
+    let dir = data_dir("query");
+    let audit = AuditLog::try_new(&dir).unwrap();
+    for voucher in ["a", "b", "c", "d", "e"] {
+        audit.append(record(voucher));
+    }
+    audit.append(AuditRecord::new(AuditAction::Delete, Actor::System));
+
+    let page = audit.query(&AuditQuery {
+        action: Some(AuditAction::Create),
+        offset: Some(1),
+        limit: Some(2),
+        ..Default::default()
+    });
+    assert_eq!(page.total, 5);
+    let seqs: Vec<u64> = page.data.iter().map(|entry| entry.seq).collect();
+    assert_eq!(seqs, [4, 3]);
+
+    let past_the_end = audit.query(&AuditQuery {
+        offset: Some(10),
+        ..Default::default()
+    });
+    assert_eq!((past_the_end.total, past_the_end.data.len()), (6, 0));
+}
+
+#[test]
+fn entries_since_leaves_out_older_entries() {
+    let dir = data_dir("since");
+    let audit = AuditLog::try_new(&dir).unwrap();
+    audit.append(record("a"));
+    // Timestamps are recorded to the millisecond
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    let since = Utc::now();
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    audit.append(record("b"));
+    audit.append(record("c"));
+
+    let seqs: Vec<u64> = audit
+        .entries_since(since)
+        .iter()
+        .map(|entry| entry.seq)
+        .collect();
+    assert_eq!(seqs, [2, 3]);
+    assert!(
+        audit
+            .entries_since(Utc::now() + chrono::Duration::hours(1))
+            .is_empty()
+    );
+}
+
+/// Sends a GET request for `uri` carrying the headers a client could forge and
+/// returns the body of the response.
+async fn get_forged<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, uri: &str) -> String {
+    let request = format!(
+        "GET {uri} HTTP/1.1\r\nHost: localhost\r\nX-Forwarded-User: jane\r\n\
+        X-Kiosk-Id: lobby\r\nX-Forwarded-For: 203.0.113.7, 198.51.100.4, 10.0.0.2\r\n\
+        Connection: close\r\n\r\n"
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response.rsplit("\r\n\r\n").next().unwrap().to_string()
+}
+
+// The trusted proxies are read from the global environment, so a single test
+// covers both listeners
+#[tokio::test]
+async fn forwarded_headers_are_only_trusted_from_configured_proxies() {
+    let fake = FakeController::start().await;
+    let dir = data_dir("proxies");
+    fs::create_dir_all(&dir).unwrap();
+    let environment = fake.environment_with(|environment| {
+        environment.trusted_proxies = vec![Peer::UnixSocket, Peer::Ip("10.0.0.2".parse().unwrap())];
+    });
+    ENVIRONMENT
+        .set(environment.clone())
+        .expect("Environment already set");
+    let app = Router::new()
+        .route(
+            "/actor",
+            get(|headers: HeaderMap| async move { Actor::from_headers(&headers).await.label() }),
+        )
+        .route("/source", get(|SourceIp(ip): SourceIp| async move { ip }))
+        .layer(middleware::from_fn(drop_untrusted_user));
+
+    let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = tcp.local_addr().unwrap();
+    let service = app.clone().into_make_service_with_connect_info::<Peer>();
+    tokio::spawn(async move { axum::serve(tcp, service).await });
+    let socket = dir.join("backend.sock");
+    let unix = listener::bind_unix(&socket, 0o600).unwrap();
+    let service = app.into_make_service_with_connect_info::<Peer>();
+    tokio::spawn(async move { axum::serve(unix, service).await });
+
+    // A client connecting directly cannot name the user or its address
+    let tcp = || async { TcpStream::connect(address).await.unwrap() };
+    assert_eq!(
+        get_forged(tcp().await, "/actor").await,
+        "unverified:kiosk:lobby"
+    );
+    assert_eq!(get_forged(tcp().await, "/source").await, "127.0.0.1");
+
+    // Behind the proxies, the right-most hop they did not add is the client
+    let unix = || async { UnixStream::connect(&socket).await.unwrap() };
+    assert_eq!(get_forged(unix().await, "/actor").await, "user:jane");
+    assert_eq!(get_forged(unix().await, "/source").await, "198.51.100.4");
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-real-ip", "198.51.100.9".parse().unwrap());
+    assert_eq!(
+        SourceIp::new(Some(Peer::UnixSocket), &headers).0,
+        "198.51.100.9"
+    );
+    headers.insert("x-forwarded-for", "10.0.0.2".parse().unwrap());
+    assert_eq!(
+        SourceIp::new(Some(Peer::UnixSocket), &headers).0,
+        "10.0.0.2"
+    );
+    assert_eq!(SourceIp::new(None, &headers).0, "unknown");
+}
+
+#[test]
+fn trusted_proxies_are_addresses_or_the_unix_socket() {
+    assert_eq!(
+        Peer::parse_list(" 10.0.0.2, ::1,unix,").unwrap(),
+        [
+            Peer::Ip("10.0.0.2".parse().unwrap()),
+            Peer::Ip("::1".parse().unwrap()),
+            Peer::UnixSocket,
+        ]
+    );
+    assert!(Peer::parse_list("proxy.local").is_err());
+}
@@ -0,0 +1,135 @@
+//! Circuit breaker guarding controller requests.
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use backend::{
+    error::ApiError,
+    resilience::{CircuitBreaker, CircuitState},
+    unifi_api::UnifiAPI,
+};
+use common::FakeController;
+
+const COOLDOWN: Duration = Duration::from_millis(50);
+
+fn unreachable() -> ApiError {
+    ApiError::ControllerUnreachable("connection refused".to_string())
+}
+
+async fn breaker(threshold: u32) -> CircuitBreaker {
+    let fake = FakeController::start().await;
+    CircuitBreaker::from_environment(fake.environment_with(|environment| {
+        environment.unifi_circuit_failure_threshold = threshold;
+        environment.unifi_circuit_cooldown = COOLDOWN;
+    }))
+}
+
+/// Opens `breaker` by failing `threshold` requests.
+fn trip(breaker: &CircuitBreaker, threshold: u32) {
+    for _ in 0..threshold {
+        breaker.acquire().unwrap().record(Err(&unreachable()));
+    }
+}
+
+#[tokio::test]
+async fn opens_after_consecutive_failures_and_closes_after_a_probe() {
+    let breaker = breaker(2).await;
+    breaker.acquire().unwrap().record(Err(&unreachable()));
+    assert_eq!(breaker.status().state, CircuitState::Closed);
+    // Rejections of a particular request say nothing about the controller
+    breaker
+        .acquire()
+        .unwrap()
+        .record(Err(&ApiError::ControllerRejected {
+            status: 400,
+            message: "api.err.InvalidArgs".to_string(),
+        }));
+    assert_eq!(breaker.status().consecutive_failures, 0);
+
+    trip(&breaker, 2);
+    let status = breaker.status();
+    assert_eq!(
+        (status.state, status.consecutive_failures),
+        (CircuitState::Open, 2)
+    );
+    assert!(status.opened_at.is_some());
+    assert!(matches!(
+        breaker.acquire(),
+        Err(ApiError::ControllerUnreachable(_))
+    ));
+
+    tokio::time::sleep(COOLDOWN).await;
+    let probe = breaker.acquire().expect("The cooldown elapsed");
+    assert_eq!(breaker.status().state, CircuitState::HalfOpen);
+    // A single probe at a time
+    assert!(matches!(
+        breaker.acquire(),
+        Err(ApiError::ControllerUnreachable(_))
+    ));
+    probe.record(Ok(()));
+
+    let status = breaker.status();
+    assert_eq!(
+        (status.state, status.consecutive_failures),
+        (CircuitState::Closed, 0)
+    );
+    assert!(status.last_error.is_none());
+    assert!(breaker.acquire().is_ok());
+}
+
+#[tokio::test]
+async fn failed_probe_opens_the_circuit_again() {
+    let breaker = breaker(3).await;
+    trip(&breaker, 3);
+    tokio::time::sleep(COOLDOWN).await;
+
+    breaker.acquire().unwrap().record(Err(&unreachable()));
+    assert_eq!(breaker.status().state, CircuitState::Open);
+    assert!(breaker.acquire().is_err());
+}
+
+#[tokio::test]
+async fn dropped_probe_lets_the_next_request_probe() {
+    let breaker = breaker(1).await;
+    trip(&breaker, 1);
+    tokio::time::sleep(COOLDOWN).await;
+
+    drop(breaker.acquire().unwrap());
+    assert_eq!(breaker.status().state, CircuitState::HalfOpen);
+    breaker
+        .acquire()
+        .expect("No probe in flight")
+        .record(Ok(()));
+    assert_eq!(breaker.status().state, CircuitState::Closed);
+}
+
+#[tokio::test]
+async fn cancelled_probe_request_does_not_keep_the_circuit_open() {
+    let fake = FakeController::start().await;
+    let environment = fake.environment_with(|environment| {
+        environment.unifi_retry_attempts = 1;
+        environment.unifi_circuit_failure_threshold = 1;
+        environment.unifi_circuit_cooldown = COOLDOWN;
+    });
+    let client = UnifiAPI::try_from_environment(environment).await.unwrap();
+    fake.fail_next(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "api.err.ServiceUnavailable",
+    );
+    assert!(client.get_all_vouchers().await.is_err());
+    assert_eq!(client.circuit_status().state, CircuitState::Open);
+
+    // The probe is cancelled, as when the client of a handler disconnects
+    tokio::time::sleep(COOLDOWN).await;
+    fake.stall_next(Duration::from_secs(10));
+    let cancelled =
+        tokio::time::timeout(Duration::from_millis(100), client.get_all_vouchers()).await;
+    assert!(cancelled.is_err());
+
+    client
+        .get_all_vouchers()
+        .await
+        .expect("The next request probes the controller");
+    assert_eq!(client.circuit_status().state, CircuitState::Closed);
+}
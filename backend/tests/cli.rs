@@ -0,0 +1,113 @@
+//! Admin commands and the rendering of their output.
+mod common;
+
+use backend::{
+    audit::{Actor, AuditAction, AuditLog},
+    cli::{Cli, OutputFormat},
+    commands,
+    environment::ENVIRONMENT,
+    output::Output,
+};
+use clap::Parser;
+use common::FakeController;
+use serde_json::json;
+
+fn sample() -> Output {
+    let mut output = Output::new(
+        json!([{ "id": "a1", "name": "Front desk" }, { "id": "b22", "name": "Bar, \"VIP\"" }]),
+        &["ID", "NAME"],
+    );
+    output.row(["a1", "Front desk"]);
+    output.row(["b22", "Bar, \"VIP\""]);
+    output.summary("2 vouchers".to_string())
+}
+
+#[test]
+fn table_aligns_columns_and_ends_with_the_summary() {
+    assert_eq!(
+        sample().render(OutputFormat::Table),
+        "ID   NAME\na1   Front desk\nb22  Bar, \"VIP\"\n\n2 vouchers\n"
+    );
+}
+
+#[test]
+fn json_keeps_the_structure_without_the_summary() {
+    let rendered = sample().render(OutputFormat::Json);
+    assert!(rendered.ends_with("}\n]\n"), "{rendered}");
+    let value: serde_json::Value = serde_json::from_str(&rendered).unwrap();
+    assert_eq!(value[1]["name"], "Bar, \"VIP\"");
+    assert!(!rendered.contains("2 vouchers"));
+}
+
+#[test]
+fn csv_quotes_fields_that_need_it() {
+    assert_eq!(
+        sample().render(OutputFormat::Csv),
+        "ID,NAME\r\na1,Front desk\r\nb22,\"Bar, \"\"VIP\"\"\"\r\n"
+    );
+}
+
+#[test]
+fn settings_list_one_setting_per_row() {
+    let mut output = Output::settings(json!({ "controller": "https://unifi.local" }));
+    output.row(["controller", "https://unifi.local"]);
+    assert_eq!(
+        output.render(OutputFormat::Table),
+        "SETTING     VALUE\ncontroller  https://unifi.local\n"
+    );
+    assert!(output.failure().is_none());
+}
+
+// Commands use the global environment and controller client, so a single
+// test runs one against the fake controller
+#[tokio::test]
+async fn create_command_creates_and_audits_vouchers() {
+    let fake = FakeController::start().await;
+    let data_dir = std::env::temp_dir().join(format!("backend-cli-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+    let environment = fake.environment_with(|environment| environment.data_dir = data_dir.clone());
+    ENVIRONMENT
+        .set(environment.clone())
+        .expect("Environment already set");
+    let cli = Cli::try_parse_from([
+        "backend",
+        "--output",
+        "csv",
+        "vouchers",
+        "create",
+        "--count",
+        "2",
+        "--name",
+        "Front desk",
+        "--minutes",
+        "90",
+    ])
+    .unwrap();
+
+    let output = commands::execute(cli.command.unwrap())
+        .await
+        .expect("Command failed");
+
+    assert!(output.failure().is_none());
+    let rendered = output.render(cli.output);
+    let lines: Vec<&str> = rendered.lines().collect();
+    assert_eq!(
+        lines[0],
+        "ID,CODE,NAME,CREATED,EXPIRES,MINUTES,GUESTS,STATUS"
+    );
+    assert_eq!(lines.len(), 3);
+    assert!(
+        lines[1].contains(",Front desk,") && lines[1].ends_with(",90,0/1,unused"),
+        "{rendered}"
+    );
+    assert_eq!(fake.vouchers_named("Front desk").len(), 2);
+
+    let entries = AuditLog::try_new(&data_dir).unwrap().entries();
+    let created = entries
+        .iter()
+        .find(|entry| entry.action == AuditAction::Create)
+        .expect("Creation not audited");
+    assert!(matches!(created.actor, Actor::Cli(_)));
+    assert_eq!(created.voucher_ids.len(), 2);
+    assert_eq!(created.parameters["timeLimitMinutes"], 90);
+}
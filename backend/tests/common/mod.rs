@@ -0,0 +1,802 @@
+//! In-process fake of the classic UniFi controller API, serving the routes
+//! `UnifiAPI` uses with the payloads and error bodies of a real controller.
+#![allow(dead_code)]
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use backend::{
+    environment::Environment,
+    logging::{LogFormat, LogRotation},
+    totp::Totp,
+    unifi_api::UnifiAPI,
+    voucher_config::RollingVoucherConfig,
+};
+use serde::Serialize;
+use serde_json::{Value, json};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    },
+};
+
+pub const USERNAME: &str = "admin";
+pub const PASSWORD: &str = "correct horse battery staple";
+pub const SITE: &str = "default";
+const SESSION_COOKIE: &str = "unifises";
+
+/// Voucher as stored by the controller and returned by `stat/voucher`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FakeVoucher {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub site_id: String,
+    pub create_time: i64,
+    pub code: String,
+    pub note: String,
+    /// Guests allowed, 0 for unlimited
+    pub quota: u64,
+    pub used: u64,
+    /// Minutes the voucher is valid once first used
+    pub duration: u64,
+    pub status: String,
+    pub admin_name: String,
+    pub for_hotspot: bool,
+    pub qos_overwrite: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qos_rate_max_up: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qos_rate_max_down: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub qos_usage_quota: Option<u64>,
+    /// Listings that still omit the voucher, as a lagging controller would
+    #[serde(skip)]
+    pub hidden_listings: usize,
+}
+
+impl FakeVoucher {
+    /// Unused single-use voucher valid for a day, created just now.
+    pub fn new(note: &str) -> Self {
+        Self {
+            id: String::new(),
+            site_id: SITE.to_string(),
+            create_time: unix_now(),
+            code: String::new(),
+            note: note.to_string(),
+            quota: 1,
+            used: 0,
+            duration: 24 * 60,
+            status: String::new(),
+            admin_name: USERNAME.to_string(),
+            for_hotspot: false,
+            qos_overwrite: false,
+            start_time: None,
+            end_time: None,
+            qos_rate_max_up: None,
+            qos_rate_max_down: None,
+            qos_usage_quota: None,
+            hidden_listings: 0,
+        }
+    }
+
+    pub fn created_ago(mut self, age: Duration) -> Self {
+        self.create_time -= age.as_secs() as i64;
+        self
+    }
+
+    pub fn quota(mut self, quota: u64) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    /// Redeemed by `guests` guests, the validity starting with the first one.
+    pub fn used_by(mut self, guests: u64) -> Self {
+        let start = unix_now();
+        self.used = guests;
+        self.start_time = Some(start);
+        self.end_time = Some(start + self.duration as i64 * 60);
+        self
+    }
+
+    /// Redeemed and already past its validity.
+    pub fn expired(mut self) -> Self {
+        let end = unix_now() - 60;
+        self.used = self.used.max(1);
+        self.start_time = Some(end - self.duration as i64 * 60);
+        self.end_time = Some(end);
+        self
+    }
+
+    fn refresh_status(&mut self) {
+        self.status = match (self.quota, self.used) {
+            (1, 0) => "VALID_ONE",
+            (0, _) => "VALID_MULTI",
+            (quota, used) if used >= quota => "USED",
+            _ => "VALID_MULTI",
+        }
+        .to_string();
+    }
+}
+
+/// Guest authorization as returned by `stat/guest`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FakeGuest {
+    pub voucher_id: String,
+    pub tx_bytes: u64,
+    pub rx_bytes: u64,
+}
+
+/// Failure returned instead of the next API response, login excluded.
+#[derive(Debug, Clone)]
+struct Fault {
+    status: StatusCode,
+    message: String,
+    retry_after: Option<u64>,
+}
+
+struct ControllerState {
+    sessions: HashMap<String, i64>,
+    session_lifetime: i64,
+    /// Whether the session cookie carries its `Max-Age`
+    announce_lifetime: bool,
+    vouchers: Vec<FakeVoucher>,
+    guests: Vec<FakeGuest>,
+    faults: VecDeque<Fault>,
+    /// Delay before answering the next voucher listing
+    stall: Option<Duration>,
+    /// Whether status probes go unanswered, as when the controller is down
+    unresponsive: bool,
+    /// Frozen controller time, the system clock when `None`
+    clock: Option<i64>,
+    /// Listings omitting the vouchers of the next creation
+    listing_lag: usize,
+    /// Vouchers the next creation silently fails to create
+    creation_shortfall: u64,
+    next_id: u64,
+    /// Seed of the admin account's second factor, when it requires one
+    mfa: Option<Totp>,
+    /// Whether logins are refused, as after a password change
+    refuse_logins: bool,
+    login_attempts: usize,
+    logins: usize,
+    logouts: usize,
+    api_requests: usize,
+}
+
+impl ControllerState {
+    fn now(&self) -> i64 {
+        self.clock.unwrap_or_else(unix_now)
+    }
+
+    fn insert(&mut self, mut voucher: FakeVoucher) -> String {
+        self.next_id += 1;
+        if voucher.id.is_empty() {
+            voucher.id = format!("{:024x}", 0x6500_0000_0000_0000_u64 + self.next_id);
+        }
+        if voucher.code.is_empty() {
+            voucher.code = format!("{:010}", rand::random_range(0..10_000_000_000_u64));
+        }
+        voucher.refresh_status();
+        let id = voucher.id.clone();
+        self.vouchers.push(voucher);
+        id
+    }
+}
+
+type Shared = Arc<Mutex<ControllerState>>;
+
+pub struct FakeController {
+    pub url: String,
+    state: Shared,
+}
+
+impl FakeController {
+    /// Serves the fake controller on an ephemeral port of the test's runtime.
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind the fake controller");
+        let fake = Self::new(listener.local_addr().expect("No local address"));
+        fake.serve(listener);
+        fake
+    }
+
+    /// Fake controller on a free port nothing listens on, as a controller
+    /// that is down, until `come_up` serves it.
+    pub fn down() -> Self {
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("No free port for the fake controller");
+        Self::new(address)
+    }
+
+    /// Starts serving a controller created with `down`.
+    pub async fn come_up(&self) {
+        let listener = TcpListener::bind(self.url.trim_start_matches("http://"))
+            .await
+            .expect("Failed to bind the fake controller");
+        self.serve(listener);
+    }
+
+    fn new(address: SocketAddr) -> Self {
+        let state: Shared = Arc::new(Mutex::new(ControllerState {
+            sessions: HashMap::new(),
+            session_lifetime: 3600,
+            announce_lifetime: false,
+            vouchers: Vec::new(),
+            guests: Vec::new(),
+            faults: VecDeque::new(),
+            stall: None,
+            unresponsive: false,
+            clock: None,
+            listing_lag: 0,
+            creation_shortfall: 0,
+            next_id: 0,
+            mfa: None,
+            refuse_logins: false,
+            login_attempts: 0,
+            logins: 0,
+            logouts: 0,
+            api_requests: 0,
+        }));
+        Self {
+            url: format!("http://{address}"),
+            state,
+        }
+    }
+
+    fn serve(&self, listener: TcpListener) {
+        let app = Router::new()
+            .route("/status", get(status))
+            .route("/api/login", post(login))
+            .route("/api/logout", post(logout))
+            .route("/api/self/sites", get(sites))
+            .route(
+                "/api/s/{site}/stat/voucher",
+                get(list_vouchers).post(query_vouchers),
+            )
+            .route("/api/s/{site}/stat/guest", post(list_guests))
+            .route("/api/s/{site}/cmd/hotspot", post(hotspot_command))
+            .with_state(self.state.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+    }
+
+    /// Serves this controller over HTTPS as well, with the certificate of
+    /// `tests/fixtures/controller.pem` issued by `ca.pem` for `localhost`,
+    /// and returns its URL.
+    pub async fn serve_tls(&self) -> String {
+        let certificates = CertificateDer::pem_file_iter(fixture("controller.pem"))
+            .expect("Missing certificate fixture")
+            .collect::<Result<Vec<_>, _>>()
+            .expect("Invalid certificate fixture");
+        let key =
+            PrivateKeyDer::from_pem_file(fixture("controller.key")).expect("Invalid key fixture");
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("No TLS version supported")
+            .with_no_client_auth()
+            .with_single_cert(certificates, key)
+            .expect("Invalid TLS fixture");
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("Failed to bind the TLS front");
+        let port = listener.local_addr().expect("No local address").port();
+        let plain = self.url.trim_start_matches("http://").to_string();
+        // Terminates TLS and forwards to the plain HTTP listener
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                let plain = plain.clone();
+                tokio::spawn(async move {
+                    let Ok(mut tls) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    if let Ok(mut upstream) = TcpStream::connect(plain).await {
+                        let _ = tokio::io::copy_bidirectional(&mut tls, &mut upstream).await;
+                    }
+                });
+            }
+        });
+        format!("https://localhost:{port}")
+    }
+
+    /// Configuration pointing at this controller, with fast retries and no
+    /// circuit breaking so that failures are deterministic.
+    pub fn environment(&self) -> &'static Environment {
+        self.environment_with(|_| {})
+    }
+
+    pub fn environment_with(
+        &self,
+        customize: impl FnOnce(&mut Environment),
+    ) -> &'static Environment {
+        let mut environment = Environment {
+            unifi_controller_url: self.url.clone(),
+            unifi_site_id: SITE.to_string(),
+            unifi_username: USERNAME.to_string(),
+            unifi_password: PASSWORD.to_string(),
+            unifi_totp: None,
+            backend_bind_host: "127.0.0.1".to_string(),
+            backend_bind_port: 0,
+            backend_tcp_enabled: true,
+            backend_tls: None,
+            backend_unix_socket: None,
+            backend_unix_socket_mode: 0o660,
+            trusted_proxies: Vec::new(),
+            unifi_has_valid_cert: true,
+            unifi_ca_bundle: None,
+            unifi_certificate_pins: Vec::new(),
+            timezone: chrono_tz::UTC,
+            log_dir: None,
+            log_format: LogFormat::Text,
+            log_rotation: LogRotation::Never,
+            log_max_files: 0,
+            syslog: None,
+            data_dir: PathBuf::from("/nonexistent"),
+            shutdown_timeout: Duration::from_secs(1),
+            unifi_read_timeout: Duration::from_secs(5),
+            unifi_write_timeout: Duration::from_secs(5),
+            unifi_retry_attempts: 3,
+            unifi_retry_base_delay: Duration::from_millis(1),
+            unifi_retry_max_delay: Duration::from_millis(10),
+            unifi_circuit_failure_threshold: 100,
+            unifi_circuit_cooldown: Duration::from_secs(1),
+        };
+        customize(&mut environment);
+        Box::leak(Box::new(environment))
+    }
+
+    /// Logs in to this controller.
+    pub async fn connect(&self) -> UnifiAPI<'static> {
+        UnifiAPI::try_from_environment(self.environment())
+            .await
+            .expect("Failed to log in to the fake controller")
+    }
+
+    fn state(&self) -> MutexGuard<'_, ControllerState> {
+        self.state.lock().expect("Fake controller state poisoned")
+    }
+
+    /// Adds a voucher as if created on the controller and returns its id.
+    pub fn insert(&self, voucher: FakeVoucher) -> String {
+        self.state().insert(voucher)
+    }
+
+    /// Records a guest authorized with the voucher `voucher_id` that
+    /// downloaded `rx_bytes` and uploaded `tx_bytes`.
+    pub fn add_guest(&self, voucher_id: &str, rx_bytes: u64, tx_bytes: u64) {
+        self.state().guests.push(FakeGuest {
+            voucher_id: voucher_id.to_string(),
+            tx_bytes,
+            rx_bytes,
+        });
+    }
+
+    /// Counts one more guest on the voucher `voucher_id`, as when a guest
+    /// enters its code.
+    pub fn redeem(&self, voucher_id: &str) {
+        self.update(voucher_id, |voucher| {
+            let start = voucher.start_time.unwrap_or_else(unix_now);
+            voucher.used += 1;
+            voucher.start_time = Some(start);
+            voucher.end_time = Some(start + voucher.duration as i64 * 60);
+        });
+    }
+
+    /// Ends the validity of the voucher `voucher_id` without another guest.
+    pub fn expire(&self, voucher_id: &str) {
+        self.update(voucher_id, |voucher| {
+            voucher.end_time = Some(unix_now() - 60)
+        });
+    }
+
+    fn update(&self, voucher_id: &str, change: impl FnOnce(&mut FakeVoucher)) {
+        let mut state = self.state();
+        let voucher = state
+            .vouchers
+            .iter_mut()
+            .find(|v| v.id == voucher_id)
+            .expect("Unknown voucher");
+        change(voucher);
+        voucher.refresh_status();
+    }
+
+    pub fn vouchers(&self) -> Vec<FakeVoucher> {
+        self.state().vouchers.clone()
+    }
+
+    pub fn voucher(&self, id: &str) -> Option<FakeVoucher> {
+        self.state().vouchers.iter().find(|v| v.id == id).cloned()
+    }
+
+    /// Vouchers whose note starts with `prefix`.
+    pub fn vouchers_named(&self, prefix: &str) -> Vec<FakeVoucher> {
+        self.state()
+            .vouchers
+            .iter()
+            .filter(|v| v.note.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    /// Stops the controller clock, as if every following request happened
+    /// within the same second.
+    pub fn freeze_clock(&self) {
+        let mut state = self.state();
+        state.clock = Some(state.now());
+    }
+
+    /// Omits the vouchers of the next creation from the following `listings`.
+    pub fn lag_listings(&self, listings: usize) {
+        self.state().listing_lag = listings;
+    }
+
+    /// Makes the next creation create `missing` vouchers fewer than asked.
+    pub fn fall_short(&self, missing: u64) {
+        self.state().creation_shortfall = missing;
+    }
+
+    /// Invalidates every session, as a controller restart would.
+    pub fn expire_sessions(&self) {
+        self.state().sessions.clear();
+    }
+
+    pub fn set_session_lifetime(&self, lifetime: Duration) {
+        self.state().session_lifetime = lifetime.as_secs() as i64;
+    }
+
+    /// Sets the session lifetime and sends it as the cookie's `Max-Age`, as
+    /// UniFi OS consoles do.
+    pub fn announce_session_lifetime(&self, lifetime: Duration) {
+        let mut state = self.state();
+        state.session_lifetime = lifetime.as_secs() as i64;
+        state.announce_lifetime = true;
+    }
+
+    /// Sessions that were not logged out nor expired.
+    pub fn open_sessions(&self) -> usize {
+        let state = self.state();
+        let now = state.now();
+        state
+            .sessions
+            .values()
+            .filter(|expiry| **expiry > now)
+            .count()
+    }
+
+    /// Answers the next API request with `status` and the controller error
+    /// `message`. A `200` status reports the error in the body only, as the
+    /// classic API does for some commands.
+    pub fn fail_next(&self, status: StatusCode, message: &str) {
+        self.state().faults.push_back(Fault {
+            status,
+            message: message.to_string(),
+            retry_after: None,
+        });
+    }
+
+    /// Delays the answer to the next voucher listing by `delay`, as an
+    /// overloaded controller would.
+    pub fn stall_next(&self, delay: Duration) {
+        self.state().stall = Some(delay);
+    }
+
+    /// Leaves status probes unanswered, as a controller that went down, or
+    /// answers them again.
+    pub fn set_unresponsive(&self, unresponsive: bool) {
+        self.state().unresponsive = unresponsive;
+    }
+
+    pub fn rate_limit_next(&self, retry_after: u64) {
+        self.state().faults.push_back(Fault {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            message: "api.err.RateLimited".to_string(),
+            retry_after: Some(retry_after),
+        });
+    }
+
+    /// Requires the current code of `seed` on login, as an admin account
+    /// with two-factor authentication does.
+    pub fn require_mfa(&self, seed: &str) {
+        self.state().mfa = Some(Totp::from_base32(seed).expect("Invalid TOTP seed"));
+    }
+
+    /// Refuses the credentials on the following logins, or accepts them
+    /// again.
+    pub fn refuse_logins(&self, refuse: bool) {
+        self.state().refuse_logins = refuse;
+    }
+
+    /// Logins, failed ones included.
+    pub fn login_attempts(&self) -> usize {
+        self.state().login_attempts
+    }
+
+    pub fn logins(&self) -> usize {
+        self.state().logins
+    }
+
+    pub fn logouts(&self) -> usize {
+        self.state().logouts
+    }
+
+    /// Requests to the site API, failed ones included.
+    pub fn api_requests(&self) -> usize {
+        self.state().api_requests
+    }
+}
+
+/// Path of a file of `tests/fixtures`.
+pub fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Clock before 1970")
+        .as_secs() as i64
+}
+
+fn ok(data: Value) -> Response {
+    Json(json!({ "meta": { "rc": "ok" }, "data": data })).into_response()
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (
+        status,
+        Json(json!({ "meta": { "rc": "error", "msg": message }, "data": [] })),
+    )
+        .into_response()
+}
+
+/// Checks the session cookie and the site, and applies the next injected
+/// fault. Returns the response rejecting the request, if any.
+fn reject(state: &mut ControllerState, headers: &HeaderMap, site: &str) -> Option<Response> {
+    state.api_requests += 1;
+    if let Some(fault) = state.faults.pop_front() {
+        let mut response = error(fault.status, &fault.message);
+        if let Some(retry_after) = fault.retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after.into());
+        }
+        return Some(response);
+    }
+
+    let now = state.now();
+    match session_token(headers).and_then(|token| state.sessions.get(&token).copied()) {
+        Some(expiry) if expiry > now => {}
+        _ => return Some(error(StatusCode::UNAUTHORIZED, "api.err.LoginRequired")),
+    }
+    (site != SITE).then(|| error(StatusCode::BAD_REQUEST, "api.err.NoSiteContext"))
+}
+
+fn session_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == SESSION_COOKIE)
+        .map(|(_, token)| token.to_string())
+}
+
+async fn status(State(state): State<Shared>) -> Response {
+    let unresponsive = state
+        .lock()
+        .expect("Fake controller state poisoned")
+        .unresponsive;
+    if unresponsive {
+        std::future::pending::<()>().await;
+    }
+    Json(json!({ "meta": { "rc": "ok", "up": true, "server_version": "9.0.114" }, "data": [] }))
+        .into_response()
+}
+
+async fn login(State(state): State<Shared>, Json(body): Json<Value>) -> Response {
+    let mut state = state.lock().expect("Fake controller state poisoned");
+    state.login_attempts += 1;
+    if state.refuse_logins || body["username"] != USERNAME || body["password"] != PASSWORD {
+        return error(StatusCode::BAD_REQUEST, "api.err.Invalid");
+    }
+    if let Some(totp) = &state.mfa {
+        match body["ubic_2fa_token"].as_str() {
+            None => return error(StatusCode::BAD_REQUEST, "api.err.Ubic2faTokenRequired"),
+            // The previous code is still accepted, as real controllers allow
+            // for some clock drift
+            Some(code)
+                if code != totp.code_at(state.now() as u64)
+                    && code != totp.code_at(state.now() as u64 - 30) =>
+            {
+                return error(StatusCode::BAD_REQUEST, "api.err.Invalid2FAToken");
+            }
+            Some(_) => {}
+        }
+    }
+    state.logins += 1;
+    let token = format!("{:032x}", rand::random::<u128>());
+    let expiry = state.now() + state.session_lifetime;
+    state.sessions.insert(token.clone(), expiry);
+
+    let mut response = ok(json!([]));
+    let mut cookie = format!("{SESSION_COOKIE}={token}; Path=/; Secure; HttpOnly");
+    if state.announce_lifetime {
+        cookie.push_str(&format!("; Max-Age={}", state.session_lifetime));
+    }
+    response.headers_mut().insert(
+        header::SET_COOKIE,
+        cookie.parse().expect("Invalid session cookie"),
+    );
+    response
+}
+
+async fn logout(State(state): State<Shared>, headers: HeaderMap) -> Response {
+    let mut state = state.lock().expect("Fake controller state poisoned");
+    if let Some(token) = session_token(&headers) {
+        state.sessions.remove(&token);
+    }
+    state.logouts += 1;
+    ok(json!([]))
+}
+
+async fn sites(State(state): State<Shared>, headers: HeaderMap) -> Response {
+    let mut state = state.lock().expect("Fake controller state poisoned");
+    if let Some(response) = reject(&mut state, &headers, SITE) {
+        return response;
+    }
+    ok(
+        json!([{ "_id": "5f0000000000000000000001", "name": SITE, "desc": "Default", "role": "admin" }]),
+    )
+}
+
+async fn list_vouchers(
+    State(state): State<Shared>,
+    Path(site): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    // A stalled listing answers with the vouchers as they were when asked
+    let (response, stall) = {
+        let mut state = state.lock().expect("Fake controller state poisoned");
+        let response = match reject(&mut state, &headers, &site) {
+            Some(response) => response,
+            None => ok(json!(visible_vouchers(&mut state, None))),
+        };
+        (response, state.stall.take())
+    };
+    if let Some(delay) = stall {
+        tokio::time::sleep(delay).await;
+    }
+    response
+}
+
+/// Listing filtered by `create_time`, as `stat/voucher` answers a POST.
+async fn query_vouchers(
+    State(state): State<Shared>,
+    Path(site): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    let mut state = state.lock().expect("Fake controller state poisoned");
+    if let Some(response) = reject(&mut state, &headers, &site) {
+        return response;
+    }
+    ok(json!(visible_vouchers(
+        &mut state,
+        body["create_time"].as_i64()
+    )))
+}
+
+async fn list_guests(
+    State(state): State<Shared>,
+    Path(site): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    let mut state = state.lock().expect("Fake controller state poisoned");
+    if let Some(response) = reject(&mut state, &headers, &site) {
+        return response;
+    }
+    if body["within"].as_u64().is_none() {
+        return error(StatusCode::BAD_REQUEST, "api.err.InvalidPayload");
+    }
+    ok(json!(state.guests))
+}
+
+fn visible_vouchers(state: &mut ControllerState, create_time: Option<i64>) -> Vec<FakeVoucher> {
+    let mut visible = Vec::new();
+    for voucher in &mut state.vouchers {
+        if voucher.hidden_listings > 0 {
+            voucher.hidden_listings -= 1;
+        } else if create_time.is_none_or(|time| time == voucher.create_time) {
+            visible.push(voucher.clone());
+        }
+    }
+    visible
+}
+
+async fn hotspot_command(
+    State(state): State<Shared>,
+    Path(site): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<Value>,
+) -> Response {
+    let mut state = state.lock().expect("Fake controller state poisoned");
+    if let Some(response) = reject(&mut state, &headers, &site) {
+        return response;
+    }
+
+    match body["cmd"].as_str() {
+        Some("create-voucher") => {
+            let (Some(count), Some(expire)) = (body["n"].as_u64(), body["expire"].as_u64()) else {
+                return error(StatusCode::BAD_REQUEST, "api.err.InvalidArgs");
+            };
+            if count == 0 || expire == 0 {
+                return error(StatusCode::BAD_REQUEST, "api.err.InvalidArgs");
+            }
+            let create_time = state.now();
+            let hidden_listings = std::mem::take(&mut state.listing_lag);
+            let shortfall = std::mem::take(&mut state.creation_shortfall);
+            for _ in 0..count.saturating_sub(shortfall) {
+                let mut voucher = FakeVoucher::new(body["note"].as_str().unwrap_or_default());
+                voucher.create_time = create_time;
+                voucher.duration = expire;
+                voucher.quota = body["quota"].as_u64().unwrap_or(1);
+                voucher.qos_rate_max_up = body["up"].as_u64();
+                voucher.qos_rate_max_down = body["down"].as_u64();
+                voucher.qos_usage_quota = body["bytes"].as_u64();
+                voucher.qos_overwrite = voucher.qos_rate_max_up.is_some()
+                    || voucher.qos_rate_max_down.is_some()
+                    || voucher.qos_usage_quota.is_some();
+                voucher.hidden_listings = hidden_listings;
+                state.insert(voucher);
+            }
+            ok(json!([{ "create_time": create_time }]))
+        }
+        Some("delete-voucher") => {
+            let id = body["_id"].as_str().unwrap_or_default();
+            let before = state.vouchers.len();
+            state.vouchers.retain(|v| v.id != id);
+            if state.vouchers.len() == before {
+                return error(StatusCode::BAD_REQUEST, "api.err.IdInvalid");
+            }
+            ok(json!([]))
+        }
+        _ => error(StatusCode::BAD_REQUEST, "api.err.UnknownCommand"),
+    }
+}
+
+/// Enabled pool with the default settings.
+pub fn pool(name: &str) -> RollingVoucherConfig {
+    RollingVoucherConfig {
+        name: name.to_string(),
+        prefix: None,
+        enabled: true,
+        ..RollingVoucherConfig::default()
+    }
+}
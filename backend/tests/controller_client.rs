@@ -0,0 +1,705 @@
+//! Sessions, error mapping, listing and creation of `UnifiAPI` against the
+//! fake controller.
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use backend::{
+    error::ApiError,
+    models::{CreateVoucherRequest, VoucherStatus},
+    session::LifetimeSource,
+    totp::Totp,
+    unifi_api::UnifiAPI,
+};
+use chrono::Utc;
+use common::{FakeController, FakeVoucher};
+
+const MFA_SEED: &str = "GEZD GNBV GY3T QOJQ GEZD GNBV GY3T QOJQ";
+
+fn request(name: &str, count: u32) -> CreateVoucherRequest {
+    CreateVoucherRequest {
+        count,
+        name: name.to_string(),
+        authorized_guest_limit: None,
+        time_limit_minutes: 60,
+        data_usage_limit_mbytes: None,
+        rx_rate_limit_kbps: None,
+        tx_rate_limit_kbps: None,
+    }
+}
+
+#[tokio::test]
+async fn login_rejects_wrong_credentials() {
+    let fake = FakeController::start().await;
+    let environment = fake.environment_with(|e| e.unifi_password = "wrong".to_string());
+
+    let error = UnifiAPI::try_from_environment(environment)
+        .await
+        .unwrap_err();
+    assert!(
+        error.contains("rejected the configured credentials"),
+        "{error}"
+    );
+    assert!(error.contains("api.err.Invalid"), "{error}");
+    assert_eq!(fake.logins(), 0);
+}
+
+#[tokio::test]
+async fn login_reports_an_unreachable_controller() {
+    let fake = FakeController::start().await;
+    let environment = fake.environment_with(|e| {
+        e.unifi_controller_url = "http://127.0.0.1:9".to_string();
+    });
+
+    let error = UnifiAPI::try_from_environment(environment)
+        .await
+        .unwrap_err();
+    assert!(error.contains("unreachable"), "{error}");
+}
+
+#[tokio::test]
+async fn session_is_established_on_connect() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    assert_eq!(fake.logins(), 1);
+    let session = client.session_info().expect("No session after login");
+    assert!(session.expires_at > session.established_at);
+    assert!(client.last_sync().is_some());
+}
+
+#[tokio::test]
+async fn expired_session_is_renewed_transparently() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("Guest"));
+
+    fake.expire_sessions();
+    let vouchers = client
+        .get_all_vouchers()
+        .await
+        .expect("Request after re-login failed");
+
+    assert_eq!(vouchers.data.len(), 1);
+    assert_eq!(fake.logins(), 2);
+}
+
+#[tokio::test]
+async fn login_sends_the_current_two_factor_code() {
+    let fake = FakeController::start().await;
+    fake.require_mfa(MFA_SEED);
+    let environment = fake.environment_with(|environment| {
+        environment.unifi_totp = Some(Totp::from_base32(MFA_SEED).unwrap());
+    });
+
+    let client = UnifiAPI::try_from_environment(environment)
+        .await
+        .expect("Login with a two-factor code failed");
+
+    client
+        .get_all_vouchers()
+        .await
+        .expect("Request after MFA login failed");
+    assert_eq!(fake.logins(), 1);
+}
+
+#[tokio::test]
+async fn missing_two_factor_seed_is_explained() {
+    let fake = FakeController::start().await;
+    fake.require_mfa(MFA_SEED);
+
+    let error = UnifiAPI::try_from_environment(fake.environment())
+        .await
+        .unwrap_err();
+
+    assert!(error.contains("requires a two-factor code"), "{error}");
+    assert!(error.contains("UNIFI_TOTP_SECRET"), "{error}");
+    assert_eq!(fake.logins(), 0);
+}
+
+#[tokio::test]
+async fn wrong_two_factor_seed_is_an_auth_failure() {
+    let fake = FakeController::start().await;
+    fake.require_mfa(MFA_SEED);
+    let environment = fake.environment_with(|environment| {
+        environment.unifi_totp = Some(Totp::from_base32("JBSWY3DPEHPK3PXPJBSWY3DP").unwrap());
+    });
+
+    let error = UnifiAPI::try_from_environment(environment)
+        .await
+        .unwrap_err();
+
+    assert!(error.contains("rejected the two-factor code"), "{error}");
+    assert_eq!(fake.logins(), 0);
+}
+
+#[tokio::test]
+async fn concurrent_requests_share_one_login_after_expiry() {
+    let fake = FakeController::start().await;
+    fake.announce_session_lifetime(Duration::from_secs(1));
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("Guest"));
+
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    assert!(client.session_info().is_none());
+    let results = tokio::join!(
+        client.get_all_vouchers(),
+        client.get_all_vouchers(),
+        client.get_all_vouchers(),
+        client.get_all_vouchers(),
+        client.get_all_vouchers(),
+    );
+
+    for result in [results.0, results.1, results.2, results.3, results.4] {
+        assert_eq!(result.expect("Request after expiry failed").data.len(), 1);
+    }
+    assert_eq!(fake.logins(), 2);
+}
+
+#[tokio::test]
+async fn concurrent_rejections_share_one_login() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    fake.expire_sessions();
+    let (a, b, c) = tokio::join!(
+        client.get_all_vouchers(),
+        client.get_all_vouchers(),
+        client.get_all_vouchers(),
+    );
+
+    assert!(a.is_ok() && b.is_ok() && c.is_ok());
+    assert_eq!(fake.logins(), 2);
+}
+
+#[tokio::test]
+async fn session_lifetime_is_learned_from_the_cookie() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let session = client.session_info().expect("No session after login");
+    assert_eq!(session.lifetime_source, LifetimeSource::Assumed);
+    assert_eq!(session.lifetime_seconds, 30 * 60);
+
+    fake.announce_session_lifetime(Duration::from_secs(120));
+    let client = fake.connect().await;
+    let session = client.session_info().expect("No session after login");
+    assert_eq!(session.lifetime_source, LifetimeSource::Cookie);
+    assert_eq!(session.lifetime_seconds, 120);
+    assert!(session.refresh_at < session.expires_at);
+}
+
+#[tokio::test]
+async fn session_is_renewed_ahead_of_expiry() {
+    let fake = FakeController::start().await;
+    fake.announce_session_lifetime(Duration::from_secs(3));
+    let client: &'static UnifiAPI<'static> = Box::leak(Box::new(fake.connect().await));
+    let first = client.session_info().expect("No session after login");
+    tokio::spawn(client.keep_session_fresh());
+
+    tokio::time::sleep(Duration::from_millis(2800)).await;
+
+    assert_eq!(fake.logins(), 2);
+    let renewed = client.session_info().expect("Session lapsed");
+    assert!(renewed.established_at > first.established_at);
+    assert_eq!(renewed.logins, 2);
+    client
+        .get_all_vouchers()
+        .await
+        .expect("Request on the renewed session failed");
+    assert_eq!(fake.logins(), 2);
+}
+
+#[tokio::test]
+async fn failed_renewals_are_spaced_and_stop_at_expiry() {
+    let fake = FakeController::start().await;
+    fake.announce_session_lifetime(Duration::from_secs(2));
+    let client: &'static UnifiAPI<'static> = Box::leak(Box::new(fake.connect().await));
+    tokio::spawn(client.keep_session_fresh());
+    fake.refuse_logins(true);
+
+    // Renewal is due after 1.6s and the session expires before a retry
+    tokio::time::sleep(Duration::from_secs(4)).await;
+    assert_eq!(fake.login_attempts(), 2);
+    let session = client.session_info();
+    assert!(session.is_none(), "{session:?}");
+
+    // Requests log in again by themselves, and renewals resume
+    fake.refuse_logins(false);
+    client
+        .get_all_vouchers()
+        .await
+        .expect("Request after expiry failed");
+    assert_eq!(fake.login_attempts(), 3);
+    tokio::time::sleep(Duration::from_millis(1800)).await;
+    assert_eq!(fake.logins(), 3);
+}
+
+#[tokio::test]
+async fn logout_ends_the_session() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    client.logout().await;
+
+    assert_eq!(fake.logouts(), 1);
+    assert_eq!(fake.open_sessions(), 0);
+    assert!(client.session_info().is_none());
+    client
+        .get_all_vouchers()
+        .await
+        .expect("Request after logout failed");
+    assert_eq!(fake.logins(), 2);
+}
+
+#[tokio::test]
+async fn rejected_fresh_session_is_an_auth_failure() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    fake.fail_next(StatusCode::UNAUTHORIZED, "api.err.LoginRequired");
+    fake.fail_next(StatusCode::UNAUTHORIZED, "api.err.LoginRequired");
+    let error = client.get_all_vouchers().await.unwrap_err();
+
+    assert!(
+        matches!(error, ApiError::ControllerAuthFailed(_)),
+        "{error:?}"
+    );
+    assert_eq!(fake.logins(), 2);
+}
+
+#[tokio::test]
+async fn controller_error_bodies_are_surfaced() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    fake.fail_next(StatusCode::BAD_REQUEST, "api.err.NoPermission");
+    let error = client.get_all_vouchers().await.unwrap_err();
+
+    match error {
+        ApiError::ControllerRejected { status, message } => {
+            assert_eq!(status, 400);
+            assert_eq!(message, "api.err.NoPermission");
+        }
+        other => panic!("Unexpected error {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn errors_reported_in_a_success_body_are_rejections() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    fake.fail_next(StatusCode::OK, "api.err.InvalidPayload");
+    let error = client.get_all_vouchers().await.unwrap_err();
+
+    assert!(
+        matches!(&error, ApiError::ControllerRejected { status: 200, message } if message == "api.err.InvalidPayload"),
+        "{error:?}"
+    );
+}
+
+#[tokio::test]
+async fn unknown_site_is_rejected() {
+    let fake = FakeController::start().await;
+    let environment = fake.environment_with(|e| e.unifi_site_id = "missing".to_string());
+    let client = UnifiAPI::try_from_environment(environment)
+        .await
+        .expect("Login failed");
+
+    let error = client.get_all_vouchers().await.unwrap_err();
+    assert!(
+        matches!(&error, ApiError::ControllerRejected { message, .. } if message == "api.err.NoSiteContext"),
+        "{error:?}"
+    );
+}
+
+#[tokio::test]
+async fn reads_are_retried_after_server_errors() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("Guest"));
+
+    fake.fail_next(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "api.err.ServiceUnavailable",
+    );
+    fake.rate_limit_next(0);
+    let vouchers = client
+        .get_all_vouchers()
+        .await
+        .expect("Retried read failed");
+
+    assert_eq!(vouchers.data.len(), 1);
+    assert_eq!(fake.api_requests(), 3);
+}
+
+#[tokio::test]
+async fn reads_give_up_after_the_configured_attempts() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    for _ in 0..3 {
+        fake.rate_limit_next(0);
+    }
+    let error = client.get_all_vouchers().await.unwrap_err();
+
+    assert!(
+        matches!(
+            error,
+            ApiError::RateLimited {
+                retry_after: Some(0),
+                ..
+            }
+        ),
+        "{error:?}"
+    );
+    assert_eq!(fake.api_requests(), 3);
+}
+
+#[tokio::test]
+async fn writes_are_not_retried() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    fake.fail_next(StatusCode::BAD_GATEWAY, "api.err.BadGateway");
+    let error = client
+        .create_voucher(request("Guest", 1))
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(error, ApiError::ControllerRejected { status: 502, .. }),
+        "{error:?}"
+    );
+    assert_eq!(fake.api_requests(), 1);
+    assert!(fake.vouchers().is_empty());
+}
+
+#[tokio::test]
+async fn vouchers_are_listed_with_local_dates_and_limits() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let mut stored = FakeVoucher::new("Conference").quota(5).used_by(2);
+    stored.create_time = 1_735_689_600; // 2025-01-01 00:00:00 UTC
+    stored.qos_rate_max_down = Some(10_000);
+    stored.qos_usage_quota = Some(500);
+    let id = fake.insert(stored);
+
+    let vouchers = client
+        .get_all_vouchers()
+        .await
+        .expect("Listing failed")
+        .data;
+
+    assert_eq!(vouchers.len(), 1);
+    let voucher = &vouchers[0];
+    assert_eq!(voucher.id, id);
+    assert_eq!(voucher.name, "Conference");
+    assert_eq!(voucher.created_at, "2025-01-01T00:00:00+00:00");
+    assert_eq!(voucher.created_at_epoch, Some(1_735_689_600));
+    assert_eq!(voucher.authorized_guest_limit, Some(5));
+    assert_eq!(voucher.authorized_guest_count, 2);
+    assert_eq!(voucher.rx_rate_limit_kbps, Some(10_000));
+    assert_eq!(voucher.data_usage_limit_mbytes, Some(500));
+    assert!(voucher.activated_at.is_some());
+    assert!(!voucher.expired);
+    assert_eq!(voucher.status, VoucherStatus::Active);
+}
+
+#[tokio::test]
+async fn dates_carry_the_offset_of_the_configured_timezone() {
+    let fake = FakeController::start().await;
+    let environment = fake.environment_with(|e| e.timezone = chrono_tz::Europe::Paris);
+    let client = UnifiAPI::try_from_environment(environment)
+        .await
+        .expect("Login failed");
+    let mut stored = FakeVoucher::new("Conference").used_by(1);
+    stored.create_time = 1_735_689_600; // 2025-01-01 00:00:00 UTC
+    stored.start_time = Some(1_751_328_000); // 2025-07-01 00:00:00 UTC, summer time
+    fake.insert(stored);
+
+    let voucher = client
+        .get_all_vouchers()
+        .await
+        .expect("Listing failed")
+        .data
+        .remove(0);
+
+    assert_eq!(voucher.created_at, "2025-01-01T01:00:00+01:00");
+    assert_eq!(voucher.created_at_epoch, Some(1_735_689_600));
+    assert_eq!(
+        voucher.activated_at.as_deref(),
+        Some("2025-07-01T02:00:00+02:00")
+    );
+    assert_eq!(voucher.activated_at_epoch, Some(1_751_328_000));
+}
+
+#[tokio::test]
+async fn remaining_validity_and_data_are_computed() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let mut unused = FakeVoucher::new("Unused");
+    unused.qos_usage_quota = Some(500);
+    fake.insert(unused);
+    let mut active = FakeVoucher::new("Active").used_by(1);
+    active.qos_usage_quota = Some(500);
+    active.end_time = Some(Utc::now().timestamp() + 90 * 60 - 30);
+    fake.insert(active);
+    fake.insert(FakeVoucher::new("Expired").expired());
+
+    let vouchers = client
+        .get_all_vouchers()
+        .await
+        .expect("Listing failed")
+        .data;
+    let voucher = |name: &str| vouchers.iter().find(|v| v.name == name).unwrap();
+
+    let unused = voucher("Unused");
+    assert_eq!(unused.status, VoucherStatus::Unused);
+    assert_eq!(unused.remaining_minutes, 24 * 60);
+    assert_eq!(unused.remaining_data_mbytes, Some(500));
+    assert!(unused.expires_at_epoch.is_none());
+
+    let active = voucher("Active");
+    assert_eq!(active.status, VoucherStatus::Active);
+    assert_eq!(active.remaining_minutes, 90);
+    // The controller does not report usage once a voucher is in use
+    assert_eq!(active.remaining_data_mbytes, None);
+
+    let expired = voucher("Expired");
+    assert_eq!(expired.status, VoucherStatus::Expired);
+    assert_eq!(expired.remaining_minutes, 0);
+    assert!(
+        expired
+            .expires_at_epoch
+            .is_some_and(|end| end < Utc::now().timestamp())
+    );
+}
+
+#[tokio::test]
+async fn vouchers_past_their_end_time_are_expired() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("Old").expired());
+    fake.insert(FakeVoucher::new("Fresh"));
+
+    let expired = client.get_expired_vouchers().await.expect("Listing failed");
+
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].name, "Old");
+}
+
+#[tokio::test]
+async fn newest_voucher_is_found_regardless_of_controller_order() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("Newest"));
+    fake.insert(FakeVoucher::new("Oldest").created_ago(Duration::from_secs(7200)));
+    fake.insert(FakeVoucher::new("Older").created_ago(Duration::from_secs(3600)));
+
+    let newest = client.get_newest_voucher().await.expect("Lookup failed");
+    assert_eq!(newest.name, "Newest");
+}
+
+#[tokio::test]
+async fn newest_voucher_of_an_empty_controller_is_not_found() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    let error = client.get_newest_voucher().await.unwrap_err();
+    assert!(matches!(error, ApiError::NotFound(_)), "{error:?}");
+}
+
+#[tokio::test]
+async fn voucher_details_are_looked_up_by_id() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let id = fake.insert(FakeVoucher::new("Guest"));
+
+    let voucher = client
+        .get_voucher_details(id.clone())
+        .await
+        .expect("Lookup failed");
+    assert_eq!(voucher.id, id);
+
+    let error = client
+        .get_voucher_details("unknown".to_string())
+        .await
+        .unwrap_err();
+    assert!(matches!(error, ApiError::NotFound(_)), "{error:?}");
+}
+
+#[tokio::test]
+async fn creation_returns_the_new_vouchers_with_their_limits() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("Earlier").created_ago(Duration::from_secs(600)));
+
+    let created = client
+        .create_voucher(CreateVoucherRequest {
+            authorized_guest_limit: Some(3),
+            data_usage_limit_mbytes: Some(1024),
+            rx_rate_limit_kbps: Some(20_000),
+            tx_rate_limit_kbps: Some(5_000),
+            ..request("Conference", 2)
+        })
+        .await
+        .expect("Creation failed")
+        .vouchers;
+
+    assert_eq!(created.len(), 2);
+    for voucher in &created {
+        assert_eq!(voucher.name, "Conference");
+        assert_eq!(voucher.time_limit_minutes, 60);
+        assert_eq!(voucher.authorized_guest_limit, Some(3));
+        assert_eq!(voucher.data_usage_limit_mbytes, Some(1024));
+        let stored = fake
+            .voucher(&voucher.id)
+            .expect("Returned voucher not stored");
+        assert_eq!(stored.code, voucher.code);
+        assert_eq!(stored.qos_rate_max_down, Some(20_000));
+        assert_eq!(stored.qos_rate_max_up, Some(5_000));
+    }
+    assert_ne!(created[0].id, created[1].id);
+    assert_eq!(fake.vouchers().len(), 3);
+}
+
+#[tokio::test]
+async fn creation_without_limits_sends_none() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    let created = client
+        .create_voucher(request("", 1))
+        .await
+        .expect("Creation failed")
+        .vouchers;
+
+    assert_eq!(created.len(), 1);
+    assert_eq!(created[0].name, "");
+    let stored = fake
+        .voucher(&created[0].id)
+        .expect("Returned voucher not stored");
+    assert!(stored.note.starts_with("[ref:"), "{}", stored.note);
+    assert_eq!(stored.quota, 1);
+    assert!(!stored.qos_overwrite);
+}
+
+#[tokio::test]
+async fn creations_in_the_same_second_get_their_own_vouchers() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.freeze_clock();
+
+    let (first, second) = tokio::join!(
+        client.create_voucher(request("First", 2)),
+        client.create_voucher(request("Second", 3)),
+    );
+    let first = first.expect("First creation failed").vouchers;
+    let second = second.expect("Second creation failed").vouchers;
+
+    assert_eq!(first.len(), 2);
+    assert!(first.iter().all(|v| v.name == "First"));
+    assert_eq!(second.len(), 3);
+    assert!(second.iter().all(|v| v.name == "Second"));
+    assert_eq!(fake.vouchers().len(), 5);
+}
+
+#[tokio::test]
+async fn correlation_markers_are_hidden_from_listings() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("Manual [ref:not-a-marker]"));
+
+    let created = client
+        .create_voucher(request("Guest", 1))
+        .await
+        .expect("Creation failed")
+        .vouchers;
+    let stored = fake
+        .voucher(&created[0].id)
+        .expect("Returned voucher not stored");
+    assert!(stored.note.starts_with("Guest [ref:"), "{}", stored.note);
+
+    let mut names: Vec<String> = client
+        .get_all_vouchers()
+        .await
+        .expect("Listing failed")
+        .data
+        .into_iter()
+        .map(|v| v.name)
+        .collect();
+    names.sort();
+    assert_eq!(names, vec!["Guest", "Manual [ref:not-a-marker]"]);
+}
+
+#[tokio::test]
+async fn creation_waits_for_a_lagging_controller() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    fake.lag_listings(1);
+    let created = client
+        .create_voucher(request("Guest", 2))
+        .await
+        .expect("Creation failed")
+        .vouchers;
+
+    assert_eq!(created.len(), 2);
+    // Creation, a listing without the vouchers and one with them
+    assert_eq!(fake.api_requests(), 3);
+}
+
+#[tokio::test]
+async fn missing_vouchers_are_a_partial_creation() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    fake.fall_short(1);
+    let error = client
+        .create_voucher(request("Guest", 3))
+        .await
+        .unwrap_err();
+
+    match error {
+        ApiError::PartialCreation(message) => {
+            assert!(message.contains("2 of 3"), "{message}");
+            for voucher in fake.vouchers() {
+                assert!(message.contains(&voucher.code), "{message}");
+            }
+        }
+        other => panic!("Unexpected error {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn invalid_creation_surfaces_the_controller_message() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    let error = client
+        .create_voucher(CreateVoucherRequest {
+            time_limit_minutes: 0,
+            ..request("Guest", 1)
+        })
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(&error, ApiError::ControllerRejected { status: 400, message } if message == "api.err.InvalidArgs"),
+        "{error:?}"
+    );
+}
+
+#[tokio::test]
+async fn sites_are_listed_with_the_account_role() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+
+    let sites = client.get_sites().await.expect("Listing sites failed");
+
+    assert_eq!(sites.len(), 1);
+    assert_eq!(sites[0].name, "default");
+    assert_eq!(sites[0].role.as_deref(), Some("admin"));
+}
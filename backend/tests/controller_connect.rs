@@ -0,0 +1,67 @@
+//! Serving requests while the controller is still being connected to in the
+//! background.
+mod common;
+
+use std::time::Duration;
+
+use axum::{
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+};
+use backend::{app, unifi_api::connect_controller};
+use common::{FakeController, FakeVoucher};
+use serde_json::Value;
+use tower::ServiceExt;
+
+async fn list_vouchers() -> (StatusCode, Option<String>, Value) {
+    let response = app::router()
+        .oneshot(Request::get("/api/vouchers").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .map(|value| value.to_str().unwrap().to_string());
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (status, content_type, serde_json::from_slice(&body).unwrap())
+}
+
+// The controller client is a global, so the whole scenario runs in a single
+// test
+#[tokio::test]
+async fn requests_succeed_once_the_controller_comes_up() {
+    let fake = FakeController::down();
+    let connecting = tokio::spawn(connect_controller(
+        fake.environment(),
+        Duration::from_millis(20),
+    ));
+
+    // Several attempts fail before the controller is up
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!connecting.is_finished());
+    let (status, content_type, problem) = list_vouchers().await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(content_type.as_deref(), Some("application/problem+json"));
+    assert_eq!(problem["code"], "controller_unreachable");
+    assert!(
+        problem["detail"]
+            .as_str()
+            .unwrap()
+            .contains("Not connected to the UniFi controller yet"),
+        "{problem}"
+    );
+
+    fake.come_up().await;
+    let voucher = fake.insert(FakeVoucher::new("lobby"));
+    let connected = tokio::time::timeout(Duration::from_secs(5), connecting)
+        .await
+        .expect("Not connected once the controller came up")
+        .unwrap();
+    assert!(connected);
+    assert_eq!(fake.logins(), 1);
+
+    let (status, _, body) = list_vouchers().await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"][0]["id"], voucher);
+}
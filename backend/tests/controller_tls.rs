@@ -0,0 +1,142 @@
+//! Verification of the controller certificate with a CA bundle and pins.
+mod common;
+
+use std::path::Path;
+
+use backend::{
+    tls::{self, CaBundle, CertificatePin},
+    unifi_api::UnifiAPI,
+};
+use common::{FakeController, fixture};
+use tokio_rustls::rustls::pki_types::{CertificateDer, pem::PemObject};
+
+/// `openssl x509 -in tests/fixtures/controller.pem -noout -fingerprint -sha256`
+const CERT_SHA256: &str = "66:2B:FE:95:3A:C7:5E:86:B5:C1:73:CE:0E:2E:6F:78:26:57:76:E5:3A:38:F2:EC:EE:08:7B:CA:4E:4C:C5:05";
+/// `openssl x509 -in tests/fixtures/controller.pem -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256`
+const SPKI_SHA256: &str = "e66b86909f72f24e7d1eb22e1e6e63931092f2b76d168020547bd261ccf8b52a";
+
+fn controller_certificate() -> CertificateDer<'static> {
+    CertificateDer::from_pem_file(fixture("controller.pem")).unwrap()
+}
+
+fn pins(value: &str, kind: fn([u8; 32]) -> CertificatePin) -> Vec<CertificatePin> {
+    CertificatePin::parse_list(value, kind).unwrap()
+}
+
+#[test]
+fn fingerprints_match_openssl() {
+    let fingerprints = tls::fingerprints(&controller_certificate());
+    assert_eq!(
+        fingerprints.certificate,
+        CERT_SHA256.replace(':', "").to_lowercase()
+    );
+    assert_eq!(fingerprints.public_key.as_deref(), Some(SPKI_SHA256));
+}
+
+#[test]
+fn invalid_fingerprints_and_bundles_are_rejected() {
+    assert!(CertificatePin::parse_list("abcd", CertificatePin::Certificate).is_err());
+    assert!(CertificatePin::parse_list(&"zz".repeat(32), CertificatePin::PublicKey).is_err());
+    assert_eq!(
+        pins(
+            &format!("{SPKI_SHA256}, {SPKI_SHA256},"),
+            CertificatePin::PublicKey
+        )
+        .len(),
+        2
+    );
+
+    assert!(CaBundle::load(Path::new("/nonexistent/ca.pem")).is_err());
+    let error = CaBundle::load(&fixture("controller.key")).unwrap_err();
+    assert!(error.contains("no certificate"), "{error}");
+}
+
+#[tokio::test]
+async fn untrusted_certificate_is_rejected_by_default() {
+    let fake = FakeController::start().await;
+    let url = fake.serve_tls().await;
+    let environment = fake.environment_with(|environment| environment.unifi_controller_url = url);
+
+    assert!(UnifiAPI::try_from_environment(environment).await.is_err());
+    assert_eq!(fake.logins(), 0);
+}
+
+#[tokio::test]
+async fn ca_bundle_verifies_the_controller() {
+    let fake = FakeController::start().await;
+    let url = fake.serve_tls().await;
+    let environment = fake.environment_with(|environment| {
+        environment.unifi_controller_url = url;
+        environment.unifi_ca_bundle = Some(CaBundle::load(&fixture("ca.pem")).unwrap());
+    });
+
+    let client = UnifiAPI::try_from_environment(environment)
+        .await
+        .expect("Login through the CA bundle failed");
+    client
+        .get_all_vouchers()
+        .await
+        .expect("Request over TLS failed");
+}
+
+#[tokio::test]
+async fn pinned_certificate_is_trusted_without_a_ca() {
+    for pin in [
+        pins(CERT_SHA256, CertificatePin::Certificate),
+        pins(SPKI_SHA256, CertificatePin::PublicKey),
+    ] {
+        let fake = FakeController::start().await;
+        let url = fake.serve_tls().await;
+        let environment = fake.environment_with(|environment| {
+            environment.unifi_controller_url = url;
+            environment.unifi_certificate_pins = pin.clone();
+        });
+
+        UnifiAPI::try_from_environment(environment)
+            .await
+            .unwrap_or_else(|e| panic!("Login with {pin:?} failed: {e}"));
+        assert_eq!(fake.logins(), 1);
+    }
+}
+
+#[tokio::test]
+async fn mismatched_pin_is_rejected_even_without_cert_validation() {
+    let fake = FakeController::start().await;
+    let url = fake.serve_tls().await;
+    let environment = fake.environment_with(|environment| {
+        environment.unifi_controller_url = url;
+        environment.unifi_has_valid_cert = false;
+        environment.unifi_ca_bundle = Some(CaBundle::load(&fixture("ca.pem")).unwrap());
+        environment.unifi_certificate_pins = pins(&"00".repeat(32), CertificatePin::PublicKey);
+    });
+
+    assert!(UnifiAPI::try_from_environment(environment).await.is_err());
+    assert_eq!(fake.logins(), 0);
+}
+
+#[tokio::test]
+async fn presented_chain_is_inspected_whether_trusted_or_not() {
+    let fake = FakeController::start().await;
+    let url = fake.serve_tls().await;
+    let port: u16 = url.rsplit(':').next().unwrap().parse().unwrap();
+    let address = ([127, 0, 0, 1], port).into();
+    let timeout = std::time::Duration::from_secs(5);
+
+    let environment = fake.environment();
+    let (chain, verification) = tls::inspect(environment, "localhost", address, timeout)
+        .await
+        .unwrap();
+    assert!(verification.is_err());
+    assert_eq!(
+        tls::fingerprints(&chain[0]).public_key.as_deref(),
+        Some(SPKI_SHA256)
+    );
+
+    let environment = fake.environment_with(|environment| {
+        environment.unifi_certificate_pins = pins(SPKI_SHA256, CertificatePin::PublicKey);
+    });
+    let (_, verification) = tls::inspect(environment, "localhost", address, timeout)
+        .await
+        .unwrap();
+    assert!(verification.is_ok(), "{verification:?}");
+}
@@ -0,0 +1,94 @@
+//! Diagnosis of the connection to the controller.
+mod common;
+
+use backend::doctor::{CheckStatus, DoctorReport, diagnose};
+use common::FakeController;
+
+fn status(report: &DoctorReport, name: &str) -> CheckStatus {
+    report
+        .checks
+        .iter()
+        .find(|check| check.name == name)
+        .unwrap_or_else(|| panic!("No {name} check in {report:?}"))
+        .status
+}
+
+fn message<'r>(report: &'r DoctorReport, name: &str) -> &'r str {
+    &report
+        .checks
+        .iter()
+        .find(|check| check.name == name)
+        .unwrap()
+        .message
+}
+
+#[tokio::test]
+async fn healthy_controller_passes_and_the_session_is_closed() {
+    let fake = FakeController::start().await;
+
+    let report = diagnose(fake.environment()).await;
+
+    for name in [
+        "controllerUrl",
+        "dns",
+        "tcp",
+        "clock",
+        "login",
+        "site",
+        "permissions",
+    ] {
+        assert_eq!(
+            status(&report, name),
+            CheckStatus::Pass,
+            "{name}: {}",
+            message(&report, name)
+        );
+    }
+    // Plain HTTP has no certificate to check
+    assert_eq!(status(&report, "tls"), CheckStatus::Skip);
+    assert_eq!(fake.logins(), 1);
+    assert_eq!(fake.logouts(), 1);
+    assert_eq!(fake.open_sessions(), 0);
+}
+
+#[tokio::test]
+async fn rejected_credentials_fail_the_login_and_are_redacted() {
+    let fake = FakeController::start().await;
+    let environment = fake.environment_with(|environment| {
+        environment.unifi_password = "hunter2-secret".to_string();
+    });
+
+    let report = diagnose(environment).await;
+
+    assert_eq!(status(&report, "tcp"), CheckStatus::Pass);
+    assert_eq!(status(&report, "login"), CheckStatus::Fail);
+    assert!(message(&report, "login").contains("rejected the configured credentials"));
+    assert_eq!(status(&report, "site"), CheckStatus::Skip);
+    assert_eq!(status(&report, "permissions"), CheckStatus::Skip);
+    assert_eq!(report.status, CheckStatus::Fail);
+    let serialized = serde_json::to_string(&report).unwrap();
+    assert!(!serialized.contains("hunter2-secret"));
+    assert_eq!(fake.logins(), 0);
+}
+
+#[tokio::test]
+async fn unreachable_controller_skips_the_checks_that_need_it() {
+    let fake = FakeController::start().await;
+    let environment = fake.environment_with(|environment| {
+        environment.unifi_controller_url = "http://127.0.0.1:9".to_string();
+    });
+
+    let report = diagnose(environment).await;
+
+    assert_eq!(status(&report, "dns"), CheckStatus::Pass);
+    assert_eq!(status(&report, "tcp"), CheckStatus::Fail);
+    assert!(
+        message(&report, "tcp").contains("127.0.0.1:9"),
+        "{}",
+        message(&report, "tcp")
+    );
+    for name in ["tls", "clock", "login", "site", "permissions"] {
+        assert_eq!(status(&report, name), CheckStatus::Skip, "{name}");
+    }
+    assert_eq!(report.status, CheckStatus::Fail);
+}
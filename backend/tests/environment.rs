@@ -0,0 +1,193 @@
+//! Layers of the configuration: file, environment variables and flags.
+use std::{
+    env, fs,
+    path::PathBuf,
+    sync::{Mutex, MutexGuard},
+};
+
+use backend::{cli::ConfigArgs, environment::Environment};
+
+/// Environment variables are shared by the whole process, so the tests setting
+/// them run one at a time.
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+/// Sets environment variables until dropped.
+struct EnvVars {
+    names: Vec<String>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl EnvVars {
+    fn set(vars: &[(&str, &str)]) -> Self {
+        let lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        for (name, value) in vars {
+            // SAFETY: the lock keeps the other tests of this binary from
+            // reading or writing the environment meanwhile
+            unsafe { env::set_var(name, value) };
+        }
+        Self {
+            names: vars.iter().map(|(name, _)| name.to_string()).collect(),
+            _lock: lock,
+        }
+    }
+}
+
+impl Drop for EnvVars {
+    fn drop(&mut self) {
+        for name in &self.names {
+            // SAFETY: see `EnvVars::set`
+            unsafe { env::remove_var(name) };
+        }
+    }
+}
+
+/// Writes `contents` to a file of its own in the temporary directory.
+fn file(name: &str, contents: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("backend-environment-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+const REQUIRED: &str = r#"
+[unifi]
+controller_url = "https://unifi.local"
+username = "admin"
+password = "secret"
+"#;
+
+fn args(config: PathBuf) -> ConfigArgs {
+    ConfigArgs {
+        config: Some(config),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn flags_override_the_environment_which_overrides_the_file() {
+    let config = file(
+        "precedence.toml",
+        &format!(
+            "{REQUIRED}\n[server]\nbind_host = \"10.0.0.1\"\nbind_port = 1000\nshutdown_timeout_secs = 5\n"
+        ),
+    );
+    let _env = EnvVars::set(&[
+        ("BACKEND_BIND_HOST", "10.0.0.2"),
+        ("BACKEND_BIND_PORT", "2000"),
+    ]);
+    let environment = Environment::try_new(&ConfigArgs {
+        bind_port: Some("3000".to_string()),
+        ..args(config)
+    })
+    .unwrap();
+
+    assert_eq!(environment.shutdown_timeout.as_secs(), 5);
+    assert_eq!(environment.backend_bind_host, "10.0.0.2");
+    assert_eq!(environment.backend_bind_port, 3000);
+    // Defaults fill in the rest
+    assert_eq!(environment.unifi_site_id, "default");
+}
+
+#[test]
+fn secrets_are_read_from_the_files_named() {
+    let username = file("username", "file-admin\n");
+    let password = file("password", "file-secret\r\n");
+    let config = file(
+        "secrets.toml",
+        &format!(
+            "[unifi]\ncontroller_url = \"https://unifi.local\"\nusername_file = {:?}\n",
+            username.display().to_string()
+        ),
+    );
+    let password_var = password.display().to_string();
+    let vars = EnvVars::set(&[("UNIFI_PASSWORD_FILE", &password_var)]);
+    let environment = Environment::try_new(&args(config.clone())).unwrap();
+    assert_eq!(environment.unifi_username, "file-admin");
+    assert_eq!(environment.unifi_password, "file-secret");
+    drop(vars);
+
+    // Only secrets can be read from a file, and only one way at a time
+    let _env = EnvVars::set(&[
+        ("UNIFI_PASSWORD", "inline"),
+        ("UNIFI_PASSWORD_FILE", &password_var),
+    ]);
+    let error = Environment::try_new(&args(config)).unwrap_err();
+    assert!(
+        error.contains("set either UNIFI_PASSWORD or UNIFI_PASSWORD_FILE, not both"),
+        "{error}"
+    );
+}
+
+#[test]
+fn problems_name_where_the_value_came_from() {
+    let config = file(
+        "origins.toml",
+        &format!("{REQUIRED}\n[server]\nbind_port = \"eighty\"\n"),
+    );
+    let _env = EnvVars::set(&[("BACKEND_LOG_MAX_FILES", "many")]);
+    let error = Environment::try_new(&ConfigArgs {
+        log_format: Some("xml".to_string()),
+        ..args(config.clone())
+    })
+    .unwrap_err();
+
+    assert!(
+        error.starts_with("Invalid configuration (3 problems):\n"),
+        "{error}"
+    );
+    let lines: Vec<&str> = error.lines().skip(1).collect();
+    // The file first, then the environment, then the flags
+    assert!(
+        lines[0].starts_with(&format!(
+            "  - {}:8:13: server.bind_port: ",
+            config.display()
+        )),
+        "{error}"
+    );
+    assert!(
+        lines[1].starts_with("  - environment variable BACKEND_LOG_MAX_FILES: log.max_files: "),
+        "{error}"
+    );
+    assert!(
+        lines[2].starts_with("  - flag --log-format: log.format: "),
+        "{error}"
+    );
+}
+
+#[test]
+fn unknown_keys_and_tables_are_only_errors_in_strict_mode() {
+    let config = file(
+        "unknown.toml",
+        &format!("colour = \"blue\"\n{REQUIRED}\nbind_prot = 80\n\n[extra]\nanswer = 42\n"),
+    );
+    let _env = EnvVars::set(&[]);
+    assert!(Environment::try_new(&args(config.clone())).is_ok());
+
+    let error = Environment::try_new(&ConfigArgs {
+        strict: true,
+        ..args(config)
+    })
+    .unwrap_err();
+    assert!(
+        error.starts_with("Invalid configuration (3 problems):\n"),
+        "{error}"
+    );
+    assert!(error.contains(": colour: unknown key"), "{error}");
+    assert!(error.contains(": unifi.bind_prot: unknown key"), "{error}");
+    assert!(error.contains(": extra: unknown table"), "{error}");
+}
+
+#[test]
+fn known_keys_must_be_scalars_even_when_lenient() {
+    let config = file(
+        "types.toml",
+        &format!("{REQUIRED}\n[server]\nbind_port = [80, 443]\n"),
+    );
+    let _env = EnvVars::set(&[]);
+    let error = Environment::try_new(&args(config)).unwrap_err();
+    assert!(
+        error.contains("server.bind_port: expected a string, number or boolean, found array"),
+        "{error}"
+    );
+}
@@ -0,0 +1,79 @@
+//! Readiness of the backend as the controller comes and goes.
+mod common;
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use backend::{
+    handlers::readiness_handler,
+    health::HealthStatus,
+    unifi_api::{UNIFI_API, UnifiAPI},
+    voucher_config::{ConfigSource, RollingVoucherConfig, VOUCHER_CONFIG, VoucherConfig},
+};
+use common::FakeController;
+
+async fn ready() -> (StatusCode, HealthStatus, HealthStatus) {
+    let (status, readiness) = readiness_handler().await;
+    (
+        status,
+        readiness.status,
+        readiness.components["controller"].status,
+    )
+}
+
+// Readiness reads the global controller client, so the whole lifecycle runs
+// in a single test
+#[tokio::test]
+async fn readiness_follows_the_controller() {
+    let fake = FakeController::start().await;
+    VOUCHER_CONFIG
+        .set(VoucherConfig {
+            pools: vec![RollingVoucherConfig {
+                enabled: false,
+                ..common::pool("lobby")
+            }],
+            tiers: Vec::new(),
+            source: ConfigSource::File {
+                path: "voucher-tiers.json".to_string(),
+            },
+        })
+        .unwrap();
+
+    // Still retrying the first login
+    let (status, overall, controller) = ready().await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        (overall, controller),
+        (HealthStatus::Down, HealthStatus::Down)
+    );
+
+    let environment = fake.environment_with(|environment| {
+        environment.unifi_read_timeout = Duration::from_millis(200);
+    });
+    let client = UnifiAPI::try_from_environment(environment).await.unwrap();
+    UNIFI_API.set(client).unwrap();
+    let (status, readiness) = readiness_handler().await;
+    assert_eq!(status, StatusCode::OK);
+    for name in ["controller", "session", "sync", "config", "rollingPools"] {
+        assert_eq!(
+            readiness.components[name].status,
+            HealthStatus::Ok,
+            "{name}"
+        );
+    }
+    // No scheduler runs in this test
+    assert_eq!(readiness.status, HealthStatus::Degraded);
+
+    fake.set_unresponsive(true);
+    let (status, overall, controller) = ready().await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        (overall, controller),
+        (HealthStatus::Down, HealthStatus::Down)
+    );
+
+    fake.set_unresponsive(false);
+    let (status, _, controller) = ready().await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(controller, HealthStatus::Ok);
+}
@@ -0,0 +1,110 @@
+//! Vouchers reserved by registered kiosks.
+mod common;
+
+use std::time::Duration;
+
+use axum::http::HeaderMap;
+use backend::{
+    audit::Actor,
+    kiosks::{KIOSK_REGISTRY, KioskRegistry, RegisterKioskRequest},
+    unifi_api::{UNIFI_API, client},
+    voucher_config::{ConfigSource, RollingVoucherConfig, VOUCHER_CONFIG, VoucherConfig},
+};
+use common::FakeController;
+
+// The registry, the controller client and the pools are globals, so the
+// whole scenario runs in a single test
+#[tokio::test]
+async fn reservations_do_not_count_as_available_or_block_other_kiosks() {
+    let fake = FakeController::start().await;
+    let data_dir = std::env::temp_dir().join(format!("backend-kiosks-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+    UNIFI_API.set(fake.connect().await).unwrap();
+    VOUCHER_CONFIG
+        .set(VoucherConfig {
+            pools: vec![RollingVoucherConfig {
+                min_rolling_vouchers: 2,
+                ..common::pool("lobby")
+            }],
+            tiers: Vec::new(),
+            source: ConfigSource::File {
+                path: "voucher-tiers.json".to_string(),
+            },
+        })
+        .unwrap();
+    KIOSK_REGISTRY
+        .set(KioskRegistry::try_new(&data_dir).unwrap())
+        .unwrap();
+    let registry = KIOSK_REGISTRY.get().unwrap();
+    let pool = VOUCHER_CONFIG.get().unwrap().pool(Some("lobby")).unwrap();
+    let mut tokens = Vec::new();
+    for id in ["desk", "bar"] {
+        let request = RegisterKioskRequest {
+            id: id.to_string(),
+            name: None,
+            pool: Some("lobby".to_string()),
+        };
+        tokens.push(
+            registry
+                .register(request, Actor::System)
+                .await
+                .unwrap()
+                .token,
+        );
+    }
+
+    // Only the device token proves which kiosk a request comes from
+    let actor = |kiosk: Option<&str>, token: Option<&str>| {
+        let mut headers = HeaderMap::new();
+        if let Some(kiosk) = kiosk {
+            headers.insert("x-kiosk-id", kiosk.parse().unwrap());
+        }
+        if let Some(token) = token {
+            headers.insert("authorization", format!("Bearer {token}").parse().unwrap());
+        }
+        async move { Actor::from_headers(&headers).await }
+    };
+    assert_eq!(
+        actor(Some("desk"), Some(&tokens[0])).await,
+        Actor::Kiosk("desk".to_string())
+    );
+    assert_eq!(
+        actor(Some("desk"), Some(&tokens[1])).await,
+        Actor::Unverified("kiosk:desk".to_string())
+    );
+    assert_eq!(
+        actor(Some("desk"), None).await,
+        Actor::Unverified("kiosk:desk".to_string())
+    );
+    let Actor::Unverified(token) = actor(None, Some(&tokens[0])).await else {
+        panic!("A bearer token alone identified the client");
+    };
+    assert!(token.starts_with("token:"), "{token}");
+    assert!(!token.contains(&tokens[0]));
+
+    let client = client().unwrap();
+    assert_eq!(client.top_up_rolling_vouchers(pool).await.unwrap().len(), 2);
+    let desk = registry.reserved_voucher("desk").await.unwrap();
+    // One voucher is left to the other kiosks, which is below the minimum
+    assert_eq!(client.top_up_rolling_vouchers(pool).await.unwrap().len(), 1);
+    assert!(
+        client
+            .top_up_rolling_vouchers(pool)
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // Another kiosk is served while the controller is slow to answer this one
+    fake.stall_next(Duration::from_millis(500));
+    let slow = tokio::spawn(registry.reserved_voucher("desk"));
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    tokio::time::timeout(Duration::from_millis(200), registry.heartbeat("bar"))
+        .await
+        .expect("The registry is locked during the controller call")
+        .unwrap();
+    assert_eq!(slow.await.unwrap().unwrap().id, desk.id);
+
+    let bar = registry.reserved_voucher("bar").await.unwrap();
+    assert_ne!(bar.id, desk.id);
+}
@@ -0,0 +1,219 @@
+//! HTTPS and Unix socket listeners of the backend.
+mod common;
+
+use std::{
+    fs,
+    net::SocketAddr,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use axum::{Router, routing::get, serve::Listener};
+use backend::{
+    environment::Environment,
+    listener::{self, ServerTls, TlsListener},
+    tls,
+};
+use common::{FakeController, fixture};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+use tokio_rustls::rustls::pki_types::{CertificateDer, pem::PemObject};
+
+const RELOAD_INTERVAL: Duration = Duration::from_millis(50);
+
+fn app() -> Router {
+    Router::new().route("/ping", get(|| async { "pong" }))
+}
+
+/// Empty directory for the files of one test.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("backend-listener-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Copies the certificate fixture `name` into `dir` as `server.pem` and
+/// `server.key`.
+fn install_certificate(dir: &Path, name: &str) -> ServerTls {
+    let cert = dir.join("server.pem");
+    let key = dir.join("server.key");
+    fs::copy(fixture(&format!("{name}.pem")), &cert).unwrap();
+    fs::copy(fixture(&format!("{name}.key")), &key).unwrap();
+    ServerTls::load(cert, key).expect("Invalid certificate fixture")
+}
+
+async fn serve_tls(tls: &ServerTls) -> SocketAddr {
+    let listener = TlsListener::bind("127.0.0.1:0", tls, RELOAD_INTERVAL)
+        .await
+        .unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app()).await });
+    address
+}
+
+/// SHA-256 of the certificate the server at `address` presents.
+async fn presented_fingerprint(environment: &Environment, address: SocketAddr) -> String {
+    let (chain, _) = tls::inspect(environment, "localhost", address, Duration::from_secs(5))
+        .await
+        .expect("TLS handshake failed");
+    tls::fingerprints(&chain[0]).certificate
+}
+
+fn fixture_fingerprint(name: &str) -> String {
+    let certificate = CertificateDer::from_pem_file(fixture(&format!("{name}.pem"))).unwrap();
+    tls::fingerprints(&certificate).certificate
+}
+
+/// Sends a GET request over the Unix socket at `path` and returns the response.
+async fn get_over_socket(path: &Path, uri: &str) -> String {
+    let mut stream = UnixStream::connect(path)
+        .await
+        .expect("Failed to connect to the socket");
+    let request = format!("GET {uri} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[tokio::test]
+async fn serves_https_with_the_configured_certificate() {
+    let dir = scratch_dir("https");
+    let address = serve_tls(&install_certificate(&dir, "controller")).await;
+
+    let ca = reqwest::Certificate::from_pem(&fs::read(fixture("ca.pem")).unwrap()).unwrap();
+    let client = reqwest::Client::builder()
+        .use_rustls_tls()
+        .tls_built_in_root_certs(false)
+        .add_root_certificate(ca)
+        .build()
+        .unwrap();
+    let body = client
+        .get(format!("https://localhost:{}/ping", address.port()))
+        .send()
+        .await
+        .expect("HTTPS request failed")
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(body, "pong");
+
+    // Plain HTTP gets no answer on the HTTPS port
+    let plain = reqwest::get(format!("http://127.0.0.1:{}/ping", address.port())).await;
+    assert!(plain.is_err());
+}
+
+#[test]
+fn mismatched_certificate_and_key_are_rejected() {
+    let error = ServerTls::load(fixture("controller.pem"), fixture("renewed.key")).unwrap_err();
+    assert!(error.contains("valid pair"), "{error}");
+    let error = ServerTls::load(fixture("controller.key"), fixture("controller.key")).unwrap_err();
+    assert!(error.contains("no certificate"), "{error}");
+}
+
+#[tokio::test]
+async fn renewed_certificate_is_served_without_a_restart() {
+    let fake = FakeController::start().await;
+    let dir = scratch_dir("reload");
+    let tls = install_certificate(&dir, "controller");
+    let address = serve_tls(&tls).await;
+    assert_eq!(
+        presented_fingerprint(fake.environment(), address).await,
+        fixture_fingerprint("controller")
+    );
+
+    // A renewal tool replacing both files
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    fs::copy(fixture("renewed.key"), &tls.key).unwrap();
+    fs::copy(fixture("renewed.pem"), &tls.cert).unwrap();
+
+    let renewed = fixture_fingerprint("renewed");
+    for _ in 0..100 {
+        if presented_fingerprint(fake.environment(), address).await == renewed {
+            return;
+        }
+        tokio::time::sleep(RELOAD_INTERVAL).await;
+    }
+    panic!("The renewed certificate was not picked up");
+}
+
+#[tokio::test]
+async fn broken_renewal_keeps_the_previous_certificate() {
+    let fake = FakeController::start().await;
+    let dir = scratch_dir("broken-reload");
+    let tls = install_certificate(&dir, "controller");
+    let address = serve_tls(&tls).await;
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    fs::write(&tls.cert, "not a certificate").unwrap();
+    tokio::time::sleep(RELOAD_INTERVAL * 4).await;
+
+    assert_eq!(
+        presented_fingerprint(fake.environment(), address).await,
+        fixture_fingerprint("controller")
+    );
+}
+
+#[tokio::test]
+async fn unix_socket_is_created_with_the_configured_mode() {
+    let dir = scratch_dir("socket-mode");
+    let path = dir.join("backend.sock");
+    let unix = listener::bind_unix(&path, 0o600).unwrap();
+    tokio::spawn(async move { axum::serve(unix, app()).await });
+
+    let mode = fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    let response = get_over_socket(&path, "/ping").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.ends_with("pong"), "{response}");
+}
+
+#[tokio::test]
+async fn only_stale_sockets_are_replaced() {
+    let dir = scratch_dir("socket-stale");
+    let path = dir.join("backend.sock");
+    // Left behind by a previous run that did not shut down cleanly
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    let unix = listener::bind_unix(&path, 0o660).unwrap();
+    tokio::spawn(async move { axum::serve(unix, app()).await });
+
+    // Another instance started with the same configuration
+    let error = listener::bind_unix(&path, 0o660).unwrap_err();
+    assert!(error.contains("in use"), "{error}");
+    let response = get_over_socket(&path, "/ping").await;
+    assert!(response.ends_with("pong"), "{response}");
+
+    let file = dir.join("backend.conf");
+    fs::write(&file, "keep me").unwrap();
+    let error = listener::bind_unix(&file, 0o660).unwrap_err();
+    assert!(error.contains("not a socket"), "{error}");
+    assert_eq!(fs::read_to_string(&file).unwrap(), "keep me");
+
+    // Nothing is left of the directory the sockets were bound in
+    let mut names: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names, ["backend.conf", "backend.sock"]);
+}
+
+#[tokio::test]
+async fn serves_on_the_unix_socket_alone() {
+    let fake = FakeController::start().await;
+    let dir = scratch_dir("socket-only");
+    let path = dir.join("backend.sock");
+    let environment = fake.environment_with(|environment| {
+        environment.backend_tcp_enabled = false;
+        environment.backend_unix_socket = Some(path.clone());
+    });
+
+    let servers = listener::start(environment, app()).await.unwrap();
+    assert_eq!(servers.len(), 1);
+    let response = get_over_socket(&path, "/ping").await;
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+}
@@ -0,0 +1,78 @@
+//! Log files, their format and the syslog sink.
+mod common;
+
+use std::{fs, net::UdpSocket, time::Duration};
+
+use backend::logging::{self, AUDIT_TARGET, LogFormat, LogRotation, SyslogConfig, SyslogProtocol};
+use common::FakeController;
+
+#[test]
+fn syslog_urls_need_a_protocol_and_a_port() {
+    assert_eq!(
+        "tcp://logs.local:6514".parse::<SyslogConfig>().unwrap(),
+        SyslogConfig {
+            protocol: SyslogProtocol::Tcp,
+            address: "logs.local:6514".to_string(),
+        }
+    );
+    assert!("logs.local:514".parse::<SyslogConfig>().is_err());
+    assert!("udp://logs.local".parse::<SyslogConfig>().is_err());
+    assert_eq!(" JSON ".parse::<LogFormat>(), Ok(LogFormat::Json));
+    assert!("weekly".parse::<LogRotation>().is_err());
+}
+
+// The subscriber is global, so a single test installs it
+#[tokio::test]
+async fn events_go_to_the_files_and_syslog_in_the_configured_format() {
+    let fake = FakeController::start().await;
+    let syslog = UdpSocket::bind("127.0.0.1:0").unwrap();
+    syslog
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let log_dir = std::env::temp_dir().join(format!("backend-logging-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&log_dir);
+    let environment = fake.environment_with(|environment| {
+        environment.log_dir = Some(log_dir.clone());
+        environment.log_format = LogFormat::Json;
+        environment.syslog = Some(SyslogConfig {
+            protocol: SyslogProtocol::Udp,
+            address: syslog.local_addr().unwrap().to_string(),
+        });
+    });
+
+    let guards = logging::init(environment).expect("Failed to install the subscriber");
+    tracing::info!("Server started");
+    tracing::info!(target: AUDIT_TARGET, event = "voucher_issued", code = "12345", "Voucher issued");
+    // Flushes the file writers
+    drop(guards);
+
+    let mut datagram = [0; 2048];
+    let len = syslog.recv(&mut datagram).expect("No syslog message");
+    let message = String::from_utf8_lossy(&datagram[..len]);
+    // user facility, informational severity, RFC 5424
+    assert!(message.starts_with("<14>1 "), "{message}");
+    assert!(message.contains(" unifi-voucher-manager "), "{message}");
+    assert!(message.ends_with("Server started"), "{message}");
+    let len = syslog.recv(&mut datagram).expect("No syslog message");
+    let message = String::from_utf8_lossy(&datagram[..len]);
+    assert!(
+        message.contains(&format!(" {} audit - ", std::process::id())),
+        "{message}"
+    );
+
+    let lines = |name: &str| -> Vec<serde_json::Value> {
+        fs::read_to_string(log_dir.join(name))
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("Not a JSON line"))
+            .collect()
+    };
+    let application = lines("vouchers.log");
+    assert_eq!(application.len(), 2);
+    assert_eq!(application[0]["fields"]["message"], "Server started");
+    // The audit log only holds the audit events
+    let audit = lines("audit.log");
+    assert_eq!(audit.len(), 1);
+    assert_eq!(audit[0]["target"], AUDIT_TARGET);
+    assert_eq!(audit[0]["fields"]["code"], "12345");
+}
@@ -0,0 +1,109 @@
+//! Generated OpenAPI document.
+use backend::openapi::ApiDoc;
+use serde_json::Value;
+use utoipa::OpenApi;
+
+fn document() -> Value {
+    serde_json::to_value(ApiDoc::openapi()).expect("Document does not serialize")
+}
+
+fn parameter<'a>(document: &'a Value, path: &str, method: &str, name: &str) -> &'a Value {
+    document["paths"][path][method]["parameters"]
+        .as_array()
+        .unwrap_or_else(|| panic!("{method} {path} has no parameters"))
+        .iter()
+        .find(|p| p["name"] == name)
+        .unwrap_or_else(|| panic!("{method} {path} does not document `{name}`"))
+}
+
+#[test]
+fn document_is_openapi_3_1() {
+    let document = document();
+    assert!(document["openapi"].as_str().unwrap().starts_with("3.1"));
+    assert_eq!(document["info"]["title"], "UniFi Voucher Manager API");
+}
+
+#[test]
+fn every_route_is_documented() {
+    let document = document();
+    let routes = [
+        ("/api/v1/vouchers", "get"),
+        ("/api/vouchers", "get"),
+        ("/api/vouchers", "post"),
+        ("/api/vouchers/details", "get"),
+        ("/api/vouchers/newest", "get"),
+        ("/api/vouchers/selected", "delete"),
+        ("/api/vouchers/expired", "delete"),
+        ("/api/vouchers/expired/rolling", "delete"),
+        ("/api/vouchers/rolling", "get"),
+        ("/api/vouchers/rolling", "post"),
+        ("/api/vouchers/rolling/all", "get"),
+        ("/api/vouchers/rolling/rotate", "post"),
+        ("/api/kiosks", "get"),
+        ("/api/kiosks", "post"),
+        ("/api/kiosks/{id}", "delete"),
+        ("/api/kiosks/{id}/heartbeat", "post"),
+        ("/api/kiosks/{id}/voucher", "get"),
+        ("/api/events", "get"),
+        ("/api/audit", "get"),
+        ("/api/audit/verify", "get"),
+        ("/api/reports", "get"),
+        ("/api/jobs", "get"),
+        ("/api/jobs/{name}/run", "post"),
+        ("/api/admin/doctor", "get"),
+        ("/api/health/live", "get"),
+        ("/api/health/ready", "get"),
+    ];
+    for (path, method) in routes {
+        assert!(
+            document["paths"][path][method].is_object(),
+            "{method} {path} is not documented"
+        );
+    }
+}
+
+#[test]
+fn query_parameters_are_documented() {
+    let document = document();
+
+    let index = parameter(&document, "/api/vouchers/rolling", "get", "index");
+    assert_eq!(index["in"], "query");
+    assert_eq!(index["required"], false);
+
+    let ids = parameter(&document, "/api/vouchers/selected", "delete", "ids");
+    assert_eq!(ids["in"], "query");
+    assert_eq!(ids["required"], true);
+    assert!(
+        ids["description"]
+            .as_str()
+            .unwrap()
+            .to_lowercase()
+            .contains("comma-separated")
+    );
+}
+
+#[test]
+fn errors_are_problem_documents() {
+    let document = document();
+    let responses = &document["paths"]["/api/vouchers"]["get"]["responses"];
+    for status in ["400", "429", "502", "503"] {
+        let content = &responses[status]["content"]["application/problem+json"];
+        assert_eq!(
+            content["schema"]["$ref"], "#/components/schemas/Problem",
+            "GET /api/vouchers {status}"
+        );
+    }
+    assert!(responses["429"]["headers"]["Retry-After"].is_object());
+    assert!(document["components"]["schemas"]["Problem"].is_object());
+}
+
+#[test]
+fn kiosk_routes_require_the_device_token() {
+    let document = document();
+    assert_eq!(
+        document["components"]["securitySchemes"]["kioskToken"]["scheme"],
+        "bearer"
+    );
+    let security = &document["paths"]["/api/kiosks/{id}/voucher"]["get"]["security"];
+    assert!(security[0]["kioskToken"].is_array());
+}
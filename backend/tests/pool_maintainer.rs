@@ -0,0 +1,120 @@
+//! Background maintenance of the rolling pools against the fake controller.
+mod common;
+
+use std::time::{Duration, Instant};
+
+use axum::http::StatusCode;
+use backend::{
+    events::{self, Event},
+    pool_maintainer::{observation, run_pool_maintainer},
+    unifi_api::UNIFI_API,
+    voucher_config::{ConfigSource, RollingVoucherConfig, VOUCHER_CONFIG, VoucherConfig},
+};
+use common::FakeController;
+use tokio::sync::broadcast::Receiver;
+
+async fn next_event(events: &mut Receiver<Event>) -> Event {
+    tokio::time::timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("No event within 5s")
+        .unwrap()
+}
+
+/// Ids of the vouchers created by the top-up `event`, checking the size of
+/// the pool it reports.
+fn topped_up(event: Event, created: usize) -> Vec<String> {
+    let Event::RollingPoolToppedUp {
+        pool,
+        vouchers,
+        unused,
+    } = event
+    else {
+        panic!("Expected a top-up, got {event:?}");
+    };
+    assert_eq!(pool, "lobby");
+    assert_eq!(vouchers.len(), created);
+    assert_eq!(unused, 2);
+    vouchers.into_iter().map(|v| v.id).collect()
+}
+
+/// Instants at which the controller receives its next `count` requests.
+async fn request_times(fake: &FakeController, count: usize) -> Vec<Instant> {
+    let mut seen = fake.api_requests();
+    let mut times = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(15);
+    while times.len() < count {
+        assert!(Instant::now() < deadline, "The maintainer stopped checking");
+        let requests = fake.api_requests();
+        if requests > seen {
+            times.push(Instant::now());
+            seen = requests;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    times
+}
+
+// The controller client and the pools are globals, so the whole scenario
+// runs in a single test
+#[tokio::test]
+async fn redeemed_and_expired_vouchers_are_replaced_with_backoff_on_failures() {
+    let fake = FakeController::start().await;
+    UNIFI_API.set(fake.connect().await).unwrap();
+    VOUCHER_CONFIG
+        .set(VoucherConfig {
+            pools: vec![RollingVoucherConfig {
+                min_rolling_vouchers: 2,
+                check_interval_seconds: 1,
+                ..common::pool("lobby")
+            }],
+            tiers: Vec::new(),
+            source: ConfigSource::File {
+                path: "voucher-tiers.json".to_string(),
+            },
+        })
+        .unwrap();
+    let mut events = events::subscribe();
+
+    run_pool_maintainer().await;
+    let vouchers = topped_up(next_event(&mut events).await, 2);
+    assert_eq!(fake.vouchers_named("[ROLLING:lobby]").len(), 2);
+    assert_eq!(observation("lobby").unwrap().unused, 2);
+
+    fake.redeem(&vouchers[0]);
+    let Event::RollingVoucherRedeemed { voucher_id, .. } = next_event(&mut events).await else {
+        panic!("The redemption was not reported");
+    };
+    assert_eq!(voucher_id, vouchers[0]);
+    let replacement = topped_up(next_event(&mut events).await, 1);
+    assert_eq!(fake.vouchers_named("[ROLLING:lobby]").len(), 3);
+
+    fake.expire(&vouchers[1]);
+    let Event::RollingVoucherExpired { voucher_id, .. } = next_event(&mut events).await else {
+        panic!("The expiry was not reported");
+    };
+    assert_eq!(voucher_id, vouchers[1]);
+    topped_up(next_event(&mut events).await, 1);
+    assert_eq!(fake.vouchers_named("[ROLLING:lobby]").len(), 4);
+
+    // Each failed check doubles the delay before the next one, and the first
+    // successful check goes back to the configured interval. A check of a
+    // full pool is a single listing.
+    let checks = request_times(&fake, 1).await;
+    fake.fail_next(StatusCode::BAD_REQUEST, "api.err.Invalid");
+    fake.fail_next(StatusCode::BAD_REQUEST, "api.err.Invalid");
+    let checks = [checks, request_times(&fake, 4).await].concat();
+    let delays: Vec<Duration> = checks.windows(2).map(|w| w[1] - w[0]).collect();
+    for (delay, (min, max)) in
+        delays
+            .iter()
+            .zip([(800, 1500), (1800, 2500), (3800, 4500), (800, 1500)])
+    {
+        assert!(
+            (Duration::from_millis(min)..Duration::from_millis(max)).contains(delay),
+            "Delays between checks: {delays:?}"
+        );
+    }
+    assert!(events.try_recv().is_err(), "A failed check emitted events");
+    assert!(fake.voucher(&replacement[0]).is_some_and(|v| v.used == 0));
+    assert_eq!(observation("lobby").unwrap().unused, 2);
+}
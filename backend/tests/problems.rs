@@ -0,0 +1,94 @@
+//! Problem details returned by the API when the controller fails a request.
+mod common;
+
+use axum::{
+    body::{Body, to_bytes},
+    http::{HeaderMap, Request, StatusCode, header},
+};
+use backend::{app, unifi_api::UNIFI_API};
+use common::FakeController;
+use serde_json::Value;
+use tower::ServiceExt;
+
+/// Lists the vouchers through the whole router, sending `request_id` as the
+/// `X-Request-Id`, and returns the status, headers and problem body.
+async fn list_vouchers(request_id: Option<&str>) -> (StatusCode, HeaderMap, Value) {
+    let mut request = Request::get("/api/vouchers");
+    if let Some(id) = request_id {
+        request = request.header("x-request-id", id);
+    }
+    let response = app::router()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let problem = serde_json::from_slice(&body).expect("The body is not JSON");
+    (status, headers, problem)
+}
+
+fn assert_problem(headers: &HeaderMap, problem: &Value, status: StatusCode, code: &str) {
+    assert_eq!(headers[header::CONTENT_TYPE], "application/problem+json");
+    assert_eq!(problem["status"], status.as_u16());
+    assert_eq!(problem["code"], code);
+    assert!(
+        problem["type"].as_str().unwrap().ends_with(code),
+        "{problem}"
+    );
+    assert!(problem["title"].is_string(), "{problem}");
+    // The request id ties the problem to the response header and the logs
+    assert_eq!(
+        problem["requestId"],
+        headers["x-request-id"].to_str().unwrap()
+    );
+}
+
+// The controller client is a global, so the whole scenario runs in a single
+// test
+#[tokio::test]
+async fn controller_failures_are_reported_as_problem_details() {
+    let fake = FakeController::start().await;
+    UNIFI_API.set(fake.connect().await).unwrap();
+
+    // A request the controller refused is the caller's problem
+    fake.fail_next(StatusCode::BAD_REQUEST, "api.err.InvalidPayload");
+    let (status, headers, problem) = list_vouchers(Some("trace-1")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_problem(&headers, &problem, status, "controller_rejected");
+    assert_eq!(problem["requestId"], "trace-1");
+    assert!(
+        problem["detail"]
+            .as_str()
+            .unwrap()
+            .contains("api.err.InvalidPayload"),
+        "{problem}"
+    );
+
+    // A controller failing on its own is a bad gateway, once retries are
+    // exhausted
+    for _ in 0..3 {
+        fake.fail_next(StatusCode::INTERNAL_SERVER_ERROR, "api.err.ServerError");
+    }
+    let (status, headers, problem) = list_vouchers(None).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_problem(&headers, &problem, status, "controller_rejected");
+
+    // The controller's Retry-After is passed on
+    for _ in 0..3 {
+        fake.rate_limit_next(30);
+    }
+    let (status, headers, problem) = list_vouchers(None).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_problem(&headers, &problem, status, "rate_limited");
+    assert_eq!(headers[header::RETRY_AFTER], "30");
+
+    // Losing the session when the credentials no longer work
+    fake.refuse_logins(true);
+    fake.expire_sessions();
+    let (status, headers, problem) = list_vouchers(Some("trace-2")).await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_problem(&headers, &problem, status, "controller_auth_failed");
+    assert_eq!(problem["requestId"], "trace-2");
+    assert!(!headers.contains_key(header::RETRY_AFTER));
+}
@@ -0,0 +1,110 @@
+//! Deletion and purging of vouchers against the fake controller.
+mod common;
+
+use axum::http::StatusCode;
+use common::{FakeController, FakeVoucher, pool};
+
+#[tokio::test]
+async fn purge_deletes_only_expired_vouchers() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("Old").expired());
+    fake.insert(FakeVoucher::new("[ROLLING:lobby] old").expired());
+    let fresh = fake.insert(FakeVoucher::new("Fresh"));
+    let in_use = fake.insert(FakeVoucher::new("In use").used_by(1));
+
+    let response = client
+        .delete_expired_vouchers()
+        .await
+        .expect("Purge failed");
+
+    assert_eq!(response.data.len(), 2);
+    let remaining: Vec<String> = fake.vouchers().into_iter().map(|v| v.id).collect();
+    assert_eq!(remaining, vec![fresh, in_use]);
+}
+
+#[tokio::test]
+async fn rolling_purge_is_limited_to_the_pool() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let lobby = pool("lobby");
+    fake.insert(FakeVoucher::new("[ROLLING:lobby] old").expired());
+    let patio = fake.insert(FakeVoucher::new("[ROLLING:patio] old").expired());
+    let manual = fake.insert(FakeVoucher::new("Old").expired());
+    let unused = fake.insert(FakeVoucher::new("[ROLLING:lobby] unused"));
+
+    let response = client
+        .delete_expired_rolling_vouchers(Some(&lobby))
+        .await
+        .expect("Purge failed");
+
+    assert_eq!(response.data.len(), 1);
+    let remaining: Vec<String> = fake.vouchers().into_iter().map(|v| v.id).collect();
+    assert_eq!(remaining, vec![patio, manual, unused]);
+}
+
+#[tokio::test]
+async fn purge_with_nothing_expired_sends_no_deletion() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("Fresh"));
+
+    let response = client
+        .delete_expired_vouchers()
+        .await
+        .expect("Purge failed");
+
+    assert!(response.data.is_empty());
+    assert_eq!(fake.api_requests(), 1);
+}
+
+#[tokio::test]
+async fn deletion_counts_only_vouchers_the_controller_deleted() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let first = fake.insert(FakeVoucher::new("First"));
+    let second = fake.insert(FakeVoucher::new("Second"));
+
+    let response = client
+        .delete_vouchers_by_ids(vec![first, "unknown".to_string(), second])
+        .await
+        .expect("Deletion failed");
+
+    assert_eq!(response.data.len(), 2);
+    assert!(fake.vouchers().is_empty());
+}
+
+#[tokio::test]
+async fn deletion_continues_after_a_failed_voucher() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let first = fake.insert(FakeVoucher::new("First"));
+    let second = fake.insert(FakeVoucher::new("Second"));
+
+    fake.fail_next(StatusCode::INTERNAL_SERVER_ERROR, "api.err.ServerError");
+    let response = client
+        .delete_vouchers_by_ids(vec![first.clone(), second])
+        .await
+        .expect("Deletion failed");
+
+    assert_eq!(response.data.len(), 1);
+    let remaining: Vec<String> = fake.vouchers().into_iter().map(|v| v.id).collect();
+    assert_eq!(remaining, vec![first]);
+}
+
+#[tokio::test]
+async fn deletion_renews_an_expired_session() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let id = fake.insert(FakeVoucher::new("Guest"));
+
+    fake.expire_sessions();
+    let response = client
+        .delete_vouchers_by_ids(vec![id])
+        .await
+        .expect("Deletion failed");
+
+    assert_eq!(response.data.len(), 1);
+    assert!(fake.vouchers().is_empty());
+    assert_eq!(fake.logins(), 2);
+}
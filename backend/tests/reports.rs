@@ -0,0 +1,406 @@
+//! Usage and activation reports.
+mod common;
+
+use std::{path::PathBuf, time::Duration};
+
+use backend::{
+    audit::{Actor, AuditAction, AuditLog, AuditRecord},
+    error::ApiError,
+    models::{CreateVoucherRequest, Voucher},
+    reports::{Report, ReportGroup, ReportQuery, ReportSources},
+    unifi_api::UnifiAPI,
+    voucher_config::{RollingVoucherConfig, VoucherTier},
+};
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use chrono_tz::Tz;
+use common::{FakeController, FakeVoucher};
+
+const HOUR: Duration = Duration::from_secs(3600);
+const MIB: u64 = 1024 * 1024;
+
+fn tier() -> VoucherTier {
+    VoucherTier {
+        id: "day".to_string(),
+        name: "Day pass".to_string(),
+        description: String::new(),
+        duration_hours: 24.0,
+        download_mbps: Some(20),
+        upload_mbps: None,
+        data_limit_mb: None,
+    }
+}
+
+fn pool() -> RollingVoucherConfig {
+    RollingVoucherConfig {
+        name: "lobby".to_string(),
+        prefix: None,
+        ..Default::default()
+    }
+}
+
+/// Unused voucher created from [`tier`].
+fn day_pass(note: &str) -> FakeVoucher {
+    let mut voucher = FakeVoucher::new(note);
+    voucher.qos_rate_max_down = Some(20_000);
+    voucher
+}
+
+/// Settings [`tier`] creates vouchers with, as recorded in the audit trail.
+fn day_pass_request() -> serde_json::Value {
+    serde_json::to_value(CreateVoucherRequest {
+        count: 1,
+        name: "Guest".to_string(),
+        authorized_guest_limit: Some(1),
+        time_limit_minutes: 24 * 60,
+        data_usage_limit_mbytes: None,
+        rx_rate_limit_kbps: Some(20_000),
+        tx_rate_limit_kbps: None,
+    })
+    .unwrap()
+}
+
+/// Empty audit trail for one test.
+fn audit_log(name: &str) -> AuditLog {
+    let dir = std::env::temp_dir().join(format!("backend-reports-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    AuditLog::try_new(&PathBuf::from(&dir)).unwrap()
+}
+
+fn today() -> NaiveDate {
+    Utc::now().date_naive()
+}
+
+/// Report of yesterday and today in UTC, so that vouchers created a few
+/// hours ago count whatever the time of day.
+async fn report(client: &UnifiAPI<'_>, audit: Option<&AuditLog>) -> Report {
+    let from = today().checked_sub_days(Days::new(1)).unwrap();
+    report_between(client, audit, from, today(), Tz::UTC).await
+}
+
+async fn report_between(
+    client: &UnifiAPI<'_>,
+    audit: Option<&AuditLog>,
+    from: NaiveDate,
+    to: NaiveDate,
+    timezone: Tz,
+) -> Report {
+    let vouchers = client
+        .get_all_vouchers()
+        .await
+        .expect("Listing failed")
+        .data;
+    let guests = client
+        .get_guests(48 * HOUR)
+        .await
+        .expect("Guest listing failed");
+    let history = audit.map(AuditLog::entries).unwrap_or_default();
+    let sources = ReportSources {
+        vouchers: &vouchers,
+        guests: &guests,
+        history: &history,
+        tiers: &[tier()],
+        pools: &[pool()],
+    };
+    Report::compute(from, to, &sources, timezone, Utc::now())
+}
+
+fn group<'r>(groups: &'r [ReportGroup], key: Option<&str>) -> &'r ReportGroup {
+    groups
+        .iter()
+        .find(|group| group.key.as_deref() == key)
+        .unwrap_or_else(|| panic!("No group {key:?} in {groups:?}"))
+}
+
+async fn listed(client: &UnifiAPI<'_>, id: &str) -> Voucher {
+    client.get_voucher_details(id.to_string()).await.unwrap()
+}
+
+#[tokio::test]
+async fn vouchers_are_counted_by_outcome_tier_and_pool() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(day_pass("Unused"));
+    let mut activated = day_pass("Activated").created_ago(2 * HOUR);
+    activated.used = 1;
+    activated.start_time = Some(activated.create_time + 30 * 60);
+    activated.end_time = Some(activated.create_time + 30 * 60 + 24 * 3600);
+    fake.insert(activated);
+    let mut lapsed = FakeVoucher::new("Lapsed").created_ago(3 * HOUR);
+    lapsed.end_time = Some(lapsed.create_time + 3600);
+    fake.insert(lapsed);
+    fake.insert(FakeVoucher::new("[ROLLING:lobby] Guest"));
+    fake.insert(FakeVoucher::new("Last week").created_ago(7 * 24 * HOUR));
+
+    let report = report(&client, None).await;
+
+    let totals = &report.totals;
+    assert_eq!(
+        (
+            totals.issued,
+            totals.activated,
+            totals.unused,
+            totals.expired_unused,
+            totals.unknown
+        ),
+        (4, 1, 2, 1, 0)
+    );
+    assert_eq!(totals.activation_rate, Some(0.25));
+    assert_eq!(totals.expired_unused_rate, Some(0.25));
+    assert_eq!(totals.avg_minutes_to_activation, Some(30.0));
+
+    let day = &group(&report.by_tier, Some("day")).metrics;
+    assert_eq!(
+        (day.issued, day.activated, day.activation_rate),
+        (2, 1, Some(0.5))
+    );
+    assert_eq!(group(&report.by_tier, None).metrics.issued, 1);
+    // Rolling vouchers belong to their pool only
+    assert_eq!(
+        report.by_tier.iter().map(|g| g.metrics.issued).sum::<u64>(),
+        3
+    );
+    assert_eq!(group(&report.by_pool, Some("lobby")).metrics.issued, 1);
+    assert_eq!(group(&report.by_pool, None).metrics.issued, 3);
+
+    // Without an audit trail, no operator is known
+    assert_eq!(report.by_operator.len(), 1);
+    assert_eq!(group(&report.by_operator, None).metrics.issued, 4);
+    assert_eq!(report.by_day.len(), 2);
+    assert_eq!(
+        report.by_day.iter().map(|g| g.metrics.issued).sum::<u64>(),
+        4
+    );
+}
+
+#[tokio::test]
+async fn days_are_counted_in_the_configured_timezone() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    // 23:30 in UTC, half past midnight the next day in Berlin
+    let created = DateTime::parse_from_rfc3339("2026-01-10T23:30:00Z").unwrap();
+    let mut voucher = FakeVoucher::new("Late");
+    voucher.create_time = created.timestamp();
+    fake.insert(voucher);
+
+    let day = NaiveDate::from_ymd_opt(2026, 1, 11).unwrap();
+    let berlin = report_between(&client, None, day, day, chrono_tz::Europe::Berlin).await;
+    assert_eq!(berlin.totals.issued, 1);
+    assert_eq!(berlin.timezone, "Europe/Berlin");
+    let utc = report_between(&client, None, day, day, Tz::UTC).await;
+    assert_eq!(utc.totals.issued, 0);
+    assert_eq!(utc.by_day.len(), 1);
+    assert_eq!(utc.by_day[0].key.as_deref(), Some("2026-01-11"));
+}
+
+#[tokio::test]
+async fn deleted_vouchers_are_taken_from_the_audit_trail() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let audit = audit_log("deleted");
+    let kept = fake.insert(day_pass("Kept"));
+    let lapsed = fake.insert(day_pass("Lapsed"));
+    let used = fake.insert(day_pass("Used").used_by(1));
+    let forgotten = fake.insert(day_pass("Forgotten"));
+    let rolling = fake.insert(FakeVoucher::new("[ROLLING:lobby] Guest"));
+
+    let mut vouchers = Vec::new();
+    for id in [&kept, &lapsed, &used, &forgotten] {
+        vouchers.push(listed(&client, id).await);
+    }
+    audit.append(
+        AuditRecord::new(AuditAction::Create, Actor::User("alice".to_string()))
+            .parameters(day_pass_request())
+            .vouchers(&vouchers),
+    );
+    audit.append(
+        AuditRecord::new(AuditAction::Rotate, Actor::System)
+            .parameters(serde_json::json!({ "trigger": "pool_maintainer", "pool": "lobby" }))
+            .vouchers([&listed(&client, &rolling).await]),
+    );
+    // A deletion noting which vouchers were unused, and one recorded before
+    // deletions did
+    audit.append(AuditRecord::new(AuditAction::Delete, Actor::Anonymous).removed(&vouchers[1..3]));
+    audit.append(
+        AuditRecord::new(AuditAction::Purge, Actor::System)
+            .voucher_ids(std::slice::from_ref(&forgotten)),
+    );
+    client
+        .delete_vouchers_by_ids(vec![lapsed, used, forgotten])
+        .await
+        .unwrap();
+
+    let report = report(&client, Some(&audit)).await;
+
+    let totals = &report.totals;
+    assert_eq!(
+        (
+            totals.issued,
+            totals.activated,
+            totals.unused,
+            totals.expired_unused,
+            totals.unknown
+        ),
+        (5, 1, 2, 1, 1)
+    );
+    // The outcome of the forgotten voucher is not known
+    assert_eq!(totals.activation_rate, Some(0.25));
+    assert_eq!(group(&report.by_tier, Some("day")).metrics.issued, 4);
+
+    let alice = &group(&report.by_operator, Some("user:alice")).metrics;
+    assert_eq!(
+        (alice.issued, alice.expired_unused, alice.unknown),
+        (4, 1, 1)
+    );
+    assert_eq!(group(&report.by_operator, Some("system")).metrics.issued, 1);
+    assert_eq!(group(&report.by_pool, Some("lobby")).metrics.issued, 1);
+}
+
+#[tokio::test]
+async fn utilisation_comes_from_validity_and_guest_traffic() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let mut voucher = FakeVoucher::new("Capped").created_ago(7 * HOUR);
+    voucher.qos_usage_quota = Some(100);
+    voucher.used = 1;
+    voucher.start_time = Some(voucher.create_time + 3600);
+    voucher.end_time = Some(voucher.create_time + 3600 + 24 * 3600);
+    let capped = fake.insert(voucher);
+    fake.add_guest(&capped, 40 * MIB, 10 * MIB);
+    let unlimited = fake.insert(FakeVoucher::new("Unlimited"));
+    fake.add_guest(&unlimited, 0, 0);
+
+    let report = report(&client, None).await;
+
+    // Activated six hours into a day of validity
+    assert_eq!(report.totals.time_utilisation, Some(0.25));
+    assert_eq!(report.totals.data_used_mbytes, 50.0);
+    assert_eq!(report.totals.data_utilisation, Some(0.5));
+}
+
+#[tokio::test]
+async fn data_utilisation_leaves_out_deleted_vouchers_of_unknown_traffic() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let audit = audit_log("traffic");
+    let capped = |note: &str| {
+        let mut voucher = FakeVoucher::new(note).used_by(1);
+        voucher.qos_usage_quota = Some(100);
+        voucher
+    };
+    let kept = fake.insert(capped("Kept"));
+    fake.add_guest(&kept, 40 * MIB, 10 * MIB);
+    let known = fake.insert(capped("Known"));
+    fake.add_guest(&known, 30 * MIB, 0);
+    let forgotten = fake.insert(capped("Forgotten"));
+
+    let deleted = vec![
+        listed(&client, &known).await,
+        listed(&client, &forgotten).await,
+    ];
+    let request = CreateVoucherRequest {
+        data_usage_limit_mbytes: Some(100),
+        ..serde_json::from_value(day_pass_request()).unwrap()
+    };
+    audit.append(
+        AuditRecord::new(AuditAction::Create, Actor::System)
+            .parameters(serde_json::to_value(request).unwrap())
+            .vouchers(&deleted),
+    );
+    audit.append(AuditRecord::new(AuditAction::Delete, Actor::System).removed(&deleted));
+    client
+        .delete_vouchers_by_ids(vec![known, forgotten])
+        .await
+        .unwrap();
+
+    let report = report(&client, Some(&audit)).await;
+
+    assert_eq!(report.totals.activated, 3);
+    assert_eq!(report.totals.data_used_mbytes, 80.0);
+    // The controller no longer lists the guests of the forgotten voucher
+    assert_eq!(report.totals.data_utilisation, Some(0.4));
+}
+
+#[test]
+fn range_defaults_to_the_last_seven_days() {
+    let today = NaiveDate::from_ymd_opt(2026, 3, 10).unwrap();
+    let (from, to) = ReportQuery::default().range(today).unwrap();
+    assert_eq!(
+        (from.to_string(), to.to_string()),
+        ("2026-03-04".to_string(), "2026-03-10".to_string())
+    );
+
+    let backwards = ReportQuery {
+        from: Some(today),
+        to: NaiveDate::from_ymd_opt(2026, 3, 1),
+        ..Default::default()
+    };
+    assert!(matches!(
+        backwards.range(today),
+        Err(ApiError::Validation(_))
+    ));
+    let too_long = ReportQuery {
+        from: NaiveDate::from_ymd_opt(2025, 1, 1),
+        ..Default::default()
+    };
+    assert!(matches!(
+        too_long.range(today),
+        Err(ApiError::Validation(_))
+    ));
+}
+
+#[tokio::test]
+async fn csv_lists_the_totals_then_every_group() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let audit = audit_log("csv");
+    let id = fake.insert(day_pass("Guest").used_by(1));
+    audit.append(
+        AuditRecord::new(AuditAction::Create, Actor::User("Doe, Jane".to_string()))
+            .vouchers([&listed(&client, &id).await]),
+    );
+
+    let csv = report(&client, Some(&audit)).await.to_csv();
+    let lines: Vec<&str> = csv.split("\r\n").collect();
+
+    assert!(
+        lines[0].starts_with(
+            "group,key,issued,activated,unused,expired_unused,unknown,activation_rate"
+        )
+    );
+    assert!(lines[1].starts_with("total,,1,1,0,0,0,1,0,"), "{csv}");
+    assert!(lines.contains(&"tier,day,1,1,0,0,0,1,0,0,0,0,"), "{csv}");
+    assert!(
+        lines
+            .iter()
+            .any(|line| line.starts_with("operator,\"user:Doe, Jane\",1,")),
+        "{csv}"
+    );
+    assert_eq!(
+        lines.iter().filter(|line| line.starts_with("day,")).count(),
+        2
+    );
+    assert_eq!(lines.last(), Some(&""));
+}
+
+#[tokio::test]
+async fn saved_report_is_named_after_its_range() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(day_pass("Guest"));
+    let data_dir =
+        std::env::temp_dir().join(format!("backend-reports-{}-saved", std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+
+    let report = report(&client, None).await;
+    let path = report.save(&data_dir).unwrap();
+
+    assert_eq!(path.parent(), Some(data_dir.join("reports").as_path()));
+    assert_eq!(
+        path.file_name().unwrap().to_string_lossy(),
+        format!("voucher-report-{}-{}.json", report.from, report.to)
+    );
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(saved["totals"]["issued"], 1);
+    assert_eq!(saved["from"], report.from.to_string());
+}
@@ -0,0 +1,474 @@
+//! Rolling pool bookkeeping of `UnifiAPI` against the fake controller.
+mod common;
+
+use std::{collections::HashSet, time::Duration};
+
+use backend::voucher_config::RollingVoucherConfig;
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::{Europe::Berlin, Tz};
+use common::{FakeController, FakeVoucher, pool};
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+fn at(time: &str) -> NaiveTime {
+    time.parse().unwrap()
+}
+
+fn utc(time: &str) -> DateTime<Utc> {
+    time.parse().unwrap()
+}
+
+/// Local time in Berlin at which the rotation period of `pool` running at
+/// `now` started.
+fn rotation_start(pool: &RollingVoucherConfig, now: &str) -> String {
+    rotation_start_in(pool, now, Berlin)
+}
+
+fn rotation_start_in(pool: &RollingVoucherConfig, now: &str, timezone: Tz) -> String {
+    pool.rotation_start(utc(now), timezone)
+        .expect("No rotation configured")
+        .with_timezone(&timezone)
+        .to_rfc3339()
+}
+
+#[tokio::test]
+async fn top_up_creates_the_missing_vouchers() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let pool = RollingVoucherConfig {
+        min_rolling_vouchers: 3,
+        ..pool("lobby")
+    };
+    fake.insert(FakeVoucher::new("[ROLLING:lobby] 20250101000000-auto-0"));
+
+    let created = client
+        .top_up_rolling_vouchers(&pool)
+        .await
+        .expect("Top-up failed");
+
+    assert_eq!(created.len(), 2);
+    assert_eq!(fake.vouchers_named("[ROLLING:lobby]").len(), 3);
+    // Listing, one creation and its lookup, without waiting between vouchers
+    assert_eq!(fake.api_requests(), 3);
+    assert_eq!(
+        client
+            .get_all_unused_rolling_vouchers(&pool)
+            .await
+            .expect("Listing failed")
+            .len(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn concurrent_top_ups_create_the_missing_vouchers_once() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let pool = RollingVoucherConfig {
+        min_rolling_vouchers: 3,
+        ..pool("lobby")
+    };
+    // The first listing is slow enough for the second top-up to start
+    fake.stall_next(Duration::from_millis(200));
+
+    let (first, second) = tokio::join!(
+        client.top_up_rolling_vouchers(&pool),
+        client.top_up_rolling_vouchers(&pool)
+    );
+
+    assert_eq!(
+        first.expect("Top-up failed").len() + second.expect("Top-up failed").len(),
+        3
+    );
+    assert_eq!(fake.vouchers_named("[ROLLING:lobby]").len(), 3);
+}
+
+#[tokio::test]
+async fn top_up_of_a_full_pool_does_nothing() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let pool = pool("lobby");
+    fake.insert(FakeVoucher::new("[ROLLING:lobby] existing"));
+
+    let created = client
+        .top_up_rolling_vouchers(&pool)
+        .await
+        .expect("Top-up failed");
+
+    assert!(created.is_empty());
+    assert_eq!(fake.api_requests(), 1);
+}
+
+#[tokio::test]
+async fn top_up_applies_the_pool_limits() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let pool = RollingVoucherConfig {
+        duration_hours: 2.0,
+        guest_limit: Some(4),
+        data_limit_mb: Some(250),
+        ..pool("lobby")
+    };
+
+    client
+        .top_up_rolling_vouchers(&pool)
+        .await
+        .expect("Top-up failed");
+
+    let stored = fake.vouchers_named("[ROLLING:lobby]");
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].duration, 120);
+    assert_eq!(stored[0].quota, 4);
+    assert_eq!(stored[0].qos_usage_quota, Some(250));
+}
+
+#[tokio::test]
+async fn used_expired_and_foreign_vouchers_are_not_available() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let pool = pool("lobby");
+    let available = fake.insert(FakeVoucher::new("[ROLLING:lobby] available"));
+    fake.insert(FakeVoucher::new("[ROLLING:lobby] redeemed").used_by(1));
+    fake.insert(FakeVoucher::new("[ROLLING:lobby] expired").expired());
+    fake.insert(FakeVoucher::new("[ROLLING:patio] other pool"));
+    fake.insert(FakeVoucher::new("Conference"));
+
+    let unused = client
+        .get_all_unused_rolling_vouchers(&pool)
+        .await
+        .expect("Listing failed");
+
+    assert_eq!(unused.len(), 1);
+    assert_eq!(unused[0].id, available);
+}
+
+#[tokio::test]
+async fn shared_vouchers_stay_available_until_the_guest_limit() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let pool = RollingVoucherConfig {
+        guest_limit: Some(3),
+        ..pool("lobby")
+    };
+    let partly_used = fake.insert(FakeVoucher::new("[ROLLING:lobby] a").quota(3).used_by(2));
+    fake.insert(FakeVoucher::new("[ROLLING:lobby] b").quota(3).used_by(3));
+
+    let unused = client
+        .get_all_unused_rolling_vouchers(&pool)
+        .await
+        .expect("Listing failed");
+
+    assert_eq!(unused.len(), 1);
+    assert_eq!(unused[0].id, partly_used);
+}
+
+#[tokio::test]
+async fn current_rolling_voucher_is_the_newest_unused_one() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let pool = pool("lobby");
+    let newest = fake.insert(FakeVoucher::new("[ROLLING:lobby] newest"));
+    fake.insert(FakeVoucher::new("[ROLLING:lobby] oldest").created_ago(2 * HOUR));
+    fake.insert(FakeVoucher::new("[ROLLING:lobby] older").created_ago(HOUR));
+
+    let current = client
+        .get_rolling_voucher(&pool)
+        .await
+        .expect("Lookup failed");
+
+    assert_eq!(current.map(|v| v.id), Some(newest));
+}
+
+#[tokio::test]
+async fn vouchers_are_handed_out_oldest_first_skipping_reserved_ones() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let pool = pool("lobby");
+    let newest = fake.insert(FakeVoucher::new("[ROLLING:lobby] newest"));
+    let oldest = fake.insert(FakeVoucher::new("[ROLLING:lobby] oldest").created_ago(2 * HOUR));
+    let middle = fake.insert(FakeVoucher::new("[ROLLING:lobby] middle").created_ago(HOUR));
+
+    let first = client
+        .get_rolling_voucher_by_index(&pool, 0, &HashSet::new())
+        .await
+        .expect("Lookup failed");
+    assert_eq!(first.map(|v| v.id), Some(oldest.clone()));
+
+    let reserved = HashSet::from([oldest]);
+    let second = client
+        .get_rolling_voucher_by_index(&pool, 1, &reserved)
+        .await
+        .expect("Lookup failed");
+    assert_eq!(second.map(|v| v.id), Some(newest));
+    let first = client
+        .get_rolling_voucher_by_index(&pool, 0, &reserved)
+        .await
+        .expect("Lookup failed");
+    assert_eq!(first.map(|v| v.id), Some(middle));
+}
+
+#[tokio::test]
+async fn ip_claims_match_the_whole_address() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let pool = pool("lobby");
+    fake.insert(FakeVoucher::new("[ROLLING:lobby] 20250101000000-10.0.0.1"));
+
+    assert!(
+        client
+            .check_rolling_voucher_ip(&pool, "10.0.0.1")
+            .await
+            .expect("Check failed")
+    );
+    assert!(
+        !client
+            .check_rolling_voucher_ip(&pool, "0.0.0.1")
+            .await
+            .expect("Check failed")
+    );
+    assert!(
+        !client
+            .check_rolling_voucher_ip(&pool, "10.0.0.12")
+            .await
+            .expect("Check failed")
+    );
+}
+
+#[tokio::test]
+async fn ip_claims_ignore_expired_vouchers_and_other_pools() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let pool = pool("lobby");
+    fake.insert(FakeVoucher::new("[ROLLING:lobby] 20250101000000-10.0.0.1").expired());
+    fake.insert(FakeVoucher::new("[ROLLING:patio] 20250101000000-10.0.0.2"));
+
+    assert!(
+        !client
+            .check_rolling_voucher_ip(&pool, "10.0.0.1")
+            .await
+            .expect("Check failed")
+    );
+    assert!(
+        !client
+            .check_rolling_voucher_ip(&pool, "10.0.0.2")
+            .await
+            .expect("Check failed")
+    );
+}
+
+#[tokio::test]
+async fn claiming_creates_a_voucher_named_after_the_address() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let pool = pool("lobby");
+
+    let voucher = client
+        .create_rolling_voucher(&pool, "10.0.0.7")
+        .await
+        .expect("Creation failed");
+
+    assert!(
+        voucher.name.starts_with("[ROLLING:lobby] "),
+        "{}",
+        voucher.name
+    );
+    assert!(voucher.name.ends_with("-10.0.0.7"), "{}", voucher.name);
+    assert!(
+        client
+            .check_rolling_voucher_ip(&pool, "10.0.0.7")
+            .await
+            .expect("Check failed")
+    );
+}
+
+#[tokio::test]
+async fn vouchers_from_a_previous_rotation_are_retired() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    // Periods of one hour: anything created two hours ago is from an earlier one
+    let pool = RollingVoucherConfig {
+        rotation_interval_hours: Some(1.0),
+        ..pool("lobby")
+    };
+    let stale = fake.insert(FakeVoucher::new("[ROLLING:lobby] stale").created_ago(2 * HOUR));
+    let current = fake.insert(FakeVoucher::new("[ROLLING:lobby] current"));
+    let redeemed = fake.insert(
+        FakeVoucher::new("[ROLLING:lobby] redeemed")
+            .created_ago(2 * HOUR)
+            .used_by(1),
+    );
+    let foreign = fake.insert(FakeVoucher::new("[ROLLING:patio] stale").created_ago(2 * HOUR));
+
+    let unused = client
+        .get_all_unused_rolling_vouchers(&pool)
+        .await
+        .expect("Listing failed");
+    assert_eq!(
+        unused.iter().map(|v| &v.id).collect::<Vec<_>>(),
+        vec![&current]
+    );
+
+    let retired = client
+        .retire_rotated_rolling_vouchers(&pool)
+        .await
+        .expect("Retiring failed");
+
+    assert_eq!(
+        retired.iter().map(|v| &v.id).collect::<Vec<_>>(),
+        vec![&stale]
+    );
+    assert!(fake.voucher(&stale).is_none());
+    for kept in [current, redeemed, foreign] {
+        assert!(fake.voucher(&kept).is_some());
+    }
+}
+
+#[tokio::test]
+async fn pools_without_rotation_retire_nothing() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let pool = pool("lobby");
+    fake.insert(FakeVoucher::new("[ROLLING:lobby] old").created_ago(48 * HOUR));
+
+    let retired = client
+        .retire_rotated_rolling_vouchers(&pool)
+        .await
+        .expect("Retiring failed");
+
+    assert!(retired.is_empty());
+    assert_eq!(fake.vouchers().len(), 1);
+}
+
+#[test]
+fn rotation_times_start_at_the_latest_slot_reached() {
+    let pool = RollingVoucherConfig {
+        rotation_times: vec![at("18:00"), at("06:00")],
+        // Ignored, the fixed times take precedence
+        rotation_interval_hours: Some(1.0),
+        ..pool("lobby")
+    };
+
+    // 12:00 CEST
+    assert_eq!(
+        rotation_start(&pool, "2026-06-10T10:00:00Z"),
+        "2026-06-10T06:00:00+02:00"
+    );
+    // 18:00 CEST, the slot starts a period of its own
+    assert_eq!(
+        rotation_start(&pool, "2026-06-10T16:00:00Z"),
+        "2026-06-10T18:00:00+02:00"
+    );
+    // 05:00 CEST, before the first slot of the day
+    assert_eq!(
+        rotation_start(&pool, "2026-06-10T03:00:00Z"),
+        "2026-06-09T18:00:00+02:00"
+    );
+}
+
+#[test]
+fn rotation_times_in_the_dst_gap_move_forward_an_hour() {
+    let pool = RollingVoucherConfig {
+        rotation_times: vec![at("02:30")],
+        ..pool("lobby")
+    };
+
+    // On 2026-03-29 the clocks skip from 02:00 to 03:00, so 02:30 is 03:30
+    // 03:15 CEST
+    assert_eq!(
+        rotation_start(&pool, "2026-03-29T01:15:00Z"),
+        "2026-03-28T02:30:00+01:00"
+    );
+    // 03:45 CEST
+    assert_eq!(
+        rotation_start(&pool, "2026-03-29T01:45:00Z"),
+        "2026-03-29T03:30:00+02:00"
+    );
+}
+
+#[test]
+fn rotation_times_on_ambiguous_days_rotate_once() {
+    let pool = RollingVoucherConfig {
+        rotation_times: vec![at("02:30")],
+        ..pool("lobby")
+    };
+
+    // On 2026-10-25 the clocks go back from 03:00 to 02:00, and 02:30 happens
+    // twice: the first one rotates, the second one does not
+    // 02:45 CEST
+    assert_eq!(
+        rotation_start(&pool, "2026-10-25T00:45:00Z"),
+        "2026-10-25T02:30:00+02:00"
+    );
+    // 02:45 CET
+    assert_eq!(
+        rotation_start(&pool, "2026-10-25T01:45:00Z"),
+        "2026-10-25T02:30:00+02:00"
+    );
+}
+
+#[test]
+fn rotation_intervals_keep_their_wall_clock_slots() {
+    let six_hourly = RollingVoucherConfig {
+        rotation_interval_hours: Some(6.0),
+        ..pool("lobby")
+    };
+
+    // 12:30 local time on a normal day, then on both DST changes, where the day is
+    // 23 and 25 hours long
+    for (now, start) in [
+        ("2026-06-10T10:30:00Z", "2026-06-10T12:00:00+02:00"),
+        ("2026-03-29T10:30:00Z", "2026-03-29T12:00:00+02:00"),
+        ("2026-10-25T11:30:00Z", "2026-10-25T12:00:00+01:00"),
+        // 01:30 CEST, before the clocks go back
+        ("2026-10-24T23:30:00Z", "2026-10-25T00:00:00+02:00"),
+    ] {
+        assert_eq!(rotation_start(&six_hourly, now), start, "at {now}");
+    }
+
+    // An interval not dividing the day restarts at midnight
+    let uneven = RollingVoucherConfig {
+        rotation_interval_hours: Some(5.0),
+        ..pool("lobby")
+    };
+    assert_eq!(
+        rotation_start_in(&uneven, "2026-06-10T23:30:00Z", chrono_tz::UTC),
+        "2026-06-10T20:00:00+00:00"
+    );
+    assert_eq!(
+        rotation_start_in(&uneven, "2026-06-11T00:30:00Z", chrono_tz::UTC),
+        "2026-06-11T00:00:00+00:00"
+    );
+}
+
+#[tokio::test]
+async fn rotated_vouchers_are_retired_after_the_grace_period() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let stale = fake.insert(FakeVoucher::new("[ROLLING:lobby] stale").created_ago(HOUR));
+    // The fake controller runs in UTC
+    let rotated_ago = |minutes| {
+        let time = Utc::now() - chrono::Duration::minutes(minutes);
+        RollingVoucherConfig {
+            rotation_times: vec![time.time()],
+            rotation_grace_minutes: 15,
+            ..pool("lobby")
+        }
+    };
+
+    let retired = client
+        .retire_rotated_rolling_vouchers(&rotated_ago(10))
+        .await
+        .expect("Retiring failed");
+    assert!(retired.is_empty());
+    assert!(fake.voucher(&stale).is_some());
+
+    let retired = client
+        .retire_rotated_rolling_vouchers(&rotated_ago(20))
+        .await
+        .expect("Retiring failed");
+    assert_eq!(
+        retired.iter().map(|v| &v.id).collect::<Vec<_>>(),
+        vec![&stale]
+    );
+    assert!(fake.voucher(&stale).is_none());
+}
@@ -0,0 +1,103 @@
+//! Top-ups of the rolling pools through the API and the scheduled job, and
+//! the audit trail they leave.
+mod common;
+
+use axum::{Json, http::HeaderMap};
+use backend::{
+    audit::{AUDIT_LOG, Actor, AuditAction, AuditLog, SourceIp},
+    error::ApiQuery,
+    handlers::rotate_rolling_voucher_handler,
+    models::{PoolRequest, RotateResponse},
+    scheduler::{JobConfig, JobKind},
+    tasks,
+    unifi_api::UNIFI_API,
+    voucher_config::{ConfigSource, RollingVoucherConfig, VOUCHER_CONFIG, VoucherConfig},
+};
+use common::FakeController;
+
+/// Ids of the vouchers named by the audited rotations of `pool`.
+fn audited(pool: &str) -> Vec<Vec<String>> {
+    AUDIT_LOG
+        .get()
+        .unwrap()
+        .entries()
+        .into_iter()
+        .filter(|entry| entry.action == AuditAction::Rotate && entry.parameters["pool"] == pool)
+        .map(|entry| entry.voucher_ids)
+        .collect()
+}
+
+// The controller client, the pools and the audit trail are globals, so the
+// whole scenario runs in a single test
+#[tokio::test]
+async fn every_voucher_of_a_top_up_is_audited_and_returned() {
+    let fake = FakeController::start().await;
+    let data_dir = std::env::temp_dir().join(format!("backend-rotation-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+    UNIFI_API.set(fake.connect().await).unwrap();
+    AUDIT_LOG
+        .set(AuditLog::try_new(&data_dir).unwrap())
+        .unwrap();
+    let pool = |name: &str| RollingVoucherConfig {
+        min_rolling_vouchers: 3,
+        ..common::pool(name)
+    };
+    VOUCHER_CONFIG
+        .set(VoucherConfig {
+            pools: vec![pool("lobby"), pool("patio")],
+            tiers: Vec::new(),
+            source: ConfigSource::File {
+                path: "voucher-tiers.json".to_string(),
+            },
+        })
+        .unwrap();
+
+    let source = || SourceIp("127.0.0.1".to_string());
+    let request = || {
+        ApiQuery(PoolRequest {
+            pool: Some("lobby".to_string()),
+        })
+    };
+    let Json(response) = rotate_rolling_voucher_handler(HeaderMap::new(), source(), request())
+        .await
+        .unwrap();
+    let RotateResponse::Created { vouchers } = response else {
+        panic!("The empty pool was not topped up: {response:?}");
+    };
+    assert_eq!(vouchers.len(), 3);
+    let lobby = audited("lobby");
+    assert_eq!(lobby.len(), 1);
+    assert_eq!(
+        lobby[0],
+        vouchers.iter().map(|v| v.id.clone()).collect::<Vec<_>>()
+    );
+
+    let Json(response) = rotate_rolling_voucher_handler(HeaderMap::new(), source(), request())
+        .await
+        .unwrap();
+    assert!(matches!(response, RotateResponse::NoActionNeeded { .. }));
+    assert_eq!(audited("lobby").len(), 1);
+
+    // The scheduled job tops up the other pool, the same way
+    let config = JobConfig {
+        enabled: true,
+        schedule: "*/5 * * * *".to_string(),
+        retention_days: None,
+        report_days: None,
+    };
+    let summary = tasks::run(JobKind::PoolTopUp, &config, "schedule", Actor::System)
+        .await
+        .unwrap();
+    assert!(
+        summary.contains("lobby: already at its minimum size"),
+        "{summary}"
+    );
+    assert!(
+        summary.contains("patio: created 3 rolling vouchers"),
+        "{summary}"
+    );
+    let patio = audited("patio");
+    assert_eq!(patio.len(), 1);
+    assert_eq!(patio[0].len(), 3);
+    assert_eq!(fake.vouchers_named("[ROLLING:patio]").len(), 3);
+}
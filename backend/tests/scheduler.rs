@@ -0,0 +1,133 @@
+//! Job schedules across daylight saving time changes, and the retention of
+//! log files.
+use std::{
+    fs::{self, File},
+    time::{Duration, SystemTime},
+};
+
+use backend::{
+    logging,
+    scheduler::{JobKind, Scheduler, SchedulerConfigFile},
+};
+use chrono::{DateTime, Utc};
+use chrono_tz::Europe::Berlin;
+
+fn scheduler(schedule: &str) -> Scheduler {
+    let config: SchedulerConfigFile = serde_json::from_value(serde_json::json!({
+        "jobs": { "reports": { "schedule": schedule } }
+    }))
+    .unwrap();
+    Scheduler::from_config(Berlin, config).unwrap()
+}
+
+fn utc(time: &str) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339(time).unwrap().to_utc()
+}
+
+/// Successive runs of the reports job after `after`, in Berlin time.
+fn runs(scheduler: &Scheduler, after: &str, count: usize) -> Vec<String> {
+    let mut after = utc(after);
+    (0..count)
+        .map(|_| {
+            let next = scheduler.next_run(JobKind::Reports, after).unwrap();
+            after = next.to_utc();
+            next.to_rfc3339()
+        })
+        .collect()
+}
+
+#[test]
+fn run_in_the_spring_gap_happens_when_the_gap_ends() {
+    // Clocks go from 02:00 to 03:00 on 29 March 2026
+    assert_eq!(
+        runs(&scheduler("30 2 * * *"), "2026-03-28T12:00:00Z", 3),
+        [
+            "2026-03-29T03:00:00+02:00",
+            "2026-03-30T02:30:00+02:00",
+            "2026-03-31T02:30:00+02:00"
+        ]
+    );
+}
+
+#[test]
+fn run_in_the_autumn_overlap_happens_once() {
+    // Clocks go from 03:00 back to 02:00 on 25 October 2026
+    assert_eq!(
+        runs(&scheduler("30 2 * * *"), "2026-10-24T12:00:00Z", 2),
+        ["2026-10-25T02:30:00+02:00", "2026-10-26T02:30:00+01:00"]
+    );
+    // Not again once the clocks went back
+    assert_eq!(
+        runs(&scheduler("30 2 * * *"), "2026-10-25T00:30:00Z", 1),
+        ["2026-10-26T02:30:00+01:00"]
+    );
+}
+
+#[test]
+fn interval_skips_the_repeated_hour() {
+    // 02:00 and 02:30 are not run again after the clocks went back
+    assert_eq!(
+        runs(&scheduler("*/30 * * * *"), "2026-10-25T00:10:00Z", 2),
+        ["2026-10-25T02:30:00+02:00", "2026-10-25T03:00:00+01:00"]
+    );
+}
+
+#[test]
+fn invalid_schedules_and_report_ranges_are_rejected() {
+    let config = |job: serde_json::Value| -> SchedulerConfigFile {
+        serde_json::from_value(serde_json::json!({ "jobs": { "reports": job } })).unwrap()
+    };
+    let error = Scheduler::from_config(
+        Berlin,
+        config(serde_json::json!({ "schedule": "61 * * * *" })),
+    )
+    .unwrap_err();
+    assert!(error.starts_with("reports: invalid schedule"), "{error}");
+    let error = Scheduler::from_config(
+        Berlin,
+        config(serde_json::json!({ "schedule": "0 6 * * 1", "reportDays": 0 })),
+    )
+    .unwrap_err();
+    assert!(error.contains("reportDays"), "{error}");
+}
+
+#[test]
+fn retention_keeps_the_files_being_written() {
+    let log_dir = std::env::temp_dir().join(format!("backend-retention-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&log_dir);
+    fs::create_dir_all(&log_dir).unwrap();
+    let old = SystemTime::now() - Duration::from_secs(100 * 24 * 60 * 60);
+    for name in [
+        // Without rotation
+        "vouchers.log",
+        "audit.log",
+        // Rotated
+        "vouchers.log.2025-01-01",
+        "audit.log.2025-01-01",
+        "unrelated.txt",
+    ] {
+        File::create(log_dir.join(name))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+    }
+    File::create(log_dir.join("vouchers.log.2025-12-31")).unwrap();
+
+    assert_eq!(logging::remove_logs_older_than(&log_dir, 90).unwrap(), 2);
+
+    let mut left: Vec<String> = fs::read_dir(&log_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    left.sort();
+    assert_eq!(
+        left,
+        [
+            "audit.log",
+            "unrelated.txt",
+            "vouchers.log",
+            "vouchers.log.2025-12-31"
+        ]
+    );
+    fs::remove_dir_all(&log_dir).unwrap();
+}
@@ -0,0 +1,83 @@
+//! Graceful shutdown of the servers and background operations.
+mod common;
+
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use axum::{Router, routing::get};
+use backend::{listener, shutdown};
+use common::FakeController;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+const SLOW_REQUEST: Duration = Duration::from_millis(500);
+
+fn app() -> Router {
+    Router::new().route(
+        "/slow",
+        get(|| async {
+            tokio::time::sleep(SLOW_REQUEST).await;
+            "done"
+        }),
+    )
+}
+
+async fn get_over_socket(path: &Path, uri: &str) -> std::io::Result<String> {
+    let mut stream = UnixStream::connect(path).await?;
+    let request = format!("GET {uri} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await?;
+    Ok(response)
+}
+
+// The shutdown is global, so the whole scenario runs in a single test
+#[tokio::test]
+async fn shutdown_finishes_open_requests_and_refuses_new_ones() {
+    let fake = FakeController::start().await;
+    let dir = std::env::temp_dir().join(format!("backend-shutdown-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("backend.sock");
+    let environment = fake.environment_with(|environment| {
+        environment.backend_tcp_enabled = false;
+        environment.backend_unix_socket = Some(path.clone());
+    });
+    let servers = listener::start(environment, app()).await.unwrap();
+
+    let slow = tokio::spawn({
+        let path = path.clone();
+        async move { get_over_socket(&path, "/slow").await }
+    });
+    let job = shutdown::operation();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let started = Instant::now();
+    shutdown::trigger();
+    let draining = tokio::spawn(shutdown::drain(servers, Duration::from_secs(5)));
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(
+        get_over_socket(&path, "/slow").await.is_err(),
+        "A new connection was accepted during the shutdown"
+    );
+
+    let response = slow.await.unwrap().expect("The open request was cut off");
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.ends_with("done"), "{response}");
+
+    // The background operation is waited for as well
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!draining.is_finished());
+    drop(job);
+    tokio::time::timeout(Duration::from_secs(1), draining)
+        .await
+        .expect("The shutdown did not complete")
+        .unwrap()
+        .unwrap();
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(!path.exists(), "The socket was not removed");
+}
@@ -0,0 +1,50 @@
+//! Time-based one-time passwords for controller accounts with MFA.
+use backend::totp::Totp;
+
+/// Seed of the RFC 6238 SHA-1 test vectors, "12345678901234567890" in base32.
+const RFC_SEED: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+#[test]
+fn codes_match_the_rfc_6238_test_vectors() {
+    let totp = Totp::from_base32(RFC_SEED).unwrap();
+    // The RFC lists 8 digits, authenticator apps show the last 6
+    for (time, code) in [
+        (59, "287082"),
+        (1_111_111_109, "081804"),
+        (1_111_111_111, "050471"),
+        (1_234_567_890, "005924"),
+        (2_000_000_000, "279037"),
+        (20_000_000_000, "353130"),
+    ] {
+        assert_eq!(totp.code_at(time), code, "at {time}");
+    }
+}
+
+#[test]
+fn seeds_are_accepted_as_shown_by_enrollment_screens() {
+    let expected = Totp::from_base32(RFC_SEED).unwrap().code_at(59);
+    for seed in [
+        "gezd gnbv gy3t qojq gezd gnbv gy3t qojq",
+        "GEZD-GNBV-GY3T-QOJQ-GEZD-GNBV-GY3T-QOJQ",
+        "otpauth://totp/UniFi:admin?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=UniFi",
+    ] {
+        assert_eq!(
+            Totp::from_base32(seed).unwrap().code_at(59),
+            expected,
+            "{seed}"
+        );
+    }
+}
+
+#[test]
+fn invalid_seeds_are_rejected() {
+    assert!(Totp::from_base32("GEZDGNBV!Y3TQOJQ").is_err());
+    assert!(Totp::from_base32("GEZDGNBV").is_err());
+    assert!(Totp::from_base32("otpauth://totp/UniFi:admin?issuer=UniFi").is_err());
+}
+
+#[test]
+fn seed_is_not_printed() {
+    let totp = Totp::from_base32(RFC_SEED).unwrap();
+    assert!(!format!("{totp:?}").contains(RFC_SEED));
+}
@@ -0,0 +1,332 @@
+//! Filtering, sorting and pagination of the versioned voucher list.
+mod common;
+
+use std::time::Duration;
+
+use backend::{
+    error::ApiError,
+    models::VoucherStatus,
+    unifi_api::UnifiAPI,
+    voucher_config::VoucherTier,
+    voucher_query::{SortOrder, VoucherListQuery, VoucherPage, VoucherSort},
+};
+use chrono::Utc;
+use common::{FakeController, FakeVoucher};
+
+const HOUR: Duration = Duration::from_secs(3600);
+
+async fn list(client: &UnifiAPI<'_>, query: VoucherListQuery) -> Result<VoucherPage, ApiError> {
+    let vouchers = client
+        .get_all_vouchers()
+        .await
+        .expect("Listing failed")
+        .data;
+    query.page(vouchers, &[tier()])
+}
+
+fn names(page: &VoucherPage) -> Vec<&str> {
+    page.data.iter().map(|v| v.name.as_str()).collect()
+}
+
+fn tier() -> VoucherTier {
+    VoucherTier {
+        id: "day".to_string(),
+        name: "Day pass".to_string(),
+        description: String::new(),
+        duration_hours: 24.0,
+        download_mbps: Some(20),
+        upload_mbps: None,
+        data_limit_mb: None,
+    }
+}
+
+#[tokio::test]
+async fn vouchers_are_filtered_by_status() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("Unused"));
+    fake.insert(FakeVoucher::new("Active").used_by(1));
+    fake.insert(FakeVoucher::new("Expired").expired());
+
+    for (status, expected) in [
+        (VoucherStatus::Unused, "Unused"),
+        (VoucherStatus::Active, "Active"),
+        (VoucherStatus::Expired, "Expired"),
+    ] {
+        let page = list(
+            &client,
+            VoucherListQuery {
+                status: Some(status),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(names(&page), vec![expected], "{status:?}");
+        assert_eq!(page.total, 1);
+    }
+}
+
+#[tokio::test]
+async fn vouchers_are_filtered_by_name_code_and_guest_limit() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("Conference day 1").quota(0));
+    fake.insert(FakeVoucher::new("conference day 2").quota(5));
+    let mut coded = FakeVoucher::new("Lobby");
+    coded.code = "1234567890".to_string();
+    fake.insert(coded);
+
+    let page = list(
+        &client,
+        VoucherListQuery {
+            name: Some("CONFERENCE".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(page.total, 2);
+
+    let page = list(
+        &client,
+        VoucherListQuery {
+            code: Some("12345-67890".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(names(&page), vec!["Lobby"]);
+
+    let page = list(
+        &client,
+        VoucherListQuery {
+            guest_limit: Some(0),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(names(&page), vec!["Conference day 1"]);
+}
+
+#[tokio::test]
+async fn vouchers_are_filtered_by_tier_settings() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    let mut matching = FakeVoucher::new("Day pass Voucher");
+    matching.qos_overwrite = true;
+    matching.qos_rate_max_down = Some(20_000);
+    fake.insert(matching);
+    fake.insert(FakeVoucher::new("Unlimited day"));
+
+    let page = list(
+        &client,
+        VoucherListQuery {
+            tier: Some("day".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(names(&page), vec!["Day pass Voucher"]);
+
+    let error = list(
+        &client,
+        VoucherListQuery {
+            tier: Some("week".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(error, ApiError::Validation(_)), "{error:?}");
+}
+
+#[tokio::test]
+async fn vouchers_are_filtered_by_creation_and_expiry_ranges() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("Recent").created_ago(HOUR));
+    fake.insert(FakeVoucher::new("Old").created_ago(48 * HOUR));
+    fake.insert(
+        FakeVoucher::new("Expiring")
+            .created_ago(48 * HOUR)
+            .used_by(1),
+    );
+
+    let page = list(
+        &client,
+        VoucherListQuery {
+            created_after: Some(Utc::now() - chrono::Duration::hours(2)),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(names(&page), vec!["Recent"]);
+
+    // Only activated vouchers have an expiry
+    let page = list(
+        &client,
+        VoucherListQuery {
+            expires_before: Some(Utc::now() + chrono::Duration::days(2)),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(names(&page), vec!["Expiring"]);
+}
+
+#[tokio::test]
+async fn vouchers_are_sorted_newest_first_by_default() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("b").created_ago(2 * HOUR));
+    fake.insert(FakeVoucher::new("A"));
+    fake.insert(FakeVoucher::new("c").created_ago(HOUR));
+
+    let page = list(&client, VoucherListQuery::default()).await.unwrap();
+    assert_eq!(names(&page), vec!["A", "c", "b"]);
+
+    let page = list(
+        &client,
+        VoucherListQuery {
+            sort: Some(VoucherSort::Name),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(names(&page), vec!["A", "b", "c"]);
+
+    let page = list(
+        &client,
+        VoucherListQuery {
+            sort: Some(VoucherSort::Name),
+            order: Some(SortOrder::Desc),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(names(&page), vec!["c", "b", "A"]);
+}
+
+#[tokio::test]
+async fn cursor_pages_through_every_voucher_once() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    // Same creation time, the id breaks the tie
+    let created = Utc::now().timestamp();
+    for index in 0..7 {
+        let mut voucher = FakeVoucher::new(&format!("Guest {index}"));
+        voucher.create_time = created;
+        fake.insert(voucher);
+    }
+
+    let mut seen = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = list(
+            &client,
+            VoucherListQuery {
+                limit: Some(3),
+                cursor,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.total, 7);
+        assert!(page.data.len() <= 3);
+        seen.extend(page.data.iter().map(|v| v.id.clone()));
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+
+    let mut unique = seen.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(seen.len(), 7);
+    assert_eq!(unique.len(), 7);
+}
+
+#[tokio::test]
+async fn cursor_survives_vouchers_created_meanwhile() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    for index in 0..4 {
+        fake.insert(FakeVoucher::new(&format!("Guest {index}")).created_ago((index + 1) * HOUR));
+    }
+
+    let first = list(
+        &client,
+        VoucherListQuery {
+            limit: Some(2),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(names(&first), vec!["Guest 0", "Guest 1"]);
+    fake.insert(FakeVoucher::new("Newer"));
+
+    let second = list(
+        &client,
+        VoucherListQuery {
+            limit: Some(2),
+            cursor: first.next_cursor,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    assert_eq!(names(&second), vec!["Guest 2", "Guest 3"]);
+    assert_eq!(second.total, 5);
+    assert!(second.next_cursor.is_none());
+}
+
+#[tokio::test]
+async fn invalid_or_mismatched_cursors_are_rejected() {
+    let fake = FakeController::start().await;
+    let client = fake.connect().await;
+    fake.insert(FakeVoucher::new("a"));
+    fake.insert(FakeVoucher::new("b"));
+
+    let error = list(
+        &client,
+        VoucherListQuery {
+            cursor: Some("zz".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(error, ApiError::Validation(_)), "{error:?}");
+
+    let page = list(
+        &client,
+        VoucherListQuery {
+            limit: Some(1),
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap();
+    let error = list(
+        &client,
+        VoucherListQuery {
+            sort: Some(VoucherSort::Code),
+            cursor: page.next_cursor,
+            ..Default::default()
+        },
+    )
+    .await
+    .unwrap_err();
+    assert!(matches!(error, ApiError::Validation(_)), "{error:?}");
+}
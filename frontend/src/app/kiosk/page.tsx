@@ -5,7 +5,7 @@ import Spinner from "@/components/utils/Spinner";
 import WifiQr from "@/components/utils/WifiQr";
 import { TriState } from "@/types/state";
 import { Voucher } from "@/types/voucher";
-import { api } from "@/utils/api";
+import { api, ApiError } from "@/utils/api";
 import { formatCode } from "@/utils/format";
 import { useGlobal } from "@/contexts/GlobalContext";
 
@@ -13,13 +13,42 @@ export default function KioskPage() {
   const [voucher, setVoucher] = useState<Voucher | null>(null);
   const [state, setState] = useState<TriState | null>(null);
   const [kioskIndex, setKioskIndex] = useState<number | null>(null);
+  const [kioskPool, setKioskPool] = useState<string | undefined>(undefined);
+  // Registered kiosk identity, which gets a voucher reserved by the backend
+  const [device, setDevice] = useState<{ id: string; token: string } | null>(null);
+  // Set when the last failure was the backend not reaching the controller
+  const [unavailable, setUnavailable] = useState(false);
   const [countdown, setCountdown] = useState<number>(10);
   const { wifiConfig, wifiString } = useGlobal();
   const loadingRef = useRef(false);
 
-  // Get kiosk index from URL parameter or localStorage
+  // Get kiosk pool and index from URL parameters or localStorage
   useEffect(() => {
     const params = new URLSearchParams(window.location.search);
+
+    const urlKiosk = params.get("kiosk");
+    const urlToken = params.get("token");
+    if (urlKiosk && urlToken) {
+      localStorage.setItem("kioskId", urlKiosk);
+      localStorage.setItem("kioskToken", urlToken);
+    }
+    const storedKiosk = localStorage.getItem("kioskId");
+    const storedToken = localStorage.getItem("kioskToken");
+    if (storedKiosk && storedToken) {
+      setDevice({ id: storedKiosk, token: storedToken });
+    }
+
+    // An empty ?pool= switches the kiosk back to the default pool
+    const urlPool = params.get("pool");
+    if (urlPool !== null) {
+      if (urlPool) {
+        localStorage.setItem("kioskPool", urlPool);
+      } else {
+        localStorage.removeItem("kioskPool");
+      }
+    }
+    setKioskPool(urlPool || localStorage.getItem("kioskPool") || undefined);
+
     const urlIndex = params.get("index");
 
     if (urlIndex !== null) {
@@ -45,42 +74,81 @@ export default function KioskPage() {
     setKioskIndex(0);
   }, []);
 
+  const fail = useCallback((error: unknown) => {
+    setUnavailable(error instanceof ApiError && error.status === 503);
+    setState("error");
+  }, []);
+
   const load = useCallback(async () => {
     if (loadingRef.current || kioskIndex === null) return;
 
     loadingRef.current = true;
+    // Retries keep the error message on screen instead of flashing a spinner
+    const setLoading = () => setState((s) => (s === "error" ? s : "loading"));
+    if (device) {
+      try {
+        setLoading();
+        await api.getKioskVoucher(device.id, device.token).then(setVoucher);
+        setState("ok");
+      } catch (error) {
+        console.error(`Kiosk ${device.id}: Failed to load reserved voucher:`, error);
+        fail(error);
+      } finally {
+        loadingRef.current = false;
+      }
+      return;
+    }
     try {
-      setState("loading");
-      await api.getRollingVoucher(kioskIndex).then(setVoucher);
+      setLoading();
+      await api.getRollingVoucher(kioskIndex, kioskPool).then(setVoucher);
       setState("ok");
     } catch (error: any) {
       if (error?.status !== 404) {
-        setState("error");
+        fail(error);
         return;
       }
       // No voucher found at this index, try to create more
       try {
         console.log(`Kiosk ${kioskIndex}: No voucher found, requesting rotation...`);
-        await api.rotateRollingVoucherIfNeeded();
+        await api.rotateRollingVoucherIfNeeded(kioskPool);
         // Try loading again after creating new vouchers
-        await api.getRollingVoucher(kioskIndex).then(setVoucher);
+        await api.getRollingVoucher(kioskIndex, kioskPool).then(setVoucher);
         setState("ok");
       } catch (retryError) {
         console.error(`Kiosk ${kioskIndex}: Failed to load voucher after rotation:`, retryError);
-        setState("error");
+        fail(retryError);
       }
     } finally {
       loadingRef.current = false;
     }
-  }, [kioskIndex]);
+  }, [kioskIndex, kioskPool, device, fail]);
 
   // Check for voucher usage and rotate if needed
   const checkAndRotate = useCallback(async () => {
+    // Keep retrying, e.g. while the controller is still booting
+    if (state === "error") {
+      await load();
+      return;
+    }
     if (!voucher || state !== "ok" || kioskIndex === null) return;
 
+    // The backend keeps the reservation until the voucher is redeemed
+    if (device) {
+      try {
+        const reserved = await api.getKioskVoucher(device.id, device.token);
+        if (reserved.id !== voucher.id) {
+          console.log(`Kiosk ${device.id}: Reserved voucher changed`);
+          setVoucher(reserved);
+        }
+      } catch (error) {
+        console.error(`Kiosk ${device.id}: Error checking reserved voucher:`, error);
+      }
+      return;
+    }
+
     try {
       // Get fresh voucher data to check if it's been used
-      const currentVoucher = await api.getRollingVoucher(kioskIndex);
+      const currentVoucher = await api.getRollingVoucher(kioskIndex, kioskPool);
 
       // If no voucher is returned or if the current voucher ID changed, we need to reload
       if (!currentVoucher || currentVoucher.id !== voucher.id) {
@@ -93,7 +161,7 @@ export default function KioskPage() {
       if (currentVoucher.authorizedGuestCount > 0) {
         console.log(`Kiosk ${kioskIndex}: Current voucher has been used, attempting to rotate...`);
         try {
-          await api.rotateRollingVoucherIfNeeded();
+          await api.rotateRollingVoucherIfNeeded(kioskPool);
           // Load the new voucher at our assigned index
           await load();
         } catch (error) {
@@ -107,7 +175,7 @@ export default function KioskPage() {
         await load();
       }
     }
-  }, [voucher, state, kioskIndex, load]);
+  }, [voucher, state, kioskIndex, kioskPool, device, load]);
 
   // Store functions in refs for stable event listeners
   const loadRef = useRef(load);
@@ -144,13 +212,35 @@ export default function KioskPage() {
     };
   }, [kioskIndex]); // Only run when kioskIndex is set
 
+  // Registered kiosks report that they are alive
+  useEffect(() => {
+    if (!device) return;
+
+    const beat = () =>
+      api.sendKioskHeartbeat(device.id, device.token).catch((error) => {
+        console.error(`Kiosk ${device.id}: Heartbeat failed:`, error);
+      });
+    beat();
+    const interval = setInterval(beat, 30000);
+    return () => clearInterval(interval);
+  }, [device]);
+
   const renderContent = useCallback(() => {
     switch (state) {
       case null:
       case "loading":
         return <Spinner />;
       case "error":
-        return (
+        return unavailable ? (
+          <div className="text-center space-y-4">
+            <div className="text-5xl sm:text-6xl md:text-7xl">
+              WiFi codes are temporarily unavailable
+            </div>
+            <p className="text-lg sm:text-xl text-gray-600 dark:text-gray-400">
+              The network is starting up, a code will appear here shortly
+            </p>
+          </div>
+        ) : (
           <div className="text-center text-5xl sm:text-6xl md:text-7xl text-status-danger">
             Could not load rolling voucher
           </div>
@@ -218,7 +308,7 @@ export default function KioskPage() {
           </div>
         );
     }
-  }, [voucher, state, wifiConfig, wifiString, countdown]);
+  }, [voucher, state, unavailable, wifiConfig, wifiString, countdown]);
 
   return (
     <main className="flex-center h-screen w-full px-4">{renderContent()}</main>
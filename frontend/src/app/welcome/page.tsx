@@ -16,7 +16,8 @@ export default function WelcomePage() {
 
   const rotateVoucher = useCallback(async () => {
     try {
-      await api.createRollingVoucher();
+      const pool = new URLSearchParams(window.location.search).get("pool");
+      await api.createRollingVoucher(pool || undefined);
     } catch (error: any) {
       // Error 403 is expected if the user already created a rolling voucher
       if (error?.status !== 403) {
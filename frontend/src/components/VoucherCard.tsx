@@ -1,6 +1,7 @@
 import { Voucher } from "@/types/voucher";
 import {
   formatCode,
+  formatDate,
   formatDuration,
   formatGuestUsage,
   formatStatus,
@@ -68,7 +69,7 @@ const VoucherCard = ({ voucher, selected, editMode, onClick }: Props) => {
         {voucher.activatedAt && (
           <div className="flex justify-between">
             <span>First Used:</span>
-            <span className="text-xs">{voucher.activatedAt}</span>
+            <span className="text-xs">{formatDate(voucher.activatedAt)}</span>
           </div>
         )}
 
@@ -79,7 +80,7 @@ const VoucherCard = ({ voucher, selected, editMode, onClick }: Props) => {
             {formatStatus(voucher.expired, voucher.activatedAt)}
           </span>
           {voucher.expiresAt && (
-            <span className="text-xs">Expires: {voucher.expiresAt}</span>
+            <span className="text-xs">Expires: {formatDate(voucher.expiresAt)}</span>
           )}
         </div>
       </div>
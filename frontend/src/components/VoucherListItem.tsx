@@ -1,6 +1,7 @@
 import { Voucher } from "@/types/voucher";
 import {
   formatCode,
+  formatDate,
   formatDuration,
   formatGuestUsage,
   formatStatus,
@@ -79,9 +80,9 @@ const VoucherListItem = ({ voucher, selected, editMode, onClick }: Props) => {
         {/* First Used / Expires */}
         <div className="flex-shrink-0 w-40 text-xs text-secondary text-right">
           {voucher.activatedAt ? (
-            <div>Used: {voucher.activatedAt}</div>
+            <div>Used: {formatDate(voucher.activatedAt)}</div>
           ) : voucher.expiresAt ? (
-            <div>Expires: {voucher.expiresAt}</div>
+            <div>Expires: {formatDate(voucher.expiresAt)}</div>
           ) : (
             <div>—</div>
           )}
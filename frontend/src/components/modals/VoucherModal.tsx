@@ -6,6 +6,7 @@ import { api } from "@/utils/api";
 import { useCallback, useEffect, useRef, useState } from "react";
 import {
   formatBytes,
+  formatDate,
   formatDuration,
   formatGuestUsage,
   formatSpeed,
@@ -66,11 +67,11 @@ export default function VoucherModal({ voucher, onClose }: Props) {
               [
                 ["Status", formatStatus(details.expired, details.activatedAt)],
                 ["Name", details.name || "No note"],
-                ["Created", details.createdAt],
+                ["Created", formatDate(details.createdAt)],
                 ...(details.activatedAt
-                  ? [["Activated", details.activatedAt]]
+                  ? [["Activated", formatDate(details.activatedAt)]]
                   : []),
-                ...(details.expiresAt ? [["Expires", details.expiresAt]] : []),
+                ...(details.expiresAt ? [["Expires", formatDate(details.expiresAt)]] : []),
                 ["Duration", formatDuration(details.timeLimitMinutes)],
                 [
                   "Guest Usage",
@@ -2,7 +2,7 @@
 
 import SuccessModal from "@/components/modals/SuccessModal";
 import { Voucher, VoucherCreateData } from "@/types/voucher";
-import { api } from "@/utils/api";
+import { api, errorMessage } from "@/utils/api";
 import { map } from "@/utils/functional";
 import { notify } from "@/utils/notifications";
 import { useCallback, useState, FormEvent } from "react";
@@ -43,8 +43,8 @@ export default function CustomCreateTab() {
           "warning",
         );
       }
-    } catch {
-      notify("Failed to create voucher", "error");
+    } catch (error) {
+      notify(errorMessage(error, "Failed to create voucher"), "error");
     }
     setLoading(false);
   };
@@ -0,0 +1,157 @@
+"use client";
+
+import Spinner from "@/components/utils/Spinner";
+import { Kiosk, RegisteredKiosk } from "@/types/kiosk";
+import { TriState } from "@/types/state";
+import { api, errorMessage } from "@/utils/api";
+import { formatCode } from "@/utils/format";
+import { notify } from "@/utils/notifications";
+import { useCallback, useEffect, useState, FormEvent } from "react";
+
+export default function KiosksTab() {
+  const [kiosks, setKiosks] = useState<Kiosk[]>([]);
+  const [state, setState] = useState<TriState | null>(null);
+  const [loading, setLoading] = useState(false);
+  const [registered, setRegistered] = useState<RegisteredKiosk | null>(null);
+
+  const load = useCallback(async () => {
+    try {
+      setKiosks(await api.getKiosks());
+      setState("ok");
+    } catch {
+      setState("error");
+    }
+  }, []);
+
+  useEffect(() => {
+    setState("loading");
+    load();
+    const interval = setInterval(load, 15000);
+    return () => clearInterval(interval);
+  }, [load]);
+
+  const handleSubmit = async (e: FormEvent<HTMLFormElement>) => {
+    e.preventDefault();
+    setLoading(true);
+
+    const form = e.currentTarget;
+    const data = new FormData(form);
+    const optional = (x: FormDataEntryValue | null) =>
+      x ? String(x).trim() || undefined : undefined;
+
+    try {
+      const kiosk = await api.registerKiosk({
+        id: String(data.get("id")).trim(),
+        name: optional(data.get("name")),
+        pool: optional(data.get("pool")),
+      });
+      setRegistered(kiosk);
+      form.reset();
+      await load();
+    } catch (error) {
+      notify(errorMessage(error, "Failed to register kiosk"), "error");
+    }
+    setLoading(false);
+  };
+
+  const remove = async (kiosk: Kiosk) => {
+    if (!confirm(`Remove kiosk "${kiosk.name}"?`)) return;
+    try {
+      await api.deleteKiosk(kiosk.id);
+      notify(`Removed kiosk ${kiosk.name}`, "success");
+      await load();
+    } catch (error) {
+      notify(errorMessage(error, "Failed to remove kiosk"), "error");
+    }
+  };
+
+  const kioskUrl = (kiosk: RegisteredKiosk) =>
+    `${window.location.origin}/kiosk?kiosk=${encodeURIComponent(kiosk.id)}&token=${kiosk.token}`;
+
+  return (
+    <div className="space-y-6">
+      <form onSubmit={handleSubmit} className="card max-w-lg mx-auto space-y-6">
+        <h2 className="text-lg font-semibold text-primary">Register Kiosk</h2>
+        {[
+          {
+            label: "Id",
+            name: "id",
+            props: { required: true, pattern: "[A-Za-z0-9_-]+", placeholder: "reception" },
+          },
+          { label: "Name", name: "name", props: { placeholder: "Same as id" } },
+          { label: "Pool", name: "pool", props: { placeholder: "default" } },
+        ].map(({ label, name, props }) => (
+          <div key={name}>
+            <label className="block font-medium mb-1">{label}</label>
+            <input name={name} type="text" {...props} />
+          </div>
+        ))}
+        <button type="submit" disabled={loading} className="btn-primary w-full">
+          {loading ? "Registering…" : "Register Kiosk"}
+        </button>
+        {registered && (
+          <div className="space-y-2 text-sm">
+            <p>
+              Open this address on <strong>{registered.name}</strong>. The device
+              token is only shown once.
+            </p>
+            <code className="block break-all">{kioskUrl(registered)}</code>
+          </div>
+        )}
+      </form>
+
+      <div className="card max-w-3xl mx-auto">
+        <h2 className="text-lg font-semibold text-primary mb-4">Kiosks</h2>
+        {state === "loading" && <Spinner />}
+        {state === "error" && (
+          <p className="text-status-danger">Could not load kiosks</p>
+        )}
+        {state === "ok" && kiosks.length === 0 && <p>No kiosks registered</p>}
+        {state === "ok" && kiosks.length > 0 && (
+          <table className="w-full text-left">
+            <thead>
+              <tr>
+                <th>Name</th>
+                <th>Pool</th>
+                <th>Last Seen</th>
+                <th>Current Code</th>
+                <th>Redemptions</th>
+                <th />
+              </tr>
+            </thead>
+            <tbody>
+              {kiosks.map((kiosk) => (
+                <tr key={kiosk.id}>
+                  <td>
+                    <span
+                      className={kiosk.online ? "text-status-success" : "text-status-danger"}
+                      title={kiosk.online ? "Online" : "Offline"}
+                    >
+                      ●
+                    </span>{" "}
+                    {kiosk.name}
+                  </td>
+                  <td>{kiosk.pool}</td>
+                  <td>
+                    {kiosk.lastSeen
+                      ? new Date(kiosk.lastSeen).toLocaleString()
+                      : "Never"}
+                  </td>
+                  <td className="font-mono">
+                    {kiosk.currentCode ? formatCode(kiosk.currentCode) : "-"}
+                  </td>
+                  <td>{kiosk.redemptions}</td>
+                  <td>
+                    <button onClick={() => remove(kiosk)} className="btn-danger">
+                      Remove
+                    </button>
+                  </td>
+                </tr>
+              ))}
+            </tbody>
+          </table>
+        )}
+      </div>
+    </div>
+  );
+}
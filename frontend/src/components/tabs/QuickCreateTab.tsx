@@ -2,7 +2,7 @@
 
 import SuccessModal from "@/components/modals/SuccessModal";
 import { Voucher, VoucherCreateData } from "@/types/voucher";
-import { api } from "@/utils/api";
+import { api, errorMessage } from "@/utils/api";
 import { notify } from "@/utils/notifications";
 import { useCallback, useState, useEffect, FormEvent } from "react";
 
@@ -75,8 +75,8 @@ export default function QuickCreateTab() {
           "warning",
         );
       }
-    } catch {
-      notify("Failed to create voucher", "error");
+    } catch (error) {
+      notify(errorMessage(error, "Failed to create voucher"), "error");
     }
     setLoading(false);
   };
@@ -1,6 +1,7 @@
 "use client";
 
 import CustomCreateTab from "@/components/tabs/CustomCreateTab";
+import KiosksTab from "@/components/tabs/KiosksTab";
 import TestTab from "@/components/tabs/TestTab";
 import QuickCreateTab from "@/components/tabs/QuickCreateTab";
 import VouchersTab from "@/components/tabs/VouchersTab";
@@ -25,6 +26,12 @@ const TAB_CONFIG = [
     component: CustomCreateTab,
     enabled: true,
   },
+  {
+    id: "kiosks",
+    label: "Kiosks",
+    component: KiosksTab,
+    enabled: true,
+  },
   {
     id: "test",
     label: "Test",
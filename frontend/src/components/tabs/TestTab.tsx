@@ -24,15 +24,18 @@ export default function TestTab() {
 
   const v: Voucher = {
     id: "test-voucher",
-    createdAt: "2025-12-31",
+    createdAt: "2025-12-31T00:00:00+00:00",
+    createdAtEpoch: 1767139200,
     name: "Test Voucher",
     code: "TEST123",
     authorizedGuestCount: 0,
     authorizedGuestLimit: null,
     expired: false,
+    status: "unused",
+    remainingMinutes: 1440,
     timeLimitMinutes: 1440,
     activatedAt: null,
-    expiresAt: "2025-12-31",
+    expiresAt: "2025-12-31T00:00:00+00:00",
     dataUsageLimitMBytes: null,
     rxRateLimitKbps: null,
     txRateLimitKbps: null,
@@ -72,11 +75,14 @@ export default function TestTab() {
             key={123}
             voucher={{
               id: "abc123",
-              createdAt: "2025-12-31",
+              createdAt: "2025-12-31T00:00:00+00:00",
+              createdAtEpoch: 1767139200,
               name: "test voucher",
               code: "1234567890",
               authorizedGuestCount: 0,
               expired: false,
+              status: "unused",
+              remainingMinutes: 1440,
               timeLimitMinutes: 1440,
             }}
             editMode={false}
@@ -87,11 +93,14 @@ export default function TestTab() {
             key={456}
             voucher={{
               id: "abc123",
-              createdAt: "2025-12-31",
+              createdAt: "2025-12-31T00:00:00+00:00",
+              createdAtEpoch: 1767139200,
               name: "test voucher",
               code: "1234567890",
               authorizedGuestCount: 0,
               expired: false,
+              status: "unused",
+              remainingMinutes: 1440,
               timeLimitMinutes: 1440,
             }}
             editMode={true}
@@ -102,13 +111,16 @@ export default function TestTab() {
             key={789}
             voucher={{
               id: "abc123",
-              createdAt: "2025-12-31",
+              createdAt: "2025-12-31T00:00:00+00:00",
+              createdAtEpoch: 1767139200,
               name: "test voucher",
               code: "1234567890",
               authorizedGuestCount: 1,
               expired: true,
+              status: "expired",
+              remainingMinutes: 0,
               timeLimitMinutes: 1440,
-              expiresAt: "2025-12-31",
+              expiresAt: "2025-12-31T00:00:00+00:00",
             }}
             editMode={true}
             selected={false}
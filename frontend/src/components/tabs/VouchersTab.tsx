@@ -6,7 +6,7 @@ import VoucherListItem from "@/components/VoucherListItem";
 import VoucherModal from "@/components/modals/VoucherModal";
 import { PrintMode } from "@/app/print/page";
 import { Voucher } from "@/types/voucher";
-import { api } from "@/utils/api";
+import { api, errorMessage } from "@/utils/api";
 import { notify } from "@/utils/notifications";
 import { useMemo, useEffect, useCallback, useState } from "react";
 import { useRouter } from "next/navigation";
@@ -48,8 +48,8 @@ export default function VouchersTab() {
     try {
       const res = await api.getAllVouchers();
       setVouchers(res.data || []);
-    } catch {
-      notify("Failed to load vouchers", "error");
+    } catch (error) {
+      notify(errorMessage(error, "Failed to load vouchers"), "error");
     }
     setLoading(false);
   }, []);
@@ -110,8 +110,8 @@ export default function VouchersTab() {
         } else {
           notify(`No ${kind_word} vouchers were deleted`, "info");
         }
-      } catch {
-        notify(`Failed to delete ${kind_word} vouchers`, "error");
+      } catch (error) {
+        notify(errorMessage(error, `Failed to delete ${kind_word} vouchers`), "error");
       }
       setBusy(false);
       cancelEdit();
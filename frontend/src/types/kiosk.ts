@@ -0,0 +1,21 @@
+export interface Kiosk {
+  id: string;
+  name: string;
+  pool: string;
+  registeredAt: string;
+  lastSeen?: string | null;
+  online: boolean;
+  voucherId?: string | null;
+  currentCode?: string | null;
+  redemptions: number;
+}
+
+export interface KioskRegisterData {
+  id: string;
+  name?: string;
+  pool?: string;
+}
+
+export interface RegisteredKiosk extends Kiosk {
+  token: string;
+}
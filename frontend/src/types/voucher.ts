@@ -1,13 +1,23 @@
+export type VoucherStatus = "unused" | "active" | "expired";
+
+// Dates are RFC 3339 with the offset of the backend's timezone, see
+// formatDate for display
 export interface Voucher {
   id: string;
   createdAt: string;
+  createdAtEpoch: number | null;
   name: string;
   code: string;
   authorizedGuestLimit?: number | null;
   authorizedGuestCount: number;
   activatedAt?: string | null;
+  activatedAtEpoch?: number | null;
   expiresAt?: string | null;
+  expiresAtEpoch?: number | null;
   expired: boolean;
+  status: VoucherStatus;
+  remainingMinutes: number;
+  remainingDataMBytes?: number | null;
   timeLimitMinutes: number;
   dataUsageLimitMBytes?: number | null;
   rxRateLimitKbps?: number | null;
@@ -19,11 +29,17 @@ export interface VoucherCreateData
     Voucher,
     | "id"
     | "createdAt"
+    | "createdAtEpoch"
     | "code"
     | "authorizedGuestCount"
     | "activatedAt"
+    | "activatedAtEpoch"
     | "expiresAt"
+    | "expiresAtEpoch"
     | "expired"
+    | "status"
+    | "remainingMinutes"
+    | "remainingDataMBytes"
   > {
   count: number;
 }
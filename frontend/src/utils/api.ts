@@ -1,3 +1,4 @@
+import { Kiosk, KioskRegisterData, RegisteredKiosk } from "@/types/kiosk";
 import {
   Voucher,
   VoucherCreateData,
@@ -14,6 +15,39 @@ function removeNullUndefined<T extends Record<string, any>>(obj: T): T {
   ) as T;
 }
 
+// RFC 7807 problem details returned by the backend on errors
+interface Problem {
+  type?: string;
+  title?: string;
+  status?: number;
+  detail?: string;
+  code?: string;
+  requestId?: string;
+}
+
+export class ApiError extends Error {
+  status: number;
+  code?: string;
+  requestId?: string;
+
+  constructor(status: number, message: string, problem: Problem = {}) {
+    super(message);
+    this.name = "ApiError";
+    this.status = status;
+    this.code = problem.code;
+    this.requestId = problem.requestId;
+  }
+}
+
+/** Message to show for a failed API call, with the backend's explanation. */
+export function errorMessage(error: unknown, fallback: string) {
+  if (error instanceof ApiError && error.message) {
+    const reference = error.requestId ? ` (request ${error.requestId})` : "";
+    return `${fallback}: ${error.message}${reference}`;
+  }
+  return fallback;
+}
+
 async function call<T>(endpoint: string, opts: RequestInit = {}) {
   // Remove leading slash from endpoint if present to avoid double slashes
   const cleanEndpoint = endpoint.startsWith('/') ? endpoint.slice(1) : endpoint;
@@ -22,22 +56,43 @@ async function call<T>(endpoint: string, opts: RequestInit = {}) {
     ...opts,
   });
   if (!res.ok) {
-    const error = new Error(res.statusText);
-    (error as any).status = res.status;
-    throw error;
+    const problem: Problem = await res.json().catch(() => ({}));
+    throw new ApiError(
+      res.status,
+      problem.detail || problem.title || res.statusText,
+      problem,
+    );
   }
   return res.json() as Promise<T>;
 }
 
+// Headers authenticating a registered kiosk with its device token
+function kioskHeaders(id: string, token: string) {
+  return {
+    "Content-Type": "application/json",
+    Authorization: `Bearer ${token}`,
+    "X-Kiosk-Id": id,
+  };
+}
+
+// Query string selecting a rolling pool, the backend's default pool if unset
+function poolQuery(pool?: string) {
+  return pool ? `?pool=${encodeURIComponent(pool)}` : "";
+}
+
 export const api = {
   getAllVouchers: () => call<{ data: Voucher[] }>("/vouchers"),
 
-  getRollingVoucher: (index?: number) => {
-    const url = index !== undefined ? `/vouchers/rolling?index=${index}` : "/vouchers/rolling";
-    return call<Voucher>(url);
+  getRollingVoucher: (index?: number, pool?: string) => {
+    const params = new URLSearchParams();
+    if (index !== undefined) params.set("index", index.toString());
+    if (pool) params.set("pool", pool);
+    const qs = params.toString();
+    return call<Voucher>(qs ? `/vouchers/rolling?${qs}` : "/vouchers/rolling");
   },
 
-  getAllRollingVouchers: () => call<Voucher[]>("/vouchers/rolling/all"),
+  getAllRollingVouchers: (pool?: string) =>
+    call<Voucher[]>(`/vouchers/rolling/all${poolQuery(pool)}`),
 
   getNewestVoucher: () => call<Voucher>("/vouchers/newest"),
 
@@ -54,16 +109,16 @@ export const api = {
     return result;
   },
 
-  createRollingVoucher: async () => {
-    const result = await call<Voucher>("/vouchers/rolling", {
+  createRollingVoucher: async (pool?: string) => {
+    const result = await call<Voucher>(`/vouchers/rolling${poolQuery(pool)}`, {
       method: "POST",
     });
     await notifyVouchersUpdated();
     return result;
   },
 
-  rotateRollingVoucherIfNeeded: async () => {
-    const result = await call<{status: string, voucher?: Voucher}>("/vouchers/rolling/rotate", {
+  rotateRollingVoucherIfNeeded: async (pool?: string) => {
+    const result = await call<{status: string, vouchers?: Voucher[]}>(`/vouchers/rolling/rotate${poolQuery(pool)}`, {
       method: "POST",
     });
     if (result.status === "created") {
@@ -80,9 +135,10 @@ export const api = {
     return result;
   },
 
-  deleteExpiredRollingVouchers: async () => {
+  // Without a pool, the expired vouchers of every pool are deleted
+  deleteExpiredRollingVouchers: async (pool?: string) => {
     const result = await call<VoucherDeletedResponse>(
-      "/vouchers/expired/rolling",
+      `/vouchers/expired/rolling${poolQuery(pool)}`,
       {
         method: "DELETE",
       },
@@ -102,4 +158,28 @@ export const api = {
     await notifyVouchersUpdated();
     return result;
   },
+
+  getKiosks: () => call<Kiosk[]>("/kiosks"),
+
+  registerKiosk: (data: KioskRegisterData) =>
+    call<RegisteredKiosk>("/kiosks", {
+      method: "POST",
+      body: JSON.stringify(removeNullUndefined(data)),
+    }),
+
+  deleteKiosk: (id: string) =>
+    call<Kiosk>(`/kiosks/${encodeURIComponent(id)}`, {
+      method: "DELETE",
+    }),
+
+  getKioskVoucher: (id: string, token: string) =>
+    call<Voucher>(`/kiosks/${encodeURIComponent(id)}/voucher`, {
+      headers: kioskHeaders(id, token),
+    }),
+
+  sendKioskHeartbeat: (id: string, token: string) =>
+    call<Kiosk>(`/kiosks/${encodeURIComponent(id)}/heartbeat`, {
+      method: "POST",
+      headers: kioskHeaders(id, token),
+    }),
 };
@@ -15,6 +15,17 @@ export function formatStatus(
   return "Available";
 }
 
+export function formatDate(date: string | null | undefined) {
+  if (!date) return "";
+  const parsed = new Date(date);
+  return isNaN(parsed.getTime())
+    ? date
+    : parsed.toLocaleString(undefined, {
+        dateStyle: "medium",
+        timeStyle: "short",
+      });
+}
+
 export function formatDuration(m: number | null | undefined) {
   if (!m) return "Unlimited";
   const days = Math.floor(m / 1440),
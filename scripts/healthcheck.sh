@@ -2,11 +2,28 @@
 set -e
 
 # Check backend health
-echo "Checking backend on port $BACKEND_BIND_PORT..."
-wget --no-verbose --tries=1 --spider --timeout=5 "http://localhost:$BACKEND_BIND_PORT/api/health" 2>/dev/null || {
-  echo "Backend health check failed on port $BACKEND_BIND_PORT"
-  exit 1
-}
+if [ "$BACKEND_TCP_ENABLED" = "false" ]; then
+  # Serving on the Unix socket only, which wget cannot reach
+  echo "Checking backend socket $BACKEND_UNIX_SOCKET..."
+  curl --silent --fail --output /dev/null --max-time 5 --unix-socket "$BACKEND_UNIX_SOCKET" "http://localhost/api/health/live" || {
+    echo "Backend health check failed on socket $BACKEND_UNIX_SOCKET"
+    exit 1
+  }
+else
+  scheme="http"
+  if [ -n "$BACKEND_TLS_CERT" ]; then
+    # The certificate may not name localhost
+    scheme="https"
+    tls_flags="--no-check-certificate"
+  fi
+  echo "Checking backend on port $BACKEND_BIND_PORT..."
+  # Liveness only: the backend keeps retrying while the controller is down,
+  # restarting the container would not help
+  wget --no-verbose --tries=1 --spider --timeout=5 $tls_flags "$scheme://localhost:$BACKEND_BIND_PORT/api/health/live" 2>/dev/null || {
+    echo "Backend health check failed on port $BACKEND_BIND_PORT"
+    exit 1
+  }
+fi
 
 # Check frontend health
 echo "Checking frontend on port $FRONTEND_BIND_PORT..."
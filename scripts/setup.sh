@@ -35,6 +35,25 @@ else
     }
 fi
 
+# Create data directory with correct permissions
+echo ""
+echo "Creating data directory..."
+if [ ! -d "data" ]; then
+    mkdir -p data
+    echo "✓ Created data directory"
+else
+    echo "✓ Data directory already exists"
+fi
+
+if command -v sudo &> /dev/null; then
+    sudo chown -R 1001:1001 data
+else
+    chown -R 1001:1001 data 2>/dev/null || {
+        echo "⚠ Warning: Could not set permissions. You may need to run:"
+        echo "  sudo chown -R 1001:1001 data"
+    }
+fi
+
 # Create frontend/public directory for logo
 echo ""
 echo "Creating frontend/public directory for logo..."